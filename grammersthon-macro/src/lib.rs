@@ -1,8 +1,9 @@
 use proc_macro::TokenStream;
-use quote::quote;
+use quote::{quote, ToTokens};
 use regex::Regex;
 use syn::punctuated::Punctuated;
-use syn::{parse_macro_input, ItemFn, Result, Lit, ExprClosure, DeriveInput, Data, FieldsUnnamed, Ident, Fields, FieldsNamed, DataEnum, Attribute, Token};
+use syn::{parse_macro_input, ItemFn, Result, LitStr, Expr, ExprClosure, DeriveInput, Data, FieldsUnnamed, Ident, Fields, FieldsNamed, DataEnum, Attribute, Token};
+use syn::{Meta, MetaNameValue, ExprLit, Lit, FnArg, PatType, Type, PathArguments, GenericArgument};
 use syn::parse::{ParseStream, Parse};
 
 extern crate proc_macro;
@@ -22,28 +23,136 @@ extern crate proc_macro;
 /// #[handler(|m, h| true)]
 /// ```
 /// 
+/// ### Filter expression:
+/// Any expression evaluating to `HandlerFilter` (including variables, except the option names below)
+/// ```
+/// #[handler(filters::admin_only())]
+/// ```
+/// 
 /// ### Combined:
 /// 
 /// ```
-/// #[handler("regex", |m, h| true)]
+/// #[handler("regex", |m, h| true, filters::admin_only())]
+/// ```
+/// 
+/// Doc comment of the function is used as description in command schema.
+/// 
+/// ### Options:
+/// `capture_args` - populate `Args` from named capture groups of the pattern
+/// ```
+/// #[handler("^/color (?P<r>\\d+) (?P<g>\\d+) (?P<b>\\d+)$", capture_args)]
+/// ```
+/// 
+/// `scope = CommandScope` - where to show the command in command menu (otherwise derived from filters)
+/// ```
+/// #[handler("^/stats", scope = CommandScope::PrivateChats)]
+/// ```
+/// 
+/// `hidden` - exclude from help and command menu, the handler still works
+/// ```
+/// #[handler("^/debug", hidden)]
+/// ```
+/// 
+/// `edits` - also handle edited messages (`Edit` extractor, `filters::edited()` for only them)
+/// ```
+/// #[handler("", edits, filters::edited())]
+/// ```
+/// 
+/// `context` or `context = 20` - fetch the `Context` argument (default size or count of messages).
+/// Required unless the argument is written as `grammersthon::Context`
+/// ```
+/// #[handler("^/ask", context = 20)]
+/// ```
+/// 
+/// `category = "Name"` - section of the help message and command schema
+/// ```
+/// #[handler("^/play", category = "Music")]
+/// ```
+/// 
+/// `timeout = "30s"` - cancel the handler if it runs longer, reported to error handler as `GrammersthonError::Timeout`
+/// (same format as `Duration` argument, checked at compile time, or `Duration` expression)
+/// ```
+/// #[handler("^/report", timeout = "2m")]
+/// ```
+/// 
+/// `command = "name"` - match the command, optionally with `@botname` and arguments.
+/// The prefix (default `/`) and anchoring are configured at compile time with the
+/// `GRAMMERSTHON_COMMAND_PREFIX` and `GRAMMERSTHON_COMMAND_ANCHORED` (`true` = no arguments allowed)
+/// environment variables, for example in `.cargo/config.toml` under `[env]`.
+/// Run `cargo clean` after changing them, cargo doesn't rebuild on environment changes.
+/// ```
+/// #[handler(command = "start")]
 /// ```
 #[proc_macro_attribute]
 pub fn handler(metadata: TokenStream, input: TokenStream) -> TokenStream {
     let filters = parse_macro_input!(metadata as HandlerFilters);
     let input_fn = parse_macro_input!(input as ItemFn);
 
-    // Generate filters code
+    // Generate filters and options code
     let mut filters_code = vec![];
+    let mut options_code = vec![];
     for filter in filters.0 {
         let code = match filter {
             HandlerFilter::Regex(r) => quote! { ::grammersthon::HandlerFilter::Regex(#r.to_string()) },
             HandlerFilter::Fn(f) => quote! { ::grammersthon::HandlerFilter::Fn(::std::sync::Arc::new(::std::boxed::Box::new(#f))) },
+            HandlerFilter::Expr(e) => quote! { #e },
+            HandlerFilter::Option(option, value) if option == "command" => match value {
+                Expr::Lit(ExprLit { lit: Lit::Str(command), .. }) => {
+                    let pattern = command_pattern(&command.value());
+                    quote! { ::grammersthon::HandlerFilter::Regex(#pattern.to_string()) }
+                },
+                value => return syn::Error::new_spanned(value, "Expected command name string").to_compile_error().into()
+            },
+            HandlerFilter::Option(option, Expr::Lit(ExprLit { lit: Lit::Str(timeout), .. })) if option == "timeout" => {
+                let millis = match duration_millis(&timeout.value()) {
+                    Some(millis) => millis,
+                    None => return syn::Error::new_spanned(timeout, "Invalid timeout, expected duration such as \"30s\" or \"2m\"").to_compile_error().into()
+                };
+                options_code.push(quote! { .timeout(::std::time::Duration::from_millis(#millis)) });
+                continue;
+            },
+            HandlerFilter::Flag(option) if option == "context" => {
+                options_code.push(quote! { .uses_context() });
+                continue;
+            },
+            HandlerFilter::Flag(option) => {
+                options_code.push(quote! { .#option(true) });
+                continue;
+            },
+            HandlerFilter::Option(option, value) => {
+                options_code.push(quote! { .#option(#value) });
+                continue;
+            }
         };
         filters_code.push(code);
     }
 
+    // Description from doc comment
+    let description = input_fn.attrs.iter().filter_map(|a| match &a.meta {
+        Meta::NameValue(MetaNameValue { path, value: Expr::Lit(ExprLit { lit: Lit::Str(s), .. }), .. }) if path.is_ident("doc") => Some(s.value().trim().to_string()),
+        _ => None
+    }).collect::<Vec<_>>().join("\n");
+    if !description.is_empty() {
+        options_code.push(quote! { .description(#description) });
+    }
+
+    // Schema of `Args<T>` parameter, `Data<T>` parameters checked at startup
+    for arg in &input_fn.sig.inputs {
+        if let Some(ty) = wrapper_inner_type(arg, "Args") {
+            options_code.push(quote! { .args(<#ty as ::grammersthon::FromArgs>::schema()) });
+            options_code.push(quote! { .reply_args(<#ty as ::grammersthon::FromArgs>::uses_reply()) });
+        }
+        if let Some(ty) = wrapper_inner_type(arg, "Data") {
+            options_code.push(quote! { .requires_data::<#ty>() });
+        }
+        if is_grammersthon_type(arg, "Context") {
+            options_code.push(quote! { .uses_context() });
+        }
+    }
+
     // Function name
     let ident = input_fn.sig.ident.clone();
+    let name = ident.to_string();
     let out = quote! {
         #input_fn
 
@@ -53,8 +162,9 @@ pub fn handler(metadata: TokenStream, input: TokenStream) -> TokenStream {
 
         impl #ident {
             #[allow(non_snake_case, unreachable_patterns, unreachable_code)]
-            pub fn info() -> ::std::vec::Vec<::grammersthon::HandlerFilter> {
-                ::std::vec![#(#filters_code),*]
+            pub fn info() -> ::grammersthon::HandlerInfo {
+                ::grammersthon::HandlerInfo::new(#name, ::std::vec![#(#filters_code),*])
+                    #(#options_code)*
             }
         }
     };
@@ -62,6 +172,84 @@ pub fn handler(metadata: TokenStream, input: TokenStream) -> TokenStream {
     TokenStream::from(out)
 }
 
+/// Milliseconds of duration string, same format as `Duration` argument (`90`, `10s`, `2h30m`, `500ms`)
+fn duration_millis(input: &str) -> Option<u64> {
+    let input = input.trim();
+    if let Ok(seconds) = input.parse::<u64>() {
+        return seconds.checked_mul(1000);
+    }
+    let mut millis = 0u64;
+    let mut chars = input.chars().filter(|c| !c.is_whitespace()).peekable();
+    chars.peek()?;
+    while chars.peek().is_some() {
+        let number = std::iter::from_fn(|| chars.next_if(|c| c.is_ascii_digit())).collect::<String>().parse::<u64>().ok()?;
+        let unit = std::iter::from_fn(|| chars.next_if(|c| c.is_alphabetic())).collect::<String>();
+        let multiplier = match unit.to_lowercase().as_str() {
+            "ms" => 1,
+            "s" | "sec" | "secs" | "second" | "seconds" => 1000,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60 * 1000,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 60 * 60 * 1000,
+            "d" | "day" | "days" => 24 * 60 * 60 * 1000,
+            "w" | "week" | "weeks" => 7 * 24 * 60 * 60 * 1000,
+            _ => return None
+        };
+        millis = millis.checked_add(number.checked_mul(multiplier)?)?;
+    }
+    Some(millis)
+}
+
+/// Pattern of the `command = "name"` option, with prefix and anchoring from the environment
+fn command_pattern(command: &str) -> String {
+    let prefix = std::env::var("GRAMMERSTHON_COMMAND_PREFIX").unwrap_or_else(|_| "/".to_string());
+    let anchored = std::env::var("GRAMMERSTHON_COMMAND_ANCHORED").map(|v| v == "true" || v == "1").unwrap_or(false);
+    let end = match anchored {
+        true => "$",
+        false => r"(?:\s|$)",
+    };
+    let pattern = format!(r"^{}{}(?:@\w+)?{end}", regex::escape(&prefix), regex::escape(command));
+    Regex::new(&pattern).expect("Invalid command");
+    pattern
+}
+
+/// Get `T` if function argument is `Wrapper<T>` (such as `Args<T>`)
+fn wrapper_inner_type<'a>(arg: &'a FnArg, wrapper: &str) -> Option<&'a Type> {
+    let ty = match arg {
+        FnArg::Typed(PatType { ty, .. }) => ty,
+        FnArg::Receiver(_) => return None
+    };
+    let segment = match &**ty {
+        Type::Path(path) => path.path.segments.last()?,
+        _ => return None
+    };
+    if segment.ident != wrapper {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(a) => match a.args.first()? {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// Whether the argument is of type from this crate, by the full path (`grammersthon::Name`),
+/// so other types with the same name aren't matched
+fn is_grammersthon_type(arg: &FnArg, name: &str) -> bool {
+    let path = match arg {
+        FnArg::Typed(PatType { ty, .. }) => match &**ty {
+            Type::Path(path) if path.qself.is_none() => &path.path,
+            _ => return false
+        },
+        FnArg::Receiver(_) => return false
+    };
+    let segments = path.segments.iter().map(|s| s.ident.to_string()).collect::<Vec<_>>();
+    segments == ["grammersthon", name]
+}
+
+/// Options of the `handler` macro enabled by bare identifier, other identifiers are filter variables
+const HANDLER_FLAGS: &[&str] = &["capture_args", "hidden", "edits", "context"];
+
 struct HandlerFilters(Vec<HandlerFilter>);
 
 impl Parse for HandlerFilters {
@@ -73,54 +261,175 @@ impl Parse for HandlerFilters {
 
 enum HandlerFilter {
     Regex(String),
-    Fn(ExprClosure)
+    Fn(ExprClosure),
+    Expr(Expr),
+    /// Option enabled by bare identifier
+    Flag(Ident),
+    /// Option with value (`key = value`)
+    Option(Ident, Expr)
 }
 
 impl Parse for HandlerFilter {
     fn parse(input: ParseStream) -> Result<Self> {
         // Try to parse as String pattern
-        match Lit::parse(input) {
-            Ok(Lit::Str(pattern)) => {
-                // Validate
-                let regex = pattern.value().to_string();
-                Regex::new(&regex).expect("Invalid pattern regex!");
-                return Ok(Self::Regex(regex));
-            },
-            _ => {}
+        if input.peek(LitStr) {
+            let pattern = input.parse::<LitStr>()?;
+            // Validate
+            let regex = pattern.value().to_string();
+            Regex::new(&regex).expect("Invalid pattern regex!");
+            return Ok(Self::Regex(regex));
         }
 
-        // Parse as fn
-        let closure = ExprClosure::parse(input)?;
-        Ok(HandlerFilter::Fn(closure))
+        // Parse as fn, option or filter expression
+        match Expr::parse(input)? {
+            Expr::Closure(closure) => Ok(HandlerFilter::Fn(closure)),
+            Expr::Path(path) if path.path.get_ident().map(|i| HANDLER_FLAGS.iter().any(|f| i == f)).unwrap_or(false) => {
+                Ok(HandlerFilter::Flag(path.path.get_ident().unwrap().clone()))
+            },
+            Expr::Assign(assign) => match &*assign.left {
+                Expr::Path(path) if path.path.get_ident().is_some() => Ok(HandlerFilter::Option(path.path.get_ident().unwrap().clone(), *assign.right)),
+                _ => Err(syn::Error::new_spanned(assign.left, "Expected option name"))
+            },
+            expr => Ok(HandlerFilter::Expr(expr))
+        }
     }
 }
 
 /// Derive `FromArgs`
-#[proc_macro_derive(FromArgs, attributes(rest, ignore_case))]
+/// 
+/// Fields are parsed in order, `#[rest]` on the last field takes the rest of the input
+/// and trailing `Option<T>` fields can be omitted.
+/// 
+/// Fields with `#[named]` are parsed from `--name value` or `name=value` anywhere in the input,
+/// `#[flag]` bool fields are true when `--name` is present (`--name yes/no` works as well):
+/// ```ignore
+/// #[derive(FromArgs)]
+/// struct Search {
+///     #[rest]
+///     query: String,
+///     #[named]
+///     limit: Option<u32>,
+///     #[flag]
+///     safe: bool,
+/// }
+/// // /search cats --limit 5 --safe
+/// ```
+/// 
+/// Enum variants are selected by the first argument (`#[ignore_case]` on the enum to match lowercase),
+/// the rest of the input is parsed into the variant fields same as for structs:
+/// ```ignore
+/// #[derive(FromArgs)]
+/// #[ignore_case]
+/// enum Action {
+///     Pause,
+///     Volume(u8),
+///     Seek { #[rest] position: Duration },
+/// }
+/// // /player volume 50
+/// ```
+/// 
+/// With `#[subcommand]` on the enum, variants are matched by their kebab-case name (case insensitive),
+/// `#[subcommand(name = "rm", alias = "delete")]` on variant renames it or adds alias.
+/// `#[subcommand]` on field takes the rest of the input, for nested subcommands:
+/// ```ignore
+/// #[derive(FromArgs)]
+/// #[subcommand]
+/// enum Playlist {
+///     Add { url: String },
+///     #[subcommand(alias = "rm")]
+///     Remove { idx: usize },
+///     Clear,
+///     Queue(#[subcommand] QueueCommand),
+/// }
+/// // /playlist add https://..., /playlist rm 2, /playlist queue shuffle
+/// ```
+/// 
+/// Fields can be validated with `#[validate(range(min = 1, max = 100))]`, `#[validate(length(max = 64))]`
+/// and `#[validate(regex = "^#")]` (checked on the raw argument, before parsing).
+/// 
+/// `#[from_str]` on field parses it using `FromStr` of the type instead of `FromArgs`,
+/// on the struct or enum it implements `FromArgs` using its `FromStr` (for existing domain types):
+/// ```ignore
+/// #[derive(FromArgs)]
+/// #[from_str]
+/// struct OrderId(u64); // impl FromStr for OrderId
+///
+/// #[derive(FromArgs)]
+/// struct Ship { order: OrderId, #[from_str] carrier: Carrier }
+/// ```
+/// 
+/// `#[or_reply]` on positional struct field takes it from the replied message when the command is a reply
+/// (sender for `UserRef`, text for `String`, see `FromReply`):
+/// ```ignore
+/// #[derive(FromArgs)]
+/// struct BanArgs { #[or_reply] user: UserRef, #[rest] reason: Option<String> }
+/// // /ban @user spam, or /ban spam as reply
+/// ```
+/// 
+/// Parse errors are `GrammersthonError::InvalidArgs` with the name of the failed field,
+/// usage is generated from the fields (`FromArgs::usage`).
+#[proc_macro_derive(FromArgs, attributes(rest, ignore_case, flag, named, subcommand, validate, from_str, or_reply))]
 pub fn derive_from_args(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
     let name = input.ident;
 
+    // Delegate to FromStr
+    if has_attr(&input.attrs, "from_str") {
+        return TokenStream::from(quote! {
+            impl FromArgs for #name {
+                fn parse_arg(input: &::std::primitive::str) -> ::std::result::Result<#name, ::grammersthon::GrammersthonError> {
+                    ::grammersthon::from_str_arg(input)
+                }
+            }
+        });
+    }
+
     match input.data {
         // Parse struct
         Data::Struct(s) => {
             // Parse fields
-            let (field_count, out) = match s.fields {
-                Fields::Named(f) => from_args_named_fields(&name, f),
-                Fields::Unnamed(f) => from_args_unnamed_fields(&name, f),
-                Fields::Unit => panic!("Unsupported struct type (Unit)"),
+            let captures = match &s.fields {
+                Fields::Named(f) => from_captures_named_fields(&name, f),
+                _ => quote! {},
+            };
+            let schema = schema_fields(&s.fields);
+            if let Fields::Unit = s.fields {
+                panic!("Unsupported struct type (Unit)");
+            }
+            let body = from_args_fields(&name, &s.fields);
+            let parse = match s.fields.iter().any(|f| has_attr(&f.attrs, "or_reply")) {
+                false => quote! {
+                    fn parse_arg(input: &::std::primitive::str) -> ::std::result::Result<#name, ::grammersthon::GrammersthonError> {
+                        #body
+                    }
+                },
+                true => quote! {
+                    fn parse_arg(input: &::std::primitive::str) -> ::std::result::Result<#name, ::grammersthon::GrammersthonError> {
+                        Self::parse_arg_reply(input, None)
+                    }
+
+                    fn parse_arg_reply(
+                        input: &::std::primitive::str,
+                        reply: ::std::option::Option<&::grammersthon::grammers_client::types::Message>
+                    ) -> ::std::result::Result<#name, ::grammersthon::GrammersthonError> {
+                        #body
+                    }
+
+                    fn uses_reply() -> bool {
+                        true
+                    }
+                },
             };
 
             // Generate output impl
             let output = quote! {
                 impl FromArgs for #name {
-                    fn parse_arg(input: &::std::primitive::str) -> ::std::result::Result<#name, ::grammersthon::GrammersthonError> {
-                        // Split
-                        let (args, rest) = ::grammersthon::RawArgs::parse_n(input, #field_count);
-                        if args.0.len() < #field_count {
-                            return Err(::grammersthon::GrammersthonError::Parse(input.to_string(), None))
-                        }
-                        #out
+                    #parse
+
+                    #captures
+
+                    fn schema() -> ::std::vec::Vec<::grammersthon::ArgInfo> {
+                        #schema
                     }
                 }
 
@@ -129,6 +438,7 @@ pub fn derive_from_args(input: TokenStream) -> TokenStream {
         },
         Data::Enum(e) => {
             let match_code = from_args_enum(&name, &e, &input.attrs);
+            let schema = schema_enum(&name, &e, &input.attrs);
 
             // Generate impl
             let output = quote! {
@@ -136,6 +446,10 @@ pub fn derive_from_args(input: TokenStream) -> TokenStream {
                     fn parse_arg(input: &::std::primitive::str) -> ::std::result::Result<#name, ::grammersthon::GrammersthonError> {
                         #match_code
                     }
+
+                    fn schema() -> ::std::vec::Vec<::grammersthon::ArgInfo> {
+                        #schema
+                    }
                 }
             };
             return TokenStream::from(output);
@@ -147,73 +461,515 @@ pub fn derive_from_args(input: TokenStream) -> TokenStream {
 
 }
 
+/// Number of positional fields which aren't trailing `Option<_>` (and so can't be missing)
+fn required_count<'a>(fields: impl Iterator<Item = &'a syn::Field>, count: usize) -> usize {
+    let fields = fields.take(count).collect::<Vec<_>>();
+    count - fields.iter().rev().take_while(|f| option_inner_type(&f.ty).is_some()).count()
+}
+
+/// Code of positional argument, trailing optional arguments can be missing
+fn positional_arg(i: usize, required: usize) -> proc_macro2::TokenStream {
+    match i < required {
+        true => quote! { &args.0[#i] },
+        false => quote! { args.0.get(#i).map(|a| a.as_str()).unwrap_or("") }
+    }
+}
+
+/// Generate code parsing `input` into the fields of struct or enum variant (at path)
+fn from_args_fields(path: &impl ToTokens, fields: &Fields) -> proc_macro2::TokenStream {
+    // Names of positional fields, for reporting which one is missing
+    let positional = fields.iter().enumerate()
+        .filter(|(_, f)| !is_named_field(f))
+        .map(|(i, f)| f.ident.as_ref().map(|i| i.to_string()).unwrap_or(i.to_string()))
+        .collect::<Vec<_>>();
+    let (field_count, required, prelude, out) = match fields {
+        Fields::Named(f) => from_args_named_fields(path, f.clone()),
+        Fields::Unnamed(f) => {
+            let (count, required, out) = from_args_unnamed_fields(path, f.clone());
+            (count, required, quote! {}, out)
+        },
+        Fields::Unit => return quote! { Ok(#path) },
+    };
+    // Placeholders for the fields taken from the replied message
+    let reply_positions = fields.iter().filter(|f| !is_named_field(f)).enumerate()
+        .filter(|(_, f)| has_attr(&f.attrs, "or_reply"))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    let reply_prelude = match reply_positions.is_empty() {
+        true => quote! {},
+        false => quote! {
+            let input = match reply {
+                Some(_) => ::grammersthon::RawArgs::insert_reply_args(input, &[#(#reply_positions),*]),
+                None => input.to_string(),
+            };
+            let input = input.as_str();
+        }
+    };
+    quote! {
+        #prelude
+        #reply_prelude
+        // Split
+        let (args, rest) = ::grammersthon::RawArgs::parse_n(input, #field_count);
+        if args.0.len() < #required {
+            let field = [#(#positional),*][args.0.len()];
+            return Err(::grammersthon::GrammersthonError::missing_argument().in_field(field))
+        }
+        #out
+    }
+}
+
 /// Parse struct with unnamed fields into FromArgs body
-fn from_args_unnamed_fields(name: &Ident, fields: FieldsUnnamed) -> (usize, proc_macro2::TokenStream) {
+fn from_args_unnamed_fields(name: &impl ToTokens, fields: FieldsUnnamed) -> (usize, usize, proc_macro2::TokenStream) {
     let mut count = fields.unnamed.len();
+    let rest = fields.unnamed.last().map(is_rest_field).unwrap_or(false);
+    let required = required_count(fields.unnamed.iter(), count - rest as usize);
     let fields = fields.unnamed.iter().enumerate().map(|(i, f)| {
-        let ty = &f.ty;
         // Check for #[rest] attribute
-        let rest_attr = f.attrs.iter().any(|a| a.path().get_ident().map(|i| i.to_string().as_str() == "rest").unwrap_or(false));
+        let rest_attr = is_rest_field(f);
+        let field_name = i.to_string();
         // Last field use rest
         if i == (count - 1) && rest_attr {
             count -= 1;
-            quote! { <#ty>::parse_arg(&rest)? }
+            parse_field(f, &field_name, quote! { &rest })
         } else {
-            quote! { <#ty>::parse_arg(&args.0[#i])? }
+            parse_field(f, &field_name, positional_arg(i, required))
         }
     }).collect::<Vec<_>>();
     let out = quote! { Ok(#name (#(#fields),*)) };
-    (count, out)
+    (count, required, out)
+}
+
+/// Code parsing field from input expression, with the `#[validate(..)]` checks.
+/// Validation is skipped for omitted `Option<T>` fields, `#[from_str]` fields are parsed with `FromStr`
+fn parse_field(f: &syn::Field, field_name: &str, input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    let ty = &f.ty;
+    let validators = field_validators(f);
+    let validate = match (validators.is_empty(), option_inner_type(ty).is_some()) {
+        (true, _) => quote! {},
+        (false, false) => quote! {
+            ::grammersthon::Validator::validate_arg(input, &[#(#validators),*]).map_err(|e| e.in_field(#field_name))?;
+        },
+        (false, true) => quote! {
+            if !input.trim().is_empty() {
+                ::grammersthon::Validator::validate_arg(input, &[#(#validators),*]).map_err(|e| e.in_field(#field_name))?;
+            }
+        },
+    };
+    let parse = match (has_attr(&f.attrs, "from_str"), option_inner_type(ty)) {
+        (false, _) => quote! { <#ty>::parse_arg(input) },
+        (true, None) => quote! { ::grammersthon::from_str_arg::<#ty>(input) },
+        (true, Some(inner)) => quote! {
+            match input.trim().is_empty() {
+                true => Ok(None),
+                false => ::grammersthon::from_str_arg::<#inner>(input).map(Some),
+            }
+        },
+    };
+    if has_attr(&f.attrs, "or_reply") {
+        return quote! {{
+            let input: &::std::primitive::str = #input;
+            match reply {
+                Some(reply) if input == ::grammersthon::REPLY_ARG => <#ty as ::grammersthon::FromReply>::from_reply(reply).ok_or_else(|| {
+                    ::grammersthon::GrammersthonError::Parse("Replied message".to_string(), None).in_field(#field_name)
+                })?,
+                _ => {
+                    #validate
+                    (#parse).map_err(|e| e.in_field(#field_name))?
+                }
+            }
+        }};
+    }
+    quote! {{
+        let input: &::std::primitive::str = #input;
+        #validate
+        (#parse).map_err(|e| e.in_field(#field_name))?
+    }}
+}
+
+/// Validators of `#[validate(range(min = 1, max = 100), length(min = 1, max = 64), regex = "...")]`
+fn field_validators(f: &syn::Field) -> Vec<proc_macro2::TokenStream> {
+    let mut validators = vec![];
+    for attr in f.attrs.iter().filter(|a| a.path().is_ident("validate")) {
+        attr.parse_nested_meta(|meta| {
+            let option = meta.path.get_ident().map(|i| i.to_string()).unwrap_or_default();
+            match option.as_str() {
+                "regex" => {
+                    let regex = meta.value()?.parse::<LitStr>()?;
+                    Regex::new(&regex.value()).map_err(|e| syn::Error::new_spanned(&regex, format!("Invalid regex: {e}")))?;
+                    validators.push(quote! { ::grammersthon::Validator::Regex(#regex.to_string()) });
+                },
+                "range" | "length" => meta.parse_nested_meta(|bound| {
+                    let value = bound.value()?.parse::<Expr>()?;
+                    validators.push(match (option.as_str(), bound.path.get_ident().map(|i| i.to_string()).as_deref()) {
+                        ("range", Some("min")) => quote! { ::grammersthon::Validator::Min((#value) as f64) },
+                        ("range", Some("max")) => quote! { ::grammersthon::Validator::Max((#value) as f64) },
+                        ("length", Some("min")) => quote! { ::grammersthon::Validator::MinLen((#value) as usize) },
+                        ("length", Some("max")) => quote! { ::grammersthon::Validator::MaxLen((#value) as usize) },
+                        _ => return Err(bound.error("Expected min or max")),
+                    });
+                    Ok(())
+                })?,
+                _ => return Err(meta.error("Expected range, length or regex")),
+            }
+            Ok(())
+        }).expect("Invalid validate attribute");
+    }
+    validators
+}
+
+/// Field takes the rest of the input (`#[rest]` or nested `#[subcommand]`)
+fn is_rest_field(f: &syn::Field) -> bool {
+    has_attr(&f.attrs, "rest") || has_attr(&f.attrs, "subcommand")
+}
+
+/// Field is parsed by name (`#[flag]` or `#[named]`) instead of position
+fn is_named_field(f: &syn::Field) -> bool {
+    has_attr(&f.attrs, "flag") || has_attr(&f.attrs, "named")
 }
 
 /// Parse struct with named fields into FromArgs body
-fn from_args_named_fields(name: &Ident, fields: FieldsNamed) -> (usize, proc_macro2::TokenStream) {
-    let mut count = fields.named.len();
-    let fields = fields.named.iter().enumerate().map(|(i, f)| {
+fn from_args_named_fields(name: &impl ToTokens, fields: FieldsNamed) -> (usize, usize, proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    let positional = fields.named.iter().filter(|f| !is_named_field(f)).collect::<Vec<_>>();
+    let mut count = positional.len();
+    let rest = positional.last().map(|f| is_rest_field(f)).unwrap_or(false);
+    let required = required_count(positional.iter().copied(), count - rest as usize);
+
+    // Extract named arguments first
+    let flags = fields.named.iter().filter(|f| has_attr(&f.attrs, "flag")).map(|f| f.ident.as_ref().unwrap().to_string()).collect::<Vec<_>>();
+    let named = fields.named.iter().filter(|f| has_attr(&f.attrs, "named")).map(|f| f.ident.as_ref().unwrap().to_string()).collect::<Vec<_>>();
+    let prelude = match flags.is_empty() && named.is_empty() {
+        true => quote! {},
+        false => quote! {
+            let (named, input) = ::grammersthon::RawArgs::extract_named(input, &[#(#named),*], &[#(#flags),*]);
+            let input = input.as_str();
+        }
+    };
+
+    let mut i = 0;
+    let fields = fields.named.iter().map(|f| {
         let ty = &f.ty;
         let name = f.ident.as_ref().unwrap();
-        // Check for #[rest] attribute
-        let rest_attr = f.attrs.iter().any(|a| a.path().get_ident().map(|i| i.to_string().as_str() == "rest").unwrap_or(false));
-        // Last field use rest
-        if i == (count - 1) && rest_attr {
+        let field_name = name.to_string();
+        // Flag is false if missing
+        if has_attr(&f.attrs, "flag") {
+            let value = parse_field(f, &field_name, quote! { named.get(#field_name).map(|a| a.as_str()).unwrap_or("false") });
+            return quote! { #name: #value };
+        }
+        if has_attr(&f.attrs, "named") {
+            let input = match option_inner_type(ty).is_some() {
+                true => quote! { named.get(#field_name).map(|a| a.as_str()).unwrap_or("") },
+                false => quote! {
+                    named.get(#field_name).ok_or_else(|| {
+                        ::grammersthon::GrammersthonError::MissingArgument { named: true }.in_field(#field_name)
+                    })?
+                }
+            };
+            let value = parse_field(f, &field_name, input);
+            return quote! { #name: #value };
+        }
+
+        // Last positional field use rest
+        i += 1;
+        let value = if i == count && is_rest_field(f) {
             count -= 1;
-            quote! { #name: <#ty>::parse_arg(&rest)? }
+            parse_field(f, &field_name, quote! { &rest })
         } else {
-            quote! { #name: <#ty>::parse_arg(&args.0[#i])? }
-        }
+            parse_field(f, &field_name, positional_arg(i - 1, required))
+        };
+        quote! { #name: #value }
     }).collect::<Vec<_>>();
     let out = quote! { Ok(#name { #(#fields),* }) };
-    (count, out)
+    (count, required, prelude, out)
+}
+
+/// Generate `parse_captures` for struct with named fields (field name = group name)
+fn from_captures_named_fields(name: &Ident, fields: &FieldsNamed) -> proc_macro2::TokenStream {
+    // Captures don't use the reply
+    let reply = match fields.named.iter().any(|f| has_attr(&f.attrs, "or_reply")) {
+        true => quote! { let reply: ::std::option::Option<&::grammersthon::grammers_client::types::Message> = None; },
+        false => quote! {},
+    };
+    let fields = fields.named.iter().map(|f| {
+        let name = f.ident.as_ref().unwrap();
+        let group = name.to_string();
+        // Optional group didn't participate in the match
+        let input = match option_inner_type(&f.ty).is_some() {
+            true => quote! { captures.get(#group).map(|c| c.as_str()).unwrap_or("") },
+            false => quote! {
+                captures.get(#group).ok_or_else(|| {
+                    ::grammersthon::GrammersthonError::Parse(::std::format!("Missing capture group: {}", #group), None).in_field(#group)
+                })?
+            }
+        };
+        let value = parse_field(f, &group, input);
+        quote! { #name: #value }
+    }).collect::<Vec<_>>();
+    quote! {
+        fn parse_captures(captures: &::std::collections::HashMap<::std::string::String, ::std::string::String>) -> ::std::result::Result<#name, ::grammersthon::GrammersthonError> {
+            #reply
+            Ok(#name { #(#fields),* })
+        }
+    }
+}
+
+/// Check if field has attribute
+fn has_attr(attrs: &[Attribute], name: &str) -> bool {
+    attrs.iter().any(|a| a.path().is_ident(name))
+}
+
+/// Generate `schema` body for struct fields
+fn schema_fields(fields: &Fields) -> proc_macro2::TokenStream {
+    // Rest is the last positional field, named fields are parsed separately
+    let last_positional = fields.iter().rposition(|f| !is_named_field(f));
+    let fields = fields.iter().enumerate().map(|(i, f)| {
+        let ty = &f.ty;
+        let type_name = quote!(#ty).to_string().replace(' ', "");
+        let name = f.ident.as_ref().map(|i| i.to_string()).unwrap_or(i.to_string());
+        let rest = Some(i) == last_positional && is_rest_field(f);
+        let named = is_named_field(f);
+        let optional = option_inner_type(ty).is_some() || has_attr(&f.attrs, "flag") || has_attr(&f.attrs, "or_reply");
+        // FromStr types don't have schema
+        let nested = match has_attr(&f.attrs, "from_str") {
+            true => quote! {},
+            false => quote! { .nested(<#ty as ::grammersthon::FromArgs>::schema()) },
+        };
+        quote! { ::grammersthon::ArgInfo::new(#name, #type_name, #rest).optional(#optional).named(#named)#nested }
+    }).collect::<Vec<_>>();
+    quote! { ::std::vec![#(#fields),*] }
+}
+
+/// Names of enum variant, the first one is primary.
+/// In subcommand mode the variant name is kebab-case and can be overriden with `#[subcommand(name = "..", alias = "..")]`
+fn variant_names(v: &syn::Variant, subcommand: bool, ignore_case: bool) -> Vec<String> {
+    if !subcommand {
+        return match ignore_case {
+            true => vec![v.ident.to_string().to_lowercase()],
+            false => vec![v.ident.to_string()]
+        };
+    }
+
+    // AddAll -> add-all
+    let mut names = vec![v.ident.to_string().chars().enumerate().fold(String::new(), |mut name, (i, c)| {
+        if c.is_uppercase() && i > 0 {
+            name.push('-');
+        }
+        name.extend(c.to_lowercase());
+        name
+    })];
+    for attr in v.attrs.iter().filter(|a| a.path().is_ident("subcommand")) {
+        attr.parse_nested_meta(|meta| {
+            let value = meta.value()?.parse::<LitStr>()?.value().to_lowercase();
+            match meta.path.get_ident().map(|i| i.to_string()).as_deref() {
+                Some("name") => names[0] = value,
+                Some("alias") => names.push(value),
+                _ => return Err(meta.error("Expected name or alias"))
+            }
+            Ok(())
+        }).expect("Invalid subcommand attribute");
+    }
+    names
+}
+
+/// Generate `schema` body for enum
+fn schema_enum(name: &Ident, e: &DataEnum, attributes: &[Attribute]) -> proc_macro2::TokenStream {
+    let ignore_case = has_attr(attributes, "ignore_case");
+    let subcommand = has_attr(attributes, "subcommand");
+    let values = e.variants.iter().map(|v| variant_names(v, subcommand, ignore_case).remove(0)).collect::<Vec<_>>();
+    let subcommands = match subcommand {
+        true => {
+            let subcommands = e.variants.iter().zip(&values).map(|(v, name)| {
+                let schema = schema_fields(&v.fields);
+                quote! { ::grammersthon::SubcommandInfo::new(#name, #schema) }
+            });
+            quote! { .subcommands(::std::vec![#(#subcommands),*]) }
+        },
+        false => quote! {}
+    };
+    let name = name.to_string();
+    quote! { 
+        ::std::vec![::grammersthon::ArgInfo::new(#name, #name, false).values(::std::vec![#(#values.to_string()),*])#subcommands]
+    }
 }
 
 // Parse enum
 fn from_args_enum(name: &Ident, e: &DataEnum, attributes: &Vec<Attribute>) -> proc_macro2::TokenStream {
-    // Check if ignore case enabled
-    let ignore_case = attributes.iter().any(|a| a.path().get_ident().map(|i| &i.to_string() == "ignore_case").unwrap_or(false));
+    // Check if ignore case enabled, subcommands are always case insensitive
+    let subcommand = has_attr(attributes, "subcommand");
+    let ignore_case = subcommand || has_attr(attributes, "ignore_case");
     
     // Parse variants
     let options = e.variants.iter().map(|v| {
         let v_name = &v.ident;
-        let mut v_name_str = v_name.to_string();
-        if ignore_case {
-            v_name_str = v_name_str.to_lowercase();
-        }
+        let names = variant_names(v, subcommand, ignore_case);
         match v.fields {
-            Fields::Unit => quote! { #v_name_str => Ok(#name::#v_name), },
-            _ => panic!("Not supported yet!")
+            Fields::Unit => quote! { #(#names)|* if rest.trim().is_empty() => Ok(#name::#v_name), },
+            // Parse fields from the rest
+            _ => {
+                let body = from_args_fields(&quote! { #name::#v_name }, &v.fields);
+                quote! {
+                    #(#names)|* => {
+                        let input = rest.as_str();
+                        #body
+                    },
+                }
+            }
         }
     }).collect::<Vec<_>>();
 
     // If case should be ignored
-    let input = match ignore_case {
-        true => quote! { input.to_lowercase().as_str() },
-        false => quote! { input }
+    let variant = match ignore_case {
+        true => quote! { variant.to_lowercase().as_str() },
+        false => quote! { variant }
     };
 
     quote! { 
-        match #input { 
+        // First argument selects the variant
+        let (args, rest) = ::grammersthon::RawArgs::parse_n(input, 1);
+        let variant = args.0.first().map(|a| a.as_str()).unwrap_or("");
+        match #variant { 
             #(#options)* 
             _ => Err(::grammersthon::GrammersthonError::Parse(input.to_string(), None))
         }
     }
-}
\ No newline at end of file
+}
+/// Derive `Wizard` for struct with named fields.
+/// 
+/// Field options: `#[wizard(prompt = "...", min = 1, max = 10, min_len = 1, max_len = 10, regex = "...")]`,
+/// `Option<T>` fields can be skipped.
+/// Struct option `#[wizard(name = "...")]` overrides the wizard name (struct name by default).
+#[proc_macro_derive(Wizard, attributes(wizard))]
+pub fn derive_wizard(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match wizard_impl(input) {
+        Ok(out) => TokenStream::from(out),
+        Err(e) => TokenStream::from(e.to_compile_error()),
+    }
+}
+
+fn wizard_impl(input: DeriveInput) -> Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let fields = match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(f) => f,
+            _ => return Err(syn::Error::new_spanned(&input.ident, "Wizard only supports structs with named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(&input.ident, "Wizard only supports structs")),
+    };
+
+    // Wizard name
+    let mut wizard_name = name.to_string();
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("wizard")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                wizard_name = meta.value()?.parse::<LitStr>()?.value();
+                return Ok(());
+            }
+            Err(meta.error("Unknown wizard option"))
+        })?;
+    }
+
+    let mut fields_code = vec![];
+    let mut values_code = vec![];
+    for f in &fields.named {
+        let ident = f.ident.as_ref().unwrap();
+        let field_name = ident.to_string();
+        let (optional, ty) = match option_inner_type(&f.ty) {
+            Some(ty) => (true, ty),
+            None => (false, &f.ty),
+        };
+
+        // Options
+        let mut prompt = format!("Send {}:", field_name.replace('_', " "));
+        let mut validators = vec![];
+        for attr in f.attrs.iter().filter(|a| a.path().is_ident("wizard")) {
+            attr.parse_nested_meta(|meta| {
+                let option = meta.path.get_ident().map(|i| i.to_string()).unwrap_or_default();
+                match option.as_str() {
+                    "prompt" => prompt = meta.value()?.parse::<LitStr>()?.value(),
+                    "regex" => {
+                        let regex = meta.value()?.parse::<LitStr>()?;
+                        Regex::new(&regex.value()).map_err(|e| syn::Error::new_spanned(&regex, format!("Invalid regex: {e}")))?;
+                        validators.push(quote! { ::grammersthon::Validator::Regex(#regex.to_string()) });
+                    },
+                    "min" | "max" => {
+                        let value = meta.value()?.parse::<Expr>()?;
+                        let variant = Ident::new(if option == "min" { "Min" } else { "Max" }, ident.span());
+                        validators.push(quote! { ::grammersthon::Validator::#variant((#value) as f64) });
+                    },
+                    "min_len" | "max_len" => {
+                        let value = meta.value()?.parse::<Expr>()?;
+                        let variant = Ident::new(if option == "min_len" { "MinLen" } else { "MaxLen" }, ident.span());
+                        validators.push(quote! { ::grammersthon::Validator::#variant((#value) as usize) });
+                    },
+                    _ => return Err(meta.error("Unknown wizard option")),
+                }
+                Ok(())
+            })?;
+        }
+
+        fields_code.push(quote! {
+            ::grammersthon::WizardField::new(#field_name, #prompt, |i| <#ty as ::grammersthon::FromArgs>::parse_arg(i).map(|_| ()))
+                .optional(#optional)
+                #(.validator(#validators))*
+        });
+        values_code.push(match optional {
+            true => quote! {
+                #ident: match values.get(#field_name) {
+                    Some(v) => Some(<#ty as ::grammersthon::FromArgs>::parse_arg(v)?),
+                    None => None
+                }
+            },
+            false => quote! {
+                #ident: <#ty as ::grammersthon::FromArgs>::parse_arg(values.get(#field_name).ok_or_else(|| {
+                    ::grammersthon::GrammersthonError::Parse(::std::format!("Missing wizard field: {}", #field_name), None)
+                })?)?
+            }
+        });
+    }
+
+    Ok(quote! {
+        impl ::grammersthon::Wizard for #name {
+            fn name() -> &'static str {
+                #wizard_name
+            }
+
+            fn fields() -> ::std::vec::Vec<::grammersthon::WizardField> {
+                ::std::vec![#(#fields_code),*]
+            }
+
+            fn from_values(values: &::std::collections::HashMap<::std::string::String, ::std::string::String>) -> ::std::result::Result<#name, ::grammersthon::GrammersthonError> {
+                Ok(#name { #(#values_code),* })
+            }
+        }
+    })
+}
+
+/// Get `T` if type is `Option<T>`
+fn option_inner_type(ty: &Type) -> Option<&Type> {
+    let segment = match ty {
+        Type::Path(path) => path.path.segments.last()?,
+        _ => return None
+    };
+    if segment.ident != "Option" {
+        return None;
+    }
+    match &segment.arguments {
+        PathArguments::AngleBracketed(a) => match a.args.first()? {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+
+/// Test parsing the handler timeout
+#[test]
+fn test_duration_millis() {
+    assert_eq!(duration_millis("90"), Some(90_000));
+    assert_eq!(duration_millis("2h 30m"), Some(9_000_000));
+    assert_eq!(duration_millis("500ms"), Some(500));
+    assert_eq!(duration_millis("5x"), None);
+    assert_eq!(duration_millis(""), None);
+    assert_eq!(duration_millis("18446744073709551615s"), None);
+}
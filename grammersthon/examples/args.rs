@@ -1,6 +1,7 @@
 #[macro_use] extern crate log;
 
 use std::error::Error;
+use std::time::Duration;
 use grammers_client::{types::Message, Client};
 use grammersthon::{Grammersthon, HandlerResult, FromArgs, Args, handler, h, RawArgs};
 
@@ -15,11 +16,17 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .interactive(true)
         .connect()
         .await?
+        // Reply with usage of the command when arguments are invalid
+        .reply_usage(true)
+        // Ask for the missing arguments instead
+        .prompt_args(Duration::from_secs(60))
         .add_handler(h!(hi))
         .add_handler(h!(sum))
         .add_handler(h!(repeat))
         .add_handler(h!(action))
+        .add_handler(h!(playlist))
         .add_handler(h!(any_args))
+        .add_handler(h!(color))
         .start_event_loop()
         .await?;
 
@@ -40,9 +47,10 @@ async fn hi(message: Message, args: Args<Name>) -> HandlerResult {
 }
 
 
-/// Requires u32, rest of the message will be inside of `text`
+/// Requires u32 (at most 10), rest of the message will be inside of `text`
 #[derive(Debug, Clone, FromArgs)]
 struct RepeatArgs {
+    #[validate(range(min = 1, max = 10))]
     amount: u32,
     #[rest]
     text: String,
@@ -58,22 +66,54 @@ async fn repeat(client: Client, message: Message, args: Args<RepeatArgs>) -> Han
     Ok(())
 }
 
-/// Enum example (only unit variants are supported)
+/// Enum example, first argument selects the variant and rest is parsed into its fields
 #[derive(Debug, FromArgs)]
 #[ignore_case]
 enum Action {
-    Play, Pause, Skip
+    Play, Pause, Skip,
+    Volume(u8),
+    Seek { seconds: u32 },
 }
 
-/// Wrapper because without it the enum would never get matched due to first arg == function
+/// Wrapper because without it the enum would never get matched due to first arg == function,
+/// `#[rest]` passes the variant fields to the enum
 #[derive(Debug, FromArgs)]
-struct ActionArgs(Action);
+struct ActionArgs(#[rest] Action);
 
 /// Enum example 
 #[handler("/action")]
 async fn action(args: Args<ActionArgs>) -> HandlerResult {
-    let action = args.0.0;
-    info!("Player: {action:?}");
+    match args.0.0 {
+        Action::Volume(volume) => info!("Player volume: {volume}"),
+        Action::Seek { seconds } => info!("Player seek: {seconds}s"),
+        action => info!("Player: {action:?}"),
+    }
+    Ok(())
+}
+
+
+/// Subcommands, first argument selects the variant by kebab-case name
+#[derive(Debug, FromArgs)]
+#[subcommand]
+enum Playlist {
+    Add { url: String },
+    #[subcommand(alias = "rm")]
+    Remove { index: usize },
+    Clear,
+}
+
+#[derive(Debug, FromArgs)]
+struct PlaylistArgs(#[subcommand] Playlist);
+
+/// Manage the playlist: /playlist add <url>, /playlist rm <index>, /playlist clear
+#[handler("/playlist")]
+async fn playlist(message: Message, args: Args<PlaylistArgs>) -> HandlerResult {
+    let reply = match args.0.0 {
+        Playlist::Add { url } => format!("Added {url}"),
+        Playlist::Remove { index } => format!("Removed #{index}"),
+        Playlist::Clear => "Cleared".to_string(),
+    };
+    message.reply(reply).await?;
     Ok(())
 }
 
@@ -96,4 +136,21 @@ async fn sum(message: Message, args: Args<Sum>) -> HandlerResult {
 async fn any_args(message: Message, args: RawArgs) -> HandlerResult {
     message.reply(args.0.join("\n")).await?;
     Ok(())
-}
\ No newline at end of file
+}
+
+
+/// Populated from named capture groups
+#[derive(Debug, FromArgs)]
+struct Color {
+    r: u8,
+    g: u8,
+    b: u8
+}
+
+/// Args from the pattern capture groups instead of splitting on whitespace
+#[handler("^/color (?P<r>\\d+) (?P<g>\\d+) (?P<b>\\d+)$", capture_args)]
+async fn color(message: Message, args: Args<Color>) -> HandlerResult {
+    let Color { r, g, b } = args.0;
+    message.reply(format!("#{r:02x}{g:02x}{b:02x}")).await?;
+    Ok(())
+}
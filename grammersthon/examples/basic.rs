@@ -4,8 +4,7 @@ use std::error::Error;
 use grammersthon::grammers_client::types::{Media, User, Message, Chat};
 use grammersthon::grammers_client::types::media::Sticker;
 use grammersthon::grammers_client::{Client, InputMessage};
-use grammersthon::{Grammersthon, HandlerResult, handler, h};
-use regex::Regex;
+use grammersthon::{Grammersthon, HandlerResult, handler, h, filters, mutators};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -26,13 +25,14 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // Add handlers
     grammersthon
         // Add pattern mutator which will prefix `/` to every pattern
-        .pattern_mutator(|pattern| Regex::new(&format!("/{pattern}")).unwrap())
+        .pattern_mutator(mutators::prefix("/"))
 
         // Register individual handlers
         .add_handler(h!(ping))
         .add_handler(h!(save_media))
         .add_handler(h!(with_sticker))
         .add_handler(h!(fn_handler_example))
+        .add_handler(h!(admin_example))
 
         // Fallback handler for unhandled messages
         .message_fallback_handler(fallback)
@@ -72,6 +72,13 @@ async fn fn_handler_example(message: Message, user: User) -> HandlerResult {
     Ok(())
 }
 
+/// Only handle messages from group admins (async filter)
+#[handler("admin$", filters::admin_only())]
+async fn admin_example(message: Message) -> HandlerResult {
+    message.reply("You are an admin!").await?;
+    Ok(())
+}
+
 /// Fallback handler, no #[handler] needed
 async fn fallback(message: Message) -> HandlerResult {
     info!("Unhandled message: {}", message.text());
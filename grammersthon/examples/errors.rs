@@ -2,7 +2,7 @@
 
 use std::error::Error;
 use grammersthon::grammers_client::{Update, Client, types::Message};
-use grammersthon::{Grammersthon, HandlerResult,  GrammersthonError, HandlerData};
+use grammersthon::{Grammersthon, HandlerResult,  GrammersthonError, HandlerData, Unmatched, ErrorContext};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
@@ -19,8 +19,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
         // Fallback when no message handler is matched
         .message_fallback_handler(message_fallback)
 
-        // Handle errors returned by handlers
-        .error_handler(error_handler)
+        // Handle errors returned by handlers, with the update and the matched handler
+        // (use `.error_handler` for just the `Client` and `Update`)
+        .error_context_handler(error_handler)
 
         // Called before handling message
         .interceptor(interceptor)
@@ -36,15 +37,24 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
 
 /// Fallback handler, no #[handler] needed
-async fn message_fallback(message: Message) -> HandlerResult {
-    info!("Unhandled message: {}", message.text());
+/// `Unmatched` describes why no handler was called
+async fn message_fallback(message: Message, unmatched: Unmatched) -> HandlerResult {
+    match unmatched {
+        Unmatched::NoMatch => info!("Unhandled message: {}", message.text()),
+        Unmatched::ExtractorFailed { handler, error } => {
+            info!("Handler {handler} couldn't get argument #{} ({}) for message: {}", error.index, error.type_name, message.text());
+        }
+    }
     Ok(())
 }
 
 
 /// Error handler, static parameters, no #[handler]
-async fn error_handler(error: GrammersthonError, _client: Client, update: Update) -> HandlerResult {
-    error!("An error occured while handling: {update:?}: {error}");
+async fn error_handler(error: GrammersthonError, ctx: ErrorContext) -> HandlerResult {
+    match ctx.handler {
+        Some(handler) => error!("Handler {handler} failed while handling: {:?}: {error}", ctx.update),
+        None => error!("An error occured while handling: {:?}: {error}", ctx.update),
+    }
     Ok(())
 }
 
@@ -0,0 +1,108 @@
+use std::time::Duration;
+use grammers_client::Client;
+use grammers_session::PackedChat;
+use grammers_tl_types as tl;
+use tokio::task::JoinHandle;
+
+use crate::{GrammersthonError, HandlerData};
+
+/// Chat actions are shown by Telegram for 5 seconds
+const ACTION_REFRESH: Duration = Duration::from_secs(4);
+
+/// Action shown to other chat members (such as "typing...")
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatAction {
+    Typing,
+    UploadPhoto,
+    RecordVideo,
+    UploadVideo,
+    RecordVoice,
+    UploadVoice,
+    UploadDocument,
+    RecordRound,
+    UploadRound,
+    ChooseSticker,
+    ChooseContact,
+    FindLocation,
+    /// Cancel the current action
+    Cancel,
+}
+
+impl ChatAction {
+    fn to_tl(self) -> tl::enums::SendMessageAction {
+        use tl::enums::SendMessageAction as A;
+        match self {
+            ChatAction::Typing => A::SendMessageTypingAction,
+            ChatAction::UploadPhoto => A::SendMessageUploadPhotoAction(tl::types::SendMessageUploadPhotoAction { progress: 0 }),
+            ChatAction::RecordVideo => A::SendMessageRecordVideoAction,
+            ChatAction::UploadVideo => A::SendMessageUploadVideoAction(tl::types::SendMessageUploadVideoAction { progress: 0 }),
+            ChatAction::RecordVoice => A::SendMessageRecordAudioAction,
+            ChatAction::UploadVoice => A::SendMessageUploadAudioAction(tl::types::SendMessageUploadAudioAction { progress: 0 }),
+            ChatAction::UploadDocument => A::SendMessageUploadDocumentAction(tl::types::SendMessageUploadDocumentAction { progress: 0 }),
+            ChatAction::RecordRound => A::SendMessageRecordRoundAction,
+            ChatAction::UploadRound => A::SendMessageUploadRoundAction(tl::types::SendMessageUploadRoundAction { progress: 0 }),
+            ChatAction::ChooseSticker => A::SendMessageChooseStickerAction,
+            ChatAction::ChooseContact => A::SendMessageChooseContactAction,
+            ChatAction::FindLocation => A::SendMessageGeoLocationAction,
+            ChatAction::Cancel => A::SendMessageCancelAction,
+        }
+    }
+}
+
+/// Send chat action to chat
+pub async fn send_action(client: &Client, chat: PackedChat, action: ChatAction) -> Result<(), GrammersthonError> {
+    client.invoke(&tl::functions::messages::SetTyping {
+        peer: chat.to_input_peer(),
+        top_msg_id: None,
+        action: action.to_tl(),
+    }).await?;
+    Ok(())
+}
+
+/// Keeps the chat action alive until dropped
+pub struct ActionGuard {
+    task: JoinHandle<()>,
+}
+
+impl ActionGuard {
+    /// Stop the action
+    pub fn stop(self) {}
+}
+
+impl Drop for ActionGuard {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl HandlerData {
+    /// Show "typing..." in the chat of the message
+    pub async fn typing(&self) -> Result<(), GrammersthonError> {
+        self.send_action(ChatAction::Typing).await
+    }
+
+    /// Send chat action to the chat of the message
+    pub async fn send_action(&self, action: ChatAction) -> Result<(), GrammersthonError> {
+        send_action(&self.client, self.message.chat().pack(), action).await
+    }
+
+    /// Keep sending the chat action until the returned guard is dropped, for long running handlers:
+    /// ```ignore
+    /// let _typing = data.keep_action(ChatAction::Typing);
+    /// let answer = slow_operation().await;
+    /// ```
+    pub fn keep_action(&self, action: ChatAction) -> ActionGuard {
+        let client = self.client.clone();
+        let chat = self.message.chat().pack();
+        let task = tokio::spawn(async move {
+            loop {
+                if let Err(e) = send_action(&client, chat, action).await {
+                    warn!("Failed sending chat action: {e}");
+                    break;
+                }
+                tokio::time::sleep(ACTION_REFRESH).await;
+            }
+        });
+        ActionGuard { task }
+    }
+}
@@ -0,0 +1,177 @@
+//! Owner-only console for operational debugging over Telegram, without SSH access
+//!
+//! Usage:
+//! ```ignore
+//! AdminConsole::new()
+//!     .owners([123456789])
+//!     .install(&mut grammersthon);
+//! ```
+//!
+//! Owner commands (read only, arguments are never evaluated):
+//! - `/admin get <key>` - storage value
+//! - `/admin keys [prefix]` - storage keys
+//! - `/admin count [prefix]` - count of storage keys
+//! - `/admin handlers` - registered handlers with calls and errors since start
+//! - `/admin updates` - tally of unrouted updates (requires `Grammersthon::update_stats`)
+//! - `/admin uptime`
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use grammers_client::InputMessage;
+use grammers_client::types::Message;
+use serde_json::Value;
+
+use crate::{Catalog, ErrorKind, Grammersthon, HandlerData, HandlerFilter, HandlerInfo, HandlerRegistry, HandlerResult, Storage, UpdateStats, chat_lang};
+
+/// Maximum length of the reply
+const REPLY_LIMIT: usize = 4000;
+/// Maximum listed keys
+const KEYS_LIMIT: usize = 50;
+
+/// Calls of handler since start
+#[derive(Debug, Clone, Copy, Default)]
+struct HandlerStats {
+    calls: u64,
+    errors: u64,
+}
+
+/// Admin console component
+#[derive(Debug, Clone)]
+pub struct AdminConsole {
+    command: String,
+    owners: HashSet<i64>,
+    started: Instant,
+    stats: Arc<Mutex<BTreeMap<String, HandlerStats>>>,
+}
+
+impl AdminConsole {
+    /// Create new instance with default settings
+    pub fn new() -> AdminConsole {
+        AdminConsole {
+            command: "/admin".to_string(),
+            owners: HashSet::new(),
+            started: Instant::now(),
+            stats: Default::default(),
+        }
+    }
+
+    /// Owner command (default: `/admin`)
+    pub fn command(mut self, command: &str) -> Self {
+        self.command = command.to_string();
+        self
+    }
+
+    /// Users which can use the console (own account always can)
+    pub fn owners(mut self, ids: impl IntoIterator<Item = i64>) -> Self {
+        self.owners.extend(ids);
+        self
+    }
+
+    /// Register the command handler and the handler stats hook
+    pub fn install(self, grammersthon: &mut Grammersthon) {
+        let stats = self.stats.clone();
+        grammersthon.after_handler(move |data: HandlerData, result: Result<(), ErrorKind>| {
+            if let Some(name) = data.handler_name() {
+                let mut stats = stats.lock().unwrap();
+                let entry = stats.entry(name.to_string()).or_default();
+                entry.calls += 1;
+                entry.errors += result.is_err() as u64;
+            }
+            async { Ok(()) }
+        });
+
+        let this = Arc::new(self);
+        let command = this.command.clone();
+        let owners = this.owners.clone();
+        let info = HandlerInfo::new("admin", vec![
+            HandlerFilter::func(move |message, data| {
+                let sender = message.sender().map(|s| s.id());
+                message.text().split_whitespace().next() == Some(command.as_str())
+                    && sender.map(|id| id == data.me.id() || owners.contains(&id)).unwrap_or(false)
+            })
+        ]).description("Admin console").hidden(true);
+        grammersthon.add_handler((info, move |message: Message, storage: Storage, registry: HandlerRegistry, catalog: Catalog, data: HandlerData| {
+            let this = this.clone();
+            async move { this.handle_command(message, data, storage, registry, catalog).await }
+        }));
+    }
+
+    /// Handle the owner command
+    async fn handle_command(&self, message: Message, data: HandlerData, storage: Storage, registry: HandlerRegistry, catalog: Catalog) -> HandlerResult {
+        let updates = data.data::<UpdateStats>();
+        let lang = message.sender().as_ref().and_then(chat_lang).map(|l| l.to_string());
+        let lang = lang.as_deref();
+        let args = message.text()[self.command.len()..].split_whitespace().collect::<Vec<_>>();
+        let reply = match args.as_slice() {
+            ["get", key] => match storage.get::<Value>(key)? {
+                Some(value) => serde_json::to_string_pretty(&value)?,
+                None => catalog.format(lang, "admin.not_found", &[("key", key)]),
+            },
+            ["keys", prefix @ ..] if prefix.len() <= 1 => {
+                let keys = storage.keys(prefix.first().unwrap_or(&""))?;
+                let mut lines = keys.iter().take(KEYS_LIMIT).cloned().collect::<Vec<_>>();
+                if keys.len() > KEYS_LIMIT {
+                    lines.push(catalog.format(lang, "admin.more", &[("count", &(keys.len() - KEYS_LIMIT).to_string())]));
+                }
+                match lines.is_empty() {
+                    true => catalog.get(lang, "admin.empty").to_string(),
+                    false => lines.join("\n"),
+                }
+            },
+            ["count", prefix @ ..] if prefix.len() <= 1 => storage.keys(prefix.first().unwrap_or(&""))?.len().to_string(),
+            ["handlers"] => {
+                let stats = self.stats.lock().unwrap();
+                registry.names().iter().map(|name| {
+                    let s = stats.get(name).copied().unwrap_or_default();
+                    format!("{name}: {} calls, {} errors", s.calls, s.errors)
+                }).collect::<Vec<_>>().join("\n")
+            },
+            ["updates"] => match updates.map(|u| u.counts()) {
+                Some(counts) if !counts.is_empty() => counts.iter().map(|(name, count)| format!("{name}: {count}")).collect::<Vec<_>>().join("\n"),
+                Some(_) => catalog.get(lang, "admin.empty").to_string(),
+                None => catalog.get(lang, "admin.no_update_stats").to_string(),
+            },
+            ["uptime"] => format_duration(self.started.elapsed()),
+            _ => catalog.format(lang, "admin.usage", &[("command", &self.command)]),
+        };
+        data.reply(InputMessage::text(truncate(reply))).await?;
+        Ok(())
+    }
+}
+
+impl Default for AdminConsole {
+    fn default() -> Self {
+        AdminConsole::new()
+    }
+}
+
+/// Format duration as `1d 2h 3m 4s`
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let parts = [(secs / 86400, "d"), (secs / 3600 % 24, "h"), (secs / 60 % 60, "m"), (secs % 60, "s")];
+    let output = parts.iter()
+        .skip_while(|(n, _)| *n == 0)
+        .map(|(n, unit)| format!("{n}{unit}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if output.is_empty() { "0s".to_string() } else { output }
+}
+
+/// Fit the reply into a message
+fn truncate(mut text: String) -> String {
+    if let Some((i, _)) = text.char_indices().nth(REPLY_LIMIT) {
+        text.truncate(i);
+        text.push('…');
+    }
+    text
+}
+
+
+/// Test formatting uptime
+#[test]
+fn test_format_duration() {
+    assert_eq!(format_duration(Duration::ZERO), "0s");
+    assert_eq!(format_duration(Duration::from_secs(61)), "1m 1s");
+    assert_eq!(format_duration(Duration::from_secs(86400 + 5)), "1d 0h 0m 5s");
+}
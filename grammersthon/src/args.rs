@@ -1,86 +1,511 @@
+use std::collections::HashMap;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::ffi::OsString;
+use std::fmt::Display;
+use std::ops::{Deref, Range};
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+use serde::Serialize;
 
-use crate::{FromHandlerData, HandlerData, GrammersthonError};
+use std::sync::Arc;
+
+use grammers_client::types::Message;
+
+use crate::{ExtractError, FromHandlerData, HandlerData, HandlerResult, GrammersthonError, MessageId};
 
 /// Wrapper for parsing arguments from message body
 pub struct Args<A: FromArgs>(pub A);
 
 impl<A: FromArgs> FromHandlerData for Args<A> {
     fn from_data(data: &HandlerData) -> Option<Self> {
-        data.args::<A>().map(|a| Args(a)).ok()
+        Self::try_from_data(data).ok()
+    }
+
+    /// Keeps the parse error, so usage can be replied (`Grammersthon::reply_usage`)
+    fn try_from_data(data: &HandlerData) -> Result<Self, ExtractError> {
+        let args = match data.capture_args {
+            true => A::parse_captures(&data.captures).map_err(|e| e.with_usage(A::usage())),
+            false => data.args::<A>()
+        };
+        args.map(|a| Args(a)).map_err(|e| ExtractError { index: 0, type_name: std::any::type_name::<Self>(), error: Some(Arc::new(e)) })
     }
 }
 
 impl HandlerData {
-    /// Parse args from message
+    /// Text of the arguments: after the matched pattern of the handler (or its `args` capture group) or the first word of the message
+    pub fn args_text(&self) -> Option<&str> {
+        if let Some(args) = &self.prompted_args {
+            return Some(args);
+        }
+        let text = self.message.text();
+        self.args_start.or_else(|| text.find(char::is_whitespace)).map(|i| text[i..].trim_start())
+    }
+
+    /// Parse args from message, errors are `GrammersthonError::InvalidArgs` with the usage
     pub fn args<A: FromArgs>(&self) -> Result<A, GrammersthonError> {
-        match self.message.text().find(" ") {
-            Some(i) => A::parse_arg(&self.message.text()[i..]),
-            None => Err(GrammersthonError::Parse("Missing arguments".to_string(), None))
+        let reply = self.reply.as_ref();
+        match self.args_text() {
+            Some(args) => A::parse_arg_reply(args, reply),
+            // `#[or_reply]` arguments can be all taken from the replied message
+            None if reply.is_some() && A::uses_reply() => A::parse_arg_reply("", reply),
+            None => Err(GrammersthonError::missing_argument())
+        }.map_err(|e| e.with_usage(A::usage()))
+    }
+
+    /// Reply with the invalid argument and usage of the command
+    pub(crate) async fn reply_usage(&self, error: &GrammersthonError) -> HandlerResult {
+        let (field, usage, error) = match error {
+            GrammersthonError::InvalidArgs { field, usage, error } => (field, usage, error.as_ref()),
+            _ => return Ok(())
+        };
+        let text = match (field, error) {
+            (Some(field), GrammersthonError::MissingArgument { named: true }) => self.tr("args.missing_field", &[("field", &format!("--{field}"))]),
+            (Some(field), GrammersthonError::MissingArgument { named: false }) => self.tr("args.missing_field", &[("field", field)]),
+            (None, GrammersthonError::MissingArgument { .. }) => self.tr("args.missing", &[]),
+            (Some(field), _) => self.tr("args.invalid_field", &[("field", field)]),
+            (None, _) => self.tr("args.invalid", &[]),
+        };
+        self.reply(self.with_usage_text(text, usage)).await?;
+        Ok(())
+    }
+
+    /// Append the usage of the command to text
+    fn with_usage_text(&self, mut text: String, usage: &[String]) -> String {
+        if !usage.is_empty() {
+            let command = self.message.text().split_whitespace().next().unwrap_or_default();
+            let usage = usage.iter().map(|u| format!("{command} {u}")).collect::<Vec<_>>().join("\n");
+            text.push_str(&format!("\n{}", self.tr("args.usage", &[("usage", &usage)])));
+        }
+        text
+    }
+
+    /// Ask the sender for the missing positional arguments (`Grammersthon::prompt_args`) and extract again,
+    /// until the extraction succeeds or fails for other reason. The answers are appended to the arguments
+    pub(crate) async fn prompt_missing_args<T>(
+        &mut self,
+        extract: impl Fn(&HandlerData) -> Result<T, ExtractError>,
+        timeout: Duration
+    ) -> Result<Result<T, ExtractError>, GrammersthonError> {
+        let mut result = extract(self);
+        for _ in 0..PROMPT_LIMIT {
+            let error = match &result {
+                Err(ExtractError { error: Some(e), .. }) if e.is_missing_argument() => e.clone(),
+                _ => break
+            };
+            let answer = self.ask(self.args_prompt(&error), timeout).await?;
+            self.prompted_args = Some(match self.args_text() {
+                Some(args) if !args.is_empty() => format!("{args} {}", answer.text()),
+                _ => answer.text().to_string()
+            });
+            result = extract(self);
+        }
+        Ok(result)
+    }
+
+    /// Prompt asking for the missing argument
+    fn args_prompt(&self, error: &GrammersthonError) -> String {
+        match error {
+            // Named fields, not positions of tuple structs
+            GrammersthonError::InvalidArgs { field: Some(field), .. } if field.parse::<usize>().is_err() => {
+                self.tr("args.prompt_field", &[("field", &field.replace('_', " "))])
+            },
+            GrammersthonError::InvalidArgs { usage, .. } => self.with_usage_text(self.tr("args.prompt", &[]), usage),
+            _ => self.tr("args.prompt", &[]),
         }
     }
 }
 
-/// Raw arguments (space separated, empty ignored)
+/// Maximum amount of prompts for missing arguments of single message
+const PROMPT_LIMIT: usize = 10;
+
+impl GrammersthonError {
+    /// Positional argument is missing (used by `FromArgs` derive)
+    pub fn missing_argument() -> GrammersthonError {
+        GrammersthonError::MissingArgument { named: false }
+    }
+
+    /// Whether the arguments failed because positional argument is missing
+    pub fn is_missing_argument(&self) -> bool {
+        match self {
+            GrammersthonError::InvalidArgs { error, .. } => error.is_missing_argument(),
+            GrammersthonError::MissingArgument { named } => !named,
+            _ => false
+        }
+    }
+
+    /// Mark the error as failure of field (used by `FromArgs` derive), the innermost field is kept
+    pub fn in_field(self, field: &str) -> GrammersthonError {
+        match self {
+            GrammersthonError::InvalidArgs { field: None, usage, error } => GrammersthonError::InvalidArgs { field: Some(field.to_string()), usage, error },
+            e @ GrammersthonError::InvalidArgs { .. } => e,
+            e => GrammersthonError::InvalidArgs { field: Some(field.to_string()), usage: vec![], error: Box::new(e) }
+        }
+    }
+
+    /// Attach usage of the arguments, if there is none
+    pub(crate) fn with_usage(self, usage: Vec<String>) -> GrammersthonError {
+        match self {
+            GrammersthonError::InvalidArgs { field, usage: u, error } if u.is_empty() => GrammersthonError::InvalidArgs { field, usage, error },
+            e @ GrammersthonError::InvalidArgs { .. } => e,
+            e => GrammersthonError::InvalidArgs { field: None, usage, error: Box::new(e) }
+        }
+    }
+}
+
+/// Raw arguments (whitespace separated, empty ignored, quotes respected)
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct RawArgs(pub Vec<String>);
 
 impl RawArgs {
-    /// Parse n amount of arguments, return rest
+    /// Parse n amount of arguments, return rest.
+    /// Arguments starting with double or single quote can contain spaces (`"My File.txt"`),
+    /// quotes and backslashes can be escaped with backslash
     pub fn parse_n(input: &str, count: usize) -> (RawArgs, String) {
-        // No args 
-        if count == 0 {
-            return (RawArgs::default(), input.to_string());
-        }
-
-        let mut args = vec![];
-        let mut arg = String::new();
-
-        // Split on space
-        let mut chars = input.chars();
-        for c in &mut chars {
-            if c == ' ' {
-                if !arg.is_empty() {
-                    args.push(arg.trim().to_string());
-                    arg.clear();
-                    if args.len() == count { 
-                        break;
+        let (args, rest) = split_args(input, count);
+        (RawArgs(args.into_iter().map(|(arg, _)| arg).collect()), input[rest..].to_string())
+    }
+
+    /// Extract `--name value` / `name=value` arguments and `--flag` (optionally followed by yes/no) anywhere in input.
+    /// Returns the values and the remaining input, as written in the input
+    pub fn extract_named(input: &str, named: &[&str], flags: &[&str]) -> (HashMap<String, String>, String) {
+        let mut values = HashMap::new();
+        // Byte ranges of the extracted arguments
+        let mut removed = vec![];
+        let args = split_args(input, usize::MAX).0;
+        let mut i = 0;
+        while i < args.len() {
+            let (arg, span) = &args[i];
+            let mut taken = 0;
+            if let Some(name) = arg.strip_prefix("--") {
+                if named.contains(&name) {
+                    if let Some((value, _)) = args.get(i + 1) {
+                        values.insert(name.to_string(), value.to_string());
                     }
+                    taken = 2;
+                } else if flags.contains(&name) {
+                    let value = match args.get(i + 1).map(|(v, _)| bool::parse_arg(v)) {
+                        Some(Ok(value)) => {
+                            taken = 1;
+                            value
+                        },
+                        _ => true
+                    };
+                    values.insert(name.to_string(), value.to_string());
+                    taken += 1;
                 }
+            }
+            if taken == 0 {
+                if let Some((name, value)) = arg.split_once('=') {
+                    if named.contains(&name) || flags.contains(&name) {
+                        values.insert(name.to_string(), value.to_string());
+                        taken = 1;
+                    }
+                }
+            }
+            if taken == 0 {
+                i += 1;
                 continue;
             }
-            arg.push(c);
+            // Remove up to the next argument, so the remaining ones keep their spacing
+            let last = (i + taken).min(args.len());
+            let end = args.get(last).map(|(_, s)| s.start).unwrap_or(input.len());
+            removed.push(span.start..end);
+            i = last;
         }
 
-        // Rest
-        if !arg.is_empty() {
-            args.push(arg);
+        let mut remaining = String::new();
+        let mut position = 0;
+        for range in removed {
+            remaining.push_str(&input[position..range.start]);
+            position = range.end;
         }
-        (RawArgs(args), chars.collect::<String>())
+        remaining.push_str(&input[position..]);
+        (values, remaining.trim().to_string())
+    }
+
+    /// Insert `REPLY_ARG` placeholders at the positions (ascending), for the `#[or_reply]` fields
+    pub fn insert_reply_args(input: &str, positions: &[usize]) -> String {
+        let mut input = input.to_string();
+        for position in positions {
+            let (args, rest) = RawArgs::parse_n(&input, *position);
+            let mut args = args.0.into_iter().map(quote_arg).collect::<Vec<_>>();
+            args.push(REPLY_ARG.to_string());
+            input = format!("{} {}", args.join(" "), rest.trim_start());
+        }
+        input
     }
 }
 
+/// Split n arguments with their byte ranges in input, returns start of the rest
+fn split_args(input: &str, count: usize) -> (Vec<(String, Range<usize>)>, usize) {
+    // No args
+    if count == 0 {
+        return (vec![], 0);
+    }
+
+    let mut args = vec![];
+    let mut arg = String::new();
+    let mut start = None;
+    // Quote of the current argument, whether it was quoted (to keep empty `""`)
+    let mut quote = None;
+    let mut quoted = false;
+
+    let mut chars = input.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if start.is_none() && !(quote.is_none() && c.is_whitespace()) {
+            start = Some(i);
+        }
+        match (c, quote) {
+            // Escaped character
+            ('\\', _) => match chars.peek() {
+                Some((_, next @ ('"' | '\'' | '\\' | ' '))) => {
+                    arg.push(*next);
+                    chars.next();
+                },
+                _ => arg.push(c),
+            },
+            // End of quoted argument
+            (c, Some(q)) if c == q => quote = None,
+            // Quotes only at the start of argument, so apostrophes in words work
+            ('"' | '\'', None) if arg.is_empty() && !quoted => {
+                quote = Some(c);
+                quoted = true;
+            },
+            // Split on whitespace
+            (c, None) if c.is_whitespace() => {
+                if !arg.is_empty() || quoted {
+                    args.push((std::mem::take(&mut arg), start.take().unwrap_or(i)..i));
+                    quoted = false;
+                    if args.len() == count {
+                        return (args, i + c.len_utf8());
+                    }
+                }
+            },
+            (c, _) => arg.push(c),
+        }
+    }
+
+    // Last argument
+    if !arg.is_empty() || quoted {
+        args.push((arg, start.unwrap_or(input.len())..input.len()));
+    }
+    (args, input.len())
+}
+
+/// Quote argument if it wouldn't be parsed back as single argument
+fn quote_arg(arg: String) -> String {
+    match arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\') {
+        true => format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\"")),
+        false => arg
+    }
+}
+
+/// Placeholder of argument taken from the replied message (used by `FromArgs` derive)
+pub const REPLY_ARG: &str = "\u{1}reply";
+
 impl FromArgs for RawArgs {
     fn parse_arg(input: &str) -> Result<Self, GrammersthonError> {
-        Ok(RawArgs(input.split(" ").filter(|a| !a.trim().is_empty()).map(|a| a.trim().to_string()).collect::<Vec<_>>()))
+        Ok(RawArgs::parse_n(input, usize::MAX).0)
     }
 }
 
 impl FromHandlerData for RawArgs {
     fn from_data(data: &HandlerData) -> Option<Self> {
-        match data.message.text().find(" ") {
-            Some(i) => RawArgs::parse_arg(&data.message.text()[i..]).ok(),
+        match data.args_text() {
+            Some(args) => RawArgs::parse_arg(args).ok(),
             None => Some(RawArgs::default())
         }
     }
 }
 
+/// Description of an argument, for generating command schema
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ArgInfo {
+    pub name: String,
+    pub type_name: String,
+    /// Takes the rest of the input
+    pub rest: bool,
+    /// Can be omitted (`Option<T>`)
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub optional: bool,
+    /// Passed as `--name value` instead of by position
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub named: bool,
+    /// Allowed values (enum variants)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<String>,
+    /// Subcommands selected by the argument (`#[subcommand]` enums)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subcommands: Vec<SubcommandInfo>,
+}
+
+/// Description of a subcommand, for generating command schema
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SubcommandInfo {
+    pub name: String,
+    pub args: Vec<ArgInfo>,
+}
+
+impl SubcommandInfo {
+    /// Create new instance
+    pub fn new(name: &str, args: Vec<ArgInfo>) -> SubcommandInfo {
+        SubcommandInfo { name: name.to_string(), args }
+    }
+}
+
+impl ArgInfo {
+    /// Create new instance
+    pub fn new(name: &str, type_name: &str, rest: bool) -> ArgInfo {
+        ArgInfo { name: name.to_string(), type_name: type_name.to_string(), rest, optional: false, named: false, values: vec![], subcommands: vec![] }
+    }
+
+    /// Set whether the argument can be omitted
+    pub fn optional(mut self, optional: bool) -> Self {
+        self.optional = optional;
+        self
+    }
+
+    /// Set whether the argument is passed by name
+    pub fn named(mut self, named: bool) -> Self {
+        self.named = named;
+        self
+    }
+
+    /// Set allowed values
+    pub fn values(mut self, values: Vec<String>) -> Self {
+        self.values = values;
+        self
+    }
+
+    /// Set subcommands
+    pub fn subcommands(mut self, subcommands: Vec<SubcommandInfo>) -> Self {
+        self.subcommands = subcommands;
+        self
+    }
+
+    /// Inherit allowed values and subcommands from schema of the field type (if it is a single enum)
+    pub fn nested(mut self, schema: Vec<ArgInfo>) -> Self {
+        if let [nested] = &schema[..] {
+            self.values = nested.values.clone();
+            self.subcommands = nested.subcommands.clone();
+        }
+        self
+    }
+
+    /// `<name>`, `[name]` if optional, `[--name]` if named, `name...` if rest.
+    /// With `typed` also the type or allowed values: `<count: u32>`, `[--limit <u32>]`, `<mode: on|off>`
+    pub fn usage(&self, typed: bool) -> String {
+        let name = match (self.named, self.rest) {
+            (true, _) => format!("--{}", self.name),
+            (false, true) => format!("{}...", self.name),
+            (false, false) => self.name.clone(),
+        };
+        let type_name = self.type_name.strip_prefix("Option<").and_then(|t| t.strip_suffix('>')).unwrap_or(&self.type_name);
+        let type_name = match self.values.is_empty() {
+            true => type_name.to_string(),
+            false => self.values.join("|"),
+        };
+        let name = match (typed, self.named) {
+            (false, _) => name,
+            // Flags have no value
+            (true, true) if type_name == "bool" => name,
+            (true, true) => format!("{name} <{type_name}>"),
+            (true, false) => format!("{name}: {type_name}"),
+        };
+        match self.optional || self.named {
+            true => format!("[{name}]"),
+            false => format!("<{name}>"),
+        }
+    }
+}
+
+/// Usage lines of arguments (with leading space), one for each subcommand
+pub(crate) fn usage_lines(args: &[ArgInfo], typed: bool) -> Vec<String> {
+    let mut usage = String::new();
+    for arg in args {
+        if !arg.subcommands.is_empty() {
+            return arg.subcommands.iter()
+                .flat_map(|s| usage_lines(&s.args, typed).into_iter().map(move |u| format!(" {}{u}", s.name)))
+                .map(|u| format!("{usage}{u}"))
+                .collect();
+        }
+        usage.push_str(&format!(" {}", arg.usage(typed)));
+    }
+    vec![usage]
+}
+
 /// Can be parsed from message arguments
 pub trait FromArgs where Self: Sized {
     /// Parse from argument string
     fn parse_arg(input: &str) -> Result<Self, GrammersthonError>;
+
+    /// Parse, filling the `#[or_reply]` fields from the replied message (generated by derive)
+    fn parse_arg_reply(input: &str, _reply: Option<&Message>) -> Result<Self, GrammersthonError> {
+        Self::parse_arg(input)
+    }
+
+    /// Whether the replied message is used for `#[or_reply]` fields, so it is fetched before parsing
+    fn uses_reply() -> bool {
+        false
+    }
+
+    /// Parse from named capture groups of the handler pattern (field name = group name)
+    fn parse_captures(_captures: &HashMap<String, String>) -> Result<Self, GrammersthonError> {
+        Err(GrammersthonError::Unimplemented)
+    }
+
+    /// Description of the arguments (generated by derive)
+    fn schema() -> Vec<ArgInfo> {
+        vec![]
+    }
+
+    /// Usage of the arguments generated from `schema`, one line for each subcommand: `<query: String...> [--limit <u32>]`
+    fn usage() -> Vec<String> {
+        usage_lines(&Self::schema(), true).into_iter().map(|u| u.trim_start().to_string()).collect()
+    }
+}
+
+/// Argument which can be taken from the replied message (`#[or_reply]` fields of `FromArgs` derive).
+/// With reply the field is always taken from the replied message, so `/ban spam` as reply bans the replied user:
+/// ```ignore
+/// #[derive(FromArgs)]
+/// struct BanArgs {
+///     #[or_reply]
+///     user: UserRef,
+///     #[rest]
+///     reason: Option<String>,
+/// }
+/// ```
+pub trait FromReply where Self: Sized {
+    /// Get the value from replied message
+    fn from_reply(reply: &Message) -> Option<Self>;
+}
+
+/// Text of the replied message
+impl FromReply for String {
+    fn from_reply(reply: &Message) -> Option<Self> {
+        Some(reply.text().to_string()).filter(|t| !t.is_empty())
+    }
+}
+
+/// Id of the replied message
+impl FromReply for MessageId {
+    fn from_reply(reply: &Message) -> Option<Self> {
+        Some(MessageId(reply.id()))
+    }
+}
+
+impl<T: FromReply> FromReply for Option<T> {
+    fn from_reply(reply: &Message) -> Option<Self> {
+        T::from_reply(reply).map(Some)
+    }
+}
+
+impl FromArgs for MessageId {
+    fn parse_arg(input: &str) -> Result<Self, GrammersthonError> {
+        i32::parse_arg(input.trim()).map(MessageId)
+    }
 }
 
 impl FromArgs for String {
@@ -110,6 +535,98 @@ impl<T: FromArgs> FromArgs for Vec<T> {
     }
 }
 
+/// List separated by `SEP` instead of whitespace, items are trimmed and empty ones skipped:
+/// ```ignore
+/// #[derive(FromArgs)]
+/// struct Tags(#[rest] Separated<String, ','>);
+/// // /tags rust,telegram, bot
+/// ```
+/// Use `#[rest]` to allow whitespace around the separators, otherwise the list ends at first whitespace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Separated<T, const SEP: char>(pub Vec<T>);
+
+impl<T, const SEP: char> Separated<T, SEP> {
+    /// Get the items
+    pub fn into_inner(self) -> Vec<T> {
+        self.0
+    }
+}
+
+impl<T, const SEP: char> Deref for Separated<T, SEP> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: FromArgs, const SEP: char> FromArgs for Separated<T, SEP> {
+    fn parse_arg(input: &str) -> Result<Self, GrammersthonError> {
+        input.split(SEP)
+            .map(|i| i.trim())
+            .filter(|i| !i.is_empty())
+            .map(T::parse_arg)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Separated)
+    }
+}
+
+/// Empty input is None
+impl<T: FromArgs> FromArgs for Option<T> {
+    fn parse_arg(input: &str) -> Result<Self, GrammersthonError> {
+        match input.trim().is_empty() {
+            true => Ok(None),
+            false => T::parse_arg(input).map(Some)
+        }
+    }
+
+    fn schema() -> Vec<ArgInfo> {
+        T::schema()
+    }
+}
+
+/// Duration as `90` (seconds) or spans like `10s`, `5m`, `2h30m`, `1d 12h`, `500ms`
+impl FromArgs for Duration {
+    fn parse_arg(input: &str) -> Result<Self, GrammersthonError> {
+        let error = || GrammersthonError::Parse(input.to_string(), None);
+        let input = input.trim();
+        if input.is_empty() {
+            return Err(error());
+        }
+        if let Ok(seconds) = input.parse::<u64>() {
+            return Ok(Duration::from_secs(seconds));
+        }
+
+        let mut duration = Duration::ZERO;
+        let mut chars = input.chars().filter(|c| !c.is_whitespace()).peekable();
+        while chars.peek().is_some() {
+            let number = std::iter::from_fn(|| chars.next_if(|c| c.is_ascii_digit())).collect::<String>();
+            let unit = std::iter::from_fn(|| chars.next_if(|c| c.is_alphabetic())).collect::<String>();
+            let number = number.parse::<u64>().map_err(|_| error())?;
+            let multiplier = match unit.to_lowercase().as_str() {
+                "ms" => {
+                    duration = duration.checked_add(Duration::from_millis(number)).ok_or_else(error)?;
+                    continue;
+                },
+                "s" | "sec" | "secs" | "second" | "seconds" => 1,
+                "m" | "min" | "mins" | "minute" | "minutes" => 60,
+                "h" | "hr" | "hrs" | "hour" | "hours" => 60 * 60,
+                "d" | "day" | "days" => 24 * 60 * 60,
+                "w" | "week" | "weeks" => 7 * 24 * 60 * 60,
+                _ => return Err(error())
+            };
+            let span = Duration::from_secs(number.checked_mul(multiplier).ok_or_else(error)?);
+            duration = duration.checked_add(span).ok_or_else(error)?;
+        }
+        Ok(duration)
+    }
+}
+
+/// Parse argument using `FromStr` (used by `#[from_str]` of `FromArgs` derive)
+pub fn from_str_arg<T: FromStr>(input: &str) -> Result<T, GrammersthonError> where T::Err: Display {
+    input.trim().parse::<T>().map_err(|e| GrammersthonError::Parse(input.to_string(), Some(e.to_string().into())))
+}
+
 /// Generate FromArgs for primitive types
 macro_rules! from_args_parse({ $($t:ty)* } => {
     $(impl FromArgs for $t {
@@ -132,3 +649,99 @@ fn test_parse_n() {
     assert_eq!(RawArgs::parse_n(input, 2), (RawArgs(vec!["aaa".to_string(), "bbb".to_string()]), "c d e  f  g".to_string()));
     assert_eq!(RawArgs::parse_n(input, 99), (RawArgs::parse_arg(input).unwrap(), String::new()));
 }
+
+/// Test quoted arguments
+#[test]
+fn test_parse_quoted() {
+    let (args, rest) = RawArgs::parse_n(r#""My File.txt" dest 'a b' "" don't \"x\" rest of it"#, 5);
+    assert_eq!(args.0, vec!["My File.txt", "dest", "a b", "", "don't"]);
+    assert_eq!(rest, r#"\"x\" rest of it"#);
+    assert_eq!(RawArgs::parse_arg(r#"say "escaped \" quote""#).unwrap().0, vec!["say", "escaped \" quote"]);
+}
+
+/// Test named arguments extraction
+#[test]
+fn test_extract_named() {
+    let (named, rest) = RawArgs::extract_named("cats --limit 5 \"big dogs\" --safe safe=no --other x sort=new", &["limit", "sort"], &["safe"]);
+    assert_eq!(named.get("limit").map(|s| s.as_str()), Some("5"));
+    assert_eq!(named.get("sort").map(|s| s.as_str()), Some("new"));
+    assert_eq!(named.get("safe").map(|s| s.as_str()), Some("no"));
+    assert_eq!(rest, "cats \"big dogs\" --other x");
+    assert_eq!(RawArgs::parse_arg(&rest).unwrap().0, vec!["cats", "big dogs", "--other", "x"]);
+    // Rest is kept as written
+    let (named, rest) = RawArgs::extract_named("don't forget --tag x", &["tag"], &[]);
+    assert_eq!(named.get("tag").map(|s| s.as_str()), Some("x"));
+    assert_eq!(rest, "don't forget");
+    assert_eq!(RawArgs::extract_named("a  \"b\" --tag x c", &["tag"], &[]).1, "a  \"b\" c");
+}
+
+/// Test duration parsing
+#[test]
+fn test_parse_duration() {
+    assert_eq!(Duration::parse_arg("90").unwrap(), Duration::from_secs(90));
+    assert_eq!(Duration::parse_arg("10s").unwrap(), Duration::from_secs(10));
+    assert_eq!(Duration::parse_arg("2h30m").unwrap(), Duration::from_secs(2 * 3600 + 30 * 60));
+    assert_eq!(Duration::parse_arg("1d 12h").unwrap(), Duration::from_secs(36 * 3600));
+    assert_eq!(Duration::parse_arg("1s500ms").unwrap(), Duration::from_millis(1500));
+    assert!(Duration::parse_arg("").is_err());
+    assert!(Duration::parse_arg("5x").is_err());
+    assert!(Duration::parse_arg("m").is_err());
+    assert!(Duration::parse_arg("18446744073709551615s 18446744073709551615s").is_err());
+}
+
+/// Test usage generation and field errors
+#[test]
+fn test_usage() {
+    let args = vec![
+        ArgInfo::new("query", "String", true),
+        ArgInfo::new("limit", "Option<u32>", false).named(true),
+        ArgInfo::new("safe", "bool", false).named(true),
+        ArgInfo::new("mode", "Mode", false).values(vec!["on".to_string(), "off".to_string()]),
+    ];
+    assert_eq!(usage_lines(&args, true), vec![" <query...: String> [--limit <u32>] [--safe] <mode: on|off>"]);
+    assert_eq!(usage_lines(&args, false), vec![" <query...> [--limit] [--safe] <mode>"]);
+
+    let e = u32::parse_arg("x").unwrap_err().in_field("count").in_field("outer").with_usage(vec!["<count: u32>".to_string()]);
+    assert_eq!(e.kind(), crate::ErrorKind::InvalidArgs);
+    assert!(e.to_string().starts_with("Invalid argument count: Error parsing x"));
+    assert!(e.to_string().ends_with("(usage: <count: u32>)"));
+}
+
+/// Test separated lists
+#[test]
+fn test_separated() {
+    let tags = Separated::<String, ','>::parse_arg("rust,telegram, bot,,").unwrap();
+    assert_eq!(tags.0, vec!["rust", "telegram", "bot"]);
+    assert_eq!(Separated::<u32, ';'>::parse_arg("1;2; 3").unwrap().into_inner(), vec![1, 2, 3]);
+    assert!(Separated::<u32, ','>::parse_arg("1,x").is_err());
+    assert!(Separated::<u32, ','>::parse_arg("").unwrap().is_empty());
+}
+
+/// Test detecting missing arguments
+#[test]
+fn test_missing_argument() {
+    assert!(GrammersthonError::missing_argument().in_field("name").with_usage(vec![]).is_missing_argument());
+    assert!(!u32::parse_arg("x").unwrap_err().in_field("amount").is_missing_argument());
+    assert!(!GrammersthonError::MissingArgument { named: true }.in_field("limit").is_missing_argument());
+    let e = GrammersthonError::missing_argument().in_field("name").with_usage(vec!["<name: String>".to_string()]);
+    assert_eq!(e.to_string(), "Missing argument name (usage: <name: String>)");
+    let e = GrammersthonError::MissingArgument { named: true }.in_field("limit").with_usage(vec![]);
+    assert_eq!(e.to_string(), "Missing argument --limit");
+    assert_eq!(GrammersthonError::missing_argument().with_usage(vec![]).to_string(), "Missing argument");
+}
+
+/// Test parsing with FromStr
+#[test]
+fn test_from_str_arg() {
+    assert_eq!(from_str_arg::<u8>(" 42 ").unwrap(), 42);
+    let e = from_str_arg::<std::net::SocketAddr>("localhost").unwrap_err();
+    assert!(matches!(e, GrammersthonError::Parse(ref input, Some(_)) if input == "localhost"));
+}
+
+/// Test reply placeholders
+#[test]
+fn test_insert_reply_args() {
+    assert_eq!(RawArgs::insert_reply_args("spam links", &[0]), format!("{REPLY_ARG} spam links"));
+    let input = RawArgs::insert_reply_args(r#""a b" c"#, &[1, 3]);
+    assert_eq!(RawArgs::parse_arg(&input).unwrap().0, vec!["a b", REPLY_ARG, "c", REPLY_ARG]);
+}
@@ -0,0 +1,134 @@
+use std::future::Future;
+use std::io::Write;
+use std::pin::Pin;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use tokio::io::{AsyncWriteExt, BufReader, AsyncBufReadExt};
+
+use crate::GrammersthonError;
+
+/// Future returned by `AuthPrompt` methods
+pub type PromptFuture<'a> = Pin<Box<dyn Future<Output = Result<String, GrammersthonError>> + Send + 'a>>;
+
+/// Source of the login details missing in the builder (`GrammersthonBuilder::auth_prompt`),
+/// so GUI apps and services can supply them programmatically:
+/// ```ignore
+/// struct WebPrompt(Receiver<String>);
+///
+/// impl AuthPrompt for WebPrompt {
+///     fn ask_phone(&self) -> PromptFuture<'_> { ... }
+///     fn ask_code(&self) -> PromptFuture<'_> { Box::pin(async { Ok(self.0.recv().await?) }) }
+///     fn ask_password(&self, _hint: Option<&str>) -> PromptFuture<'_> { ... }
+/// }
+/// ```
+pub trait AuthPrompt: Send + Sync {
+    /// Ask for phone number or bot token (containing `:`)
+    fn ask_phone(&self) -> PromptFuture<'_>;
+    /// Ask for the login code
+    fn ask_code(&self) -> PromptFuture<'_>;
+    /// Ask for the 2FA password, with its hint (if enabled by `show_password_hint`)
+    fn ask_password<'a>(&'a self, hint: Option<&'a str>) -> PromptFuture<'a>;
+}
+
+/// How is the password typed in terminal displayed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PasswordPromptStyle {
+    /// Nothing is displayed (terminal echo disabled)
+    #[default]
+    Hidden,
+    /// Every character is displayed as `*`
+    Masked,
+    /// Displayed as typed
+    Visible,
+}
+
+/// Prompt in terminal, used by default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TerminalPrompt {
+    pub password_style: PasswordPromptStyle,
+}
+
+impl TerminalPrompt {
+    /// Create new instance with password style
+    pub fn new(password_style: PasswordPromptStyle) -> TerminalPrompt {
+        TerminalPrompt { password_style }
+    }
+
+    /// Prompt for a question in CLI
+    async fn prompt(question: &str) -> Result<String, GrammersthonError> {
+        let mut stdout = tokio::io::stdout();
+        stdout.write_all(question.as_bytes()).await?;
+        stdout.flush().await?;
+
+        let stdin = tokio::io::stdin();
+        let mut reader = BufReader::new(stdin);
+        let mut output = String::new();
+        reader.read_line(&mut output).await?;
+        Ok(output.trim().to_string())
+    }
+
+    /// Prompt for password without echo, or displaying `*` for each character
+    async fn prompt_password(question: String, style: PasswordPromptStyle) -> Result<String, GrammersthonError> {
+        let password = tokio::task::spawn_blocking(move || match style {
+            PasswordPromptStyle::Masked => read_masked(&question),
+            _ => rpassword::prompt_password(question),
+        }).await.map_err(|_| GrammersthonError::Cancelled)??;
+        Ok(password.trim().to_string())
+    }
+}
+
+/// Read line in raw mode, echoing `*`
+fn read_masked(question: &str) -> std::io::Result<String> {
+    let mut stdout = std::io::stdout();
+    write!(stdout, "{question}")?;
+    stdout.flush()?;
+    terminal::enable_raw_mode()?;
+    let result = (|| {
+        let mut password = String::new();
+        loop {
+            let key = match event::read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => key,
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Enter => return Ok(password),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Err(std::io::Error::new(std::io::ErrorKind::Interrupted, "Password prompt cancelled"));
+                },
+                KeyCode::Backspace => if password.pop().is_some() {
+                    write!(stdout, "\x08 \x08")?;
+                },
+                KeyCode::Char(c) => {
+                    password.push(c);
+                    write!(stdout, "*")?;
+                },
+                _ => continue,
+            }
+            stdout.flush()?;
+        }
+    })();
+    terminal::disable_raw_mode()?;
+    writeln!(stdout)?;
+    result
+}
+
+impl AuthPrompt for TerminalPrompt {
+    fn ask_phone(&self) -> PromptFuture<'_> {
+        Box::pin(Self::prompt("Enter phone number or bot token: "))
+    }
+
+    fn ask_code(&self) -> PromptFuture<'_> {
+        Box::pin(Self::prompt("Enter the code you received: "))
+    }
+
+    fn ask_password<'a>(&'a self, hint: Option<&'a str>) -> PromptFuture<'a> {
+        let prompt = match hint {
+            Some(hint) => format!("Enter your password (hint: {hint}): "),
+            None => "Enter your password: ".to_string(),
+        };
+        match self.password_style {
+            PasswordPromptStyle::Visible => Box::pin(async move { Self::prompt(&prompt).await }),
+            style => Box::pin(Self::prompt_password(prompt, style)),
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! Pattern -> response pairs stored in `Storage`, editable at runtime by owners
+//!
+//! Usage:
+//! ```ignore
+//! AutoResponder::new()
+//!     .owners([123456789])
+//!     .response("(?i)^hello$", "Hello!")
+//!     .install(&mut grammersthon)?;
+//! ```
+//!
+//! Owner commands:
+//! - `/autoresponse add <pattern> => <response>`
+//! - `/autoresponse remove <pattern>`
+//! - `/autoresponse list`
+//!
+//! Responses are templates, `$1` or `${name}` are replaced with capture groups of the pattern.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use grammers_client::types::Message;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+
+use crate::{Catalog, Grammersthon, GrammersthonError, HandlerData, HandlerFilter, HandlerInfo, HandlerRegistry, HandlerResult, Storage, chat_lang};
+
+/// Single pattern -> response pair
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AutoResponse {
+    pub pattern: String,
+    pub response: String,
+}
+
+/// Auto responder component
+#[derive(Debug, Clone)]
+pub struct AutoResponder {
+    key: String,
+    command: String,
+    owners: HashSet<i64>,
+    defaults: Vec<AutoResponse>,
+}
+
+impl AutoResponder {
+    /// Create new instance with default settings
+    pub fn new() -> AutoResponder {
+        AutoResponder {
+            key: "autoresponder".to_string(),
+            command: "/autoresponse".to_string(),
+            owners: HashSet::new(),
+            defaults: vec![],
+        }
+    }
+
+    /// Storage key under which the pairs are saved
+    pub fn key(mut self, key: &str) -> Self {
+        self.key = key.to_string();
+        self
+    }
+
+    /// Owner command (default: `/autoresponse`)
+    pub fn command(mut self, command: &str) -> Self {
+        self.command = command.to_string();
+        self
+    }
+
+    /// Users which can edit the responses (own account always can)
+    pub fn owners(mut self, ids: impl IntoIterator<Item = i64>) -> Self {
+        self.owners.extend(ids);
+        self
+    }
+
+    /// Add default response, used only if there are none saved in storage
+    pub fn response(mut self, pattern: &str, response: &str) -> Self {
+        self.defaults.push(AutoResponse { pattern: pattern.to_string(), response: response.to_string() });
+        self
+    }
+
+    /// Load saved responses and register all the handlers
+    pub fn install(self, grammersthon: &mut Grammersthon) -> Result<(), GrammersthonError> {
+        let storage = grammersthon.get_storage();
+        let registry = grammersthon.registry();
+        let responses = match storage.get::<Vec<AutoResponse>>(&self.key)? {
+            Some(responses) => responses,
+            None => {
+                storage.set(&self.key, &self.defaults)?;
+                self.defaults.clone()
+            }
+        };
+
+        // Owner commands first, so saved patterns can't hide them
+        let this = Arc::new(self);
+        let command = this.command.clone();
+        let owners = this.owners.clone();
+        let info = HandlerInfo::new("autoresponder", vec![
+            HandlerFilter::func(move |message, data| {
+                let sender = message.sender().map(|s| s.id());
+                message.text().split_whitespace().next() == Some(command.as_str())
+                    && sender.map(|id| id == data.me.id() || owners.contains(&id)).unwrap_or(false)
+            })
+        ]).description("Manage auto responses");
+        grammersthon.add_handler((info, move |message: Message, data: HandlerData, storage: Storage, registry: HandlerRegistry, catalog: Catalog| {
+            let this = this.clone();
+            async move { this.handle_command(message, data, storage, registry, catalog).await }
+        }));
+        for response in &responses {
+            register(&registry, response)?;
+        }
+        Ok(())
+    }
+
+    /// Handle the owner command
+    async fn handle_command(&self, message: Message, data: HandlerData, storage: Storage, registry: HandlerRegistry, catalog: Catalog) -> HandlerResult {
+        let lang = message.sender().as_ref().and_then(chat_lang).map(|l| l.to_string());
+        let lang = lang.as_deref();
+        let text = message.text()[self.command.len()..].trim().to_string();
+        let (action, rest) = text.split_once(' ').unwrap_or((&text, ""));
+        let mut responses = storage.get::<Vec<AutoResponse>>(&self.key)?.unwrap_or_default();
+        let reply = match action {
+            "add" => match rest.split_once("=>") {
+                Some((pattern, response)) => {
+                    let response = AutoResponse { pattern: pattern.trim().to_string(), response: response.trim().to_string() };
+                    match register(&registry, &response) {
+                        Ok(_) => {
+                            responses.retain(|r| r.pattern != response.pattern);
+                            responses.push(response);
+                            storage.set(&self.key, &responses)?;
+                            catalog.get(lang, "autoresponder.added").to_string()
+                        }
+                        Err(e) => catalog.format(lang, "autoresponder.invalid_pattern", &[("error", &e)]),
+                    }
+                }
+                None => catalog.format(lang, "autoresponder.usage_add", &[("command", &self.command)]),
+            },
+            "remove" => {
+                let pattern = rest.trim();
+                registry.remove(&handler_name(pattern));
+                let len = responses.len();
+                responses.retain(|r| r.pattern != pattern);
+                if responses.len() == len {
+                    catalog.get(lang, "autoresponder.not_found").to_string()
+                } else {
+                    storage.set(&self.key, &responses)?;
+                    catalog.get(lang, "autoresponder.removed").to_string()
+                }
+            },
+            "list" => match responses.is_empty() {
+                true => catalog.get(lang, "autoresponder.empty").to_string(),
+                false => responses.iter().map(|r| format!("{} => {}", r.pattern, r.response)).collect::<Vec<_>>().join("\n"),
+            },
+            _ => catalog.format(lang, "autoresponder.usage", &[("command", &self.command)]),
+        };
+        data.reply(reply).await?;
+        Ok(())
+    }
+}
+
+impl Default for AutoResponder {
+    fn default() -> Self {
+        AutoResponder::new()
+    }
+}
+
+/// Name of the dynamic handler of pattern
+fn handler_name(pattern: &str) -> String {
+    format!("autoresponder::{pattern}")
+}
+
+/// Register (or replace) the response handler
+fn register(registry: &HandlerRegistry, response: &AutoResponse) -> Result<(), GrammersthonError> {
+    let regex = Regex::new(&response.pattern)
+        .map_err(|e| GrammersthonError::Parse(response.pattern.clone(), Some(Box::new(e))))?;
+    let name = handler_name(&response.pattern);
+    registry.remove(&name);
+
+    // Func filter, so global pattern mutators (such as command prefix) don't apply
+    let filter = regex.clone();
+    let info = HandlerInfo::new(&name, vec![HandlerFilter::func(move |message, _| filter.is_match(message.text()))]);
+    let template = response.response.clone();
+    registry.add_handler((info, move |message: Message, data: HandlerData| {
+        let regex = regex.clone();
+        let template = template.clone();
+        async move {
+            let reply = render(&regex, &template, message.text());
+            data.reply(reply).await?;
+            Ok(())
+        }
+    }));
+    Ok(())
+}
+
+/// Fill capture groups into response template
+fn render(regex: &Regex, template: &str, text: &str) -> String {
+    let mut output = String::new();
+    match regex.captures(text) {
+        Some(captures) => captures.expand(template, &mut output),
+        None => output.push_str(template),
+    }
+    output
+}
+
+
+/// Test response templates
+#[test]
+fn test_render() {
+    let regex = Regex::new(r"^hi (?P<name>\w+)").unwrap();
+    assert_eq!(render(&regex, "Hello ${name}!", "hi bob"), "Hello bob!");
+    assert_eq!(render(&regex, "Hello $1", "hi alice"), "Hello alice");
+    assert_eq!(render(&regex, "Hello", "nope"), "Hello");
+}
@@ -0,0 +1,26 @@
+//! Interactive login tool, creates session file and prints the exported session string.
+//! 
+//! Usage: `TG_ID=... TG_HASH=... grammersthon-session [path]`
+
+use std::error::Error;
+use grammersthon::Grammersthon;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let path = std::env::args().nth(1).unwrap_or("session.session".to_string());
+
+    let grammersthon = Grammersthon::from_env()
+        .expect("Missing TG_ID or TG_HASH env variable")
+        .session_file(&path)?
+        .interactive(true)
+        .connect()
+        .await?;
+
+    // Save session
+    grammersthon.client().session().save_to_file(&path)?;
+
+    println!("Logged in as: {}", grammersthon.me().full_name());
+    println!("Session saved to: {path}");
+    println!("Session string: {}", grammersthon.export_session());
+    Ok(())
+}
@@ -0,0 +1,250 @@
+//! Sync bot profile and command list (normally set via BotFather)
+
+use std::collections::HashMap;
+use std::fmt;
+use grammers_client::types::Chat;
+use grammers_session::{PackedChat, PackedType};
+use grammers_tl_types as tl;
+use serde::{Serialize, Serializer};
+
+use crate::{Grammersthon, GrammersthonError};
+
+/// Bot name, about text, description and command descriptions.
+/// Unset fields are left unchanged
+#[derive(Debug, Clone, Default)]
+pub struct BotProfile {
+    name: Option<String>,
+    about: Option<String>,
+    description: Option<String>,
+    /// Command -> description overrides
+    commands: HashMap<String, String>,
+    /// Language code -> translated profile
+    languages: Vec<(String, BotProfile)>,
+}
+
+impl BotProfile {
+    /// Create new empty profile
+    pub fn new() -> BotProfile {
+        BotProfile::default()
+    }
+
+    /// Set the bot name
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Set the text shown in bot's profile
+    pub fn about(mut self, about: &str) -> Self {
+        self.about = Some(about.to_string());
+        self
+    }
+
+    /// Set the text shown in empty chat with the bot
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Override description of command (by default from handler doc comment)
+    pub fn command(mut self, command: &str, description: &str) -> Self {
+        self.commands.insert(command.to_string(), description.to_string());
+        self
+    }
+
+    /// Add translated profile for language code (ISO 639-1)
+    pub fn language(mut self, lang_code: &str, profile: BotProfile) -> Self {
+        self.languages.push((lang_code.to_string(), profile));
+        self
+    }
+}
+
+/// In which chats should a command be shown in the command menu
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandScope {
+    /// All private chats
+    PrivateChats,
+    /// All groups
+    Groups,
+    /// Admins of all groups
+    GroupAdmins,
+    /// Specific chat (group or private chat with user)
+    Chat(PackedChat),
+}
+
+impl CommandScope {
+    /// Used for picking the most specific scope of multiple filters
+    pub(crate) fn specificity(&self) -> u8 {
+        match self {
+            CommandScope::PrivateChats => 0,
+            CommandScope::Groups => 0,
+            CommandScope::GroupAdmins => 1,
+            CommandScope::Chat(_) => 2,
+        }
+    }
+
+    /// Whether command with this scope should be shown in chat
+    pub(crate) fn shown_in(&self, chat: &Chat) -> bool {
+        match self {
+            CommandScope::PrivateChats => matches!(chat, Chat::User(_)),
+            CommandScope::Groups | CommandScope::GroupAdmins => !matches!(chat, Chat::User(_)),
+            CommandScope::Chat(c) => c.id == chat.id(),
+        }
+    }
+}
+
+impl fmt::Display for CommandScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandScope::PrivateChats => write!(f, "private_chats"),
+            CommandScope::Groups => write!(f, "groups"),
+            CommandScope::GroupAdmins => write!(f, "group_admins"),
+            CommandScope::Chat(chat) => write!(f, "chat:{}", chat.id),
+        }
+    }
+}
+
+impl Serialize for CommandScope {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Telegram command scope to which the command list is uploaded
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Default,
+    PrivateChats,
+    Groups,
+    GroupAdmins,
+    Chat(PackedChat),
+    ChatAdmins(PackedChat),
+}
+
+impl Target {
+    /// Whether command with scope should be shown in this target
+    fn includes(&self, scope: &Option<CommandScope>) -> bool {
+        let scope = match scope {
+            Some(scope) => scope,
+            None => return true
+        };
+        match self {
+            Target::Default => false,
+            Target::PrivateChats => scope == &CommandScope::PrivateChats,
+            Target::Groups => scope == &CommandScope::Groups,
+            Target::GroupAdmins => matches!(scope, CommandScope::Groups | CommandScope::GroupAdmins),
+            Target::Chat(chat) => match scope {
+                CommandScope::PrivateChats => is_user(chat),
+                CommandScope::Groups => !is_user(chat),
+                CommandScope::GroupAdmins => false,
+                CommandScope::Chat(c) => c == chat,
+            },
+            Target::ChatAdmins(chat) => match scope {
+                CommandScope::PrivateChats => false,
+                _ => Target::GroupAdmins.includes(&Some(scope.clone())) || Target::Chat(*chat).includes(&Some(scope.clone())),
+            },
+        }
+    }
+
+    /// Convert into TL type
+    fn to_tl(&self) -> tl::enums::BotCommandScope {
+        match self {
+            Target::Default => tl::enums::BotCommandScope::Default(tl::types::BotCommandScopeDefault {}),
+            Target::PrivateChats => tl::enums::BotCommandScope::Users(tl::types::BotCommandScopeUsers {}),
+            Target::Groups => tl::enums::BotCommandScope::Chats(tl::types::BotCommandScopeChats {}),
+            Target::GroupAdmins => tl::enums::BotCommandScope::ChatAdmins(tl::types::BotCommandScopeChatAdmins {}),
+            Target::Chat(chat) => tl::enums::BotCommandScope::Peer(tl::types::BotCommandScopePeer { peer: chat.to_input_peer() }),
+            Target::ChatAdmins(chat) => tl::enums::BotCommandScope::PeerAdmins(tl::types::BotCommandScopePeerAdmins { peer: chat.to_input_peer() }),
+        }
+    }
+}
+
+/// Packed chat is an user or bot
+fn is_user(chat: &PackedChat) -> bool {
+    matches!(chat.ty, PackedType::User | PackedType::Bot)
+}
+
+impl Grammersthon {
+    /// Get the command list for the command menu (command, description, scope)
+    pub fn bot_commands(&self) -> Vec<(String, String, Option<CommandScope>)> {
+        let mut commands: Vec<(String, String, Option<CommandScope>)> = vec![];
+        for info in self.command_schema().commands {
+            let command = match info.command {
+                Some(c) if !info.hidden => c,
+                _ => continue
+            };
+            if commands.iter().any(|(c, _, _)| c == &command) {
+                continue;
+            }
+            // First line of doc comment
+            let description = info.description
+                .and_then(|d| d.lines().next().map(String::from))
+                .filter(|d| !d.is_empty())
+                .unwrap_or(command.clone());
+            commands.push((command, description, info.scope));
+        }
+        commands
+    }
+
+    /// Set the bot name, texts and command lists for each scope (including translations)
+    pub async fn sync_botfather_profile(&self, profile: &BotProfile) -> Result<(), GrammersthonError> {
+        if !self.me.is_bot() {
+            return Err(GrammersthonError::Error("BotFather profile can only be set for bots".into()));
+        }
+
+        // Generate targets based on used scopes
+        let commands = self.bot_commands();
+        let mut targets = vec![Target::Default];
+        for (_, _, scope) in &commands {
+            let scope_targets = match scope {
+                None => vec![],
+                Some(CommandScope::PrivateChats) => vec![Target::PrivateChats],
+                Some(CommandScope::Groups) => vec![Target::Groups],
+                Some(CommandScope::GroupAdmins) => vec![Target::GroupAdmins],
+                Some(CommandScope::Chat(chat)) if is_user(chat) => vec![Target::Chat(*chat)],
+                Some(CommandScope::Chat(chat)) => vec![Target::Chat(*chat), Target::ChatAdmins(*chat)],
+            };
+            for target in scope_targets {
+                if !targets.contains(&target) {
+                    targets.push(target);
+                }
+            }
+        }
+
+        let default = ("".to_string(), profile.clone());
+        for (lang_code, profile) in std::iter::once(&default).chain(profile.languages.iter()) {
+            self.client.invoke(&tl::functions::bots::SetBotInfo {
+                bot: None,
+                lang_code: lang_code.to_string(),
+                name: profile.name.clone(),
+                about: profile.about.clone(),
+                description: profile.description.clone(),
+            }).await?;
+
+            // Translations without overriden commands use the default list
+            if !lang_code.is_empty() && profile.commands.is_empty() {
+                continue;
+            }
+            for target in &targets {
+                let commands = commands.iter()
+                    .filter(|(_, _, scope)| target.includes(scope))
+                    .map(|(command, description, _)| tl::enums::BotCommand::Command(tl::types::BotCommand {
+                        command: command.to_string(),
+                        description: profile.commands.get(command).unwrap_or(description).to_string()
+                    }))
+                    .collect::<Vec<_>>();
+                if commands.is_empty() {
+                    continue;
+                }
+                self.client.invoke(&tl::functions::bots::SetBotCommands {
+                    scope: target.to_tl(),
+                    lang_code: lang_code.to_string(),
+                    commands
+                }).await?;
+            }
+        }
+
+        info!("Synced bot profile with {} commands in {} scopes", commands.len(), targets.len());
+        Ok(())
+    }
+}
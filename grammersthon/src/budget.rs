@@ -0,0 +1,175 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use trait_bound_typemap::TypeMap;
+
+use crate::{Clock, Data, FromHandlerData, Grammersthon, HandlerData, SystemClock};
+
+/// Priority of API calls taken from `ApiBudget`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Handlers, can use the whole budget
+    Interactive,
+    /// Broadcasts, schedulers, feeds, can't use the reserved part of the budget
+    Background,
+}
+
+/// Token bucket of API calls shared by the handler helpers (`HandlerData::reply`, `send`, ...) and the background
+/// components (`Publisher`, `Digest`, `FeedIngest`, `Mirror`), so background jobs can't starve handlers of API quota:
+/// ```ignore
+/// // 20 calls per second with bursts of 40, 10 tokens are reserved for handlers
+/// grammersthon.api_budget(ApiBudget::new(20.0, 40).reserve(10));
+///
+/// // Own background job
+/// let budget = grammersthon.get_api_budget().unwrap();
+/// tokio::spawn(async move {
+///     for chat in chats {
+///         budget.acquire(Priority::Background).await;
+///         client.send_message(chat, "Hello").await?;
+///     }
+/// });
+/// ```
+#[derive(Clone)]
+pub struct ApiBudget {
+    state: Arc<Mutex<BudgetState>>,
+    clock: Arc<dyn Clock>,
+}
+
+/// Tokens of the bucket
+struct BudgetState {
+    /// Tokens added per second
+    rate: f64,
+    capacity: f64,
+    /// Tokens background calls have to leave in the bucket
+    reserve: f64,
+    tokens: f64,
+    updated: Option<Instant>,
+}
+
+impl ApiBudget {
+    /// Create new instance allowing `per_second` calls on average with bursts of up to `burst` calls
+    pub fn new(per_second: f64, burst: u32) -> ApiBudget {
+        let capacity = burst.max(1) as f64;
+        ApiBudget {
+            state: Arc::new(Mutex::new(BudgetState {
+                rate: per_second.max(0.001),
+                capacity,
+                reserve: 0.0,
+                tokens: capacity,
+                updated: None,
+            })),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Tokens reserved for interactive calls, background calls wait while fewer are available
+    pub fn reserve(self, tokens: u32) -> Self {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.reserve = (tokens as f64).min(state.capacity - 1.0);
+        }
+        self
+    }
+
+    /// Wait until API call of priority can be made
+    pub async fn acquire(&self, priority: Priority) {
+        loop {
+            let now = self.clock.now();
+            let wait = match self.state.lock().unwrap().take(now, priority) {
+                Ok(()) => return,
+                Err(wait) => wait,
+            };
+            debug!("Delaying {priority:?} API call by {wait:?}");
+            self.clock.sleep(wait).await;
+        }
+    }
+
+    /// Take token without waiting, returns false if the budget is exhausted
+    pub fn try_acquire(&self, priority: Priority) -> bool {
+        self.state.lock().unwrap().take(self.clock.now(), priority).is_ok()
+    }
+
+    /// Currently available tokens
+    pub fn available(&self) -> f64 {
+        let mut state = self.state.lock().unwrap();
+        state.refill(self.clock.now());
+        state.tokens
+    }
+}
+
+impl fmt::Debug for ApiBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ApiBudget").field("available", &self.available()).finish()
+    }
+}
+
+impl BudgetState {
+    /// Add the tokens for the time passed
+    fn refill(&mut self, now: Instant) {
+        if let Some(updated) = self.updated {
+            let elapsed = now.saturating_duration_since(updated).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        }
+        self.updated = Some(now);
+    }
+
+    /// Take token, or get the time until it's available
+    fn take(&mut self, now: Instant, priority: Priority) -> Result<(), Duration> {
+        self.refill(now);
+        let needed = match priority {
+            Priority::Interactive => 1.0,
+            Priority::Background => 1.0 + self.reserve,
+        };
+        if self.tokens >= needed {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64((needed - self.tokens) / self.rate))
+    }
+}
+
+impl FromHandlerData for ApiBudget {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.data::<ApiBudget>()
+    }
+}
+
+impl Grammersthon {
+    /// Share API budget between the handlers and background components, set after setting the clock
+    /// and before installing the components
+    pub fn api_budget(&mut self, mut budget: ApiBudget) -> &mut Self {
+        budget.clock = self.get_clock();
+        self.data.insert::<Data<ApiBudget>>(budget);
+        self
+    }
+
+    /// Get the API budget (`Grammersthon::api_budget`)
+    pub fn get_api_budget(&self) -> Option<ApiBudget> {
+        self.data.get::<Data<ApiBudget>>().cloned()
+    }
+}
+
+/// Wait for the budget if set
+pub(crate) async fn acquire(budget: &Option<ApiBudget>, priority: Priority) {
+    if let Some(budget) = budget {
+        budget.acquire(priority).await;
+    }
+}
+
+
+/// Test taking tokens with priorities
+#[test]
+fn test_api_budget() {
+    let mut state = BudgetState { rate: 2.0, capacity: 4.0, reserve: 2.0, tokens: 4.0, updated: None };
+    let now = Instant::now();
+    let ms = Duration::from_millis;
+    assert_eq!(state.take(now, Priority::Background), Ok(()));
+    assert_eq!(state.take(now, Priority::Background), Ok(()));
+    // Background calls leave the reserve to interactive ones
+    assert_eq!(state.take(now, Priority::Background), Err(ms(500)));
+    assert_eq!(state.take(now, Priority::Interactive), Ok(()));
+    assert_eq!(state.take(now, Priority::Interactive), Ok(()));
+    assert_eq!(state.take(now, Priority::Interactive), Err(ms(500)));
+    assert_eq!(state.take(now + ms(500), Priority::Interactive), Ok(()));
+    assert_eq!(state.take(now + ms(2500), Priority::Background), Ok(()));
+}
@@ -1,22 +1,43 @@
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::Path;
-use crossterm::style::Attribute;
+use std::pin::Pin;
+use std::sync::Arc;
 use grammers_client::{InitParams, Client, Config, SignInError};
+use grammers_client::types::PasswordToken;
 use grammers_session::Session;
-use tokio::io::{AsyncWriteExt, BufReader, AsyncBufReadExt};
 
-use crate::Grammersthon;
+use trait_bound_typemap::TypeMap;
+
+use crate::{AuthPrompt, Data, FileSessionStore, Grammersthon, PasswordPromptStyle, SessionStore, TerminalPrompt, UpdateKind};
 use crate::error::GrammersthonError;
+use crate::session;
+use crate::qr_login::{self, QrCallback, QrConnection, QrLogin};
+
+/// Address of the Telegram test datacenter 2
+const TEST_DC_IP: Ipv4Addr = Ipv4Addr::new(149, 154, 167, 40);
+
+type CodeProviderFn = dyn Fn() -> Pin<Box<dyn Future<Output = String> + Send>> + Send + Sync;
 
 pub struct GrammersthonBuilder {
     api_id: i32,
     api_hash: String,
     bot_token: Option<String>,
-    session: Session,
+    /// Loaded from the session store on login if None
+    session: Option<Session>,
+    session_store: Option<Arc<dyn SessionStore>>,
     phone: Option<String>,
     params: InitParams,
     interactive: bool,
     password_hint: bool,
-    password: Option<String>
+    password: Option<String>,
+    skip_outgoing: bool,
+    ignored_updates: Vec<UpdateKind>,
+    qr: Option<Arc<QrCallback>>,
+    /// Custom prompt, terminal prompt if None
+    prompt: Option<Arc<dyn AuthPrompt>>,
+    password_style: PasswordPromptStyle,
+    code_provider: Option<Arc<CodeProviderFn>>,
 }
 
 impl GrammersthonBuilder {
@@ -26,25 +47,53 @@ impl GrammersthonBuilder {
             api_id,
             api_hash: api_hash.to_string(),
             bot_token: None,
-            session: Session::new(),
+            session: Some(Session::new()),
+            session_store: None,
             phone: None,
             params: InitParams::default(),
             interactive: true,
             password_hint: false,
-            password: None
+            password: None,
+            skip_outgoing: false,
+            ignored_updates: vec![],
+            qr: None,
+            prompt: None,
+            password_style: PasswordPromptStyle::default(),
+            code_provider: None,
         }
     }
 
     /// Set session parameter for client
     pub fn use_memory_session(mut self) -> Self {
-        self.session = Session::new();
+        self.session = Some(Session::new());
+        self.session_store = None;
         self
     }
 
-    /// Shorthand for setting the session client parameter from path
-    /// Equivalent to: `.session(Session::load_file_or_create("session.session")?)`
-    pub fn session_file(mut self, path: impl AsRef<Path>) -> Result<Self, GrammersthonError> {
-        self.session = Session::load_file_or_create(path)?;
+    /// Shorthand for using session file, created if missing and saved automatically.
+    /// Equivalent to: `.session_store(FileSessionStore::new("session.session"))?`
+    pub fn session_file(self, path: impl AsRef<Path>) -> Result<Self, GrammersthonError> {
+        self.session_store(FileSessionStore::new(path))
+    }
+
+    /// Shorthand for using session file encrypted by passphrase (requires the `encryption` feature).
+    /// Equivalent to: `.session_store(EncryptedSessionStore::new(FileSessionStore::new(path), passphrase))?`
+    #[cfg(feature = "encryption")]
+    pub fn session_file_encrypted(self, path: impl AsRef<Path>, passphrase: &str) -> Result<Self, GrammersthonError> {
+        self.session_store(crate::EncryptedSessionStore::new(FileSessionStore::new(path), passphrase))
+    }
+
+    /// Load the session from store (when connecting), and save it there after login, periodically and on shutdown
+    pub fn session_store(mut self, store: impl SessionStore + 'static) -> Result<Self, GrammersthonError> {
+        self.session = None;
+        self.session_store = Some(Arc::new(store));
+        Ok(self)
+    }
+
+    /// Load session from string exported with `Grammersthon::export_session` 
+    /// (or the `grammersthon-session` tool)
+    pub fn session_string(mut self, session: &str) -> Result<Self, GrammersthonError> {
+        self.session = Some(session::decode_session(session)?);
         Ok(self)
     }
 
@@ -60,18 +109,100 @@ impl GrammersthonBuilder {
         self
     }
 
+    /// Login by scanning QR code in the Telegram app (Settings > Devices > Link Desktop Device) instead of phone code.
+    /// The QR code is printed in terminal (requires `qr` feature, otherwise the login link is printed).
+    /// Accounts with 2FA password continue with `password` or the password prompt
+    pub fn qr_login(mut self) -> Self {
+        self.qr = Some(Arc::new(qr_login::print_qr));
+        self
+    }
+
+    /// Login by QR code, the login URL (`tg://login?token=...`) is passed to callback whenever it changes, to display the QR code
+    pub fn qr_login_callback(mut self, callback: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.qr = Some(Arc::new(callback));
+        self
+    }
+
     /// Set new client `InitParams`
     pub fn params(mut self, params: InitParams) -> Self {
         self.params = params;
         self
     }
 
-    /// Enable interactive mode (prompt in terminal for missing fields)
+    /// Connect to the Telegram test datacenter (DC 2), for integration tests.
+    /// Test accounts have phone numbers `99966XYYYY` (X is the DC number, Y random) and the login code is X repeated 5 times.
+    /// Use separate session, sessions of the production and test DCs aren't interchangeable
+    pub fn use_test_dc(self) -> Self {
+        self.dc_address(TEST_DC_IP, 443)
+    }
+
+    /// Connect to custom datacenter address instead of the default one (self-hosted or test setups)
+    pub fn dc_address(mut self, ip: impl Into<IpAddr>, port: u16) -> Self {
+        self.params.server_addr = Some(SocketAddr::new(ip.into(), port));
+        self
+    }
+
+    /// Set device model reported to Telegram (shown in active sessions)
+    pub fn device_model(mut self, device_model: &str) -> Self {
+        self.params.device_model = device_model.to_string();
+        self
+    }
+
+    /// Set system version reported to Telegram
+    pub fn system_version(mut self, system_version: &str) -> Self {
+        self.params.system_version = system_version.to_string();
+        self
+    }
+
+    /// Set app version reported to Telegram
+    pub fn app_version(mut self, app_version: &str) -> Self {
+        self.params.app_version = app_version.to_string();
+        self
+    }
+
+    /// Set language code of the client (ISO 639-1), used for localized service messages
+    pub fn lang_code(mut self, lang_code: &str) -> Self {
+        self.params.lang_code = lang_code.to_string();
+        self
+    }
+
+    /// Enable interactive mode (prompt for missing fields, in terminal unless `auth_prompt` is set)
     pub fn interactive(mut self, enabled: bool) -> Self {
         self.interactive = enabled;
         self
     }
 
+    /// Ask for the missing fields using custom prompt (in interactive mode)
+    pub fn auth_prompt(mut self, prompt: impl AuthPrompt + 'static) -> Self {
+        self.prompt = Some(Arc::new(prompt));
+        self
+    }
+
+    /// How is the password displayed when typed in terminal (default hidden, without echo).
+    /// Applies only to the terminal prompt, not to custom `auth_prompt`
+    pub fn password_prompt_style(mut self, style: PasswordPromptStyle) -> Self {
+        self.password_style = style;
+        self
+    }
+
+    /// Get the login code from async function (another account, SMS gateway, web form...),
+    /// works without interactive mode, so the phone login can be completed without terminal
+    /// (accounts with 2FA also need `password`):
+    /// ```ignore
+    /// builder.phone("+420123456789").interactive(false).login_code_provider(move || {
+    ///     let codes = codes.clone();
+    ///     async move { codes.lock().await.recv().await.unwrap_or_default() }
+    /// })
+    /// ```
+    pub fn login_code_provider<F, Fut>(mut self, provider: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = String> + Send + 'static
+    {
+        self.code_provider = Some(Arc::new(move || Box::pin(provider())));
+        self
+    }
+
     /// Wether to display password hint in interactive mode
     pub fn show_password_hint(mut self, show: bool) -> Self {
         self.password_hint = show;
@@ -84,37 +215,69 @@ impl GrammersthonBuilder {
         self
     }
 
-    /// Prompt for a question in CLI
-    async fn prompt(question: &str, hide: bool) -> Result<String, GrammersthonError> {
-        let mut stdout = tokio::io::stdout();
-        stdout.write_all(question.as_bytes()).await?;
-        if hide {
-            stdout.write_all(Attribute::Hidden.to_string().as_bytes()).await?;
-        }
-        stdout.flush().await?;
-
-        let stdin = tokio::io::stdin();
-        let mut reader = BufReader::new(stdin);
-        let mut output = String::new();
-        reader.read_line(&mut output).await?;
-        if hide {
-            stdout.write_all(Attribute::NoHidden.to_string().as_bytes()).await?;
-        }
-        Ok(output.trim().to_string())
+    /// Ignore outgoing messages entirely before dispatching to handlers
+    pub fn skip_outgoing(mut self, skip: bool) -> Self {
+        self.skip_outgoing = skip;
+        self
+    }
+
+    /// Drop these classes of updates entirely, before any hook or handler
+    pub fn ignore_updates(mut self, kinds: impl IntoIterator<Item = UpdateKind>) -> Self {
+        self.ignored_updates.extend(kinds);
+        self
     }
 
     /// Build the client and try to connect
     pub async fn connect(mut self) -> Result<Grammersthon, GrammersthonError> {
+        let skip_outgoing = self.skip_outgoing;
+        let ignored_updates = std::mem::take(&mut self.ignored_updates);
+        // Account type used for self check
+        let expect_bot = match (&self.bot_token, &self.phone) {
+            (Some(_), _) => Some(true),
+            (None, Some(_)) => Some(false),
+            _ => None
+        };
+        let session_store = self.session_store.clone();
+        let client = self.login().await?;
+        let mut grammersthon = Grammersthon::from_client(client).await?;
+        if let Some(store) = session_store {
+            grammersthon.data.insert::<Data<Arc<dyn SessionStore>>>(store);
+            grammersthon.save_session().await?;
+        }
+        grammersthon.skip_outgoing(skip_outgoing);
+        grammersthon.ignore_updates(ignored_updates);
+        grammersthon.expect_bot = expect_bot;
+        Ok(grammersthon)
+    }
+
+    /// Connect and login the client
+    async fn login(mut self) -> Result<Client, GrammersthonError> {
+        let session = match (self.session.take(), &self.session_store) {
+            (Some(session), _) => session,
+            (None, Some(store)) => session::load_session(store.as_ref()).await?,
+            (None, None) => Session::new(),
+        };
         let client = Client::connect(Config {
-            session: self.session,
+            session,
             api_id: self.api_id,
             api_hash: self.api_hash.clone(),
-            params: self.params,
+            params: self.params.clone(),
         })
         .await?;
 
         if client.is_authorized().await? {
-            return Grammersthon::from_client(client).await;
+            return Ok(client);
+        }
+        let prompt = self.prompt.clone().unwrap_or_else(|| Arc::new(TerminalPrompt::new(self.password_style)));
+
+        // QR login
+        if let (Some(qr), None) = (&self.qr, &self.bot_token) {
+            // Reconnected if the account is in other DC
+            let mut connection = QrConnection { client, api_id: self.api_id, api_hash: self.api_hash.clone(), params: self.params.clone() };
+            if let QrLogin::PasswordRequired(password_token) = qr_login::qr_sign_in(&mut connection, self.api_id, &self.api_hash, qr.as_ref()).await? {
+                self.check_password(&connection.client, prompt.as_ref(), password_token).await?;
+            }
+            return Ok(connection.client);
         }
 
         // Missing bot token and phone number
@@ -122,7 +285,7 @@ impl GrammersthonBuilder {
             if !self.interactive {
                 return Err(GrammersthonError::MissingParameters("bot_token or phone number"));
             }
-            let answer = Self::prompt("Enter phone number or bot token: ", false).await?;
+            let answer = prompt.ask_phone().await?;
             if answer.contains(":") {
                 self.bot_token = Some(answer);
             } else {
@@ -133,47 +296,54 @@ impl GrammersthonBuilder {
         // Login using bot token
         if let Some(token) = self.bot_token {
             client.bot_sign_in(&token).await?;
-            return Grammersthon::from_client(client).await;
+            return Ok(client);
         }
 
         // Unauthorized (can't prompt for code)
-        if !self.interactive {
+        if !self.interactive && self.code_provider.is_none() {
             return Err(GrammersthonError::MissingParameters("interactive (code prompt)"))
         }
 
         // Interactive user login
         let token = client.request_login_code(self.phone.as_ref().unwrap()).await?;
-        let code = Self::prompt("Enter the code you received: ", false).await?;
+        let code = match &self.code_provider {
+            Some(provider) => provider().await,
+            None => prompt.ask_code().await?,
+        };
         match client.sign_in(&token, &code).await {
-            Ok(_) => Grammersthon::from_client(client).await,
+            Ok(_) => Ok(client),
             Err(SignInError::PasswordRequired(password_token)) => {
-                // Try saved password
-                if let Some(password) = &self.password {
-                    match client.check_password(password_token, password).await {
-                        Err(SignInError::InvalidPassword) => {
-                            warn!("Invalid password!");
-                            return Err(SignInError::InvalidPassword.into());
-                        }
-                        r => {
-                            r?;
-                            return Grammersthon::from_client(client).await;
-                        }
-                    };
-                // Prompt for password
-                } else {
-                    let prompt = if self.password_hint && password_token.hint().is_some() {
-                        format!("Enter your password (hint: {}) (hidden): ", password_token.hint().unwrap())
-                    } else {
-                        "Enter your password (hidden): ".to_string()
-                    };
-                    let answer = Self::prompt(&prompt, true).await?;
-                    client.check_password(password_token, &answer).await?;
-                    Grammersthon::from_client(client).await
-                }
-                
+                self.check_password(&client, prompt.as_ref(), password_token).await?;
+                Ok(client)
             }
             Err(e) => Err(e.into()),
         }
     }
+
+    /// Finish login of account with 2FA password
+    async fn check_password(&self, client: &Client, prompt: &dyn AuthPrompt, password_token: PasswordToken) -> Result<(), GrammersthonError> {
+        // Try saved password
+        if let Some(password) = &self.password {
+            match client.check_password(password_token, password).await {
+                Err(SignInError::InvalidPassword) => {
+                    warn!("Invalid password!");
+                    Err(SignInError::InvalidPassword.into())
+                }
+                r => {
+                    r?;
+                    Ok(())
+                }
+            }
+        // Can't prompt for password
+        } else if !self.interactive {
+            Err(GrammersthonError::MissingParameters("password (account has 2FA enabled)"))
+        // Prompt for password
+        } else {
+            let hint = password_token.hint().filter(|_| self.password_hint).map(String::from);
+            let answer = prompt.ask_password(hint.as_deref()).await?;
+            client.check_password(password_token, &answer).await?;
+            Ok(())
+        }
+    }
 }
 
@@ -0,0 +1,149 @@
+//! Fault injection for resilience testing (`chaos` feature), don't enable in production
+//!
+//! Usage:
+//! ```ignore
+//! Chaos::new(42)
+//!     .delays(0.3, Duration::from_secs(2))
+//!     .errors(0.1)
+//!     .duplicates(0.05)
+//!     .install(&mut grammersthon);
+//! ```
+//!
+//! Injected errors are `ChaosError` (wrapped in `GrammersthonError::Error`) and go to the error handler.
+//! The same seed gives the same sequence of decisions.
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::{Grammersthon, GrammersthonError, HandlerData};
+
+/// Small seedable PRNG (SplitMix64)
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in 0..1
+    fn float(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// True with probability
+    fn roll(&mut self, probability: f64) -> bool {
+        probability > 0.0 && self.float() < probability
+    }
+}
+
+/// Error injected by `Chaos`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChaosError;
+
+impl fmt::Display for ChaosError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Injected chaos error")
+    }
+}
+
+impl std::error::Error for ChaosError {}
+
+/// Chaos middleware configuration
+#[derive(Debug, Clone)]
+pub struct Chaos {
+    seed: u64,
+    delay_probability: f64,
+    max_delay: Duration,
+    error_probability: f64,
+    duplicate_probability: f64,
+}
+
+impl Chaos {
+    /// Create new instance which injects nothing
+    pub fn new(seed: u64) -> Chaos {
+        Chaos { seed, delay_probability: 0.0, max_delay: Duration::ZERO, error_probability: 0.0, duplicate_probability: 0.0 }
+    }
+
+    /// Delay handling of message by random duration up to `max` with probability
+    pub fn delays(mut self, probability: f64, max: Duration) -> Self {
+        self.delay_probability = probability;
+        self.max_delay = max;
+        self
+    }
+
+    /// Fail handling of message with `ChaosError` with probability
+    pub fn errors(mut self, probability: f64) -> Self {
+        self.error_probability = probability;
+        self
+    }
+
+    /// Dispatch update twice with probability
+    pub fn duplicates(mut self, probability: f64) -> Self {
+        self.duplicate_probability = probability;
+        self
+    }
+
+    /// Register the middleware
+    pub fn install(self, grammersthon: &mut Grammersthon) {
+        warn!("Chaos testing enabled, updates will be delayed, failed and duplicated");
+        let rng = Arc::new(Mutex::new(Rng(self.seed)));
+        let duplicates = rng.clone();
+        let duplicate_probability = self.duplicate_probability;
+        grammersthon.handlers.update_copies(move |_| match duplicates.lock().unwrap().roll(duplicate_probability) {
+            true => 2,
+            false => 1,
+        });
+
+        let this = Arc::new(self);
+        grammersthon.interceptor(move |data: HandlerData| {
+            let this = this.clone();
+            let (delay, error) = this.decide(&mut rng.lock().unwrap());
+            async move {
+                if let Some(delay) = delay {
+                    debug!("Chaos: delaying message {} by {delay:?}", data.message.id());
+                    tokio::time::sleep(delay).await;
+                }
+                if error {
+                    debug!("Chaos: failing message {}", data.message.id());
+                    return Err(GrammersthonError::other(ChaosError));
+                }
+                Ok(data)
+            }
+        });
+    }
+
+    /// Roll the delay and error of message
+    fn decide(&self, rng: &mut Rng) -> (Option<Duration>, bool) {
+        let delay = match rng.roll(self.delay_probability) {
+            true => Some(self.max_delay.mul_f64(rng.float())),
+            false => None,
+        };
+        (delay, rng.roll(self.error_probability))
+    }
+}
+
+
+/// Test that decisions are reproducible and respect probabilities
+#[test]
+fn test_chaos_decisions() {
+    let chaos = Chaos::new(7).delays(0.5, Duration::from_secs(1)).errors(0.2);
+    let run = |seed| {
+        let mut rng = Rng(seed);
+        (0..1000).map(|_| chaos.decide(&mut rng)).collect::<Vec<_>>()
+    };
+    let decisions = run(7);
+    assert_eq!(decisions, run(7));
+    assert_ne!(decisions, run(8));
+    let delayed = decisions.iter().filter(|(d, _)| d.is_some()).count();
+    let failed = decisions.iter().filter(|(_, e)| *e).count();
+    assert!((400..600).contains(&delayed));
+    assert!((120..280).contains(&failed));
+    assert!(decisions.iter().all(|(d, _)| d.map(|d| d <= Duration::from_secs(1)).unwrap_or(true)));
+    assert!(!Chaos::new(1).decide(&mut Rng(1)).1);
+}
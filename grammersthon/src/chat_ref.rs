@@ -0,0 +1,109 @@
+use grammers_client::Client;
+use grammers_client::types::Chat;
+use grammers_session::{PackedChat, PackedType};
+use serde::{Serialize, Deserialize};
+
+use crate::{FromHandlerData, GrammersthonError, HandlerData};
+
+/// Serializable reference to chat, can be saved to `Storage` and used after restart:
+/// ```ignore
+/// #[handler("^/subscribe")]
+/// async fn subscribe(chat: ChatRef, storage: Storage) -> HandlerResult {
+///     storage.set(&format!("subscribers:{}", chat.id), &chat)?;
+///     Ok(())
+/// }
+///
+/// // Later
+/// let chat: ChatRef = storage.get("subscribers:123")?.unwrap();
+/// client.send_message(chat, "Hello").await?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ChatRef {
+    pub id: i64,
+    pub access_hash: Option<i64>,
+    pub kind: ChatKind,
+}
+
+/// Kind of chat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatKind {
+    User,
+    Bot,
+    /// Basic group
+    Group,
+    Megagroup,
+    Channel,
+    Gigagroup,
+}
+
+impl ChatRef {
+    /// Get the full chat
+    pub async fn resolve(&self, client: &Client) -> Result<Chat, GrammersthonError> {
+        Ok(client.unpack_chat(self.pack()).await?)
+    }
+
+    /// Convert into grammers packed chat
+    pub fn pack(&self) -> PackedChat {
+        let ty = match self.kind {
+            ChatKind::User => PackedType::User,
+            ChatKind::Bot => PackedType::Bot,
+            ChatKind::Group => PackedType::Chat,
+            ChatKind::Megagroup => PackedType::Megagroup,
+            ChatKind::Channel => PackedType::Broadcast,
+            ChatKind::Gigagroup => PackedType::Gigagroup,
+        };
+        PackedChat { ty, id: self.id, access_hash: self.access_hash }
+    }
+
+    /// Chat is user or bot
+    pub fn is_user(&self) -> bool {
+        matches!(self.kind, ChatKind::User | ChatKind::Bot)
+    }
+}
+
+impl From<PackedChat> for ChatRef {
+    fn from(chat: PackedChat) -> Self {
+        let kind = match chat.ty {
+            PackedType::User => ChatKind::User,
+            PackedType::Bot => ChatKind::Bot,
+            PackedType::Chat => ChatKind::Group,
+            PackedType::Megagroup => ChatKind::Megagroup,
+            PackedType::Broadcast => ChatKind::Channel,
+            PackedType::Gigagroup => ChatKind::Gigagroup,
+        };
+        ChatRef { id: chat.id, access_hash: chat.access_hash, kind }
+    }
+}
+
+impl From<&Chat> for ChatRef {
+    fn from(chat: &Chat) -> Self {
+        chat.pack().into()
+    }
+}
+
+impl From<ChatRef> for PackedChat {
+    fn from(chat: ChatRef) -> Self {
+        chat.pack()
+    }
+}
+
+/// Chat of the message
+impl FromHandlerData for ChatRef {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        Some((&data.message.chat()).into())
+    }
+}
+
+
+/// Test conversion and serialization
+#[test]
+fn test_chat_ref() {
+    let packed = PackedChat { ty: PackedType::Megagroup, id: 123, access_hash: Some(456) };
+    let chat = ChatRef::from(packed);
+    assert_eq!(chat.pack(), packed);
+    assert!(!chat.is_user());
+    let json = serde_json::to_string(&chat).unwrap();
+    assert_eq!(json, r#"{"id":123,"access_hash":456,"kind":"megagroup"}"#);
+    assert_eq!(serde_json::from_str::<ChatRef>(&json).unwrap(), chat);
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use grammers_tl_types as tl;
+
+use crate::Grammersthon;
+
+/// Result of `Grammersthon::self_check`
+#[derive(Debug, Clone)]
+pub struct SelfCheck {
+    pub checks: Vec<Check>
+}
+
+/// Single check of the self check
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub message: String,
+}
+
+impl Check {
+    fn ok(name: &'static str, message: impl Into<String>) -> Check {
+        Check { name, ok: true, message: message.into() }
+    }
+
+    fn failed(name: &'static str, message: impl Into<String>) -> Check {
+        Check { name, ok: false, message: message.into() }
+    }
+}
+
+impl SelfCheck {
+    /// Wether all the checks passed
+    pub fn is_ok(&self) -> bool {
+        self.checks.iter().all(|c| c.ok)
+    }
+
+    /// Get only the failed checks
+    pub fn failed(&self) -> Vec<&Check> {
+        self.checks.iter().filter(|c| !c.ok).collect()
+    }
+}
+
+impl fmt::Display for SelfCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(f, "[{}] {}: {}", if check.ok { "OK" } else { "FAIL" }, check.name, check.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl Grammersthon {
+    /// Verify the client is usable, meant to be run before `start_event_loop`
+    ///
+    /// ```ignore
+    /// let report = grammersthon.self_check().await;
+    /// if !report.is_ok() {
+    ///     panic!("Self check failed:\n{report}");
+    /// }
+    /// ```
+    pub async fn self_check(&self) -> SelfCheck {
+        let mut checks = vec![];
+
+        // Session
+        checks.push(match self.client.is_authorized().await {
+            Ok(true) => Check::ok("authorization", "Session is authorized"),
+            Ok(false) => Check::failed("authorization", "Session is not authorized"),
+            Err(e) => Check::failed("authorization", format!("Failed checking authorization: {e}")),
+        });
+
+        // DC connection
+        let ping_id = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_millis() as i64;
+        let start = Instant::now();
+        checks.push(match self.client.invoke(&tl::functions::Ping { ping_id }).await {
+            Ok(tl::enums::Pong::Pong(pong)) if pong.ping_id == ping_id =>
+                Check::ok("ping", format!("Pong in {}ms", start.elapsed().as_millis())),
+            Ok(_) => Check::failed("ping", "Invalid pong received"),
+            Err(e) => Check::failed("ping", format!("Ping failed: {e}")),
+        });
+
+        // Account type
+        let kind = |bot: bool| if bot { "bot" } else { "user" };
+        checks.push(match self.expect_bot {
+            Some(bot) if bot != self.me.is_bot() =>
+                Check::failed("account", format!("Expected {}, but logged in as {} {}", kind(bot), kind(self.me.is_bot()), self.me.id())),
+            _ => Check::ok("account", format!("Logged in as {} {}", kind(self.me.is_bot()), self.me.id())),
+        });
+
+        // Handler data
+        checks.push(match self.validate_data() {
+            Ok(_) => Check::ok("data", "All the data required by handlers was added"),
+            Err(e) => Check::failed("data", e.to_string()),
+        });
+
+        // Storage
+        checks.push(match self.get_storage().ping() {
+            Ok(_) => Check::ok("storage", "Storage is reachable"),
+            Err(e) => Check::failed("storage", format!("Storage is unreachable: {e}")),
+        });
+
+        let report = SelfCheck { checks };
+        for check in report.failed() {
+            warn!("Self check {} failed: {}", check.name, check.message);
+        }
+        report
+    }
+}
@@ -0,0 +1,156 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::watch;
+use trait_bound_typemap::TypeMap;
+
+use crate::{Data, Grammersthon, HandlerData};
+
+/// Future returned by `Clock::sleep`
+pub type SleepFuture = Pin<Box<dyn Future<Output = ()> + Send + Sync>>;
+
+/// Source of time for the scheduling components (`Publisher`, `Digest`), handler timeouts and the rate limiting
+/// filters and interceptors (`filters::dedup`, `filters::tenant_rate_limit`, `middleware::loop_guard`),
+/// so tests can advance virtual time instantly (`ManualClock`):
+/// ```ignore
+/// let clock = ManualClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(86400 * 100));
+/// grammersthon.clock(clock.clone());
+/// Publisher::new(channel).slot(9, 0).install(&mut grammersthon)?;
+/// clock.advance(Duration::from_secs(9 * 3600));
+/// ```
+pub trait Clock: Send + Sync {
+    /// Monotonic time, for measuring durations
+    fn now(&self) -> Instant;
+    /// Wall clock time
+    fn system_time(&self) -> SystemTime;
+    /// Wait for duration
+    fn sleep(&self, duration: Duration) -> SleepFuture;
+}
+
+/// Real time (`tokio::time`), used by default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> SleepFuture {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Virtual time which only moves by `advance`, sleeps finish once enough time was advanced
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    instant: Instant,
+    start: SystemTime,
+    /// Advanced so far
+    elapsed: Arc<watch::Sender<Duration>>,
+}
+
+impl ManualClock {
+    /// Create new instance starting at wall clock time
+    pub fn new(start: SystemTime) -> ManualClock {
+        ManualClock { instant: Instant::now(), start, elapsed: Arc::new(watch::channel(Duration::ZERO).0) }
+    }
+
+    /// Move the time forward, wakes up the finished sleeps
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.send_modify(|elapsed| *elapsed += duration);
+    }
+
+    /// Time advanced since creation
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.borrow()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.instant + self.elapsed()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.start + self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration) -> SleepFuture {
+        let mut elapsed = self.elapsed.subscribe();
+        let until = *elapsed.borrow() + duration;
+        Box::pin(async move {
+            while *elapsed.borrow_and_update() < until {
+                // Clock dropped, time won't move anymore
+                if elapsed.changed().await.is_err() {
+                    std::future::pending::<()>().await;
+                }
+            }
+        })
+    }
+}
+
+impl Grammersthon {
+    /// Set the clock, install components using it after setting
+    pub fn clock(&mut self, clock: impl Clock + 'static) -> &mut Self {
+        self.data.insert::<Data<Arc<dyn Clock>>>(Arc::new(clock));
+        self
+    }
+
+    /// Get the clock
+    pub fn get_clock(&self) -> Arc<dyn Clock> {
+        self.data.get::<Data<Arc<dyn Clock>>>().cloned().unwrap_or_else(|| Arc::new(SystemClock))
+    }
+}
+
+impl HandlerData {
+    /// Get the clock (`Grammersthon::clock`)
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.data::<Arc<dyn Clock>>().unwrap_or_else(|| Arc::new(SystemClock))
+    }
+}
+
+/// Run future with timeout measured by clock, None if it timed out
+pub(crate) async fn timeout<F: Future>(clock: &dyn Clock, duration: Duration, f: F) -> Option<F::Output> {
+    tokio::select! {
+        biased;
+        output = f => Some(output),
+        _ = clock.sleep(duration) => None,
+    }
+}
+
+
+/// Test advancing virtual time
+#[tokio::test]
+async fn test_manual_clock() {
+    let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+    let start = clock.now();
+    let sleep = tokio::spawn(clock.sleep(Duration::from_secs(3600)));
+    clock.advance(Duration::from_secs(1800));
+    tokio::task::yield_now().await;
+    assert!(!sleep.is_finished());
+    clock.advance(Duration::from_secs(1800));
+    tokio::time::timeout(Duration::from_secs(1), sleep).await.unwrap().unwrap();
+    assert_eq!(clock.now() - start, Duration::from_secs(3600));
+    assert_eq!(clock.system_time(), SystemTime::UNIX_EPOCH + Duration::from_secs(3600));
+}
+
+/// Test timeout in virtual time
+#[tokio::test]
+async fn test_clock_timeout() {
+    let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+    assert_eq!(timeout(&clock, Duration::from_secs(60), async { 1 }).await, Some(1));
+    let slow = {
+        let clock = clock.clone();
+        tokio::spawn(async move { timeout(&clock, Duration::from_secs(60), std::future::pending::<()>()).await })
+    };
+    tokio::task::yield_now().await;
+    assert!(!slow.is_finished());
+    clock.advance(Duration::from_secs(60));
+    assert_eq!(tokio::time::timeout(Duration::from_secs(1), slow).await.unwrap().unwrap(), None);
+}
@@ -0,0 +1,91 @@
+use grammers_client::types::{Chat, Message};
+use grammers_tl_types::enums::{MessageReplyHeader, Peer};
+
+use crate::{FromHandlerData, GrammersthonError, HandlerData};
+
+/// Comment on a channel post, message in the linked discussion group replying to the post (or other comment under it).
+/// As extractor requires `filters::is_comment()`, which detects the comment:
+/// ```ignore
+/// #[handler("", filters::is_comment())]
+/// async fn comment(comment: Comment, message: Message) -> HandlerResult {
+///     info!("Comment on post {} in channel {}: {}", comment.post_id, comment.channel_id, message.text());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Comment {
+    /// Automatic forward of the post to the discussion group, root of the comment thread
+    pub thread: Message,
+    /// Id of the channel
+    pub channel_id: i64,
+    /// Id of the post in the channel
+    pub post_id: i32,
+}
+
+impl Comment {
+    /// Id of the thread root message in the discussion group
+    pub fn thread_id(&self) -> i32 {
+        self.thread.id()
+    }
+}
+
+/// Channel and post id of automatic forward of channel post
+fn channel_post(message: &Message) -> Option<(i64, i32)> {
+    let header: grammers_tl_types::types::MessageFwdHeader = message.forward_header()?.into();
+    match (header.saved_from_peer?, header.saved_from_msg_id?) {
+        (Peer::Channel(channel), id) => Some((channel.channel_id, id)),
+        _ => None
+    }
+}
+
+impl HandlerData {
+    /// Get the channel post the message comments on, fetched once and cached (also used by `filters::is_comment`)
+    pub async fn comment(&self) -> Result<Option<Comment>, GrammersthonError> {
+        self.comment.get_or_try_init(|| async {
+            let thread_id = match self.message.reply_header() {
+                Some(MessageReplyHeader::Header(h)) => match h.reply_to_top_id.or(h.reply_to_msg_id) {
+                    Some(id) => id,
+                    None => return Ok(None),
+                },
+                _ => return Ok(None),
+            };
+            let thread = self.client.get_messages_by_id(self.message.chat(), &[thread_id]).await?.pop().flatten();
+            Ok(thread.and_then(|thread| {
+                let (channel_id, post_id) = channel_post(&thread)?;
+                Some(Comment { thread, channel_id, post_id })
+            }))
+        }).await.cloned()
+    }
+
+    /// Top message of the thread (comment thread, forum topic) the message is in, None in the main chat
+    pub async fn thread_id(&self) -> Option<i32> {
+        let header = match self.message.reply_header() {
+            Some(MessageReplyHeader::Header(h)) => h,
+            _ => return None,
+        };
+        if header.reply_to_top_id.is_some() {
+            return header.reply_to_top_id;
+        }
+        if header.forum_topic {
+            return header.reply_to_msg_id;
+        }
+        // Replies to the thread root, only a thread under channel post
+        if !matches!(self.message.chat(), Chat::Group(_)) {
+            return None;
+        }
+        match self.comment().await {
+            Ok(comment) => comment.map(|c| c.thread_id()),
+            Err(e) => {
+                warn!("Failed getting thread of message {}: {e}", self.message.id());
+                None
+            }
+        }
+    }
+}
+
+/// Requires `filters::is_comment()`
+impl FromHandlerData for Comment {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.comment.get().cloned().flatten()
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use grammers_client::InputMessage;
+use grammers_client::types::Message;
+use tokio::sync::oneshot;
+
+use crate::{GrammersthonError, HandlerData};
+
+/// (chat, user) -> waiter
+type Waiters = Arc<Mutex<HashMap<(i64, i64), oneshot::Sender<Message>>>>;
+
+/// Handlers waiting for the next message of user in chat
+#[derive(Clone, Default)]
+pub struct Conversations {
+    waiters: Waiters,
+}
+
+impl Conversations {
+    /// (chat, user) key of the message, chat for anonymous senders
+    fn key(message: &Message) -> (i64, i64) {
+        let chat = message.chat().id();
+        (chat, message.sender().map(|s| s.id()).unwrap_or(chat))
+    }
+
+    /// Wait for the next message of user in chat, replaces the previous waiter
+    pub fn wait(&self, chat: i64, user: i64) -> oneshot::Receiver<Message> {
+        let (tx, rx) = oneshot::channel();
+        self.waiters.lock().unwrap().insert((chat, user), tx);
+        rx
+    }
+
+    /// Is any handler waiting for message of the user in chat
+    pub fn is_waiting(&self, chat: i64, user: i64) -> bool {
+        self.waiters.lock().unwrap().get(&(chat, user)).map(|w| !w.is_closed()).unwrap_or(false)
+    }
+
+    /// Stop waiting for message of the user in chat
+    pub fn cancel(&self, chat: i64, user: i64) {
+        self.waiters.lock().unwrap().remove(&(chat, user));
+    }
+
+    /// Pass the message to waiting handler, returns if it was consumed
+    pub(crate) fn deliver(&self, message: &Message) -> bool {
+        let waiter = self.waiters.lock().unwrap().remove(&Self::key(message));
+        match waiter {
+            Some(waiter) => waiter.send(message.clone()).is_ok(),
+            None => false
+        }
+    }
+
+    /// Interceptor passing messages to the waiting handlers instead of the other handlers
+    pub(crate) async fn intercept(data: HandlerData) -> Result<HandlerData, GrammersthonError> {
+        match data.data::<Conversations>() {
+            Some(conversations) if conversations.deliver(&data.message) => Err(GrammersthonError::Cancelled),
+            _ => Ok(data)
+        }
+    }
+}
+
+impl HandlerData {
+    /// Wait for the next message of the sender in this chat.
+    /// The message isn't passed to any other handler
+    pub async fn wait_message(&self, timeout: Duration) -> Result<Message, GrammersthonError> {
+        let conversations = self.data::<Conversations>().ok_or(GrammersthonError::MissingParameters("Conversations"))?;
+        let (chat, user) = Conversations::key(&self.message);
+        let rx = conversations.wait(chat, user);
+        match tokio::time::timeout(timeout, rx).await {
+            Ok(Ok(message)) => Ok(message),
+            // Replaced by another waiter
+            Ok(Err(_)) => Err(GrammersthonError::Cancelled),
+            Err(_) => {
+                conversations.cancel(chat, user);
+                Err(GrammersthonError::Timeout)
+            }
+        }
+    }
+
+    /// Reply with prompt and wait for the answer:
+    /// ```ignore
+    /// let name = data.ask("What's your name?", Duration::from_secs(60)).await?;
+    /// ```
+    pub async fn ask(&self, prompt: impl Into<InputMessage>, timeout: Duration) -> Result<Message, GrammersthonError> {
+        self.reply(prompt).await?;
+        self.wait_message(timeout).await
+    }
+}
@@ -0,0 +1,156 @@
+//! `FromArgs` for `chrono` dates and times
+//!
+//! Arguments are whitespace separated, so for commands like `/remind 2025-06-01 09:00 buy milk`
+//! or `/remind tomorrow 9am buy milk` use separate fields:
+//! ```ignore
+//! #[derive(FromArgs)]
+//! struct Remind {
+//!     date: NaiveDate,
+//!     time: NaiveTime,
+//!     text: String,
+//! }
+//! ```
+//! `NaiveDateTime` and `DateTime` fields take a single argument (`"tomorrow 9am"` has to be quoted).
+
+use std::time::Duration;
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, TimeZone, Utc, Weekday};
+
+use crate::{FromArgs, GrammersthonError};
+
+/// Parse date relative to today
+fn parse_date(input: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let input = input.trim().to_lowercase();
+    match input.as_str() {
+        "today" => return Some(today),
+        "tomorrow" => return today.succ_opt(),
+        "yesterday" => return today.pred_opt(),
+        _ => {}
+    }
+    // Next occurrence of weekday
+    if let Ok(weekday) = input.parse::<Weekday>() {
+        let days = (weekday.num_days_from_monday() + 6 - today.weekday().num_days_from_monday()) % 7 + 1;
+        return today.checked_add_days(Days::new(days as u64));
+    }
+    ["%Y-%m-%d", "%d.%m.%Y", "%Y/%m/%d"].into_iter().find_map(|f| NaiveDate::parse_from_str(&input, f).ok())
+}
+
+/// Parse 24 or 12 hour time
+fn parse_time(input: &str) -> Option<NaiveTime> {
+    let input = input.to_lowercase().replace(char::is_whitespace, "");
+    match input.as_str() {
+        "noon" => return NaiveTime::from_hms_opt(12, 0, 0),
+        "midnight" => return NaiveTime::from_hms_opt(0, 0, 0),
+        _ => {}
+    }
+    let twelve_hour = input.strip_suffix("am").map(|t| (t, 0))
+        .or_else(|| input.strip_suffix("pm").map(|t| (t, 12)));
+    if let Some((time, offset)) = twelve_hour {
+        let (hour, minute) = time.split_once(':').unwrap_or((time, "0"));
+        let (hour, minute) = (hour.parse::<u32>().ok()?, minute.parse::<u32>().ok()?);
+        if !(1..=12).contains(&hour) {
+            return None;
+        }
+        return NaiveTime::from_hms_opt(hour % 12 + offset, minute, 0);
+    }
+    ["%H:%M", "%H:%M:%S"].into_iter().find_map(|f| NaiveTime::parse_from_str(&input, f).ok())
+}
+
+/// Parse date and time relative to now
+fn parse_datetime(input: &str, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    let input = input.trim();
+    if let Some(dt) = ["%Y-%m-%d %H:%M", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S"].into_iter()
+        .find_map(|f| NaiveDateTime::parse_from_str(input, f).ok())
+    {
+        return Some(dt);
+    }
+    // Relative (`in 2h30m`)
+    if let Some(span) = input.strip_prefix("in ") {
+        let span = TimeDelta::from_std(Duration::parse_arg(span).ok()?).ok()?;
+        return now.checked_add_signed(span);
+    }
+    // Only time, next occurrence
+    if let Some(time) = parse_time(input) {
+        let dt = now.date().and_time(time);
+        return match dt > now {
+            true => Some(dt),
+            false => dt.checked_add_days(Days::new(1)),
+        };
+    }
+    if let Some(date) = parse_date(input, now.date()) {
+        return Some(date.and_time(NaiveTime::MIN));
+    }
+    // `tomorrow 9am`, `friday at 18:30`
+    let (date, time) = input.split_once(char::is_whitespace)?;
+    let time = time.trim_start();
+    let time = time.strip_prefix("at ").unwrap_or(time);
+    Some(parse_date(date, now.date())?.and_time(parse_time(time)?))
+}
+
+/// Date as `2025-01-01`, `1.6.2025`, `today`, `tomorrow`, `yesterday` or weekday (next occurrence)
+impl FromArgs for NaiveDate {
+    fn parse_arg(input: &str) -> Result<Self, GrammersthonError> {
+        parse_date(input, Local::now().date_naive()).ok_or_else(|| GrammersthonError::Parse(input.to_string(), None))
+    }
+}
+
+/// Time as `18:30`, `18:30:15`, `9am`, `9:15pm`, `noon` or `midnight`
+impl FromArgs for NaiveTime {
+    fn parse_arg(input: &str) -> Result<Self, GrammersthonError> {
+        parse_time(input).ok_or_else(|| GrammersthonError::Parse(input.to_string(), None))
+    }
+}
+
+/// Local date and time as `2025-06-01 09:00`, `tomorrow 9am`, `friday at 18:30`, `in 2h30m`,
+/// only date (midnight) or only time (next occurrence)
+impl FromArgs for NaiveDateTime {
+    fn parse_arg(input: &str) -> Result<Self, GrammersthonError> {
+        parse_datetime(input, Local::now().naive_local()).ok_or_else(|| GrammersthonError::Parse(input.to_string(), None))
+    }
+}
+
+/// RFC 3339 or the same formats as `NaiveDateTime`
+impl FromArgs for DateTime<Local> {
+    fn parse_arg(input: &str) -> Result<Self, GrammersthonError> {
+        if let Ok(dt) = DateTime::parse_from_rfc3339(input.trim()) {
+            return Ok(dt.with_timezone(&Local));
+        }
+        NaiveDateTime::parse_arg(input).and_then(|dt| {
+            Local.from_local_datetime(&dt).earliest().ok_or_else(|| GrammersthonError::Parse(input.to_string(), None))
+        })
+    }
+}
+
+/// RFC 3339 or the same formats as `NaiveDateTime` in UTC
+impl FromArgs for DateTime<Utc> {
+    fn parse_arg(input: &str) -> Result<Self, GrammersthonError> {
+        if let Ok(dt) = DateTime::parse_from_rfc3339(input.trim()) {
+            return Ok(dt.with_timezone(&Utc));
+        }
+        parse_datetime(input, Utc::now().naive_utc())
+            .map(|dt| dt.and_utc())
+            .ok_or_else(|| GrammersthonError::Parse(input.to_string(), None))
+    }
+}
+
+
+/// Test date and time parsing
+#[test]
+fn test_parse_datetime() {
+    // Wednesday
+    let now = NaiveDate::from_ymd_opt(2025, 1, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+    let at = |d: u32, h: u32, m: u32| NaiveDate::from_ymd_opt(2025, 1, d).unwrap().and_hms_opt(h, m, 0).unwrap();
+    assert_eq!(parse_date("Tomorrow", now.date()), NaiveDate::from_ymd_opt(2025, 1, 2));
+    assert_eq!(parse_date("wed", now.date()), NaiveDate::from_ymd_opt(2025, 1, 8));
+    assert_eq!(parse_date("3.1.2025", now.date()), NaiveDate::from_ymd_opt(2025, 1, 3));
+    assert_eq!(parse_time("9am"), NaiveTime::from_hms_opt(9, 0, 0));
+    assert_eq!(parse_time("12:30 am"), NaiveTime::from_hms_opt(0, 30, 0));
+    assert_eq!(parse_time("18:30"), NaiveTime::from_hms_opt(18, 30, 0));
+    assert_eq!(parse_time("13pm"), None);
+    assert_eq!(parse_datetime("2025-01-05 09:00", now), Some(at(5, 9, 0)));
+    assert_eq!(parse_datetime("tomorrow 9am", now), Some(at(2, 9, 0)));
+    assert_eq!(parse_datetime("friday at 18:30", now), Some(at(3, 18, 30)));
+    assert_eq!(parse_datetime("in 2h30m", now), Some(at(1, 14, 30)));
+    assert_eq!(parse_datetime("11:00", now), Some(at(2, 11, 0)));
+    assert_eq!(parse_datetime("2025-01-04", now), Some(at(4, 0, 0)));
+    assert_eq!(parse_datetime("someday", now), None);
+}
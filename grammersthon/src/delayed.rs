@@ -0,0 +1,68 @@
+use std::time::{Duration, SystemTime};
+use chrono::{DateTime, Utc};
+use grammers_client::InputMessage;
+use grammers_client::types::Message;
+use grammers_session::PackedChat;
+use tokio::task::AbortHandle;
+
+use crate::{GrammersthonError, HandlerData};
+
+/// Shorter delays are always sent from the timer queue
+const MIN_NATIVE_DELAY: Duration = Duration::from_secs(10);
+
+/// Message sent by `HandlerData::send_later` or `HandlerData::send_at`
+#[derive(Debug)]
+pub enum DelayedMessage {
+    /// Scheduled message of Telegram (user accounts), kept by Telegram over restarts
+    /// and visible in the scheduled messages of the chat
+    Scheduled(Message),
+    /// Waiting in the timer queue of this process (bots, short delays), lost on restart
+    Queued(AbortHandle),
+}
+
+impl DelayedMessage {
+    /// Cancel the queued message, scheduled messages have to be deleted from the scheduled messages of the chat.
+    /// Returns if the message was cancelled
+    pub fn cancel(&self) -> bool {
+        match self {
+            DelayedMessage::Scheduled(_) => false,
+            DelayedMessage::Queued(handle) => {
+                let pending = !handle.is_finished();
+                handle.abort();
+                pending
+            }
+        }
+    }
+}
+
+impl HandlerData {
+    /// Send message to chat after delay (see `send_at`)
+    pub async fn send_later(&self, chat: impl Into<PackedChat>, message: impl Into<InputMessage>, delay: Duration) -> Result<DelayedMessage, GrammersthonError> {
+        let at = self.clock().system_time() + delay;
+        self.send_scheduled(chat.into(), message.into(), at).await
+    }
+
+    /// Send message to chat at time. User accounts use the scheduled messages of Telegram,
+    /// bots (which can't schedule messages) and delays under 10 seconds the timer queue of this process.
+    /// Queued messages respect the outgoing limiter when sent, failures are logged
+    pub async fn send_at(&self, chat: impl Into<PackedChat>, message: impl Into<InputMessage>, at: impl Into<DateTime<Utc>>) -> Result<DelayedMessage, GrammersthonError> {
+        self.send_scheduled(chat.into(), message.into(), at.into().into()).await
+    }
+
+    async fn send_scheduled(&self, chat: PackedChat, message: InputMessage, at: SystemTime) -> Result<DelayedMessage, GrammersthonError> {
+        let clock = self.clock();
+        let delay = at.duration_since(clock.system_time()).unwrap_or_default();
+        if !self.me.is_bot() && delay >= MIN_NATIVE_DELAY {
+            let sent = self.send_unthreaded(chat, message.schedule_date(Some(at))).await?;
+            return Ok(DelayedMessage::Scheduled(sent));
+        }
+        let data = self.clone();
+        let handle = tokio::spawn(async move {
+            clock.sleep(delay).await;
+            if let Err(e) = data.send_unthreaded(chat, message).await {
+                warn!("Failed sending delayed message to {}: {e}", chat.id);
+            }
+        });
+        Ok(DelayedMessage::Queued(handle.abort_handle()))
+    }
+}
@@ -0,0 +1,238 @@
+//! Collect matching messages per chat and post them as digest on cron schedule
+//!
+//! Usage:
+//! ```ignore
+//! Digest::new("0 0 20 * * *")?
+//!     .filter(filters::photo())
+//!     .chats([chat_id])
+//!     .install(&mut grammersthon);
+//! ```
+//!
+//! Cron expressions include seconds: `sec min hour day month weekday`, times are in UTC.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use grammers_client::{Client, InputMessage};
+use grammers_session::PackedChat;
+use serde::{Serialize, Deserialize};
+
+use crate::{ApiBudget, Grammersthon, GrammersthonError, HandlerData, HandlerFilter, LeaderLock, Priority, Storage};
+use crate::{budget, leader};
+use crate::moderation::message_link;
+
+/// Maximum length of the message text in digest
+const TEXT_LIMIT: usize = 100;
+
+/// Collected messages of a chat
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct DigestState {
+    chat: Vec<u8>,
+    items: Vec<DigestItem>,
+}
+
+/// Single collected message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DigestItem {
+    pub sender: String,
+    pub text: String,
+    pub link: Option<String>,
+}
+
+/// Digest component
+pub struct Digest {
+    schedule: Schedule,
+    filters: Vec<HandlerFilter>,
+    chats: HashSet<i64>,
+    target: Option<PackedChat>,
+    template: String,
+    item_template: String,
+    max_items: usize,
+    key: String,
+    lock: Mutex<()>,
+    budget: Option<ApiBudget>,
+    leader: Option<LeaderLock>,
+}
+
+impl Digest {
+    /// Create new digest posted on cron schedule
+    pub fn new(cron: &str) -> Result<Digest, GrammersthonError> {
+        Ok(Digest {
+            schedule: Schedule::from_str(cron).map_err(|e| GrammersthonError::Parse(cron.to_string(), Some(Box::new(e))))?,
+            filters: vec![],
+            chats: HashSet::new(),
+            target: None,
+            template: "Digest ({count} messages):\n{items}".to_string(),
+            item_template: "• {sender}: {text}".to_string(),
+            max_items: 50,
+            key: "digest".to_string(),
+            lock: Mutex::new(()),
+            budget: None,
+            leader: None,
+        })
+    }
+
+    /// Only collect messages matching filter (all filters have to match)
+    pub fn filter(mut self, filter: HandlerFilter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Only collect from these chats (default: all)
+    pub fn chats(mut self, ids: impl IntoIterator<Item = i64>) -> Self {
+        self.chats.extend(ids);
+        self
+    }
+
+    /// Post all the digests to this chat, instead of the chat they were collected in
+    pub fn post_to(mut self, chat: PackedChat) -> Self {
+        self.target = Some(chat);
+        self
+    }
+
+    /// Digest template, available fields: `{count}`, `{items}`, `{date}`
+    pub fn template(mut self, template: &str) -> Self {
+        self.template = template.to_string();
+        self
+    }
+
+    /// Template of single message, available fields: `{sender}`, `{text}`, `{link}`
+    pub fn item_template(mut self, template: &str) -> Self {
+        self.item_template = template.to_string();
+        self
+    }
+
+    /// Maximum number of messages in digest, the latest are kept (default: 50)
+    pub fn max_items(mut self, max: usize) -> Self {
+        self.max_items = max;
+        self
+    }
+
+    /// Storage key prefix
+    pub fn key(mut self, key: &str) -> Self {
+        self.key = key.to_string();
+        self
+    }
+
+    /// Register the collecting interceptor and start the posting task, install after setting the clock (and `LeaderLock`)
+    pub fn install(mut self, grammersthon: &mut Grammersthon) {
+        self.budget = grammersthon.get_api_budget();
+        self.leader = grammersthon.get_leader_lock();
+        let this = Arc::new(self);
+
+        // Collect messages
+        let digest = this.clone();
+        grammersthon.interceptor(move |data: HandlerData| {
+            let digest = digest.clone();
+            async move {
+                let chat = data.message.chat().id();
+                if (digest.chats.is_empty() || digest.chats.contains(&chat)) && !data.message.text().is_empty() && digest.is_match(&data).await {
+                    if let Err(e) = digest.collect(&data) {
+                        warn!("Failed collecting message for digest: {e}");
+                    }
+                }
+                Ok(data)
+            }
+        });
+
+        // Post digests
+        let client = grammersthon.client();
+        let storage = grammersthon.get_storage();
+        let clock = grammersthon.get_clock();
+        tokio::spawn(async move {
+            loop {
+                let now = DateTime::<Utc>::from(clock.system_time());
+                let next = match this.schedule.after(&now).next() {
+                    Some(next) => next,
+                    None => break
+                };
+                clock.sleep((next - now).to_std().unwrap_or_default()).await;
+                if !leader::is_leader(&this.leader) {
+                    continue;
+                }
+                if let Err(e) = this.post(&client, &storage).await {
+                    error!("Failed posting digest: {e}");
+                }
+            }
+        });
+    }
+
+    /// Do all the filters match
+    async fn is_match(&self, data: &HandlerData) -> bool {
+        HandlerFilter::all_match(&self.filters, &data.message, &[], data).await
+    }
+
+    /// Save message
+    fn collect(&self, data: &HandlerData) -> Result<(), GrammersthonError> {
+        let storage = data.data::<Storage>().unwrap_or_else(Storage::memory);
+        let chat = data.message.chat();
+        let key = format!("{}:{}", self.key, chat.id());
+        let mut text = data.message.text().replace('\n', " ");
+        if let Some((i, _)) = text.char_indices().nth(TEXT_LIMIT) {
+            text.truncate(i);
+            text.push('…');
+        }
+        let item = DigestItem {
+            sender: data.message.sender().map(|s| s.name().to_string()).unwrap_or_default(),
+            text,
+            link: message_link(&chat, data.message.id()),
+        };
+
+        let _lock = self.lock.lock().unwrap();
+        let mut state = storage.get::<DigestState>(&key)?.unwrap_or_default();
+        state.chat = chat.pack().to_bytes().to_vec();
+        state.items.push(item);
+        let start = state.items.len().saturating_sub(self.max_items);
+        state.items.drain(..start);
+        storage.set(&key, &state)
+    }
+
+    /// Post and clear all the collected digests
+    async fn post(&self, client: &Client, storage: &Storage) -> Result<(), GrammersthonError> {
+        for key in storage.keys(&format!("{}:", self.key))? {
+            let state = {
+                let _lock = self.lock.lock().unwrap();
+                let state = storage.get::<DigestState>(&key)?;
+                storage.remove(&key)?;
+                match state {
+                    Some(state) if !state.items.is_empty() => state,
+                    _ => continue,
+                }
+            };
+            let chat = match self.target {
+                Some(chat) => chat,
+                None => PackedChat::from_bytes(&state.chat).map_err(|_| GrammersthonError::Parse("packed chat".to_string(), None))?,
+            };
+            budget::acquire(&self.budget, Priority::Background).await;
+            client.send_message(chat, InputMessage::text(self.render(&state.items))).await?;
+        }
+        Ok(())
+    }
+
+    /// Fill the templates
+    fn render(&self, items: &[DigestItem]) -> String {
+        let lines = items.iter().map(|item| self.item_template
+            .replace("{sender}", &item.sender)
+            .replace("{text}", &item.text)
+            .replace("{link}", item.link.as_deref().unwrap_or(""))
+        ).collect::<Vec<_>>();
+        self.template
+            .replace("{count}", &items.len().to_string())
+            .replace("{date}", &Utc::now().format("%Y-%m-%d").to_string())
+            .replace("{items}", &lines.join("\n"))
+    }
+}
+
+
+/// Test digest templates
+#[test]
+fn test_render_digest() {
+    let digest = Digest::new("0 0 20 * * *").unwrap().template("{count}:\n{items}").item_template("{sender} - {text}");
+    let items = vec![
+        DigestItem { sender: "A".to_string(), text: "hello".to_string(), link: None },
+        DigestItem { sender: "B".to_string(), text: "world".to_string(), link: None },
+    ];
+    assert_eq!(digest.render(&items), "2:\nA - hello\nB - world");
+}
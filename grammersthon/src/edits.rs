@@ -0,0 +1,128 @@
+use grammers_client::types::Message;
+
+use crate::{FromHandlerData, HandlerData};
+
+/// Longer texts (in words) are diffed as whole
+const DIFF_WORDS_LIMIT: usize = 2000;
+
+/// Edited message, for handlers with the `edits` option (`#[handler("", edits, filters::edited())]`):
+/// ```ignore
+/// #[handler("", edits, filters::edited())]
+/// async fn edited(edit: Edit) -> HandlerResult {
+///     if let Some(diff) = edit.diff() {
+///         info!("Message {} edited: {diff:?}", edit.current.id());
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Edit {
+    /// Version before the edit, if it's still cached (requires `Grammersthon::recent_messages` or `track_edits`)
+    pub previous: Option<Message>,
+    pub current: Message,
+}
+
+impl Edit {
+    /// Word diff of the text, None if the previous version isn't known
+    pub fn diff(&self) -> Option<Vec<TextChange>> {
+        Some(text_diff(self.previous.as_ref()?.text(), self.current.text()))
+    }
+}
+
+impl FromHandlerData for Edit {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.edit.clone()
+    }
+}
+
+/// Part of text diff
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextChange {
+    Same(String),
+    Removed(String),
+    Added(String),
+}
+
+impl TextChange {
+    /// Get the text
+    pub fn text(&self) -> &str {
+        match self {
+            TextChange::Same(t) | TextChange::Removed(t) | TextChange::Added(t) => t,
+        }
+    }
+
+    fn text_mut(&mut self) -> &mut String {
+        match self {
+            TextChange::Same(t) | TextChange::Removed(t) | TextChange::Added(t) => t,
+        }
+    }
+
+    fn into_text(self) -> String {
+        match self {
+            TextChange::Same(t) | TextChange::Removed(t) | TextChange::Added(t) => t,
+        }
+    }
+}
+
+/// Diff two texts by words (whitespace is normalized)
+pub fn text_diff(old: &str, new: &str) -> Vec<TextChange> {
+    let old = old.split_whitespace().collect::<Vec<_>>();
+    let new = new.split_whitespace().collect::<Vec<_>>();
+    if old.len() * new.len() > DIFF_WORDS_LIMIT * DIFF_WORDS_LIMIT / 4 {
+        return [TextChange::Removed(old.join(" ")), TextChange::Added(new.join(" "))].into_iter()
+            .filter(|c| !c.text().is_empty())
+            .collect();
+    }
+
+    // Longest common subsequence lengths of suffixes
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    let mut changes = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            push(&mut changes, TextChange::Same(old[i].to_string()));
+            (i, j) = (i + 1, j + 1);
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            push(&mut changes, TextChange::Added(new[j].to_string()));
+            j += 1;
+        } else {
+            push(&mut changes, TextChange::Removed(old[i].to_string()));
+            i += 1;
+        }
+    }
+    changes
+}
+
+/// Add word to diff, joining with the previous change of the same kind
+fn push(changes: &mut Vec<TextChange>, change: TextChange) {
+    match changes.last_mut() {
+        Some(last) if std::mem::discriminant(last) == std::mem::discriminant(&change) => {
+            let text = last.text_mut();
+            text.push(' ');
+            text.push_str(&change.into_text());
+        },
+        _ => changes.push(change),
+    }
+}
+
+
+/// Test word diff
+#[test]
+fn test_text_diff() {
+    use TextChange::*;
+    assert_eq!(text_diff("buy cheap pills now", "buy pills here now"), vec![
+        Same("buy".to_string()), Removed("cheap".to_string()), Same("pills".to_string()), Added("here".to_string()), Same("now".to_string())
+    ]);
+    assert_eq!(text_diff("", "hello world"), vec![Added("hello world".to_string())]);
+    assert_eq!(text_diff("same  text", "same text"), vec![Same("same text".to_string())]);
+    assert!(text_diff("", "").is_empty());
+}
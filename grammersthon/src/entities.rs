@@ -0,0 +1,147 @@
+use grammers_tl_types::enums::MessageEntity;
+
+use crate::{FromHandlerData, HandlerData};
+
+/// Parsed message entities, can be used as handler argument
+#[derive(Debug, Clone, Default)]
+pub struct Entities(pub Vec<Entity>);
+
+/// Single message entity with its text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entity {
+    pub kind: EntityKind,
+    /// Offset in UTF-16 code units (as sent by Telegram)
+    pub offset: usize,
+    /// Length in UTF-16 code units
+    pub length: usize,
+    /// The part of message text covered by the entity
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntityKind {
+    /// `@username`
+    Mention,
+    /// Mention of user without username
+    MentionName(i64),
+    Hashtag,
+    Cashtag,
+    BotCommand,
+    Url,
+    /// Text with hidden URL
+    TextUrl(String),
+    Email,
+    Phone,
+    /// Custom emoji document id
+    CustomEmoji(i64),
+    /// Formatting (bold, code, ...) and others
+    Other
+}
+
+impl Entities {
+    /// Parse entities of message text
+    pub fn parse(text: &str, entities: &[MessageEntity]) -> Entities {
+        let utf16 = text.encode_utf16().collect::<Vec<_>>();
+        Entities(entities.iter().map(|entity| {
+            let (offset, length, kind) = entity_kind(entity);
+            let (offset, length) = (offset.max(0) as usize, length.max(0) as usize);
+            let end = (offset + length).min(utf16.len());
+            let text = String::from_utf16_lossy(&utf16[offset.min(end)..end]);
+            Entity { kind, offset, length, text }
+        }).collect())
+    }
+
+    /// Get all entities of kind (compared without payload)
+    pub fn of_kind(&self, kind: &EntityKind) -> Vec<&Entity> {
+        self.0.iter().filter(|e| std::mem::discriminant(&e.kind) == std::mem::discriminant(kind)).collect()
+    }
+
+    /// `@username` mentions (without the `@`)
+    pub fn mentions(&self) -> Vec<String> {
+        self.of_kind(&EntityKind::Mention).into_iter().map(|e| e.text.trim_start_matches('@').to_string()).collect()
+    }
+
+    /// Hashtags (without the `#`)
+    pub fn hashtags(&self) -> Vec<String> {
+        self.of_kind(&EntityKind::Hashtag).into_iter().map(|e| e.text.trim_start_matches('#').to_string()).collect()
+    }
+
+    /// Bot commands (including the `/`)
+    pub fn bot_commands(&self) -> Vec<String> {
+        self.of_kind(&EntityKind::BotCommand).into_iter().map(|e| e.text.to_string()).collect()
+    }
+
+    /// Plain and hidden URLs
+    pub fn urls(&self) -> Vec<String> {
+        self.0.iter().filter_map(|e| match &e.kind {
+            EntityKind::Url => Some(e.text.to_string()),
+            EntityKind::TextUrl(url) => Some(url.to_string()),
+            _ => None
+        }).collect()
+    }
+
+    /// Ids of users mentioned without username
+    pub fn mentioned_ids(&self) -> Vec<i64> {
+        self.0.iter().filter_map(|e| match e.kind {
+            EntityKind::MentionName(id) => Some(id),
+            _ => None
+        }).collect()
+    }
+
+    /// Custom emoji document ids
+    pub fn custom_emoji(&self) -> Vec<i64> {
+        self.0.iter().filter_map(|e| match e.kind {
+            EntityKind::CustomEmoji(id) => Some(id),
+            _ => None
+        }).collect()
+    }
+}
+
+/// Get offset, length and kind of TL entity
+fn entity_kind(entity: &MessageEntity) -> (i32, i32, EntityKind) {
+    match entity {
+        MessageEntity::Mention(e) => (e.offset, e.length, EntityKind::Mention),
+        MessageEntity::MentionName(e) => (e.offset, e.length, EntityKind::MentionName(e.user_id)),
+        MessageEntity::Hashtag(e) => (e.offset, e.length, EntityKind::Hashtag),
+        MessageEntity::Cashtag(e) => (e.offset, e.length, EntityKind::Cashtag),
+        MessageEntity::BotCommand(e) => (e.offset, e.length, EntityKind::BotCommand),
+        MessageEntity::Url(e) => (e.offset, e.length, EntityKind::Url),
+        MessageEntity::TextUrl(e) => (e.offset, e.length, EntityKind::TextUrl(e.url.to_string())),
+        MessageEntity::Email(e) => (e.offset, e.length, EntityKind::Email),
+        MessageEntity::Phone(e) => (e.offset, e.length, EntityKind::Phone),
+        MessageEntity::CustomEmoji(e) => (e.offset, e.length, EntityKind::CustomEmoji(e.document_id)),
+        MessageEntity::Unknown(e) => (e.offset, e.length, EntityKind::Other),
+        MessageEntity::Bold(e) => (e.offset, e.length, EntityKind::Other),
+        MessageEntity::Italic(e) => (e.offset, e.length, EntityKind::Other),
+        MessageEntity::Code(e) => (e.offset, e.length, EntityKind::Other),
+        MessageEntity::Pre(e) => (e.offset, e.length, EntityKind::Other),
+        MessageEntity::Underline(e) => (e.offset, e.length, EntityKind::Other),
+        MessageEntity::Strike(e) => (e.offset, e.length, EntityKind::Other),
+        MessageEntity::BankCard(e) => (e.offset, e.length, EntityKind::Other),
+        MessageEntity::Spoiler(e) => (e.offset, e.length, EntityKind::Other),
+        MessageEntity::Blockquote(e) => (e.offset, e.length, EntityKind::Other),
+        MessageEntity::InputMessageEntityMentionName(e) => (e.offset, e.length, EntityKind::Other),
+    }
+}
+
+impl FromHandlerData for Entities {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        Some(Entities::parse(data.message.text(), data.message.fmt_entities().map(|e| e.as_slice()).unwrap_or(&[])))
+    }
+}
+
+
+/// Test UTF-16 offsets
+#[test]
+fn test_parse_entities() {
+    use grammers_tl_types::types::{MessageEntityHashtag, MessageEntityMention};
+
+    let text = "😀 hi @user #tag";
+    let entities = Entities::parse(text, &[
+        MessageEntity::Mention(MessageEntityMention { offset: 6, length: 5 }),
+        MessageEntity::Hashtag(MessageEntityHashtag { offset: 12, length: 4 }),
+    ]);
+    assert_eq!(entities.mentions(), vec!["user"]);
+    assert_eq!(entities.hashtags(), vec!["tag"]);
+    assert!(entities.urls().is_empty());
+}
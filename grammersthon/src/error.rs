@@ -1,4 +1,8 @@
+use std::any::Any;
+use std::collections::HashMap;
 use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use grammers_client::client::chats::{AuthorizationError, InvocationError};
 use grammers_client::client::SignInError;
 
@@ -10,6 +14,34 @@ pub enum GrammersthonError {
     SignInError(SignInError),
     InvocationError(InvocationError),
     Unimplemented,
+    /// Handler filters matched, but argument of type couldn't be extracted
+    ExtractorFailed {
+        handler: String,
+        type_name: &'static str
+    },
+    /// Handler has `Data<T>` argument, but the data was never added
+    MissingData {
+        handler: String,
+        type_name: &'static str
+    },
+    /// Processing of the update was cancelled (by interceptor), not passed to error handler
+    Cancelled,
+    /// Waiting for message timed out
+    Timeout,
+    /// Handler panicked, with the panic message
+    HandlerPanic(String),
+    /// Command arguments couldn't be parsed
+    InvalidArgs {
+        /// Field which failed, if known
+        field: Option<String>,
+        /// Usage of the arguments (`FromArgs::usage`), one line for each subcommand
+        usage: Vec<String>,
+        error: Box<GrammersthonError>
+    },
+    /// Required command argument is missing (`named` for `--name` arguments), wrapped in `InvalidArgs` with the field
+    MissingArgument {
+        named: bool
+    },
     Error(Box<dyn std::error::Error + Send + Sync>),
     Parse(String, Option<Box<dyn std::error::Error + Send + Sync>>)
 }
@@ -23,6 +55,25 @@ impl fmt::Display for GrammersthonError {
             GrammersthonError::SignInError(e) => write!(f, "Sign in error: {e}"),
             GrammersthonError::InvocationError(e) => write!(f, "Other error: {e}"),
             GrammersthonError::Unimplemented => write!(f, "Unimplemented"),
+            GrammersthonError::ExtractorFailed { handler, type_name } => write!(f, "Handler {handler} failed extracting argument: {type_name}"),
+            GrammersthonError::MissingData { handler, type_name } => write!(f, "Handler {handler} requires Data<{type_name}>, but it was never added (use add_data)"),
+            GrammersthonError::Cancelled => write!(f, "Cancelled"),
+            GrammersthonError::Timeout => write!(f, "Timed out"),
+            GrammersthonError::HandlerPanic(message) => write!(f, "Handler panicked: {message}"),
+            GrammersthonError::InvalidArgs { field, usage, error } => {
+                match (field, error.as_ref()) {
+                    (Some(field), GrammersthonError::MissingArgument { named: true }) => write!(f, "Missing argument --{field}")?,
+                    (Some(field), GrammersthonError::MissingArgument { named: false }) => write!(f, "Missing argument {field}")?,
+                    (None, e @ GrammersthonError::MissingArgument { .. }) => write!(f, "{e}")?,
+                    (Some(field), _) => write!(f, "Invalid argument {field}: {error}")?,
+                    (None, _) => write!(f, "Invalid arguments: {error}")?,
+                }
+                match usage.is_empty() {
+                    true => Ok(()),
+                    false => write!(f, " (usage: {})", usage.join(" | "))
+                }
+            },
+            GrammersthonError::MissingArgument { .. } => write!(f, "Missing argument"),
             GrammersthonError::Error(e) => write!(f, "{e}"),
             GrammersthonError::Parse(value, e) => match e {
                 Some(e) => write!(f, "Error parsing {value}: {e}"),
@@ -57,10 +108,266 @@ impl From<InvocationError> for GrammersthonError {
     }
 }
 
+impl From<serde_json::Error> for GrammersthonError {
+    fn from(e: serde_json::Error) -> Self {
+        GrammersthonError::Error(Box::new(e))
+    }
+}
+
 impl From<Box<dyn std::error::Error + Send + Sync>> for GrammersthonError {
     fn from(e: Box<dyn std::error::Error + Send + Sync>) -> Self {
         GrammersthonError::Error(e)
     }
 }
 
-impl std::error::Error for GrammersthonError {}
\ No newline at end of file
+/// The original error can be taken back with `downcast` (unless it has context attached)
+#[cfg(feature = "anyhow")]
+impl From<anyhow::Error> for GrammersthonError {
+    fn from(e: anyhow::Error) -> Self {
+        GrammersthonError::Error(e.into())
+    }
+}
+
+impl std::error::Error for GrammersthonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GrammersthonError::IO(e) => Some(e),
+            GrammersthonError::AuthorizationError(e) => Some(e),
+            GrammersthonError::SignInError(e) => Some(e),
+            GrammersthonError::InvocationError(e) => Some(e),
+            GrammersthonError::Error(e) => Some(e.as_ref()),
+            GrammersthonError::InvalidArgs { error, .. } => Some(error.as_ref()),
+            GrammersthonError::Parse(_, Some(e)) => Some(e.as_ref()),
+            _ => None
+        }
+    }
+}
+
+/// Kind of error, for branching on failures without matching the wrapped errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    Io,
+    Authorization,
+    MissingParameters,
+    SignIn,
+    /// Telegram API call failed
+    Invocation,
+    Unimplemented,
+    ExtractorFailed,
+    MissingData,
+    Cancelled,
+    Timeout,
+    HandlerPanic,
+    InvalidArgs,
+    Parse,
+    Other,
+}
+
+impl ErrorKind {
+    /// Stable machine readable code
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::Io => "io",
+            ErrorKind::Authorization => "authorization",
+            ErrorKind::MissingParameters => "missing_parameters",
+            ErrorKind::SignIn => "sign_in",
+            ErrorKind::Invocation => "invocation",
+            ErrorKind::Unimplemented => "unimplemented",
+            ErrorKind::ExtractorFailed => "extractor_failed",
+            ErrorKind::MissingData => "missing_data",
+            ErrorKind::Cancelled => "cancelled",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::HandlerPanic => "handler_panic",
+            ErrorKind::InvalidArgs => "invalid_args",
+            ErrorKind::Parse => "parse",
+            ErrorKind::Other => "other",
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+impl GrammersthonError {
+    /// Get the kind of error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            GrammersthonError::IO(_) => ErrorKind::Io,
+            GrammersthonError::AuthorizationError(_) => ErrorKind::Authorization,
+            GrammersthonError::MissingParameters(_) => ErrorKind::MissingParameters,
+            GrammersthonError::SignInError(_) => ErrorKind::SignIn,
+            GrammersthonError::InvocationError(_) => ErrorKind::Invocation,
+            GrammersthonError::Unimplemented => ErrorKind::Unimplemented,
+            GrammersthonError::ExtractorFailed { .. } => ErrorKind::ExtractorFailed,
+            GrammersthonError::MissingData { .. } => ErrorKind::MissingData,
+            GrammersthonError::Cancelled => ErrorKind::Cancelled,
+            GrammersthonError::Timeout => ErrorKind::Timeout,
+            GrammersthonError::HandlerPanic(_) => ErrorKind::HandlerPanic,
+            GrammersthonError::InvalidArgs { .. } | GrammersthonError::MissingArgument { .. } => ErrorKind::InvalidArgs,
+            GrammersthonError::Parse(..) => ErrorKind::Parse,
+            // Wrapped GrammersthonError keeps its kind
+            GrammersthonError::Error(e) => match e.downcast_ref::<GrammersthonError>() {
+                Some(e) => e.kind(),
+                None => ErrorKind::Other
+            },
+        }
+    }
+
+    /// Wrap any error
+    pub fn other(e: impl std::error::Error + Send + Sync + 'static) -> GrammersthonError {
+        GrammersthonError::Error(Box::new(e))
+    }
+
+    /// Wrap domain error of handler, so error handler can get it back with `downcast_ref`:
+    /// ```ignore
+    /// async fn error_handler(error: GrammersthonError, ctx: ErrorContext) -> HandlerResult {
+    ///     if let Some(ShopError::OutOfStock(item)) = error.downcast_ref::<ShopError>() { ... }
+    /// }
+    /// ```
+    pub fn custom<E: std::error::Error + Send + Sync + 'static>(e: E) -> GrammersthonError {
+        GrammersthonError::other(e)
+    }
+
+    /// Get the wrapped error of type (`custom`, `other`, or cause of `Parse` and `InvalidArgs`)
+    pub fn downcast_ref<E: std::error::Error + 'static>(&self) -> Option<&E> {
+        match self {
+            GrammersthonError::Error(e) => match e.downcast_ref::<E>() {
+                Some(e) => Some(e),
+                None => e.downcast_ref::<GrammersthonError>()?.downcast_ref(),
+            },
+            GrammersthonError::Parse(_, Some(e)) => e.downcast_ref(),
+            GrammersthonError::InvalidArgs { error, .. } => error.downcast_ref(),
+            _ => None
+        }
+    }
+
+    /// Whether the wrapped error is of type
+    pub fn is<E: std::error::Error + 'static>(&self) -> bool {
+        self.downcast_ref::<E>().is_some()
+    }
+
+    /// Take the wrapped error of type (`custom`, `other`), or get self back
+    pub fn downcast<E: std::error::Error + 'static>(self) -> Result<E, GrammersthonError> {
+        match self {
+            GrammersthonError::Error(e) => match e.downcast::<E>() {
+                Ok(e) => Ok(*e),
+                Err(e) => Err(GrammersthonError::Error(e)),
+            },
+            e => Err(e)
+        }
+    }
+
+    /// Create from payload of panic
+    pub(crate) fn panic(payload: &(dyn Any + Send)) -> GrammersthonError {
+        let message = payload.downcast_ref::<&str>().map(|m| m.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        GrammersthonError::HandlerPanic(message)
+    }
+}
+
+/// Identical errors without log for this long are logged as new
+const ERROR_REPEAT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Repeated error state
+struct Repeated {
+    count: u64,
+    /// Count at the last log
+    logged: u64,
+    last: Instant,
+}
+
+/// Deduplicates repeated identical errors, so a broken handler doesn't flood the logs.
+/// Repeats are logged with exponential suppression (1st, 2nd, 4th, 8th... occurrence)
+/// with the number of suppressed ones, until the error stops repeating for a minute.
+#[derive(Default)]
+pub struct ErrorLog {
+    errors: Mutex<HashMap<String, Repeated>>,
+}
+
+impl ErrorLog {
+    /// Record occurrence of error, returns the message to log or None if it should be suppressed
+    pub fn record(&self, message: &str) -> Option<String> {
+        let now = Instant::now();
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() > 256 {
+            errors.retain(|_, r| now - r.last < ERROR_REPEAT_WINDOW);
+        }
+        let entry = errors.entry(message.to_string()).or_insert(Repeated { count: 0, logged: 0, last: now });
+
+        // Wasn't repeating for a while, start over
+        let suppressed = entry.count - entry.logged;
+        if now - entry.last >= ERROR_REPEAT_WINDOW {
+            *entry = Repeated { count: 0, logged: 0, last: now };
+        }
+        entry.count += 1;
+        entry.last = now;
+        if entry.count != 1 && !entry.count.is_power_of_two() {
+            return None;
+        }
+        entry.logged = entry.count;
+        match suppressed {
+            0 => Some(message.to_string()),
+            n => Some(format!("{message} (previous error repeated {n} times)"))
+        }
+    }
+
+    /// Log the error with `error!` unless suppressed
+    pub fn log(&self, message: &str) {
+        if let Some(message) = self.record(message) {
+            error!("{message}");
+        }
+    }
+}
+
+
+/// Test repeated error suppression
+#[test]
+fn test_error_log() {
+    let log = ErrorLog::default();
+    let logged = (0..10).map(|_| log.record("error")).collect::<Vec<_>>();
+    assert_eq!(logged[0].as_deref(), Some("error"));
+    assert_eq!(logged[1].as_deref(), Some("error"));
+    assert_eq!(logged[2], None);
+    assert_eq!(logged[3].as_deref(), Some("error (previous error repeated 1 times)"));
+    assert_eq!(logged[7].as_deref(), Some("error (previous error repeated 3 times)"));
+    assert_eq!(log.record("other").as_deref(), Some("other"));
+}
+
+/// Test error kinds and sources
+#[test]
+fn test_error_kind() {
+    use std::error::Error;
+    let e = GrammersthonError::Parse("x".to_string(), Some(Box::new(std::io::Error::other("inner"))));
+    assert_eq!(e.kind(), ErrorKind::Parse);
+    assert_eq!(e.source().map(|e| e.to_string()), Some("inner".to_string()));
+    let e = GrammersthonError::other(GrammersthonError::Timeout);
+    assert_eq!(e.kind().code(), "timeout");
+    assert!(GrammersthonError::Cancelled.source().is_none());
+}
+
+/// Test panic messages
+#[test]
+fn test_panic_error() {
+    let e = std::panic::catch_unwind(|| panic!("handler {} failed", 1)).unwrap_err();
+    assert_eq!(GrammersthonError::panic(&*e).to_string(), "Handler panicked: handler 1 failed");
+    let e = std::panic::catch_unwind(|| std::panic::panic_any(5)).unwrap_err();
+    assert_eq!(GrammersthonError::panic(&*e).kind(), ErrorKind::HandlerPanic);
+}
+
+/// Test downcasting wrapped errors
+#[test]
+fn test_downcast() {
+    let e = GrammersthonError::custom(std::fmt::Error);
+    assert!(e.is::<std::fmt::Error>());
+    assert!(e.downcast_ref::<std::io::Error>().is_none());
+    let e = GrammersthonError::InvalidArgs { field: None, usage: vec![], error: Box::new(GrammersthonError::other(std::fmt::Error)) };
+    assert!(e.is::<std::fmt::Error>());
+    let e = GrammersthonError::other(GrammersthonError::custom(std::fmt::Error));
+    assert!(e.downcast_ref::<std::fmt::Error>().is_some());
+    assert!(GrammersthonError::custom(std::fmt::Error).downcast::<std::fmt::Error>().is_ok());
+    assert!(matches!(GrammersthonError::Timeout.downcast::<std::fmt::Error>(), Err(GrammersthonError::Timeout)));
+}
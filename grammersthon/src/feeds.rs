@@ -0,0 +1,198 @@
+//! Post new items of RSS / Atom feeds to chats (requires `rss` feature)
+//!
+//! Usage:
+//! ```ignore
+//! FeedIngest::new()
+//!     .feed(Feed::new("https://blog.rust-lang.org/feed.xml").chat(channel.pack()))
+//!     .interval(Duration::from_secs(600))
+//!     .install(&mut grammersthon);
+//! ```
+//!
+//! Seen item ids are kept in `Storage`, so items aren't reposted after restart.
+
+use std::time::Duration;
+use grammers_client::{Client, InputMessage};
+use grammers_session::PackedChat;
+
+use crate::{ApiBudget, Grammersthon, GrammersthonError, LeaderLock, Priority, Storage};
+use crate::{budget, leader};
+
+/// How many seen item ids are kept per feed
+const SEEN_LIMIT: usize = 1000;
+
+/// Single feed and where to post it
+#[derive(Debug, Clone)]
+pub struct Feed {
+    url: String,
+    chats: Vec<PackedChat>,
+    template: String,
+}
+
+impl Feed {
+    /// Create new feed from URL
+    pub fn new(url: &str) -> Feed {
+        Feed {
+            url: url.to_string(),
+            chats: vec![],
+            template: "{title}\n{link}".to_string(),
+        }
+    }
+
+    /// Post items of this feed to chat
+    pub fn chat(mut self, chat: PackedChat) -> Self {
+        self.chats.push(chat);
+        self
+    }
+
+    /// Message template, available fields: `{title}`, `{link}`, `{summary}`, `{feed}`
+    pub fn template(mut self, template: &str) -> Self {
+        self.template = template.to_string();
+        self
+    }
+}
+
+/// Feed item
+#[derive(Debug, Clone, Default)]
+pub struct FeedItem {
+    pub id: String,
+    pub title: String,
+    pub link: String,
+    pub summary: String,
+    /// Title of the feed
+    pub feed: String,
+}
+
+impl FeedItem {
+    /// Fill the template
+    fn render(&self, template: &str) -> String {
+        template
+            .replace("{title}", &self.title)
+            .replace("{link}", &self.link)
+            .replace("{summary}", &self.summary)
+            .replace("{feed}", &self.feed)
+    }
+}
+
+/// Feed polling component
+#[derive(Debug, Clone)]
+pub struct FeedIngest {
+    feeds: Vec<Feed>,
+    interval: Duration,
+    post_existing: bool,
+    key: String,
+    budget: Option<ApiBudget>,
+    leader: Option<LeaderLock>,
+}
+
+impl FeedIngest {
+    /// Create new instance with default settings (10 minute interval)
+    pub fn new() -> FeedIngest {
+        FeedIngest {
+            feeds: vec![],
+            interval: Duration::from_secs(600),
+            post_existing: false,
+            key: "feeds".to_string(),
+            budget: None,
+            leader: None,
+        }
+    }
+
+    /// Add feed
+    pub fn feed(mut self, feed: Feed) -> Self {
+        self.feeds.push(feed);
+        self
+    }
+
+    /// How often are the feeds polled
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Post items which were already in the feed when it was first polled (default: false)
+    pub fn post_existing(mut self, post: bool) -> Self {
+        self.post_existing = post;
+        self
+    }
+
+    /// Storage key prefix for the seen items
+    pub fn key(mut self, key: &str) -> Self {
+        self.key = key.to_string();
+        self
+    }
+
+    /// Start the polling task, install after setting `LeaderLock` (if used)
+    pub fn install(mut self, grammersthon: &mut Grammersthon) {
+        self.budget = grammersthon.get_api_budget();
+        self.leader = grammersthon.get_leader_lock();
+        let client = grammersthon.client();
+        let storage = grammersthon.get_storage();
+        tokio::spawn(async move {
+            loop {
+                for feed in self.feeds.iter().filter(|_| leader::is_leader(&self.leader)) {
+                    if let Err(e) = self.poll(&client, &storage, feed).await {
+                        warn!("Failed polling feed {}: {e}", feed.url);
+                    }
+                }
+                tokio::time::sleep(self.interval).await;
+            }
+        });
+    }
+
+    /// Post new items of feed
+    async fn poll(&self, client: &Client, storage: &Storage, feed: &Feed) -> Result<(), GrammersthonError> {
+        let key = format!("{}:{}", self.key, feed.url);
+        let seen = storage.get::<Vec<String>>(&key)?;
+        let first = seen.is_none();
+        let mut seen = seen.unwrap_or_default();
+
+        // Oldest first
+        let mut items = fetch(&feed.url).await?;
+        items.reverse();
+        for item in items {
+            if seen.contains(&item.id) {
+                continue;
+            }
+            if !first || self.post_existing {
+                let text = item.render(&feed.template);
+                for chat in &feed.chats {
+                    budget::acquire(&self.budget, Priority::Background).await;
+                    client.send_message(*chat, InputMessage::text(&text)).await?;
+                }
+            }
+            seen.push(item.id);
+            // Save after every item, so it isn't reposted if the next one fails
+            let start = seen.len().saturating_sub(SEEN_LIMIT);
+            storage.set(&key, &seen[start..])?;
+        }
+        if first {
+            storage.set(&key, &seen)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for FeedIngest {
+    fn default() -> Self {
+        FeedIngest::new()
+    }
+}
+
+/// Download and parse the feed
+pub async fn fetch(url: &str) -> Result<Vec<FeedItem>, GrammersthonError> {
+    let data = reqwest::get(url).await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| GrammersthonError::Error(Box::new(e)))?
+        .bytes().await
+        .map_err(|e| GrammersthonError::Error(Box::new(e)))?;
+    let feed = feed_rs::parser::parse(&data[..])
+        .map_err(|e| GrammersthonError::Parse(url.to_string(), Some(Box::new(e))))?;
+    let title = feed.title.map(|t| t.content).unwrap_or_default();
+    Ok(feed.entries.into_iter().map(|entry| FeedItem {
+        link: entry.links.first().map(|l| l.href.to_string()).unwrap_or_default(),
+        title: entry.title.map(|t| t.content).unwrap_or_default(),
+        summary: entry.summary.map(|t| t.content).unwrap_or_default(),
+        feed: title.clone(),
+        id: entry.id,
+    }).collect())
+}
@@ -0,0 +1,318 @@
+//! Built-in handler filters
+//! 
+//! Usage:
+//! ```ignore
+//! #[handler("^/ban", filters::admin_only())]
+//! ```
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use grammers_client::types::{Chat, Media};
+use grammers_session::PackedChat;
+
+use crate::{HandlerFilter, CommandScope, Entities, Fsm, FromHandlerData, Tenant};
+use crate::media;
+
+/// (chat, user) -> is admin
+type AdminCache = Arc<Mutex<HashMap<(i64, i64), (bool, Instant)>>>;
+/// (chat, sender, text hash) -> last seen
+type SeenCache = Arc<Mutex<HashMap<(i64, i64, u64), Instant>>>;
+/// Chat -> times of the recent matches
+type RateCache = Arc<Mutex<HashMap<i64, VecDeque<Instant>>>>;
+
+/// For how long are the admin checks cached
+pub(crate) const ADMIN_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+/// Maximum cached admin checks, expired ones are removed when reached
+const ADMIN_CACHE_SIZE: usize = 10_000;
+
+/// Sender is an admin or creator of the group/channel.
+/// The result of the permission check is cached per chat and user.
+pub fn admin_only() -> HandlerFilter {
+    let cache: AdminCache = Arc::new(Mutex::new(HashMap::new()));
+    HandlerFilter::async_fn(move |message, data| {
+        let cache = cache.clone();
+        async move {
+            let chat = message.chat();
+            let sender = match message.sender() {
+                Some(sender) => sender,
+                None => return false
+            };
+            // Private chats don't have admins
+            if let Chat::User(_) = chat {
+                return false;
+            }
+
+            // Cached
+            let key = (chat.id(), sender.id());
+            if let Some((admin, time)) = cache.lock().unwrap().get(&key) {
+                if time.elapsed() < ADMIN_CACHE_TTL {
+                    return *admin;
+                }
+            }
+
+            let admin = match data.client.get_permissions(&chat, &sender).await {
+                Ok(permissions) => permissions.is_admin() || permissions.is_creator(),
+                Err(e) => {
+                    warn!("Failed checking permissions of {} in {}: {e}", sender.id(), chat.id());
+                    false
+                }
+            };
+            let mut cache = cache.lock().unwrap();
+            if cache.len() >= ADMIN_CACHE_SIZE {
+                cache.retain(|_, (_, time)| time.elapsed() < ADMIN_CACHE_TTL);
+                // Still full of fresh checks
+                if cache.len() >= ADMIN_CACHE_SIZE {
+                    cache.clear();
+                }
+            }
+            cache.insert(key, (admin, Instant::now()));
+            admin
+        }
+    }).with_scope(CommandScope::GroupAdmins)
+}
+
+/// Message was sent in private chat
+pub fn private() -> HandlerFilter {
+    HandlerFilter::func(|message, _| matches!(message.chat(), Chat::User(_))).with_scope(CommandScope::PrivateChats)
+}
+
+/// Message was sent in a group
+pub fn groups() -> HandlerFilter {
+    HandlerFilter::func(|message, _| matches!(message.chat(), Chat::Group(_))).with_scope(CommandScope::Groups)
+}
+
+/// Message was sent by one of the users (by id)
+pub fn from_users(ids: impl IntoIterator<Item = i64>) -> HandlerFilter {
+    let ids = ids.into_iter().collect::<HashSet<_>>();
+    HandlerFilter::func(move |message, _| message.sender().map(|s| ids.contains(&s.id())).unwrap_or(false))
+}
+
+/// Message was sent by the owner, the command is shown only in the owner's command menu and help
+pub fn owner_only(owner: PackedChat) -> HandlerFilter {
+    HandlerFilter::func(move |message, _| message.sender().map(|s| s.id() == owner.id).unwrap_or(false))
+        .with_scope(CommandScope::Chat(owner))
+}
+
+/// Message wasn't sent by any of the users (by id)
+pub fn except_users(ids: impl IntoIterator<Item = i64>) -> HandlerFilter {
+    let ids = ids.into_iter().collect::<HashSet<_>>();
+    HandlerFilter::func(move |message, _| !message.sender().map(|s| ids.contains(&s.id())).unwrap_or(false))
+}
+
+/// Message was sent in one of the chats (by id)
+pub fn in_chats(ids: impl IntoIterator<Item = i64>) -> HandlerFilter {
+    let ids = ids.into_iter().collect::<HashSet<_>>();
+    HandlerFilter::func(move |message, _| ids.contains(&message.chat().id()))
+}
+
+/// Message wasn't sent in any of the chats (by id)
+pub fn except_chats(ids: impl IntoIterator<Item = i64>) -> HandlerFilter {
+    let ids = ids.into_iter().collect::<HashSet<_>>();
+    HandlerFilter::func(move |message, _| !ids.contains(&message.chat().id()))
+}
+
+/// Message was sent by the logged in account
+pub fn outgoing() -> HandlerFilter {
+    HandlerFilter::func(|message, _| message.outgoing())
+}
+
+/// Message was sent by someone else
+pub fn incoming() -> HandlerFilter {
+    HandlerFilter::func(|message, _| !message.outgoing())
+}
+
+/// Message mentions the logged in account (by `@username` or mention name entity)
+pub fn mentions_me() -> HandlerFilter {
+    HandlerFilter::func(|message, data| {
+        if message.mentioned() {
+            return true;
+        }
+        let entities = Entities::parse(message.text(), message.fmt_entities().map(|e| e.as_slice()).unwrap_or(&[]));
+        if entities.mentioned_ids().contains(&data.me.id()) {
+            return true;
+        }
+        match data.me.username() {
+            Some(username) => entities.mentions().iter().any(|m| m.eq_ignore_ascii_case(username)),
+            None => false
+        }
+    })
+}
+
+/// Message is a reply to message sent by the logged in account
+pub fn reply_to_me() -> HandlerFilter {
+    HandlerFilter::async_fn(|message, data| async move {
+        if message.reply_to_message_id().is_none() {
+            return false;
+        }
+        match message.get_reply().await {
+            Ok(Some(reply)) => reply.sender().map(|s| s.id() == data.me.id()).unwrap_or(false),
+            Ok(None) => false,
+            Err(e) => {
+                warn!("Failed getting reply of message {}: {e}", message.id());
+                false
+            }
+        }
+    })
+}
+
+/// Message was edited (for handlers with the `edits` option)
+pub fn edited() -> HandlerFilter {
+    HandlerFilter::func(|_, data| data.edit.is_some())
+}
+
+/// Message is a comment on channel post, in the linked discussion group (enables the `Comment` extractor)
+pub fn is_comment() -> HandlerFilter {
+    HandlerFilter::async_fn(|message, data| async move {
+        match data.comment().await {
+            Ok(comment) => comment.is_some(),
+            Err(e) => {
+                warn!("Failed getting thread of message {}: {e}", message.id());
+                false
+            }
+        }
+    })
+}
+
+/// Don't match identical text from the same sender in the same chat within `window`.
+/// The message is recorded only once all the other filters matched and the arguments were extracted
+pub fn dedup(window: Duration) -> HandlerFilter {
+    let seen: SeenCache = Arc::new(Mutex::new(HashMap::new()));
+    HandlerFilter::claim(move |message, data| {
+        if message.text().is_empty() {
+            return Some(Box::new(|| {}));
+        }
+        let mut hasher = DefaultHasher::new();
+        message.text().hash(&mut hasher);
+        let chat = message.chat().id();
+        let key = (chat, message.sender().map(|s| s.id()).unwrap_or(chat), hasher.finish());
+        dedup_claim(&seen, key, data.clock().now(), window)
+    })
+}
+
+/// Record the key, None if it was seen within `window`. The release forgets it again
+fn dedup_claim(seen: &SeenCache, key: (i64, i64, u64), now: Instant, window: Duration) -> Option<Box<dyn FnOnce() + Send>> {
+    let mut cache = seen.lock().unwrap();
+    if cache.len() > 1024 {
+        cache.retain(|_, time| now - *time < window);
+    }
+    if let Some(time) = cache.get(&key) {
+        if now - *time < window {
+            debug!("Ignoring duplicate message in chat {}", key.0);
+            return None;
+        }
+    }
+    cache.insert(key, now);
+    let seen = seen.clone();
+    Some(Box::new(move || {
+        let mut cache = seen.lock().unwrap();
+        if cache.get(&key) == Some(&now) {
+            cache.remove(&key);
+        }
+    }))
+}
+
+/// Sender is the owner or admin of the tenant (see `Tenant`)
+pub fn tenant_admin() -> HandlerFilter {
+    HandlerFilter::func(|message, data| {
+        let sender = match message.sender() {
+            Some(sender) => sender.id(),
+            None => return false
+        };
+        match Tenant::from_data(data).map(|t| t.is_admin(sender)) {
+            Some(Ok(admin)) => admin,
+            Some(Err(e)) => {
+                warn!("Failed checking tenant admins of {}: {e}", message.chat().id());
+                false
+            },
+            None => false
+        }
+    })
+}
+
+/// At most `max` matches per tenant (chat) within `window`, each tenant has its own limit
+pub fn tenant_rate_limit(max: usize, window: Duration) -> HandlerFilter {
+    let hits: RateCache = Arc::new(Mutex::new(HashMap::new()));
+    HandlerFilter::func(move |message, data| {
+        let now = data.clock().now();
+        let mut hits = hits.lock().unwrap();
+        if hits.len() > 1024 {
+            hits.retain(|_, times| times.back().map(|t| now - *t < window).unwrap_or(false));
+        }
+        let times = hits.entry(message.chat().id()).or_default();
+        while times.front().map(|t| now - *t >= window).unwrap_or(false) {
+            times.pop_front();
+        }
+        if times.len() >= max {
+            debug!("Rate limit of tenant {} reached", message.chat().id());
+            return false;
+        }
+        times.push_back(now);
+        true
+    })
+}
+
+/// Sender is in the FSM state (see `Fsm`)
+pub fn state(state: &str) -> HandlerFilter {
+    let state = state.to_string();
+    HandlerFilter::func(move |_, data| {
+        Fsm::from_data(data)
+            .and_then(|fsm| fsm.state().ok().flatten())
+            .map(|s| s == state)
+            .unwrap_or(false)
+    })
+}
+
+/// Message contains a photo
+pub fn photo() -> HandlerFilter {
+    HandlerFilter::func(|message, _| message.photo().is_some())
+}
+
+/// Message contains a document (any file, including voice notes and videos)
+pub fn document() -> HandlerFilter {
+    HandlerFilter::func(|message, _| matches!(message.media(), Some(Media::Document(_))))
+}
+
+/// Message contains a sticker
+pub fn sticker() -> HandlerFilter {
+    HandlerFilter::func(|message, _| matches!(message.media(), Some(Media::Sticker(_))))
+}
+
+/// Message contains a voice note
+pub fn voice() -> HandlerFilter {
+    HandlerFilter::func(|message, _| matches!(message.media(), Some(Media::Document(d)) if media::is_voice(&d)))
+}
+
+/// Message contains a video
+pub fn video() -> HandlerFilter {
+    HandlerFilter::func(|message, _| matches!(message.media(), Some(Media::Document(d)) if media::is_video(&d)))
+}
+
+/// Message contains an audio file (not a voice note)
+pub fn audio() -> HandlerFilter {
+    HandlerFilter::func(|message, _| matches!(message.media(), Some(Media::Document(d)) if media::is_audio(&d)))
+}
+
+
+/// Test that dedup rejects the same text within the window, per chat
+#[test]
+fn test_dedup_claim() {
+    use crate::{Clock, ManualClock};
+    use std::time::SystemTime;
+
+    let clock = ManualClock::new(SystemTime::UNIX_EPOCH);
+    let seen: SeenCache = Arc::new(Mutex::new(HashMap::new()));
+    let window = Duration::from_secs(60);
+    assert!(dedup_claim(&seen, (1, 2, 3), clock.now(), window).is_some());
+    assert!(dedup_claim(&seen, (1, 2, 3), clock.now(), window).is_none());
+    // Different chat
+    assert!(dedup_claim(&seen, (4, 2, 3), clock.now(), window).is_some());
+    clock.advance(Duration::from_secs(61));
+    assert!(dedup_claim(&seen, (1, 2, 3), clock.now(), window).is_some());
+    // Released claim (handler wasn't selected) doesn't block the next message
+    let release = dedup_claim(&seen, (5, 2, 3), clock.now(), window).unwrap();
+    release();
+    assert!(dedup_claim(&seen, (5, 2, 3), clock.now(), window).is_some());
+}
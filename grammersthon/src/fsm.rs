@@ -0,0 +1,101 @@
+use grammers_client::types::Message;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{FromHandlerData, GrammersthonError, HandlerData, Storage};
+
+/// Prefix of the state keys in storage
+pub(crate) const FSM_KEY: &str = "fsm";
+
+/// Saved state with its data
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct FsmEntry {
+    state: Option<String>,
+    data: Value,
+}
+
+/// State of the user in chat, kept in `Storage`.
+///
+/// Usage:
+/// ```ignore
+/// #[handler("^/order")]
+/// async fn order(message: Message, fsm: Fsm) -> HandlerResult {
+///     fsm.set_state("order:address")?;
+///     message.reply("Send your address").await?;
+///     Ok(())
+/// }
+///
+/// #[handler(filters::state("order:address"))]
+/// async fn address(message: Message, fsm: Fsm) -> HandlerResult {
+///     fsm.set_data(&message.text())?;
+///     fsm.clear()?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Fsm {
+    storage: Storage,
+    chat: i64,
+    user: i64,
+}
+
+impl Fsm {
+    /// Create new handle for user in chat
+    pub fn new(storage: Storage, chat: i64, user: i64) -> Fsm {
+        Fsm { storage, chat, user }
+    }
+
+    /// Create new handle for sender of the message (chat for anonymous senders)
+    pub fn for_message(storage: Storage, message: &Message) -> Fsm {
+        let chat = message.chat().id();
+        Fsm::new(storage, chat, message.sender().map(|s| s.id()).unwrap_or(chat))
+    }
+
+    fn key(&self) -> String {
+        format!("{FSM_KEY}:{}:{}", self.chat, self.user)
+    }
+
+    fn entry(&self) -> Result<FsmEntry, GrammersthonError> {
+        Ok(self.storage.get::<FsmEntry>(&self.key())?.unwrap_or_default())
+    }
+
+    /// Get the current state
+    pub fn state(&self) -> Result<Option<String>, GrammersthonError> {
+        Ok(self.entry()?.state)
+    }
+
+    /// Change the state, keeps the data
+    pub fn set_state(&self, state: &str) -> Result<(), GrammersthonError> {
+        let mut entry = self.entry()?;
+        entry.state = Some(state.to_string());
+        self.storage.set(&self.key(), &entry)
+    }
+
+    /// Get the data of the state
+    pub fn data<T: DeserializeOwned>(&self) -> Result<Option<T>, GrammersthonError> {
+        match self.entry()?.data {
+            Value::Null => Ok(None),
+            data => Ok(Some(serde_json::from_value(data)?)),
+        }
+    }
+
+    /// Replace the data of the state
+    pub fn set_data<T: Serialize + ?Sized>(&self, data: &T) -> Result<(), GrammersthonError> {
+        let mut entry = self.entry()?;
+        entry.data = serde_json::to_value(data)?;
+        self.storage.set(&self.key(), &entry)
+    }
+
+    /// Remove the state and its data
+    pub fn clear(&self) -> Result<(), GrammersthonError> {
+        self.storage.remove(&self.key())?;
+        Ok(())
+    }
+}
+
+impl FromHandlerData for Fsm {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        Some(Fsm::for_message(Storage::from_data(data)?, &data.message))
+    }
+}
@@ -0,0 +1,226 @@
+//! Detection of missed updates by gaps in the message id sequences (grammers doesn't expose the pts of updates)
+//!
+//! Usage:
+//! ```ignore
+//! UpdateGaps::new()
+//!     .on_gap(|client: Client, gap: UpdateGap| async move {
+//!         warn!("Missed messages {:?} in {:?}", gap.missing, gap.channel);
+//!         Ok(())
+//!     })
+//!     .install(&mut grammersthon);
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use grammers_client::{Client, Update};
+use grammers_session::PackedChat;
+use tokio::sync::mpsc;
+use trait_bound_typemap::TypeMap;
+
+use crate::{Data, Grammersthon, HandlerResult};
+
+/// Received ids kept below the last id of sequence, to tell late and duplicate messages apart
+const RECEIVED_WINDOW: i32 = 1000;
+
+type GapCallbackFn = dyn Fn(Client, UpdateGap) -> Pin<Box<dyn Future<Output = HandlerResult> + Send + Sync>> + Send + Sync;
+
+/// Missing message ids between two received messages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpdateGap {
+    /// Channel or supergroup of the gap, None for the account-wide sequence (private chats and small groups)
+    pub channel: Option<i64>,
+    /// Missing ids
+    pub missing: Range<i32>,
+}
+
+/// Update gap detection. Message ids are sequential in each channel and supergroup, elsewhere per account.
+/// On gap, the callbacks are called and the missing messages are fetched and dispatched (`resync`).
+/// Ids are recorded by the event loop in the order the updates are received, messages received again
+/// (late resync, duplicates) are not dispatched twice. Messages sent through the `HandlerData` helpers don't come
+/// back as updates, so their ids are recorded when sent, and own messages are never dispatched by resync.
+/// Meant for user accounts, bots don't receive all messages in groups (privacy mode, other bots),
+/// which would be reported as gaps. Deleted messages can't be told apart from missed ones, they are just not dispatched by resync
+#[derive(Clone)]
+pub struct UpdateGaps {
+    resync: bool,
+    max_resync: usize,
+    callbacks: Vec<Arc<GapCallbackFn>>,
+    /// Sequence (channel id or 0) -> received ids
+    sequences: Arc<Mutex<HashMap<i64, Sequence>>>,
+}
+
+/// Received ids of sequence
+#[derive(Debug, Clone, Default)]
+struct Sequence {
+    last: i32,
+    /// Received ids within `RECEIVED_WINDOW` below the last
+    received: BTreeSet<i32>,
+}
+
+/// Result of recording id
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Recorded {
+    /// Id was already received
+    Duplicate,
+    /// New id, with the gap before it
+    New(Option<UpdateGap>),
+}
+
+impl UpdateGaps {
+    /// Create new instance with resync enabled
+    pub fn new() -> UpdateGaps {
+        UpdateGaps { resync: true, max_resync: 50, callbacks: vec![], sequences: Default::default() }
+    }
+
+    /// Fetch and dispatch the missing messages (default true)
+    pub fn resync(mut self, resync: bool) -> Self {
+        self.resync = resync;
+        self
+    }
+
+    /// Maximum messages fetched per gap, only the latest ones of larger gaps are resynced (default 50)
+    pub fn max_resync(mut self, max: usize) -> Self {
+        self.max_resync = max;
+        self
+    }
+
+    /// Called on every detected gap
+    pub fn on_gap<H, F>(mut self, callback: H) -> Self
+    where
+        H: (Fn(Client, UpdateGap) -> F) + Send + Sync + 'static,
+        F: Future<Output = HandlerResult> + Send + Sync + 'static
+    {
+        self.callbacks.push(Arc::new(move |c, g| Box::pin(callback(c, g))));
+        self
+    }
+
+    /// Enable the gap detection in the event loop
+    pub fn install(self, grammersthon: &mut Grammersthon) {
+        grammersthon.data.insert::<Data<UpdateGaps>>(self);
+    }
+
+    /// Record update received by the event loop, returns false if it was already received.
+    /// Gaps are reported and resynced (if `resync`) in background
+    pub(crate) fn observe(&self, client: &Client, update: &Update, sender: &mpsc::UnboundedSender<Update>, resync: bool) -> bool {
+        let message = match update {
+            Update::NewMessage(message) => message,
+            _ => return true,
+        };
+        let chat = message.chat();
+        let channel = chat.pack().is_channel().then(|| chat.id());
+        let gap = match self.record(channel, message.id()) {
+            Recorded::Duplicate => return false,
+            Recorded::New(Some(gap)) => gap,
+            Recorded::New(None) => return true,
+        };
+        warn!("Update gap detected, missed messages {:?} (channel: {:?})", gap.missing, gap.channel);
+        let (this, client, sender, chat) = (self.clone(), client.clone(), sender.clone(), chat.pack());
+        tokio::spawn(async move {
+            for callback in &this.callbacks {
+                if let Err(e) = (*callback)(client.clone(), gap.clone()).await {
+                    warn!("Update gap callback failed: {e}");
+                }
+            }
+            if !(this.resync && resync) {
+                return;
+            }
+            // Skip messages which arrived late in the meantime
+            let ids = gap.missing.clone().rev()
+                .filter(|id| !this.is_received(gap.channel, *id))
+                .take(this.max_resync)
+                .collect::<Vec<_>>();
+            if ids.is_empty() {
+                return;
+            }
+            // Ids outside channels are account-wide, so any chat works for fetching
+            match client.get_messages_by_id(chat, &ids).await {
+                Ok(messages) => for message in messages.into_iter().flatten().rev().filter(|m| !m.outgoing()) {
+                    let _ = sender.send(Update::NewMessage(message));
+                },
+                Err(e) => warn!("Failed resyncing missed messages {:?}: {e}", gap.missing),
+            }
+        });
+        true
+    }
+
+    /// Record id of message sent by the account, it doesn't come back as update
+    pub(crate) fn sent(&self, chat: PackedChat, id: i32) {
+        let channel = chat.is_channel().then_some(chat.id);
+        let mut sequences = self.sequences.lock().unwrap();
+        if let Some(sequence) = sequences.get_mut(&channel.unwrap_or(0)) {
+            if id > sequence.last - RECEIVED_WINDOW {
+                sequence.received.insert(id);
+            }
+        }
+    }
+
+    /// Record id in sequence
+    fn record(&self, channel: Option<i64>, id: i32) -> Recorded {
+        let mut sequences = self.sequences.lock().unwrap();
+        let sequence = sequences.entry(channel.unwrap_or(0)).or_insert_with(|| Sequence { last: id - 1, received: BTreeSet::new() });
+        if id <= sequence.last - RECEIVED_WINDOW || !sequence.received.insert(id) {
+            return Recorded::Duplicate;
+        }
+        // Ids of sent messages at the ends of the gap aren't missing
+        let (mut start, mut end) = (sequence.last + 1, id);
+        while start < end && sequence.received.contains(&start) {
+            start += 1;
+        }
+        while start < end && sequence.received.contains(&(end - 1)) {
+            end -= 1;
+        }
+        let gap = (start < end).then_some(UpdateGap { channel, missing: start..end });
+        sequence.last = id.max(sequence.last);
+        let min = sequence.last - RECEIVED_WINDOW;
+        sequence.received = sequence.received.split_off(&(min + 1));
+        Recorded::New(gap)
+    }
+
+    /// Was the id received
+    fn is_received(&self, channel: Option<i64>, id: i32) -> bool {
+        self.sequences.lock().unwrap().get(&channel.unwrap_or(0)).map(|s| s.received.contains(&id)).unwrap_or(false)
+    }
+}
+
+impl Default for UpdateGaps {
+    fn default() -> Self {
+        UpdateGaps::new()
+    }
+}
+
+
+/// Test detecting gaps in sequences
+#[test]
+fn test_update_gaps() {
+    let gaps = UpdateGaps::new();
+    assert_eq!(gaps.record(None, 10), Recorded::New(None));
+    assert_eq!(gaps.record(None, 11), Recorded::New(None));
+    assert_eq!(gaps.record(Some(5), 100), Recorded::New(None));
+    assert_eq!(gaps.record(None, 14), Recorded::New(Some(UpdateGap { channel: None, missing: 12..14 })));
+    // Resynced messages
+    assert_eq!(gaps.record(None, 12), Recorded::New(None));
+    assert!(gaps.is_received(None, 12) && !gaps.is_received(None, 13));
+    assert_eq!(gaps.record(Some(5), 101), Recorded::New(None));
+    assert_eq!(gaps.record(Some(5), 103), Recorded::New(Some(UpdateGap { channel: Some(5), missing: 102..103 })));
+    // Received again
+    assert_eq!(gaps.record(None, 12), Recorded::Duplicate);
+    assert_eq!(gaps.record(Some(5), 103), Recorded::Duplicate);
+}
+
+/// Test own messages interleaved with the received ones
+#[test]
+fn test_update_gaps_sent() {
+    let gaps = UpdateGaps::new();
+    let chat = |id| PackedChat { ty: grammers_session::PackedType::User, id, access_hash: None };
+    assert_eq!(gaps.record(None, 10), Recorded::New(None));
+    gaps.sent(chat(1), 11);
+    gaps.sent(chat(2), 12);
+    assert_eq!(gaps.record(None, 13), Recorded::New(None));
+    // Missed message before own one is still a gap
+    gaps.sent(chat(1), 15);
+    assert_eq!(gaps.record(None, 16), Recorded::New(Some(UpdateGap { channel: None, missing: 14..15 })));
+    assert_eq!(gaps.record(None, 15), Recorded::Duplicate);
+}
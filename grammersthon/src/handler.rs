@@ -3,24 +3,37 @@
 /// 2. https://stackoverflow.com/questions/68700171/how-can-i-assign-metadata-to-a-trait
 
 
+use std::collections::{HashMap, HashSet};
 use std::future::Future;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 use grammers_client::types::media::{Document, Sticker};
 use grammers_client::{Update, Client};
-use grammers_client::types::{Message, Media, Photo, User, Chat, Group, Channel};
+use grammers_client::types::{Message, Media, Photo, User, Chat, Group, Channel, CallbackQuery};
 use grammers_tl_types::types::{MessageReplyHeader, MessageFwdHeader, MessageReplyStoryHeader};
 use regex::Regex;
 use trait_bound_typemap::{CloneSendSyncTypeMap, TypeMapKey, TypeMap};
 
-use crate::{GrammersthonError, Grammersthon};
+use crate::{GrammersthonError, Grammersthon, ArgInfo, CommandScope, Comment, Context, Edit, ErrorKind, ErrorLog, RecentMessages, UpdateKind};
+use crate::outbox::Outbox;
+use crate::clock;
 
 pub type HandlerResult = Result<(), GrammersthonError>;
-type HandlerFn = dyn Fn(&HandlerData) -> Option<Pin<Box<dyn Future<Output = HandlerResult> + Send + Sync>>> + Send + Sync;
-type ErrorHandlerFn = dyn Fn(GrammersthonError, Client, Update) -> Pin<Box<dyn Future<Output = HandlerResult> + Send + Sync>> + Send + Sync;
-type PatternMutatorFn = dyn Fn(&str) -> Regex + Send + Sync;
+type HandlerFn = dyn Fn(&HandlerData) -> Result<Pin<Box<dyn Future<Output = HandlerResult> + Send + Sync>>, ExtractError> + Send + Sync;
+type ErrorHandlerFn = dyn Fn(GrammersthonError, ErrorContext) -> Pin<Box<dyn Future<Output = HandlerResult> + Send + Sync>> + Send + Sync;
+type PatternMutatorFn = dyn Fn(&str) -> String + Send + Sync;
+type DataCheckFn = fn(&CloneSendSyncTypeMap) -> bool;
 type InterceptorFn = dyn Fn(HandlerData) -> Pin<Box<dyn Future<Output = Result<HandlerData, GrammersthonError>> + Send + Sync>> + Send + Sync;
+type CallbackFn = dyn Fn(CallbackQuery, Client) -> Pin<Box<dyn Future<Output = HandlerResult> + Send + Sync>> + Send + Sync;
 type FallbackFn = dyn Fn(Client, Update) -> Pin<Box<dyn Future<Output = HandlerResult> + Send + Sync>> + Send + Sync;
+type UpdateCopiesFn = dyn Fn(&Update) -> usize + Send + Sync;
+type FilterFn = dyn Fn(&Message, &HandlerData) -> bool + Send + Sync;
+type AsyncFilterFn = dyn Fn(Message, HandlerData) -> Pin<Box<dyn Future<Output = bool> + Send + Sync>> + Send + Sync;
+type ReleaseFn = Box<dyn FnOnce() + Send>;
+type ClaimFn = dyn Fn(&Message, &HandlerData) -> Option<ReleaseFn> + Send + Sync;
+type AfterHandlerFn = dyn Fn(HandlerData, Result<(), ErrorKind>) -> Pin<Box<dyn Future<Output = HandlerResult> + Send + Sync>> + Send + Sync;
 
 /// For registering handlers
 #[macro_export]
@@ -38,13 +51,27 @@ pub(crate) async fn default_message_fallback_handler(message: String) -> Handler
 
 impl Grammersthon {
     /// Register event handler
-    pub fn add_handler<F, A>(&mut self, handler: (Vec<HandlerFilter>, F)) -> &mut Self 
+    pub fn add_handler<I, F, A>(&mut self, handler: (I, F)) -> &mut Self 
     where
+        I: Into<HandlerInfo>,
         F: Handler<A>,
         A: FromHandlerData + 'static
     {
-        let (filters, handler) = handler;
-        self.handlers.add(filters, Handlers::box_handler(handler));
+        let (info, handler) = handler;
+        self.handlers.add(HandlerInfo::named::<F>(info), Handlers::box_handler(handler), vec![]);
+        self
+    }
+
+    /// Get handle for adding and removing handlers at runtime
+    pub fn registry(&self) -> HandlerRegistry {
+        self.handlers.registry()
+    }
+
+    /// Register group of handlers
+    pub fn add_group(&mut self, group: HandlerGroup) -> &mut Self {
+        for (info, handler) in group.handlers {
+            self.handlers.add(info, handler, group.pattern_mutators.clone());
+        }
         self
     }
 
@@ -71,38 +98,164 @@ impl Grammersthon {
         self
     }
 
+    /// Register handler for callback queries (inline button presses) with data starting with prefix.
+    /// Callback queries with no matching handler are passed to `fallback_handler`
+    pub fn callback_handler<H, F>(&mut self, prefix: impl Into<Vec<u8>>, handler: H) -> &mut Self
+    where
+        H: (Fn(CallbackQuery, Client) -> F) + Send + Sync + 'static,
+        F: Future<Output = HandlerResult> + Send + Sync + 'static
+    {
+        self.handlers.callbacks.push((prefix.into(), Arc::new(Box::new(move |q, c| {
+            Box::pin(handler(q, c))
+        }))));
+        self
+    }
+
+    /// Register hook called for every update (including NewMessage) before it's dispatched.
+    /// Multiple hooks are run in order of registration, errors are passed to error handler and the update is still dispatched
+    /// (unless the hook returns `GrammersthonError::Cancelled`)
+    pub fn update_hook<H, F>(&mut self, hook: H) -> &mut Self
+    where
+        H: (Fn(Client, Update) -> F) + Send + Sync + 'static,
+        F: Future<Output = HandlerResult> + Send + Sync + 'static
+    {
+        self.handlers.update_hooks.push(Arc::new(Box::new(move |c, u| {
+            Box::pin(hook(c, u))
+        })));
+        self
+    }
+
     /// Register error handler
     pub fn error_handler<H, F>(&mut self, handler: H) -> &mut Self 
     where
         H: Fn(GrammersthonError, Client, Update) -> F + Send + Sync + 'static,
         F: Future<Output = HandlerResult> + Send + Sync + 'static
     {
-        self.handlers.error = Arc::new(Box::new(move |e, c, u| {
-            Box::pin(handler(e, c, u))
+        self.handlers.error = Arc::new(Box::new(move |e, ctx| {
+            Box::pin(handler(e, ctx.client, ctx.update))
         }));
         self
     }
 
-    /// Register pattern mutator function
+    /// Register error handler which also receives the matched handler (`ErrorContext`)
+    pub fn error_context_handler<H, F>(&mut self, handler: H) -> &mut Self 
+    where
+        H: Fn(GrammersthonError, ErrorContext) -> F + Send + Sync + 'static,
+        F: Future<Output = HandlerResult> + Send + Sync + 'static
+    {
+        self.handlers.error = Arc::new(Box::new(move |e, ctx| {
+            Box::pin(handler(e, ctx))
+        }));
+        self
+    }
+
+    /// Register pattern mutator function, which modifies Regex patterns of all handlers.
+    /// Multiple mutators are applied in order of registration
     pub fn pattern_mutator<M>(&mut self, mutator: M) -> &mut Self 
     where
-        M: (Fn(&str) -> Regex) + Send + Sync + 'static
+        M: (Fn(&str) -> String) + Send + Sync + 'static
     {
-        self.handlers.pattern_mutator = Some(Arc::new(Box::new(mutator)));
+        self.handlers.pattern_mutators.push(Arc::new(Box::new(mutator)));
+        self
+    }
+
+    /// Only process updates from these users or in these chats (by id), checked before any hook or handler.
+    /// Updates without user or chat (raw updates, deletions outside channels) aren't filtered
+    pub fn allow_only(&mut self, ids: impl IntoIterator<Item = i64>) -> &mut Self {
+        let mut allowed = (*self.handlers.allowed).clone();
+        allowed.extend(ids);
+        self.handlers.allowed = Arc::new(allowed);
+        self
+    }
+
+    /// Ignore updates from these users or in these chats (by id), checked before any hook or handler
+    pub fn block(&mut self, ids: impl IntoIterator<Item = i64>) -> &mut Self {
+        let mut blocked = (*self.handlers.blocked).clone();
+        blocked.extend(ids);
+        self.handlers.blocked = Arc::new(blocked);
+        self
+    }
+
+    /// Drop these classes of updates entirely, before any hook or handler
+    pub fn ignore_updates(&mut self, kinds: impl IntoIterator<Item = UpdateKind>) -> &mut Self {
+        let mut ignored = (*self.handlers.ignored_updates).clone();
+        ignored.extend(kinds);
+        self.handlers.ignored_updates = Arc::new(ignored);
         self
     }
 
-    /// Register interceptor called before handling message
+    /// Cancel handlers running longer than timeout (unless they set their own, measured by `Grammersthon::clock`),
+    /// they are reported to the error handler as `GrammersthonError::Timeout`
+    pub fn default_handler_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.handlers.default_timeout = Some(timeout);
+        self
+    }
+
+    /// What to do when handler filters match, but extracting argument fails
+    pub fn extractor_policy(&mut self, policy: ExtractorPolicy) -> &mut Self {
+        self.handlers.extractor_policy = policy;
+        self
+    }
+
+    /// Reply with usage of the command when parsing `Args` of matched handler fails,
+    /// instead of applying the extractor policy
+    pub fn reply_usage(&mut self, reply: bool) -> &mut Self {
+        self.handlers.reply_usage = reply;
+        self
+    }
+
+    /// Ask the sender for missing positional `Args` of matched handler and wait `timeout` for each answer,
+    /// instead of failing. For example `/rename` with no arguments replies "Send the new name:"
+    pub fn prompt_args(&mut self, timeout: Duration) -> &mut Self {
+        self.handlers.prompt_args = Some(timeout);
+        self
+    }
+
+    /// Ignore outgoing messages entirely before dispatching to handlers
+    pub fn skip_outgoing(&mut self, skip: bool) -> &mut Self {
+        self.handlers.skip_outgoing = skip;
+        self
+    }
+
+    /// Buffer messages of albums (media groups) for `window` and handle them once, as single message.
+    /// The first message of album is passed to handlers, all the messages are available using `Album` extractor
+    pub fn album_window(&mut self, window: Duration) -> &mut Self {
+        self.handlers.album_window = Some(window);
+        self
+    }
+
+    /// Keep the last `per_chat` messages of each chat, so the previous version of edited message is available (`Edit`).
+    /// Shorthand for `recent_messages(RecentMessages::new(per_chat))`
+    pub fn track_edits(&mut self, per_chat: usize) -> &mut Self {
+        self.recent_messages(RecentMessages::new(per_chat))
+    }
+
+    /// Register interceptor called before handling message.
+    /// Multiple interceptors are run in order of registration,
+    /// return `GrammersthonError::Cancelled` to stop processing the message silently
     pub fn interceptor<I, F>(&mut self, interceptor: I) -> &mut Self
     where
         I: (Fn(HandlerData) -> F) + Send + Sync + 'static,
         F: Future<Output = Result<HandlerData, GrammersthonError>> + Send + Sync + 'static
     {
-        self.handlers.interceptor = Some(Arc::new(Box::new(move |d| {
+        self.handlers.interceptors.push(Arc::new(Box::new(move |d| {
             Box::pin(interceptor(d))
         })));
         self
     }
+
+    /// Register hook called after matched handler finishes, with its result (kind of the error).
+    /// Multiple hooks are run in order of registration, errors of the handler take precedence over errors of hooks
+    pub fn after_handler<H, F>(&mut self, hook: H) -> &mut Self
+    where
+        H: (Fn(HandlerData, Result<(), ErrorKind>) -> F) + Send + Sync + 'static,
+        F: Future<Output = HandlerResult> + Send + Sync + 'static
+    {
+        self.handlers.after_handler.push(Arc::new(Box::new(move |d, r| {
+            Box::pin(hook(d, r))
+        })));
+        self
+    }
 }
 
 /// All the registered handlers
@@ -110,55 +263,516 @@ impl Grammersthon {
 pub(crate) struct Handlers {
     message_fallback: Arc<Box<HandlerFn>>,
     fallback: Arc<Box<FallbackFn>>,
-    handlers: Vec<HandlerWrap>,
+    /// Callback data prefix -> handler
+    callbacks: Vec<(Vec<u8>, Arc<Box<CallbackFn>>)>,
+    handlers: HandlerRegistry,
     pub error: Arc<Box<ErrorHandlerFn>>,
-    pattern_mutator: Option<Arc<Box<PatternMutatorFn>>>,
-    interceptor: Option<Arc<Box<InterceptorFn>>>,
+    pattern_mutators: Vec<Arc<Box<PatternMutatorFn>>>,
+    interceptors: Vec<Arc<Box<InterceptorFn>>>,
+    after_handler: Vec<Arc<Box<AfterHandlerFn>>>,
+    update_hooks: Vec<Arc<Box<FallbackFn>>>,
+    /// User or chat ids to process, empty = all
+    allowed: Arc<HashSet<i64>>,
+    /// User or chat ids to ignore
+    blocked: Arc<HashSet<i64>>,
+    ignored_updates: Arc<HashSet<UpdateKind>>,
+    /// How many times to dispatch update (chaos testing)
+    update_copies: Option<Arc<Box<UpdateCopiesFn>>>,
+    skip_outgoing: bool,
+    extractor_policy: ExtractorPolicy,
+    default_timeout: Option<Duration>,
+    reply_usage: bool,
+    /// Enables prompting for missing arguments, with timeout of answer
+    prompt_args: Option<Duration>,
+    /// Recent messages, for previous versions of edited messages
+    pub recent: Option<RecentMessages>,
+    /// Enables album aggregation
+    album_window: Option<Duration>,
+    /// Grouped id -> messages received so far
+    albums: Arc<Mutex<HashMap<i64, Vec<Message>>>>,
+}
+
+/// What to do when handler filters match, but extracting argument fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExtractorPolicy {
+    /// Try next handlers (logged as debug)
+    #[default]
+    Continue,
+    /// Log warning and try next handlers
+    Warn,
+    /// Stop and pass `GrammersthonError::ExtractorFailed` to error handler
+    Error
 }
 
 /// Whether the handler should be executed or no
 #[derive(Clone)]
 pub enum HandlerFilter {
     Regex(String),
-    Fn(Arc<Box<dyn Fn(&Message, &HandlerData) -> bool + Send + Sync>>)
+    Fn(Arc<Box<FilterFn>>),
+    Async(Arc<Box<AsyncFilterFn>>),
+    /// Checked after all the other filters, claims the message for the handler.
+    /// The returned function releases the claim if the handler isn't selected after all (arguments failed to extract)
+    Claim(Arc<Box<ClaimFn>>),
+    /// Filter with command scope hint, used when syncing bot commands
+    Scoped(CommandScope, Box<HandlerFilter>)
 }
 
 impl HandlerFilter {
+    /// Get the Regex pattern with mutators applied, None for non Regex filters
+    pub(crate) fn pattern(&self, mutators: &[Arc<Box<PatternMutatorFn>>]) -> Option<String> {
+        match self {
+            HandlerFilter::Regex(r) => Some(mutators.iter().fold(r.to_string(), |pattern, mutator| (*mutator)(&pattern))),
+            HandlerFilter::Scoped(_, f) => f.pattern(mutators),
+            _ => None
+        }
+    }
+
+    /// Compile the Regex pattern with mutators applied, None for non Regex filters
+    fn regex(&self, mutators: &[Arc<Box<PatternMutatorFn>>]) -> Option<Regex> {
+        let r = match self {
+            HandlerFilter::Regex(r) => r,
+            HandlerFilter::Scoped(_, f) => return f.regex(mutators),
+            _ => return None
+        };
+        // Unwrap because regex is compile checked
+        if mutators.is_empty() {
+            return Some(Regex::new(r).unwrap());
+        }
+        let pattern = self.pattern(mutators)?;
+        match Regex::new(&pattern) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                error!("Invalid pattern after applying mutators: {pattern}: {e}");
+                None
+            }
+        }
+    }
+
+    /// Attach command scope hint to this filter
+    pub fn with_scope(self, scope: CommandScope) -> HandlerFilter {
+        HandlerFilter::Scoped(scope, Box::new(self))
+    }
+
+    /// Create new filter from sync function
+    pub fn func<F>(f: F) -> HandlerFilter
+    where
+        F: Fn(&Message, &HandlerData) -> bool + Send + Sync + 'static
+    {
+        HandlerFilter::Fn(Arc::new(Box::new(f)))
+    }
+
+    /// Create new filter from async function
+    pub fn async_fn<F, Fut>(f: F) -> HandlerFilter
+    where
+        F: Fn(Message, HandlerData) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = bool> + Send + Sync + 'static
+    {
+        HandlerFilter::Async(Arc::new(Box::new(move |m, d| Box::pin(f(m, d)))))
+    }
+
+    /// Create new claim filter, `f` returns the release function or None if it doesn't match
+    pub fn claim<F>(f: F) -> HandlerFilter
+    where
+        F: Fn(&Message, &HandlerData) -> Option<Box<dyn FnOnce() + Send>> + Send + Sync + 'static
+    {
+        HandlerFilter::Claim(Arc::new(Box::new(f)))
+    }
+
     /// Does the filter match 
-    pub fn is_match(&self, message: &Message, mutator: &Option<Arc<Box<PatternMutatorFn>>>, data: &HandlerData) -> bool {
+    pub async fn is_match(&self, message: &Message, mutators: &[Arc<Box<PatternMutatorFn>>], data: &HandlerData) -> bool {
+        match self {
+            HandlerFilter::Async(f) => (*f)(message.clone(), data.clone()).await,
+            HandlerFilter::Claim(f) => (*f)(message, data).is_some(),
+            HandlerFilter::Scoped(_, f) => Box::pin(f.is_match(message, mutators, data)).await,
+            _ => self.is_match_sync(message, mutators, data).unwrap_or(false),
+        }
+    }
+
+    /// Does the filter match, without awaiting. None for async and claim filters
+    pub fn is_match_sync(&self, message: &Message, mutators: &[Arc<Box<PatternMutatorFn>>], data: &HandlerData) -> Option<bool> {
+        match self {
+            HandlerFilter::Regex(_) => Some(self.regex(mutators).map(|r| r.is_match(message.text())).unwrap_or(false)),
+            HandlerFilter::Fn(f) => Some((*f)(message, data)),
+            HandlerFilter::Async(_) | HandlerFilter::Claim(_) => None,
+            HandlerFilter::Scoped(_, f) => f.is_match_sync(message, mutators, data),
+        }
+    }
+
+    fn is_claim(&self) -> bool {
+        match self {
+            HandlerFilter::Claim(_) => true,
+            HandlerFilter::Scoped(_, f) => f.is_claim(),
+            _ => false
+        }
+    }
+
+    fn claim_message(&self, message: &Message, data: &HandlerData) -> Option<ReleaseFn> {
         match self {
-            // Unwrap because regex is compile checked
-            HandlerFilter::Regex(r) => {
-                match mutator {
-                    Some(mutator) => (*mutator)(r).is_match(message.text()),
-                    None => Regex::new(&r).unwrap().is_match(message.text()),
+            HandlerFilter::Claim(f) => (*f)(message, data),
+            HandlerFilter::Scoped(_, f) => f.claim_message(message, data),
+            _ => None
+        }
+    }
+
+    /// Do all the filters match. Cheap sync filters (regex, chat kind) are checked first,
+    /// so the async ones (admin checks, DB lookups) run only if all of them passed, and the claims last
+    pub async fn all_match(filters: &[HandlerFilter], message: &Message, mutators: &[Arc<Box<PatternMutatorFn>>], data: &HandlerData) -> bool {
+        HandlerFilter::select(filters, message, mutators, data).await.is_some()
+    }
+
+    /// Like `all_match`, but returns the release functions of the claims
+    pub(crate) async fn select(filters: &[HandlerFilter], message: &Message, mutators: &[Arc<Box<PatternMutatorFn>>], data: &HandlerData) -> Option<Vec<ReleaseFn>> {
+        let mut pending = vec![];
+        let mut claims = vec![];
+        for filter in filters {
+            match filter.is_match_sync(message, mutators, data) {
+                Some(false) => return None,
+                Some(true) => {},
+                None if filter.is_claim() => claims.push(filter),
+                None => pending.push(filter),
+            }
+        }
+        for filter in pending {
+            if !filter.is_match(message, mutators, data).await {
+                return None;
+            }
+        }
+        let mut releases = vec![];
+        for filter in claims {
+            match filter.claim_message(message, data) {
+                Some(release) => releases.push(release),
+                None => {
+                    releases.into_iter().for_each(|release| release());
+                    return None;
                 }
-            },
-            HandlerFilter::Fn(f) => (*f)(message, data),
+            }
         }
+        Some(releases)
+    }
+}
+
+/// Handler metadata, generated by the `#[handler]` macro
+#[derive(Clone)]
+pub struct HandlerInfo {
+    /// Name of the handler function
+    pub name: String,
+    pub filters: Vec<HandlerFilter>,
+    /// Populate `Args` from named capture groups of the pattern
+    pub capture_args: bool,
+    /// From the doc comment of the handler function
+    pub description: Option<String>,
+    /// Schema of the `Args<T>` parameter
+    pub args: Vec<ArgInfo>,
+    /// Explicit command scope (otherwise derived from filters)
+    pub scope: Option<CommandScope>,
+    /// Section of the help message
+    pub category: Option<String>,
+    /// Excluded from help and command menu (still handled)
+    pub hidden: bool,
+    /// Type names and presence checks of the `Data<T>` arguments
+    pub required_data: Vec<(&'static str, DataCheckFn)>,
+    /// Cancel the handler after this long (otherwise the default handler timeout)
+    pub timeout: Option<Duration>,
+    /// Fetch the replied message for `#[or_reply]` arguments
+    pub reply_args: bool,
+    /// Also handle edited messages
+    pub edits: bool,
+    /// Count of messages of the `Context` argument
+    pub context: Option<usize>,
+}
+
+impl HandlerInfo {
+    /// Create new instance
+    pub fn new(name: &str, filters: Vec<HandlerFilter>) -> HandlerInfo {
+        HandlerInfo {
+            name: name.to_string(),
+            filters,
+            capture_args: false,
+            description: None,
+            args: vec![],
+            scope: None,
+            category: None,
+            hidden: false,
+            required_data: vec![],
+            timeout: None,
+            reply_args: false,
+            edits: false,
+            context: None,
+        }
+    }
+
+    /// Set command scope explicitly
+    pub fn scope(mut self, scope: CommandScope) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    /// Get the command scope (explicit or the most specific of filters)
+    pub fn command_scope(&self) -> Option<CommandScope> {
+        if self.scope.is_some() {
+            return self.scope.clone();
+        }
+        self.filters.iter().filter_map(|f| match f {
+            HandlerFilter::Scoped(scope, _) => Some(scope.clone()),
+            _ => None
+        }).max_by_key(|scope| scope.specificity())
+    }
+
+    /// Set the description (used in command schema)
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    /// Set the category (used to group commands in help)
+    pub fn category(mut self, category: &str) -> Self {
+        self.category = Some(category.to_string());
+        self
+    }
+
+    /// Cancel the handler if it runs longer than timeout, reported as `GrammersthonError::Timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Fetch the replied message before parsing `Args` (generated for `#[or_reply]` arguments)
+    pub fn reply_args(mut self, reply_args: bool) -> Self {
+        self.reply_args = reply_args;
+        self
+    }
+
+    /// Also handle edited messages (with `Edit` extractor), use `filters::edited()` to handle only them
+    pub fn edits(mut self, edits: bool) -> Self {
+        self.edits = edits;
+        self
+    }
+
+    /// Count of messages of the `Context` argument
+    pub fn context(mut self, count: usize) -> Self {
+        self.context = Some(count);
+        self
+    }
+
+    /// Fetch the `Context` argument (generated for the `context` flag and `grammersthon::Context` argument, default size unless set by `context`)
+    pub fn uses_context(mut self) -> Self {
+        self.context = self.context.or(Some(Context::DEFAULT_SIZE));
+        self
+    }
+
+    /// Exclude from help and command menu
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Require `Data<T>` to be added before the event loop starts (generated for `Data<T>` arguments)
+    pub fn requires_data<T: Send + Sync + Clone + 'static>(mut self) -> Self {
+        self.required_data.push((std::any::type_name::<T>(), |data| data.get::<Data<T>>().is_some()));
+        self
+    }
+
+    /// Type names of the required data missing in `data`
+    pub(crate) fn missing_data(&self, data: &CloneSendSyncTypeMap) -> Vec<&'static str> {
+        self.required_data.iter().filter(|(_, check)| !check(data)).map(|(name, _)| *name).collect()
+    }
+
+    /// Set the schema of handler arguments
+    pub fn args(mut self, args: Vec<ArgInfo>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Populate `Args` from named capture groups of the pattern
+    pub fn capture_args(mut self, enabled: bool) -> Self {
+        self.capture_args = enabled;
+        self
+    }
+
+    /// Convert into info, use type name of handler if unnamed
+    fn named<F>(info: impl Into<HandlerInfo>) -> HandlerInfo {
+        let mut info = info.into();
+        if info.name.is_empty() {
+            info.name = std::any::type_name::<F>().to_string();
+        }
+        info
+    }
+
+    /// Get named captures of all the patterns
+    /// and the start of the arguments (after the match of the first pattern)
+    fn captures(&self, text: &str, mutators: &[Arc<Box<PatternMutatorFn>>]) -> (HashMap<String, String>, Option<usize>) {
+        let mut output = HashMap::new();
+        let mut args_start = None;
+        for regex in self.filters.iter().filter_map(|f| f.regex(mutators)) {
+            let captures = match regex.captures(text) {
+                Some(c) => c,
+                None => continue
+            };
+            if let (None, Some(m)) = (args_start, captures.get(0)) {
+                args_start = Some(captures.name(ARGS_GROUP).map(|a| a.start()).unwrap_or_else(|| args_start_after(text, m.end())));
+            }
+            for name in regex.capture_names().flatten() {
+                if let Some(m) = captures.name(name) {
+                    output.insert(name.to_string(), m.as_str().to_string());
+                }
+            }
+        }
+        (output, args_start)
+    }
+}
+
+/// Capture group of pattern where the arguments start, for patterns matching the arguments too (`^/sum (?P<args>.*)`)
+const ARGS_GROUP: &str = "args";
+
+/// Start of the arguments after pattern matched text until `end`, rest of the matched word is skipped (`/hi@bot`).
+/// Patterns matching the arguments too have to mark them with the `args` capture group
+fn args_start_after(text: &str, end: usize) -> usize {
+    text[end..].find(char::is_whitespace).map(|i| end + i).unwrap_or(text.len())
+}
+
+impl From<Vec<HandlerFilter>> for HandlerInfo {
+    fn from(filters: Vec<HandlerFilter>) -> Self {
+        HandlerInfo::new("", filters)
     }
 }
 
 /// Wrapper for handler with metadata
 #[derive(Clone)]
 pub(crate) struct HandlerWrap {
-    pub filters: Vec<HandlerFilter>,
-    pub handler: Arc<Box<HandlerFn>>
+    pub info: HandlerInfo,
+    pub handler: Arc<Box<HandlerFn>>,
+    /// Pattern mutators of the group this handler belongs to
+    pub mutators: Vec<Arc<Box<PatternMutatorFn>>>
+}
+
+/// Handle for adding and removing handlers at runtime, can be used as handler argument
+#[derive(Clone, Default)]
+pub struct HandlerRegistry {
+    handlers: Arc<RwLock<Vec<HandlerWrap>>>,
+    /// Any handler handles edited messages, updated on changes
+    edits: Arc<AtomicBool>,
+}
+
+impl HandlerRegistry {
+    /// Register handler, it will be matched after all the existing ones
+    pub fn add_handler<I, F, A>(&self, handler: (I, F))
+    where
+        I: Into<HandlerInfo>,
+        F: Handler<A>,
+        A: FromHandlerData + 'static
+    {
+        let (info, handler) = handler;
+        self.push(HandlerWrap { info: HandlerInfo::named::<F>(info), handler: Handlers::box_handler(handler), mutators: vec![] });
+    }
+
+    /// Remove all handlers with name, returns if any was removed
+    pub fn remove(&self, name: &str) -> bool {
+        let mut handlers = self.handlers.write().unwrap();
+        let len = handlers.len();
+        handlers.retain(|h| h.info.name != name);
+        self.edits.store(handlers.iter().any(|h| h.info.edits), Ordering::Relaxed);
+        handlers.len() != len
+    }
+
+    /// Is handler with name registered
+    pub fn contains(&self, name: &str) -> bool {
+        self.handlers.read().unwrap().iter().any(|h| h.info.name == name)
+    }
+
+    /// Names of all the registered handlers
+    pub fn names(&self) -> Vec<String> {
+        self.handlers.read().unwrap().iter().map(|h| h.info.name.clone()).collect()
+    }
+
+    fn push(&self, handler: HandlerWrap) {
+        let mut handlers = self.handlers.write().unwrap();
+        if handler.info.edits {
+            self.edits.store(true, Ordering::Relaxed);
+        }
+        handlers.push(handler);
+    }
+
+    /// Whether any handler handles edited messages
+    fn handles_edits(&self) -> bool {
+        self.edits.load(Ordering::Relaxed)
+    }
+
+    /// Copy of the current handlers, so the lock isn't held while handling
+    fn snapshot(&self) -> Vec<HandlerWrap> {
+        self.handlers.read().unwrap().clone()
+    }
+}
+
+/// Group of handlers sharing pattern mutators
+#[derive(Clone, Default)]
+pub struct HandlerGroup {
+    handlers: Vec<(HandlerInfo, Arc<Box<HandlerFn>>)>,
+    pattern_mutators: Vec<Arc<Box<PatternMutatorFn>>>,
+}
+
+impl HandlerGroup {
+    /// Create new empty group
+    pub fn new() -> HandlerGroup {
+        HandlerGroup::default()
+    }
+
+    /// Add handler to this group
+    pub fn add_handler<I, F, A>(mut self, handler: (I, F)) -> Self
+    where
+        I: Into<HandlerInfo>,
+        F: Handler<A>,
+        A: FromHandlerData + 'static
+    {
+        let (info, handler) = handler;
+        self.handlers.push((HandlerInfo::named::<F>(info), Handlers::box_handler(handler)));
+        self
+    }
+
+    /// Register pattern mutator scoped to this group.
+    /// Group mutators are applied before the global ones
+    pub fn pattern_mutator<M>(mut self, mutator: M) -> Self
+    where
+        M: (Fn(&str) -> String) + Send + Sync + 'static
+    {
+        self.pattern_mutators.push(Arc::new(Box::new(mutator)));
+        self
+    }
 }
 
 impl Handlers {
     /// Create new empty instance
     pub(crate) fn new() -> Handlers {
         Handlers {
-            handlers: vec![],
+            handlers: HandlerRegistry::default(),
+            callbacks: vec![],
             message_fallback: Self::box_handler(default_message_fallback_handler),
-            pattern_mutator: None,
-            interceptor: None,
-            // Default error handler
-            error: Arc::new(Box::new(|e, __, ___| { Box::pin(async move { 
-                error!("Unhandled error occured: {e}");
-                Ok(()) 
-            }) })),
+            pattern_mutators: vec![],
+            interceptors: vec![],
+            after_handler: vec![],
+            update_hooks: vec![],
+            allowed: Arc::new(HashSet::new()),
+            blocked: Arc::new(HashSet::new()),
+            ignored_updates: Arc::new(HashSet::new()),
+            update_copies: None,
+            skip_outgoing: false,
+            extractor_policy: ExtractorPolicy::Continue,
+            default_timeout: None,
+            reply_usage: false,
+            prompt_args: None,
+            recent: None,
+            album_window: None,
+            albums: Arc::new(Mutex::new(HashMap::new())),
+            // Default error handler, repeated errors are suppressed
+            error: {
+                let log = Arc::new(ErrorLog::default());
+                Arc::new(Box::new(move |e, ctx| {
+                    match ctx.handler {
+                        Some(handler) => log.log(&format!("Unhandled error occured in handler {handler}: {e}")),
+                        None => log.log(&format!("Unhandled error occured: {e}")),
+                    }
+                    Box::pin(async move { Ok(()) })
+                }))
+            },
             // Default update fallback
             fallback: Arc::new(Box::new(|_, u| { Box::pin(async move {
                 error!("Unhandled Update: {u:?}");
@@ -174,47 +788,228 @@ impl Handlers {
         A: FromHandlerData + 'static
     {
         // Wrap handler with calling function
-        let f = move |data: &HandlerData| -> Option<Pin<Box<dyn Future<Output = HandlerResult> + Send + Sync>>> {
-            Some(Box::pin(handler.call(A::from_data(data)?)))
+        let f = move |data: &HandlerData| -> Result<Pin<Box<dyn Future<Output = HandlerResult> + Send + Sync>>, ExtractError> {
+            Ok(Box::pin(handler.call(A::try_from_data(data)?)))
         };
         Arc::new(Box::new(f))
     }
 
+    /// Get all the handlers with their patterns (mutators applied)
+    pub(crate) fn handlers(&self) -> Vec<(HandlerInfo, Vec<String>)> {
+        self.handlers.snapshot().into_iter().map(|handler| {
+            let mutators = handler.mutators.iter().chain(self.pattern_mutators.iter()).cloned().collect::<Vec<_>>();
+            let patterns = handler.info.filters.iter().filter_map(|f| f.pattern(&mutators)).collect();
+            (handler.info, patterns)
+        }).collect()
+    }
+
+    /// Get the runtime registry handle
+    pub(crate) fn registry(&self) -> HandlerRegistry {
+        self.handlers.clone()
+    }
+
     /// Register new handler
-    fn add(&mut self, filters: Vec<HandlerFilter>, handler: Arc<Box<HandlerFn>>) {
-        self.handlers.push(HandlerWrap { filters, handler });
+    fn add(&mut self, info: HandlerInfo, handler: Arc<Box<HandlerFn>>, mutators: Vec<Arc<Box<PatternMutatorFn>>>) {
+        self.handlers.push(HandlerWrap { info, handler, mutators });
+    }
+
+    /// Whether the class of update is ignored
+    pub(crate) fn ignores(&self, update: &Update) -> bool {
+        !self.ignored_updates.is_empty() && self.ignored_updates.contains(&UpdateKind::of(update))
+    }
+
+    /// Set how many times to dispatch update
+    #[cfg(feature = "chaos")]
+    pub(crate) fn update_copies(&mut self, f: impl Fn(&Update) -> usize + Send + Sync + 'static) {
+        self.update_copies = Some(Arc::new(Box::new(f)));
+    }
+
+    /// How many times to dispatch the update
+    pub(crate) fn copies(&self, update: &Update) -> usize {
+        self.update_copies.as_ref().map(|f| (*f)(update)).unwrap_or(1)
+    }
+
+    /// Run the after handler hooks
+    async fn after_handler(&self, data: &HandlerData, mut result: HandlerResult) -> HandlerResult {
+        let kind = result.as_ref().map_err(|e| e.kind()).copied();
+        for hook in &self.after_handler {
+            if let Err(e) = (*hook)(data.clone(), kind).await {
+                // Only the first error is passed to error handler
+                match result {
+                    Ok(_) => result = Err(e),
+                    Err(_) => warn!("After handler hook failed: {e}"),
+                }
+            }
+        }
+        result
+    }
+
+    /// Whether the update passes the allowed / blocked users and chats
+    fn permitted(&self, update: &Update) -> bool {
+        let ids = match update {
+            Update::NewMessage(m) | Update::MessageEdited(m) => vec![Some(m.chat().id()), m.sender().map(|s| s.id())],
+            Update::CallbackQuery(query) => vec![Some(query.chat().id()), Some(query.sender().id())],
+            Update::InlineQuery(query) => vec![Some(query.sender().id())],
+            Update::MessageDeleted(deletion) => vec![deletion.channel_id()],
+            _ => vec![],
+        };
+        let ids = ids.into_iter().flatten().collect::<Vec<_>>();
+        if ids.is_empty() {
+            return true;
+        }
+        if !self.allowed.is_empty() && !ids.iter().any(|id| self.allowed.contains(id)) {
+            debug!("Ignoring update from not allowed chat or user: {ids:?}");
+            return false;
+        }
+        if ids.iter().any(|id| self.blocked.contains(id)) {
+            debug!("Ignoring update from blocked chat or user: {ids:?}");
+            return false;
+        }
+        true
     }
 
-    /// Handle incoming update
-    pub(crate) async fn handle(&self, client: Client, update: Update, me: User, data: CloneSendSyncTypeMap) -> HandlerResult {
-        let message = match update {
-            Update::NewMessage(m) => m,
+    /// Handle incoming update, name of the matched handler is stored in `matched`
+    pub(crate) async fn handle(&self, client: Client, update: Update, me: User, data: CloneSendSyncTypeMap, matched: Arc<Mutex<Option<String>>>) -> HandlerResult {
+        if !self.permitted(&update) {
+            return Ok(());
+        }
+
+        // Failing hook doesn't stop the dispatch
+        for hook in &self.update_hooks {
+            match (*hook)(client.clone(), update.clone()).await {
+                Ok(()) => (),
+                Err(GrammersthonError::Cancelled) => return Ok(()),
+                Err(e) => {
+                    let ctx = ErrorContext { client: client.clone(), update: update.clone(), handler: None };
+                    if let Err(e) = (*self.error)(e, ctx).await {
+                        error!("Error occured while running error handler: {e}");
+                    }
+                }
+            }
+        }
+
+        if let Update::CallbackQuery(query) = &update {
+            if let Some((_, callback)) = self.callbacks.iter().find(|(prefix, _)| query.data().starts_with(prefix)) {
+                return (*callback)(query.clone(), client).await;
+            }
+        }
+
+        let previous = match (&self.recent, &update) {
+            (Some(recent), Update::NewMessage(m) | Update::MessageEdited(m)) => recent.insert(m),
+            _ => None
+        };
+        let (message, edit) = match update {
+            Update::NewMessage(m) => (m, None),
+            Update::MessageEdited(m) if self.handlers.handles_edits() => (m.clone(), Some(Edit { previous, current: m })),
             update => {
                 return (*self.fallback)(client, update).await;
             },
         };
 
+        if self.skip_outgoing && message.outgoing() {
+            return Ok(());
+        }
+
+        // Aggregate album, handled only by the task of the first message
+        let mut album = None;
+        if let (Some(window), Some(grouped_id), None) = (self.album_window, message.grouped_id(), &edit) {
+            {
+                let mut albums = self.albums.lock().unwrap();
+                if let Some(messages) = albums.get_mut(&grouped_id) {
+                    messages.push(message);
+                    return Ok(());
+                }
+                albums.insert(grouped_id, vec![message.clone()]);
+            }
+            tokio::time::sleep(window).await;
+            let mut messages = self.albums.lock().unwrap().remove(&grouped_id).unwrap_or_default();
+            messages.sort_by_key(|m| m.id());
+            album = Some(messages);
+        }
+        let message = album.as_ref().and_then(|a| a.first().cloned()).unwrap_or(message);
+
         // Arguments
-        let mut data = HandlerData { client, data, me, message: message.clone() };
+        let mut data = HandlerData { client, data, me, message: message.clone(), captures: HashMap::new(), capture_args: false, args_start: None, prompted_args: None, reply: None, comment: Arc::new(tokio::sync::OnceCell::new()), outbox: Outbox::default(), edit, context: None, unmatched: None, album, handler: None };
 
-        // Run interceptor
-        if let Some(interceptor) = &self.interceptor {
-            data = (*interceptor)(data).await?;
+        // Run interceptors
+        for interceptor in &self.interceptors {
+            data = match (*interceptor)(data).await {
+                Ok(data) => data,
+                Err(GrammersthonError::Cancelled) => return Ok(()),
+                Err(e) => return Err(e),
+            };
         }
 
         // Find handler
-        for handler in &self.handlers {
-            // Run all filters
-            let matched = handler.filters.iter().all(|f| f.is_match(&message, &self.pattern_mutator, &data));
-            if matched {
-                if let Some(f) = (*handler.handler)(&data) {
-                    return f.await;
+        let mut unmatched = Unmatched::NoMatch;
+        for handler in &self.handlers.snapshot() {
+            if data.edit.is_some() && !handler.info.edits {
+                continue;
+            }
+            // Group mutators first, then global
+            let mutators = handler.mutators.iter().chain(self.pattern_mutators.iter()).cloned().collect::<Vec<_>>();
+
+            if let Some(releases) = HandlerFilter::select(&handler.info.filters, &message, &mutators, &data).await {
+                *matched.lock().unwrap() = Some(handler.info.name.clone());
+                data.handler = Some(handler.info.name.clone());
+                (data.captures, data.args_start) = handler.info.captures(message.text(), &mutators);
+                data.capture_args = handler.info.capture_args;
+                if handler.info.reply_args && data.reply.is_none() && message.reply_to_message_id().is_some() {
+                    data.reply = message.get_reply().await.unwrap_or_else(|e| {
+                        warn!("Failed getting reply of message {}: {e}", message.id());
+                        None
+                    });
+                }
+                data.context = match handler.info.context {
+                    Some(count) => Some(Context::fetch(&data, count).await?),
+                    None => None,
+                };
+                let extracted = match (self.prompt_args, data.capture_args) {
+                    (Some(timeout), false) => data.prompt_missing_args(|d| (*handler.handler)(d), timeout).await?,
+                    _ => (*handler.handler)(&data)
+                };
+                match extracted {
+                    Ok(f) => {
+                        let result = match handler.info.timeout.or(self.default_timeout) {
+                            Some(timeout) => clock::timeout(data.clock().as_ref(), timeout, f).await.unwrap_or_else(|| {
+                                warn!("Handler {} timed out after {timeout:?}", handler.info.name);
+                                Err(GrammersthonError::Timeout)
+                            }),
+                            None => f.await,
+                        };
+                        return self.after_handler(&data, result).await;
+                    },
+                    Err(e) => {
+                        // Handler not selected, so it didn't claim the message
+                        releases.into_iter().for_each(|release| release());
+                        data.prompted_args = None;
+                        if let (true, Some(error)) = (self.reply_usage, &e.error) {
+                            return data.reply_usage(error).await;
+                        }
+                        match self.extractor_policy {
+                            ExtractorPolicy::Continue => debug!("Handler {} matched, but failed extracting argument #{} ({})", handler.info.name, e.index, e.type_name),
+                            ExtractorPolicy::Warn => warn!("Handler {} matched, but failed extracting argument #{} ({})", handler.info.name, e.index, e.type_name),
+                            ExtractorPolicy::Error => return Err(GrammersthonError::ExtractorFailed { handler: handler.info.name.clone(), type_name: e.type_name }),
+                        }
+                        if let Unmatched::NoMatch = unmatched {
+                            unmatched = Unmatched::ExtractorFailed { handler: handler.info.name.clone(), error: e };
+                        }
+                    },
                 }
             }
         }
 
         // Run fallback
-        if let Some(f) = (*self.message_fallback)(&data) {
+        *matched.lock().unwrap() = None;
+        if let Some(edit) = data.edit {
+            return (*self.fallback)(data.client, Update::MessageEdited(edit.current)).await;
+        }
+        data.captures.clear();
+        data.capture_args = false;
+        data.args_start = None;
+        data.handler = None;
+        data.unmatched = Some(unmatched);
+        if let Ok(f) = (*self.message_fallback)(&data) {
             return f.await;
         }
         Err(GrammersthonError::MissingParameters("Fallback handle function parameter"))
@@ -229,7 +1024,31 @@ pub struct HandlerData {
     pub client: Client,
     pub message: Message,
     pub me: User,
-    pub data: CloneSendSyncTypeMap
+    pub data: CloneSendSyncTypeMap,
+    /// Named capture groups of the matched handler patterns
+    pub captures: HashMap<String, String>,
+    /// Whether `Args` should be populated from `captures`
+    pub(crate) capture_args: bool,
+    /// Start of the arguments in the message text, after the matched pattern
+    pub(crate) args_start: Option<usize>,
+    /// Arguments answered to prompts for missing arguments, replace the arguments of message
+    pub(crate) prompted_args: Option<String>,
+    /// Replied message, fetched for `#[or_reply]` arguments
+    pub(crate) reply: Option<Message>,
+    /// Channel post the message comments on, resolved on demand (`HandlerData::comment`)
+    pub(crate) comment: Arc<tokio::sync::OnceCell<Option<Comment>>>,
+    /// Messages sent while handling
+    pub(crate) outbox: Outbox,
+    /// The message is edited (handler with `edits` option)
+    pub(crate) edit: Option<Edit>,
+    /// Last messages of the chat, fetched for handler with `Context` argument
+    pub(crate) context: Option<Context>,
+    /// Only available in message fallback handler
+    pub(crate) unmatched: Option<Unmatched>,
+    /// All messages of the album, if album aggregation is enabled
+    pub(crate) album: Option<Vec<Message>>,
+    /// Name of the matched handler
+    pub(crate) handler: Option<String>,
 }
 
 impl HandlerData {
@@ -237,6 +1056,11 @@ impl HandlerData {
     pub fn data<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
         self.data.get::<Data<T>>().map(|t| t.clone())
     }
+
+    /// Name of the matched handler, None in interceptors and fallback handlers
+    pub fn handler_name(&self) -> Option<&str> {
+        self.handler.as_deref()
+    }
 }
 
 /// Wrapper for querying user data
@@ -258,9 +1082,69 @@ impl<T: Send + Sync + Clone + 'static> TypeMapKey for Data<T> {
 #[derive(Debug, Clone)]
 pub struct Me(pub User);
 
+/// Id of the account which received the update, to tell the accounts of `GrammersthonPool` apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AccountId(pub i64);
+
+/// Id of the chat the message was sent in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChatId(pub i64);
+
+/// Id of the message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MessageId(pub i32);
+
+/// Id of the message sender (None for anonymous admins, channel posts)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SenderId(pub i64);
+
+/// Handler argument which couldn't be extracted from `HandlerData`
+#[derive(Debug, Clone)]
+pub struct ExtractError {
+    /// Index of the argument
+    pub index: usize,
+    pub type_name: &'static str,
+    /// Why it failed, if known (`Args` parse error)
+    pub error: Option<Arc<GrammersthonError>>
+}
+
+/// Where the error passed to error handler occured
+#[derive(Clone)]
+pub struct ErrorContext {
+    pub client: Client,
+    /// Update which triggered the error
+    pub update: Update,
+    /// Name of the matched handler, None if the error occured before matching
+    /// (update hooks, interceptors) or in fallback handlers
+    pub handler: Option<String>,
+}
+
+/// Why wasn't the message handled by any handler, available in message fallback handler
+#[derive(Debug, Clone)]
+pub enum Unmatched {
+    /// Filters of no handler matched the message
+    NoMatch,
+    /// Filters of handler matched, but extracting argument failed (first such handler)
+    ExtractorFailed {
+        handler: String,
+        error: ExtractError
+    }
+}
+
 /// For generating handler function parameters
 pub trait FromHandlerData: where Self: Sized {
     fn from_data(data: &HandlerData) -> Option<Self>;
+
+    /// Same as `from_data`, but reports which argument failed
+    fn try_from_data(data: &HandlerData) -> Result<Self, ExtractError> {
+        Self::from_data(data).ok_or(ExtractError { index: 0, type_name: std::any::type_name::<Self>(), error: None })
+    }
+}
+
+impl FromHandlerData for HandlerData {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        Some(data.clone())
+    }
 }
 
 impl FromHandlerData for Client {
@@ -377,12 +1261,48 @@ impl<T: Send + Sync + Clone + 'static> FromHandlerData for Data<T> {
     }
 }
 
+impl FromHandlerData for HandlerRegistry {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.data.get::<Data<HandlerRegistry>>().cloned()
+    }
+}
+
+impl FromHandlerData for Unmatched {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.unmatched.clone()
+    }
+}
+
 impl FromHandlerData for Me {
     fn from_data(data: &HandlerData) -> Option<Self> {
         Some(Me(data.me.clone()))
     }
 }
 
+impl FromHandlerData for AccountId {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        Some(AccountId(data.me.id()))
+    }
+}
+
+impl FromHandlerData for ChatId {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        Some(ChatId(data.message.chat().id()))
+    }
+}
+
+impl FromHandlerData for MessageId {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        Some(MessageId(data.message.id()))
+    }
+}
+
+impl FromHandlerData for SenderId {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.message.sender().map(|s| SenderId(s.id()))
+    }
+}
+
 
 /// Generate FromHandlerData for n-tuple of FromHandlerData:
 /// ```
@@ -398,6 +1318,16 @@ macro_rules! from_handler_data_impl({ $($param:ident)* } => {
         fn from_data(data: &HandlerData) -> Option<Self> {
             Some(($($param::from_data(data)?,)*))
         }
+
+        #[allow(unused)]
+        fn try_from_data(data: &HandlerData) -> Result<Self, ExtractError> {
+            let mut index = 0;
+            Ok(($({
+                let arg = $param::try_from_data(data).map_err(|e| ExtractError { index, ..e })?;
+                index += 1;
+                arg
+            },)*))
+        }
     }
 });
 
@@ -446,3 +1376,44 @@ handler_fn! { A B C D E }
 handler_fn! { A B C D E F }
 handler_fn! { A B C D E F G }
 handler_fn! { A B C D E F G H }
+
+
+/// Test detection of missing handler data
+#[test]
+fn test_missing_data() {
+    let info = HandlerInfo::new("handler", vec![]).requires_data::<u32>().requires_data::<String>();
+    let mut data = CloneSendSyncTypeMap::new();
+    data.insert::<Data<String>>("config".to_string());
+    assert_eq!(info.missing_data(&data), vec!["u32"]);
+    data.insert::<Data<u32>>(1);
+    assert!(info.missing_data(&data).is_empty());
+}
+
+/// Test start of arguments after the matched pattern
+#[test]
+fn test_args_start() {
+    let start = |pattern: &str, text: &str| {
+        let info = HandlerInfo::new("", vec![HandlerFilter::Regex(pattern.to_string())]);
+        text[info.captures(text, &[]).1.unwrap()..].trim_start().to_string()
+    };
+    assert_eq!(start("^/hi", "/hi   John"), "John");
+    assert_eq!(start("^/hi", "/hi@bot John"), "John");
+    assert_eq!(start("(?i)hey bot,? play", "Hey bot, play some song"), "some song");
+    assert_eq!(start("^hey bot", "hey bot"), "");
+    assert_eq!(start("^/sum (?P<args>.*)", "/sum 1 2"), "1 2");
+    assert_eq!(start("^/sum .*", "/sum 1 2"), "");
+    assert_eq!(start("^/ping$", "/ping"), "");
+}
+
+/// Test tracking of handlers of edited messages
+#[test]
+fn test_registry_edits() {
+    async fn edited(_: Message) -> HandlerResult { Ok(()) }
+    let registry = HandlerRegistry::default();
+    registry.add_handler((HandlerInfo::new("new", vec![]), edited));
+    assert!(!registry.handles_edits());
+    registry.add_handler((HandlerInfo::new("edited", vec![]).edits(true), edited));
+    assert!(registry.handles_edits());
+    registry.remove("edited");
+    assert!(!registry.handles_edits());
+}
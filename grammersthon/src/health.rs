@@ -0,0 +1,289 @@
+//! Health probes over HTTP for Kubernetes and similar (liveness, readiness)
+//!
+//! Usage:
+//! ```ignore
+//! grammersthon.health_server(([0, 0, 0, 0], 8080))?;
+//!
+//! #[handler("^/health$")]
+//! async fn health(data: HandlerData, health: Health) -> HandlerResult {
+//!     data.reply(format!("{:?}", health.status())).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, UNIX_EPOCH};
+use grammers_client::Client;
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use trait_bound_typemap::TypeMap;
+
+use crate::{Clock, Data, FromHandlerData, Grammersthon, GrammersthonError, HandlerData};
+
+/// Handled updates the error rate is calculated from
+const ERROR_RATE_WINDOW: usize = 100;
+/// How often the event loop reports it's alive
+pub(crate) const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Thresholds of the liveness probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HealthConfig {
+    /// Getting updates may fail this long before the bot is unhealthy (client reconnecting)
+    pub reconnect_timeout: Duration,
+    /// Event loop may not respond this long before the bot is unhealthy (stalled loop)
+    pub stall_timeout: Duration,
+    /// Unhealthy when no update was received for this long, for bots with steady traffic (default None)
+    pub max_update_age: Option<Duration>,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            reconnect_timeout: Duration::from_secs(5 * 60),
+            stall_timeout: Duration::from_secs(2 * 60),
+            max_update_age: None,
+        }
+    }
+}
+
+/// Status reported by the health server
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HealthStatus {
+    /// Liveness, see `Grammersthon::health_server`
+    pub live: bool,
+    /// Event loop is running
+    pub running: bool,
+    /// Getting updates works (no failure since the last success)
+    pub connected: bool,
+    /// Unix timestamp (seconds) of the first failure to get updates, while not connected
+    pub disconnected_since: Option<u64>,
+    /// Unix timestamp (seconds) of the last received update
+    pub last_update: Option<u64>,
+    /// Unix timestamp (seconds) when the event loop last reported it's alive
+    pub last_heartbeat: Option<u64>,
+    /// Share of the last 100 handled updates which failed
+    pub error_rate: f64,
+}
+
+/// Health of the bot, tracked by the event loop after `Grammersthon::health_server`
+#[derive(Clone)]
+pub struct Health {
+    config: HealthConfig,
+    clock: Arc<dyn Clock>,
+    running: Arc<AtomicBool>,
+    /// Unix timestamps in seconds, 0 if none
+    started: Arc<AtomicU64>,
+    disconnected_since: Arc<AtomicU64>,
+    last_update: Arc<AtomicU64>,
+    last_heartbeat: Arc<AtomicU64>,
+    /// Results of the last handled updates (true = error)
+    results: Arc<Mutex<VecDeque<bool>>>,
+}
+
+impl Health {
+    fn new(config: HealthConfig, clock: Arc<dyn Clock>) -> Health {
+        Health {
+            config,
+            clock,
+            running: Default::default(),
+            started: Default::default(),
+            disconnected_since: Default::default(),
+            last_update: Default::default(),
+            last_heartbeat: Default::default(),
+            results: Default::default(),
+        }
+    }
+
+    /// Current status
+    pub fn status(&self) -> HealthStatus {
+        let results = self.results.lock().unwrap();
+        let errors = results.iter().filter(|e| **e).count();
+        let timestamp = |t: &AtomicU64| Some(t.load(Ordering::Relaxed)).filter(|t| *t > 0);
+        let now = self.now();
+        let older = |t: Option<u64>, age: Duration| t.map(|t| now.saturating_sub(t) > age.as_secs());
+        let running = self.running.load(Ordering::Relaxed);
+        let disconnected_since = timestamp(&self.disconnected_since);
+        let (last_update, last_heartbeat) = (timestamp(&self.last_update), timestamp(&self.last_heartbeat));
+        let live = running
+            && !older(disconnected_since, self.config.reconnect_timeout).unwrap_or(false)
+            && !older(last_heartbeat, self.config.stall_timeout).unwrap_or(false)
+            && self.config.max_update_age.and_then(|age| older(last_update.or(timestamp(&self.started)), age)) != Some(true);
+        HealthStatus {
+            live,
+            running,
+            connected: disconnected_since.is_none(),
+            disconnected_since,
+            last_update,
+            last_heartbeat,
+            error_rate: if results.is_empty() { 0.0 } else { errors as f64 / results.len() as f64 },
+        }
+    }
+
+    /// Current unix time in seconds
+    fn now(&self) -> u64 {
+        self.clock.system_time().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+    }
+
+    /// Event loop started (with connected client) or stopped
+    pub(crate) fn set_running(&self, running: bool) {
+        self.running.store(running, Ordering::Relaxed);
+        if running {
+            self.disconnected_since.store(0, Ordering::Relaxed);
+            self.started.store(self.now(), Ordering::Relaxed);
+            self.last_heartbeat.store(self.now(), Ordering::Relaxed);
+        }
+    }
+
+    /// Result of getting update
+    pub(crate) fn received(&self, ok: bool) {
+        let now = self.now();
+        match ok {
+            true => {
+                self.disconnected_since.store(0, Ordering::Relaxed);
+                self.last_update.store(now, Ordering::Relaxed);
+            },
+            // Keep the time of the first failure
+            false => { let _ = self.disconnected_since.compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed); },
+        }
+    }
+
+    /// Event loop is alive, while not connected check whether the client reconnected
+    pub(crate) fn heartbeat(&self, client: &Client) {
+        self.last_heartbeat.store(self.now(), Ordering::Relaxed);
+        if self.disconnected_since.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let (health, client) = (self.clone(), client.clone());
+        tokio::spawn(async move {
+            if client.get_me().await.is_ok() {
+                health.disconnected_since.store(0, Ordering::Relaxed);
+            }
+        });
+    }
+
+    /// Result of handling update
+    pub(crate) fn handled(&self, error: bool) {
+        let mut results = self.results.lock().unwrap();
+        if results.len() >= ERROR_RATE_WINDOW {
+            results.pop_front();
+        }
+        results.push_back(error);
+    }
+
+    /// HTTP response for path
+    fn response(&self, path: &str) -> String {
+        let status = self.status();
+        let ok = match path {
+            "/health" => status.live,
+            "/ready" => status.running,
+            _ => return "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        };
+        let body = serde_json::to_string(&status).unwrap_or_default();
+        let code = if ok { "200 OK" } else { "503 Service Unavailable" };
+        format!("HTTP/1.1 {code}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len())
+    }
+
+    /// Answer single request
+    async fn serve(&self, mut stream: TcpStream) -> Result<(), GrammersthonError> {
+        let mut buffer = [0u8; 1024];
+        let read = stream.read(&mut buffer).await?;
+        let request = String::from_utf8_lossy(&buffer[..read]);
+        // GET /path HTTP/1.1
+        let path = request.split_whitespace().nth(1).unwrap_or_default();
+        let path = path.split('?').next().unwrap_or_default();
+        stream.write_all(self.response(path).as_bytes()).await?;
+        Ok(())
+    }
+}
+
+impl FromHandlerData for Health {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.data::<Health>()
+    }
+}
+
+impl Grammersthon {
+    /// Serve health probes over HTTP with the default `HealthConfig`, for Kubernetes and similar:
+    /// - `/health` (liveness) - 200 while the event loop runs and responds, and getting updates doesn't fail
+    ///   for longer than the reconnect timeout, 503 otherwise
+    /// - `/ready` (readiness) - 200 once the event loop runs
+    ///
+    /// Both return `HealthStatus` as JSON, with the last update timestamp and handler error rate.
+    /// Set after setting the clock
+    pub fn health_server(&mut self, addr: impl Into<SocketAddr>) -> Result<&mut Self, GrammersthonError> {
+        self.health_server_config(addr, HealthConfig::default())
+    }
+
+    /// Serve health probes with custom thresholds (see `health_server`)
+    pub fn health_server_config(&mut self, addr: impl Into<SocketAddr>, config: HealthConfig) -> Result<&mut Self, GrammersthonError> {
+        let listener = std::net::TcpListener::bind(addr.into())?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        let health = Health::new(config, self.get_clock());
+        self.data.insert::<Data<Health>>(health.clone());
+        tokio::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(e) => {
+                        warn!("Health server failed accepting connection: {e}");
+                        continue;
+                    }
+                };
+                let health = health.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = health.serve(stream).await {
+                        debug!("Health server request failed: {e}");
+                    }
+                });
+            }
+        });
+        Ok(self)
+    }
+
+    /// Health tracking, if the health server is enabled
+    pub(crate) fn get_health(&self) -> Option<Health> {
+        self.data.get::<Data<Health>>().cloned()
+    }
+}
+
+
+/// Test status and probe responses
+#[test]
+fn test_health() {
+    let clock = crate::ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000));
+    let health = Health::new(HealthConfig::default(), Arc::new(clock.clone()));
+    assert!(health.response("/ready").starts_with("HTTP/1.1 503"));
+    health.set_running(true);
+    assert!(health.response("/health").starts_with("HTTP/1.1 200"));
+
+    // Transient failure
+    health.received(false);
+    clock.advance(Duration::from_secs(60));
+    health.received(false);
+    assert!(health.response("/ready").starts_with("HTTP/1.1 200"));
+    assert!(health.response("/health").starts_with("HTTP/1.1 200"));
+    health.received(true);
+    health.handled(false);
+    health.handled(true);
+    assert_eq!(health.status(), HealthStatus {
+        live: true, running: true, connected: true, disconnected_since: None, last_update: Some(1060), last_heartbeat: Some(1000), error_rate: 0.5
+    });
+    assert!(health.response("/").starts_with("HTTP/1.1 404"));
+
+    // Stalled loop
+    clock.advance(Duration::from_secs(121));
+    assert!(health.response("/health").starts_with("HTTP/1.1 503"));
+    health.last_heartbeat.store(health.now(), Ordering::Relaxed);
+    assert!(health.status().live);
+
+    // Not reconnecting
+    health.received(false);
+    clock.advance(Duration::from_secs(301));
+    health.last_heartbeat.store(health.now(), Ordering::Relaxed);
+    assert!(!health.status().live);
+}
@@ -0,0 +1,163 @@
+//! Help message generated from the command schema, grouped by handler category
+//!
+//! Usage:
+//! ```ignore
+//! /// Play a song
+//! #[handler("^/play", category = "Music")]
+//! async fn play(message: Message, args: Args<PlayArgs>) -> HandlerResult { ... }
+//!
+//! Help::new()
+//!     .categories(&["General", "Music"])
+//!     .install(&mut grammersthon);
+//! ```
+//!
+//! Commands without category are listed last. Hidden commands and commands scoped to other chats are left out.
+//! Install after adding pattern mutators and setting the catalog.
+
+use std::collections::HashMap;
+use grammers_client::types::Message;
+
+use crate::{Catalog, CommandInfo, CommandSchema, Grammersthon, HandlerData, HandlerFilter, HandlerInfo, chat_lang};
+use crate::args::usage_lines;
+
+/// Help component
+#[derive(Debug, Clone)]
+pub struct Help {
+    command: String,
+    prefix: String,
+    categories: Vec<String>,
+}
+
+impl Help {
+    /// Create new instance with default settings
+    pub fn new() -> Help {
+        Help {
+            command: "help".to_string(),
+            prefix: "/".to_string(),
+            categories: vec![],
+        }
+    }
+
+    /// Command showing the help, without prefix (default: help)
+    pub fn command(mut self, command: &str) -> Self {
+        self.command = command.to_string();
+        self
+    }
+
+    /// Prefix of the commands in the help (default: /)
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Order of categories, the rest is sorted alphabetically after them
+    pub fn categories(mut self, categories: &[&str]) -> Self {
+        self.categories = categories.iter().map(|c| c.to_string()).collect();
+        self
+    }
+
+    /// Register the help handler
+    pub fn install(self, grammersthon: &mut Grammersthon) {
+        let handlers = grammersthon.handlers.clone();
+        let catalog = grammersthon.get_catalog();
+        let pattern = format!("^{}{}(?:@\\w+)?$", regex::escape(&self.prefix), regex::escape(&self.command));
+        let info = HandlerInfo::new("help", vec![HandlerFilter::Regex(pattern)]).description("Show the available commands");
+        grammersthon.add_handler((info, move |message: Message, data: HandlerData| {
+            let mut schema = CommandSchema::new(handlers.handlers());
+            schema.commands.retain(|c| c.scope.as_ref().map(|s| s.shown_in(&message.chat())).unwrap_or(true));
+            let text = self.render(&schema, &catalog, message.sender().as_ref().and_then(chat_lang));
+            async move {
+                data.reply(text).await?;
+                Ok(())
+            }
+        }));
+    }
+
+    /// Position of category in the help
+    fn category_key<'a>(&self, category: Option<&'a str>) -> (usize, usize, Option<&'a str>) {
+        match category {
+            Some(c) => match self.categories.iter().position(|o| o == c) {
+                Some(i) => (0, i, Some(c)),
+                None => (1, 0, Some(c)),
+            },
+            None => (2, 0, None),
+        }
+    }
+
+    /// Generate the help message
+    fn render(&self, schema: &CommandSchema, catalog: &Catalog, lang: Option<&str>) -> String {
+        let mut sections: HashMap<Option<&str>, Vec<&CommandInfo>> = HashMap::new();
+        let mut seen = vec![];
+        for info in &schema.commands {
+            let command = match &info.command {
+                Some(c) if !info.hidden && !seen.contains(&c) => c,
+                _ => continue
+            };
+            seen.push(command);
+            sections.entry(info.category.as_deref()).or_default().push(info);
+        }
+        let mut sections = sections.into_iter().collect::<Vec<_>>();
+        sections.sort_by_key(|(category, _)| self.category_key(*category));
+
+        let mut text = catalog.get(lang, "help.title").to_string();
+        for (category, commands) in sections {
+            text.push_str(&format!("\n\n{}", category.unwrap_or(catalog.get(lang, "help.other"))));
+            for info in commands {
+                // First line of doc comment
+                let description = info.description.as_deref().and_then(|d| d.lines().next()).filter(|d| !d.is_empty());
+                for usage in usage_lines(&info.args, false) {
+                    text.push_str(&format!("\n{}{}{usage}", self.prefix, info.command.as_deref().unwrap_or_default()));
+                    if let Some(description) = description {
+                        text.push_str(&format!(" - {description}"));
+                    }
+                }
+            }
+        }
+        text
+    }
+}
+
+impl Default for Help {
+    fn default() -> Self {
+        Help::new()
+    }
+}
+
+
+/// Test category ordering
+#[test]
+fn test_help_render() {
+    let command = |command: &str, category: Option<&str>| CommandInfo {
+        handler: command.to_string(),
+        command: Some(command.to_string()),
+        patterns: vec![],
+        description: Some(format!("{command} things\nMore details")),
+        args: vec![],
+        scope: None,
+        category: category.map(String::from),
+        hidden: false,
+    };
+    let mut play = command("play", Some("Music"));
+    play.args = vec![crate::ArgInfo::new("query", "String", true), crate::ArgInfo::new("limit", "u32", false).named(true)];
+    let mut playlist = command("playlist", Some("Music"));
+    playlist.args = vec![crate::ArgInfo::new("0", "Playlist", true).subcommands(vec![
+        crate::SubcommandInfo::new("add", vec![crate::ArgInfo::new("url", "String", false)]),
+        crate::SubcommandInfo::new("clear", vec![]),
+    ])];
+    let schema = CommandSchema { commands: vec![
+        command("ping", None),
+        command("ban", Some("Admin")),
+        play,
+        playlist,
+        command("start", Some("General")),
+        command("play", None),
+        CommandInfo { hidden: true, ..command("secret", None) },
+    ] };
+    let help = Help::new().categories(&["General", "Music"]);
+    assert_eq!(help.render(&schema, &Catalog::new(), None), "Commands\n\n\
+        General\n/start - start things\n\n\
+        Music\n/play <query...> [--limit] - play things\n\
+        /playlist add <url> - playlist things\n/playlist clear - playlist things\n\n\
+        Admin\n/ban - ban things\n\n\
+        Other\n/ping - ping things");
+}
@@ -0,0 +1,240 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Arc;
+use grammers_client::types::Chat;
+use trait_bound_typemap::TypeMap;
+
+use crate::{Data, FromHandlerData, Grammersthon, GrammersthonError, HandlerData, Storage};
+use crate::templates;
+
+/// Language of the built-in strings
+const BUILTIN_LANG: &str = "en";
+
+/// Built-in user facing strings of the framework, override them with `Catalog::add`
+const BUILTIN: &[(&str, &str)] = &[
+    ("wizard.resumed", "Continuing where you left off"),
+    ("wizard.cancelled", "Cancelled"),
+    ("wizard.not_optional", "This can't be skipped"),
+    ("wizard.skip_hint", "({skip} to skip)"),
+    ("validate.invalid", "Invalid value, try again"),
+    ("validate.number", "Expected a number"),
+    ("validate.min", "Has to be at least {min}"),
+    ("validate.max", "Has to be at most {max}"),
+    ("validate.min_len", "Has to be at least {len} characters long"),
+    ("validate.max_len", "Has to be at most {len} characters long"),
+    ("validate.format", "Invalid format, try again"),
+    ("menu.back", "« Back"),
+    ("menu.not_allowed", "Not allowed"),
+    ("help.title", "Commands"),
+    ("help.other", "Other"),
+    ("publisher.queued", "Post #{id} queued ({count} message(s))"),
+    ("publisher.approve", "✅ Approve"),
+    ("publisher.reject", "❌ Reject"),
+    ("publisher.not_allowed", "Not allowed"),
+    ("publisher.scheduled", "Post #{id} scheduled in {time}"),
+    ("publisher.rejected", "Post #{id} rejected"),
+    ("publisher.not_pending", "Post is no longer pending"),
+    ("publisher.usage", "Reply {command} to the message (or album) to queue it"),
+    ("publisher.failed", "Post #{id} failed to publish {count} times and won't be retried: {error}"),
+    ("autoresponder.added", "Auto response added"),
+    ("autoresponder.invalid_pattern", "Invalid pattern: {error}"),
+    ("autoresponder.not_found", "No such auto response"),
+    ("autoresponder.removed", "Auto response removed"),
+    ("autoresponder.empty", "No auto responses"),
+    ("autoresponder.usage_add", "Usage: {command} add <pattern> => <response>"),
+    ("autoresponder.usage", "Usage: {command} add|remove|list"),
+    ("report.title", "⚠️ Report: {reason}"),
+    ("report.from", "From: {name} ({id})"),
+    ("report.message", "Message: {link}"),
+    ("report.admins", "Admins: {admins}"),
+    ("args.invalid", "Invalid arguments"),
+    ("args.invalid_field", "Invalid argument {field}"),
+    ("args.missing", "Missing argument"),
+    ("args.missing_field", "Missing argument {field}"),
+    ("args.usage", "Usage:\n{usage}"),
+    ("args.prompt", "Send the arguments:"),
+    ("args.prompt_field", "Send the {field}:"),
+    ("templates.set", "Template {key} changed"),
+    ("templates.reset", "Template {key} reset to default"),
+    ("templates.unknown", "Unknown template {key}"),
+    ("templates.empty", "No customized templates"),
+    ("templates.builtin", "Template {key} is built-in and can't be customized per chat"),
+    ("admin.not_found", "No value under {key}"),
+    ("admin.empty", "Nothing found"),
+    ("admin.no_update_stats", "Update stats are not enabled"),
+    ("admin.more", "... and {count} more"),
+    ("admin.usage", "Usage: {command} get <key>|keys [prefix]|count [prefix]|handlers|updates|uptime"),
+    ("templates.usage", "Usage: {command} set <key> <template>|reset <key>|show <key>|list"),
+];
+
+/// Message catalog with translations, also used for the built-in replies.
+///
+/// Usage:
+/// ```ignore
+/// grammersthon.catalog(Catalog::new()
+///     .load_json("de", include_str!("../lang/de.json"))?
+///     .add("de", "wizard.cancelled", "Abgebrochen")
+///     .add("en", "greeting", "Hello {name}!"));
+///
+/// #[handler("^/start")]
+/// async fn start(message: Message, data: HandlerData) -> HandlerResult {
+///     message.reply(data.tr("greeting", &[("name", &message.sender().unwrap().name())])).await?;
+///     Ok(())
+/// }
+/// ```
+/// Missing translations fall back to the default language, then to English and then to the key itself.
+#[derive(Debug, Clone)]
+pub struct Catalog {
+    default_lang: String,
+    /// Language -> key -> template
+    messages: Arc<HashMap<String, HashMap<String, String>>>,
+}
+
+impl Catalog {
+    /// Create new catalog with the built-in English strings
+    pub fn new() -> Catalog {
+        Catalog { default_lang: BUILTIN_LANG.to_string(), messages: Arc::new(HashMap::new()) }
+            .add_all(BUILTIN_LANG, BUILTIN.iter().copied())
+    }
+
+    /// Language used when the user's language is unknown or missing translation (default: en)
+    pub fn default_lang(mut self, lang: &str) -> Self {
+        self.default_lang = lang.to_string();
+        self
+    }
+
+    /// Add or override translation
+    pub fn add(mut self, lang: &str, key: &str, template: &str) -> Self {
+        Arc::make_mut(&mut self.messages).entry(lang.to_string()).or_default().insert(key.to_string(), template.to_string());
+        self
+    }
+
+    /// Add multiple translations of language
+    pub fn add_all<K: AsRef<str>, V: AsRef<str>>(self, lang: &str, messages: impl IntoIterator<Item = (K, V)>) -> Self {
+        messages.into_iter().fold(self, |catalog, (k, v)| catalog.add(lang, k.as_ref(), v.as_ref()))
+    }
+
+    /// Add translations of language from JSON object (key -> template)
+    pub fn load_json(self, lang: &str, json: &str) -> Result<Self, GrammersthonError> {
+        let messages: HashMap<String, String> = serde_json::from_str(json)?;
+        Ok(self.add_all(lang, messages))
+    }
+
+    /// All the languages with translations
+    pub fn languages(&self) -> Vec<&str> {
+        self.messages.keys().map(|l| l.as_str()).collect()
+    }
+
+    /// Get the template of key in language (`en-US` falls back to `en`)
+    pub fn get<'a>(&'a self, lang: Option<&str>, key: &'a str) -> &'a str {
+        let base = lang.map(|l| l.split(['-', '_']).next().unwrap_or(l));
+        [lang, base, Some(self.default_lang.as_str()), Some(BUILTIN_LANG)].into_iter()
+            .flatten()
+            .find_map(|lang| self.messages.get(lang)?.get(key))
+            .map(|t| t.as_str())
+            .unwrap_or(key)
+    }
+
+    /// Get the translation and fill the `{name}` placeholders
+    pub fn format(&self, lang: Option<&str>, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        fill(self.get(lang, key), args)
+    }
+
+    /// Whether there is template for key in any language
+    pub fn contains(&self, key: &str) -> bool {
+        self.messages.values().any(|m| m.contains_key(key))
+    }
+
+    /// Whether key is of the built-in strings, these are not customizable per chat (see `templates`)
+    pub fn is_builtin(key: &str) -> bool {
+        BUILTIN.iter().any(|(k, _)| *k == key)
+    }
+
+    /// Get the template with override of the chat (see `templates`)
+    pub fn get_for_chat(&self, storage: &Storage, chat: i64, lang: Option<&str>, key: &str) -> String {
+        match templates::overrides(storage, chat) {
+            Ok(mut overrides) => overrides.remove(key),
+            Err(e) => {
+                warn!("Failed loading template overrides of {chat}: {e}");
+                None
+            }
+        }.unwrap_or_else(|| self.get(lang, key).to_string())
+    }
+
+    /// Get the template with override of the chat and fill the `{name}` placeholders
+    pub fn format_for_chat(&self, storage: &Storage, chat: i64, lang: Option<&str>, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        fill(&self.get_for_chat(storage, chat, lang, key), args)
+    }
+}
+
+/// Fill the `{name}` placeholders of template
+fn fill(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    args.iter().fold(template.to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{name}}}"), &value.to_string())
+    })
+}
+
+impl Default for Catalog {
+    fn default() -> Self {
+        Catalog::new()
+    }
+}
+
+impl FromHandlerData for Catalog {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        Some(data.data::<Catalog>().unwrap_or_default())
+    }
+}
+
+/// Language code of the user (None for groups and channels)
+pub fn chat_lang(chat: &Chat) -> Option<&str> {
+    match chat {
+        Chat::User(user) => user.lang_code().filter(|l| !l.is_empty()),
+        _ => None
+    }
+}
+
+impl HandlerData {
+    /// Language code of the sender
+    pub fn lang(&self) -> Option<String> {
+        self.message.sender().as_ref().and_then(chat_lang).map(|l| l.to_string())
+    }
+
+    /// Translate key to the sender's language (or use the chat override), filling the `{name}` placeholders
+    pub fn tr(&self, key: &str, args: &[(&str, &dyn Display)]) -> String {
+        let catalog = self.data::<Catalog>().unwrap_or_default();
+        match self.data::<Storage>() {
+            Some(storage) => catalog.format_for_chat(&storage, self.message.chat().id(), self.lang().as_deref(), key, args),
+            None => catalog.format(self.lang().as_deref(), key, args)
+        }
+    }
+}
+
+impl Grammersthon {
+    /// Set the message catalog (built-in English strings by default)
+    pub fn catalog(&mut self, catalog: Catalog) -> &mut Self {
+        self.data.insert::<Data<Catalog>>(catalog);
+        self
+    }
+
+    /// Get the message catalog
+    pub fn get_catalog(&self) -> Catalog {
+        self.data.get::<Data<Catalog>>().cloned().unwrap_or_default()
+    }
+}
+
+
+/// Test translation fallbacks
+#[test]
+fn test_catalog() {
+    let catalog = Catalog::new()
+        .add("de", "wizard.cancelled", "Abgebrochen")
+        .add("de", "greeting", "Hallo {name}!")
+        .add("en", "greeting", "Hello {name}!");
+    assert_eq!(catalog.get(Some("de-AT"), "wizard.cancelled"), "Abgebrochen");
+    assert_eq!(catalog.get(Some("de"), "menu.back"), "« Back");
+    assert_eq!(catalog.get(None, "missing.key"), "missing.key");
+    assert_eq!(catalog.format(Some("de"), "greeting", &[("name", &"Anna")]), "Hallo Anna!");
+    assert_eq!(catalog.clone().default_lang("de").format(None, "greeting", &[("name", &1)]), "Hallo 1!");
+    assert!(Catalog::is_builtin("wizard.cancelled") && !Catalog::is_builtin("greeting"));
+}
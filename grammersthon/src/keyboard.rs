@@ -0,0 +1,218 @@
+//! Inline and reply keyboard builders
+//!
+//! Usage:
+//! ```ignore
+//! let keyboard = keyboard![
+//!     ["Yes" => button("vote:yes"), "No" => button("vote:no")],
+//!     ["Results" => payload("results", &Page { page: 1 })],
+//!     ["Docs" => url("https://docs.rs/grammersthon")],
+//! ];
+//! message.reply(InputMessage::text("Vote:").reply_markup(&keyboard.build())).await?;
+//!
+//! // In callback handler
+//! let page: Option<Page> = keyboard::decode_payload("results", query.data());
+//! ```
+
+use grammers_client::types::{button, reply_markup};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Maximum size of callback data allowed by Telegram
+pub const CALLBACK_DATA_LIMIT: usize = 64;
+
+/// Build inline keyboard from rows of buttons, see module docs
+#[macro_export]
+macro_rules! keyboard {
+    ($([$($text:expr => $kind:ident($($value:expr),*)),* $(,)?]),* $(,)?) => {{
+        let keyboard = $crate::keyboard::InlineKeyboard::new();
+        $(
+            let keyboard = keyboard.row();
+            $(let keyboard = keyboard.$kind($text, $($value),*);)*
+        )*
+        keyboard
+    }};
+}
+
+/// Keyboard attached to message
+#[derive(Default)]
+pub struct InlineKeyboard {
+    rows: Vec<Vec<button::Inline>>,
+}
+
+impl InlineKeyboard {
+    /// Create new empty keyboard
+    pub fn new() -> InlineKeyboard {
+        InlineKeyboard::default()
+    }
+
+    /// Start new row of buttons
+    pub fn row(mut self) -> Self {
+        if self.rows.last().map(|r| !r.is_empty()).unwrap_or(true) {
+            self.rows.push(vec![]);
+        }
+        self
+    }
+
+    /// Add button to current row
+    fn push(mut self, button: button::Inline) -> Self {
+        match self.rows.last_mut() {
+            Some(row) => row.push(button),
+            None => self.rows.push(vec![button]),
+        }
+        self
+    }
+
+    /// Add callback button with raw data
+    pub fn button(self, text: &str, data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+        if data.len() > CALLBACK_DATA_LIMIT {
+            warn!("Callback data of button {text} is longer than {CALLBACK_DATA_LIMIT} bytes");
+        }
+        self.push(button::inline(text, data))
+    }
+
+    /// Add callback button with typed payload, decode with `decode_payload`
+    pub fn payload<P: Serialize>(self, text: &str, prefix: &str, payload: &P) -> Self {
+        self.button(text, encode_payload(prefix, payload))
+    }
+
+    /// Add button opening URL
+    pub fn url(self, text: &str, url: &str) -> Self {
+        self.push(button::url(text, url))
+    }
+
+    /// Convert into reply markup for `InputMessage::reply_markup`
+    pub fn build(self) -> reply_markup::Inline {
+        reply_markup::inline(self.rows.into_iter().filter(|r| !r.is_empty()).collect::<Vec<_>>())
+    }
+}
+
+impl From<InlineKeyboard> for reply_markup::Inline {
+    fn from(keyboard: InlineKeyboard) -> Self {
+        keyboard.build()
+    }
+}
+
+/// Keyboard replacing the user's keyboard
+#[derive(Default)]
+pub struct ReplyKeyboard {
+    rows: Vec<Vec<button::Keyboard>>,
+    resize: bool,
+    single_use: bool,
+    selective: bool,
+}
+
+impl ReplyKeyboard {
+    /// Create new empty keyboard
+    pub fn new() -> ReplyKeyboard {
+        ReplyKeyboard::default()
+    }
+
+    /// Start new row of buttons
+    pub fn row(mut self) -> Self {
+        if self.rows.last().map(|r| !r.is_empty()).unwrap_or(true) {
+            self.rows.push(vec![]);
+        }
+        self
+    }
+
+    /// Add button to current row
+    fn push(mut self, button: button::Keyboard) -> Self {
+        match self.rows.last_mut() {
+            Some(row) => row.push(button),
+            None => self.rows.push(vec![button]),
+        }
+        self
+    }
+
+    /// Button sending its text
+    pub fn button(self, text: &str) -> Self {
+        self.push(button::text(text))
+    }
+
+    /// Button sharing user's phone number
+    pub fn request_phone(self, text: &str) -> Self {
+        self.push(button::request_phone(text))
+    }
+
+    /// Button sharing user's location
+    pub fn request_location(self, text: &str) -> Self {
+        self.push(button::request_geo(text))
+    }
+
+    /// Fit the keyboard size to the buttons
+    pub fn resize(mut self, resize: bool) -> Self {
+        self.resize = resize;
+        self
+    }
+
+    /// Hide the keyboard after button is pressed
+    pub fn single_use(mut self, single_use: bool) -> Self {
+        self.single_use = single_use;
+        self
+    }
+
+    /// Show only to mentioned users / sender of the replied message
+    pub fn selective(mut self, selective: bool) -> Self {
+        self.selective = selective;
+        self
+    }
+
+    /// Convert into reply markup for `InputMessage::reply_markup`
+    pub fn build(self) -> reply_markup::Keyboard {
+        let mut keyboard = reply_markup::keyboard(self.rows.into_iter().filter(|r| !r.is_empty()).collect::<Vec<_>>());
+        if self.resize {
+            keyboard = keyboard.fit_size();
+        }
+        if self.single_use {
+            keyboard = keyboard.single_use();
+        }
+        if self.selective {
+            keyboard = keyboard.selective();
+        }
+        keyboard
+    }
+}
+
+impl From<ReplyKeyboard> for reply_markup::Keyboard {
+    fn from(keyboard: ReplyKeyboard) -> Self {
+        keyboard.build()
+    }
+}
+
+/// Encode typed payload as `prefix:json`
+pub fn encode_payload<P: Serialize>(prefix: &str, payload: &P) -> Vec<u8> {
+    let mut data = format!("{prefix}:").into_bytes();
+    // Serialization of plain data can't fail
+    data.extend(serde_json::to_vec(payload).unwrap());
+    data
+}
+
+/// Decode payload encoded with `encode_payload`, None if prefix doesn't match
+pub fn decode_payload<P: DeserializeOwned>(prefix: &str, data: &[u8]) -> Option<P> {
+    let data = data.strip_prefix(prefix.as_bytes())?.strip_prefix(b":")?;
+    serde_json::from_slice(data).ok()
+}
+
+
+/// Test payload encoding
+#[test]
+fn test_payload() {
+    #[derive(Serialize, serde::Deserialize, PartialEq, Debug)]
+    struct Page { page: u32 }
+
+    let data = encode_payload("results", &Page { page: 2 });
+    assert_eq!(data, b"results:{\"page\":2}");
+    assert_eq!(decode_payload::<Page>("results", &data), Some(Page { page: 2 }));
+    assert_eq!(decode_payload::<Page>("result", &data), None);
+}
+
+/// Test keyboard macro
+#[test]
+fn test_keyboard_macro() {
+    let keyboard = keyboard![
+        ["Yes" => button("yes"), "No" => button("no")],
+        ["Page" => payload("page", &1), "Docs" => url("https://docs.rs")],
+    ];
+    assert_eq!(keyboard.rows.iter().map(|r| r.len()).collect::<Vec<_>>(), vec![2, 2]);
+}
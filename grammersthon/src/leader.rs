@@ -0,0 +1,213 @@
+//! Storage-backed leader election for multiple instances of the same bot (blue/green deploys),
+//! only the leader dispatches updates, the others stay connected as hot standby
+//!
+//! Usage:
+//! ```ignore
+//! grammersthon.storage(Storage::new(RedisStorage::new(...)));
+//! let leader = LeaderLock::new().lease(Duration::from_secs(30));
+//! leader.clone().install(&mut grammersthon);
+//!
+//! // Own background jobs
+//! if leader.is_leader() { ... }
+//! ```
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use trait_bound_typemap::TypeMap;
+
+use crate::{Clock, Data, FromHandlerData, Grammersthon, GrammersthonError, HandlerData, ShutdownContext, Storage, SystemClock};
+
+/// Lease saved in storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Lease {
+    instance: String,
+    /// Unix timestamp in milliseconds
+    expires: u64,
+}
+
+/// Leader lock of the instance.
+/// `Publisher`, `Digest`, `FeedIngest`, `Mirror` and `Scheduler` only post from the leader, install the lock before them.
+/// The lease is renewed every third of its duration and released on graceful shutdown, so standby takes over
+/// right away, or after the lease expires on crash. Requires storage shared by the instances with atomic
+/// `StorageBackend::compare_and_set` (the built-in backends are only shared within one process)
+#[derive(Clone)]
+pub struct LeaderLock {
+    key: String,
+    instance: String,
+    lease: Duration,
+    leader: Arc<AtomicBool>,
+    /// Set by `release`, held while renewing so the renewal can't race the release
+    released: Arc<Mutex<bool>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl LeaderLock {
+    /// Create new instance with random instance id and 30s lease
+    pub fn new() -> LeaderLock {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or_default();
+        LeaderLock {
+            key: "grammersthon:leader".to_string(),
+            instance: format!("{}-{nanos:08x}", std::process::id()),
+            lease: Duration::from_secs(30),
+            leader: Arc::new(AtomicBool::new(false)),
+            released: Default::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Storage key of the lock
+    pub fn key(mut self, key: &str) -> Self {
+        self.key = key.to_string();
+        self
+    }
+
+    /// Id of this instance (unique per instance)
+    pub fn instance_id(mut self, id: &str) -> Self {
+        self.instance = id.to_string();
+        self
+    }
+
+    /// How long the lock is held without renewal, standby takes over after it expires
+    pub fn lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /// Is this instance the leader
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::Relaxed)
+    }
+
+    /// Acquire or renew the lease, returns if this instance is the leader
+    pub fn try_acquire(&self, storage: &Storage) -> Result<bool, GrammersthonError> {
+        let now = self.now();
+        let current = storage.get::<Value>(&self.key)?;
+        let lease = current.clone().and_then(|v| serde_json::from_value::<Lease>(v).ok());
+        let leader = match lease {
+            Some(lease) if lease.instance != self.instance && lease.expires > now => false,
+            _ => {
+                let lease = Lease { instance: self.instance.clone(), expires: now + self.lease.as_millis() as u64 };
+                storage.compare_and_set(&self.key, current.as_ref(), &lease)?
+            }
+        };
+        if leader != self.leader.swap(leader, Ordering::Relaxed) {
+            match leader {
+                true => info!("Instance {} is now the leader", self.instance),
+                false => warn!("Instance {} lost the leadership, standing by", self.instance),
+            }
+        }
+        Ok(leader)
+    }
+
+    /// Release the lease if held by this instance and stop renewing it
+    pub fn release(&self, storage: &Storage) -> Result<(), GrammersthonError> {
+        let mut released = self.released.lock().unwrap();
+        *released = true;
+        self.leader.store(false, Ordering::Relaxed);
+        if let Some(current) = storage.get::<Value>(&self.key)? {
+            if serde_json::from_value::<Lease>(current.clone()).map(|l| l.instance == self.instance).unwrap_or(false) {
+                // Expired lease can be taken by anyone
+                let released = Lease { instance: String::new(), expires: 0 };
+                storage.compare_and_set(&self.key, Some(&current), &released)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Start renewing the lease and stop dispatching updates while not the leader, install after setting the storage and clock
+    pub fn install(mut self, grammersthon: &mut Grammersthon) {
+        self.clock = grammersthon.get_clock();
+        let storage = grammersthon.get_storage();
+        grammersthon.data.insert::<Data<LeaderLock>>(self.clone());
+        let (lock, release_storage) = (self.clone(), storage.clone());
+        grammersthon.on_shutdown(move |_: ShutdownContext| {
+            let (lock, storage) = (lock.clone(), release_storage.clone());
+            async move { lock.release(&storage) }
+        });
+        tokio::spawn(async move {
+            loop {
+                {
+                    let released = self.released.lock().unwrap();
+                    if *released {
+                        break;
+                    }
+                    if let Err(e) = self.try_acquire(&storage) {
+                        warn!("Failed renewing leader lease: {e}");
+                        // Can't be sure the lease is still ours
+                        self.leader.store(false, Ordering::Relaxed);
+                    }
+                }
+                self.clock.sleep(self.lease / 3).await;
+            }
+        });
+    }
+
+    /// Current time in milliseconds
+    fn now(&self) -> u64 {
+        self.clock.system_time().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default()
+    }
+}
+
+impl Default for LeaderLock {
+    fn default() -> Self {
+        LeaderLock::new()
+    }
+}
+
+impl fmt::Debug for LeaderLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LeaderLock").field("key", &self.key).field("instance", &self.instance).field("leader", &self.is_leader()).finish()
+    }
+}
+
+impl FromHandlerData for LeaderLock {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.data::<LeaderLock>()
+    }
+}
+
+impl Grammersthon {
+    /// Should updates be dispatched (always without `LeaderLock`)
+    pub(crate) fn is_dispatching(&self) -> bool {
+        is_leader(&self.get_leader_lock())
+    }
+
+    /// Installed `LeaderLock`
+    pub(crate) fn get_leader_lock(&self) -> Option<LeaderLock> {
+        self.data.get::<Data<LeaderLock>>().cloned()
+    }
+}
+
+/// Is this instance the leader (always without `LeaderLock`)
+pub(crate) fn is_leader(leader: &Option<LeaderLock>) -> bool {
+    leader.as_ref().map(|l| l.is_leader()).unwrap_or(true)
+}
+
+
+/// Test taking over the lease
+#[test]
+fn test_leader_lock() {
+    let clock = crate::ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000));
+    let storage = Storage::memory();
+    let lock = |id: &str| LeaderLock { clock: Arc::new(clock.clone()), ..LeaderLock::new().instance_id(id) };
+    let (a, b) = (lock("a"), lock("b"));
+    assert!(a.try_acquire(&storage).unwrap());
+    assert!(!b.try_acquire(&storage).unwrap());
+    assert!(a.try_acquire(&storage).unwrap());
+
+    // Expired
+    clock.advance(Duration::from_secs(31));
+    assert!(b.try_acquire(&storage).unwrap());
+    assert!(!a.try_acquire(&storage).unwrap());
+    assert!(!a.is_leader() && b.is_leader());
+
+    // Released
+    a.release(&storage).unwrap();
+    assert!(!a.try_acquire(&storage).unwrap());
+    b.release(&storage).unwrap();
+    assert!(a.try_acquire(&storage).unwrap());
+}
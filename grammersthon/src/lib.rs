@@ -1,29 +1,135 @@
 #[macro_use] extern crate log;
 
-use grammers_client::Client;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use grammers_client::{Client, Update};
 use grammers_client::types::User;
+use tokio::sync::mpsc;
 use trait_bound_typemap::{CloneSendSyncTypeMap, TypeMap};
 use handler::Handlers;
+use reply_chain::ReplyCache;
 
 pub use grammers_client;
 pub use grammers_session;
 pub use grammers_tl_types;
-pub use grammersthon_macro::{handler, FromArgs};
+pub use grammersthon_macro::{handler, FromArgs, Wizard};
 pub use crate::builder::GrammersthonBuilder;
-pub use crate::error::GrammersthonError;
-pub use crate::handler::{HandlerResult, HandlerFilter, Data, HandlerData, FromHandlerData, Me};
-pub use crate::args::{Args, FromArgs, RawArgs};
+pub use crate::pool::GrammersthonPool;
+pub use crate::session::{SessionStore, SessionLoadFuture, SessionSaveFuture, FileSessionStore, MemorySessionStore};
+#[cfg(feature = "encryption")]
+pub use crate::session::EncryptedSessionStore;
+#[cfg(feature = "metrics")]
+pub use crate::metrics::Metrics;
+pub use crate::shutdown::ShutdownContext;
+pub use crate::health::{Health, HealthConfig, HealthStatus};
+pub use crate::status::StartupReport;
+pub use crate::auth::{AuthPrompt, TerminalPrompt, PasswordPromptStyle, PromptFuture};
+pub use crate::error::{GrammersthonError, ErrorKind, ErrorLog};
+pub use crate::handler::{HandlerResult, HandlerFilter, HandlerGroup, HandlerInfo, HandlerRegistry, ExtractorPolicy, Data, HandlerData, FromHandlerData, ExtractError, ErrorContext, Unmatched, Me, AccountId, ChatId, MessageId, SenderId};
+pub use crate::args::{Args, FromArgs, FromReply, RawArgs, Separated, ArgInfo, SubcommandInfo, from_str_arg, REPLY_ARG};
+pub use crate::schema::{CommandSchema, CommandInfo};
+pub use crate::botfather::{BotProfile, CommandScope};
+pub use crate::media::{Album, Voice, Audio, Video, RoundVideo};
+pub use crate::action::{ChatAction, ActionGuard};
+pub use crate::check::{SelfCheck, Check};
+pub use crate::entities::{Entities, Entity, EntityKind};
+pub use crate::storage::{Storage, StorageBackend, MemoryStorage, FileStorage};
+pub use crate::fsm::Fsm;
+pub use crate::conversation::Conversations;
+pub use crate::wizard::{Wizard, WizardField, WizardOptions, Validator};
+pub use crate::i18n::{Catalog, chat_lang};
+pub use crate::user_ref::UserRef;
+pub use crate::chat_ref::{ChatRef, ChatKind};
+pub use crate::comments::Comment;
+pub use crate::updates::{UpdateKind, UpdateStats};
+pub use crate::migrations::{Migrations, SCHEMA_VERSION_KEY};
+pub use crate::snapshot::{StateSnapshot, SNAPSHOT_VERSION};
+pub use crate::tenant::Tenant;
+pub use crate::clock::{Clock, SystemClock, ManualClock, SleepFuture};
+pub use crate::limiter::SendLimiter;
+pub use crate::leader::LeaderLock;
+pub use crate::gaps::{UpdateGaps, UpdateGap};
+pub use crate::budget::{ApiBudget, Priority};
+pub use crate::outbox::SentMessage;
+pub use crate::delayed::DelayedMessage;
+pub use crate::edits::{Edit, TextChange, text_diff};
+pub use crate::recent::{RecentMessages, Context};
+pub use crate::reply_chain::{ReplyChain, REPLY_CHAIN_LIMIT};
 
+pub mod admin;
+pub mod autoresponder;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+pub mod digest;
+#[cfg(feature = "rss")]
+pub mod feeds;
+pub mod filters;
+pub mod help;
+pub mod keyboard;
+pub mod menu;
+pub mod mutators;
+pub mod middleware;
+pub mod mirror;
+pub mod moderation;
+pub mod publisher;
+pub mod schedule;
+pub mod templates;
+
+mod action;
 mod args;
+mod auth;
+mod botfather;
+mod budget;
+mod chat_ref;
+mod comments;
+mod clock;
+mod check;
+mod conversation;
+mod edits;
+mod entities;
 mod error;
+mod fsm;
+mod gaps;
 mod builder;
 mod handler;
+mod health;
+mod i18n;
+mod leader;
+mod limiter;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod datetime;
+mod delayed;
+mod media;
+mod migrations;
+mod outbox;
+mod pool;
+mod qr_login;
+mod recent;
+mod reply_chain;
+mod report;
+mod schema;
+mod session;
+mod shutdown;
+mod snapshot;
+mod status;
+mod storage;
+mod tenant;
+mod updates;
+mod user_ref;
+mod wizard;
+
+/// How often is the session saved to session store
+const SESSION_AUTOSAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
 
 pub struct Grammersthon {
     client: Client,
     handlers: Handlers,
     me: User,
-    data: CloneSendSyncTypeMap
+    data: CloneSendSyncTypeMap,
+    expect_bot: Option<bool>,
+    /// Updates injected into the event loop (`update_sender`)
+    injected: (mpsc::UnboundedSender<Update>, mpsc::UnboundedReceiver<Update>),
 }
 
 impl Grammersthon {
@@ -32,19 +138,58 @@ impl Grammersthon {
         GrammersthonBuilder::new(api_id, api_hash)
     }
 
-    /// New builder instance from enviromnent variables (`TG_ID`, `TG_HASH`)
+    /// New builder instance from enviromnent variables, None if `TG_ID` or `TG_HASH` is missing or invalid.
+    /// See `try_from_env` for the variables
     pub fn from_env() -> Option<GrammersthonBuilder> {
-        Some(GrammersthonBuilder::new(std::env::var("TG_ID").ok()?.parse().ok()?, &std::env::var("TG_HASH").ok()?))
+        Self::try_from_env().map_err(|e| warn!("Failed configuring from environment: {e}")).ok()
+    }
+
+    /// New builder instance configured from enviromnent variables, so containers can run without code changes:
+    /// - `TG_ID`, `TG_HASH` - API credentials (required)
+    /// - `TG_BOT_TOKEN` - login as bot
+    /// - `TG_PHONE` - login as user
+    /// - `TG_PASSWORD` - 2FA password
+    /// - `TG_SESSION_FILE` - session file, created if missing and saved automatically
+    pub fn try_from_env() -> Result<GrammersthonBuilder, GrammersthonError> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let api_id = var("TG_ID").ok_or(GrammersthonError::MissingParameters("TG_ID"))?;
+        let api_id = api_id.parse().map_err(|e| GrammersthonError::Parse("TG_ID".to_string(), Some(Box::new(e))))?;
+        let mut builder = GrammersthonBuilder::new(api_id, &var("TG_HASH").ok_or(GrammersthonError::MissingParameters("TG_HASH"))?);
+        if let Some(token) = var("TG_BOT_TOKEN") {
+            builder = builder.bot_token(&token);
+        }
+        if let Some(phone) = var("TG_PHONE") {
+            builder = builder.phone(&phone);
+        }
+        if let Some(password) = var("TG_PASSWORD") {
+            builder = builder.password(Some(&password));
+        }
+        if let Some(path) = var("TG_SESSION_FILE") {
+            builder = builder.session_file(path)?;
+        }
+        Ok(builder)
     }
 
     /// Create new instance from client
     pub async fn from_client(client: Client) -> Result<Grammersthon, GrammersthonError> {
-        Ok(Grammersthon {
+        let handlers = Handlers::new();
+        let mut data = CloneSendSyncTypeMap::new();
+        data.insert::<Data<HandlerRegistry>>(handlers.registry());
+        data.insert::<Data<Storage>>(Storage::memory());
+        data.insert::<Data<Conversations>>(Conversations::default());
+        data.insert::<Data<Catalog>>(Catalog::new());
+        data.insert::<Data<ReplyCache>>(ReplyCache::default());
+        let mut grammersthon = Grammersthon {
             me: client.get_me().await?,
             client,
-            handlers: Handlers::new(),
-            data: CloneSendSyncTypeMap::new(),
-        })
+            handlers,
+            data,
+            expect_bot: None,
+            injected: mpsc::unbounded_channel(),
+        };
+        // Answers of conversations go to the waiting handler first
+        grammersthon.interceptor(Conversations::intercept);
+        Ok(grammersthon)
     }
 
     /// Get a client handle
@@ -57,39 +202,156 @@ impl Grammersthon {
         &self.me
     }
 
+    /// Sender of updates dispatched by the event loop as if they were received from Telegram
+    /// (resynced messages, tests)
+    pub fn update_sender(&self) -> mpsc::UnboundedSender<Update> {
+        self.injected.0.clone()
+    }
+
+    /// Export session as string, which can be loaded with `GrammersthonBuilder::session_string`
+    pub fn export_session(&self) -> String {
+        session::encode_session(self.client.session())
+    }
+
     /// Add custom data to use in handlers
     pub fn add_data<T: Send + Sync + Clone + 'static>(&mut self, data: T) -> &mut Self {
         self.data.insert::<Data<T>>(data);
         self
     }
+
+    /// Check that the `Data<T>` arguments of all the handlers were added, otherwise the handlers would never run
+    pub fn validate_data(&self) -> Result<(), GrammersthonError> {
+        for (info, _) in self.handlers.handlers() {
+            if let Some(type_name) = info.missing_data(&self.data).first() {
+                return Err(GrammersthonError::MissingData { handler: info.name.clone(), type_name });
+            }
+        }
+        Ok(())
+    }
     
-    /// Run infinite event loop
+    /// Run infinite event loop. With session store, the session is saved periodically.
+    /// With session store, shutdown hooks (`on_shutdown`) or startup report, the loop returns on Ctrl+C or SIGTERM,
+    /// after running the hooks and saving the session
     pub async fn start_event_loop(&mut self) -> Result<(), GrammersthonError> {
+        self.validate_data()?;
         info!("Starting event loop");
+        let catch_up = self.send_startup_report().await;
+        let session_store = self.get_session_store();
+        let update_stats = self.get_update_stats();
+        let graceful = self.has_shutdown_work();
+        let health = self.get_health();
+        let gaps = self.data.get::<Data<UpdateGaps>>().cloned();
+        if let Some(health) = &health {
+            health.set_running(true);
+        }
+        #[cfg(feature = "metrics")]
+        let metrics = self.get_metrics();
+        let mut autosave = tokio::time::interval_at(tokio::time::Instant::now() + SESSION_AUTOSAVE_INTERVAL, SESSION_AUTOSAVE_INTERVAL);
+        let mut heartbeat = tokio::time::interval(health::HEARTBEAT_INTERVAL);
         loop {
-            let update = match self.client.next_update().await {
+            let update = tokio::select! {
+                update = self.client.next_update() => update,
+                Some(update) = self.injected.1.recv() => Ok(update),
+                _ = autosave.tick(), if session_store.is_some() => {
+                    // In background, slow store doesn't hold the updates
+                    if let Some(store) = session_store.clone() {
+                        let session = self.client.session().save();
+                        tokio::spawn(async move {
+                            if let Err(e) = store.save(&session).await {
+                                error!("Failed saving session: {e}");
+                            }
+                        });
+                    }
+                    continue;
+                },
+                _ = heartbeat.tick(), if health.is_some() => {
+                    if let Some(health) = &health {
+                        health.heartbeat(&self.client);
+                    }
+                    continue;
+                },
+                _ = shutdown::shutdown_signal(), if graceful => {
+                    info!("Shutting down");
+                    return self.shutdown_with("signal").await;
+                },
+            };
+            if let Some(health) = &health {
+                health.received(update.is_ok());
+            }
+            let update = match update {
                 Ok(update) => update,
                 Err(e) => {
                     error!("Grammers getting update error: {e}");
+                    #[cfg(feature = "metrics")]
+                    if let Some(metrics) = &metrics {
+                        metrics.reconnect();
+                    }
                     continue;
                 }
             };
+            if let Some(stats) = &update_stats {
+                stats.record(&update);
+            }
+            if let Some(catch_up) = &catch_up {
+                catch_up.record(&update);
+            }
+            #[cfg(feature = "metrics")]
+            if let Some(metrics) = &metrics {
+                metrics.update(&update);
+            }
+            if let Some(gaps) = &gaps {
+                if !gaps.observe(&self.client, &update, &self.injected.0, self.is_dispatching()) {
+                    continue;
+                }
+            }
+            if self.handlers.ignores(&update) || !self.is_dispatching() {
+                continue;
+            }
 
             // Run handler in own task
-            let handlers = self.handlers.clone();
-            let client = self.client.clone();
-            let me = self.me.clone();
-            let data = self.data.clone();
-            tokio::task::spawn(async move {
-                match handlers.handle(client.clone(), update.clone(), me, data).await {
-                    Ok(_) => (),
-                    Err(e) => {
-                        if let Err(e) = (*handlers.error)(e, client, update).await {
-                            error!("Error occured while running error handler: {e}");
-                        }
-                    },
-                }
-            });
+            for _ in 0..self.handlers.copies(&update) {
+                let handlers = self.handlers.clone();
+                let client = self.client.clone();
+                let me = self.me.clone();
+                let data = self.data.clone();
+                let update = update.clone();
+                #[cfg(feature = "metrics")]
+                let metrics = metrics.clone();
+                let health = health.clone();
+                tokio::task::spawn(async move {
+                    // Handling in own task, so panics can be reported
+                    let matched = Arc::new(Mutex::new(None));
+                    let handling = {
+                        let (handlers, client, update, matched) = (handlers.clone(), client.clone(), update.clone(), matched.clone());
+                        tokio::task::spawn(async move { handlers.handle(client, update, me, data, matched).await })
+                    };
+                    #[cfg(feature = "metrics")]
+                    let start = std::time::Instant::now();
+                    let result = match handling.await {
+                        Ok(result) => result,
+                        Err(e) => match e.try_into_panic() {
+                            Ok(payload) => Err(GrammersthonError::panic(&*payload)),
+                            Err(_) => Ok(()),
+                        },
+                    };
+                    #[cfg(feature = "metrics")]
+                    if let Some(metrics) = &metrics {
+                        metrics.handled(matched.lock().unwrap().as_deref(), start.elapsed(), &result);
+                    }
+                    if let Some(health) = &health {
+                        health.handled(result.as_ref().is_err_and(|e| e.kind() != ErrorKind::Cancelled));
+                    }
+                    match result {
+                        Ok(_) => (),
+                        Err(e) => {
+                            let handler = matched.lock().unwrap().take();
+                            if let Err(e) = (*handlers.error)(e, ErrorContext { client, update, handler }).await {
+                                error!("Error occured while running error handler: {e}");
+                            }
+                        },
+                    }
+                });
+            }
         }
         
     }
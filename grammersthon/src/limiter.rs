@@ -0,0 +1,193 @@
+//! Outgoing messages limiter, queues sends to respect the Telegram limits
+//! (about 30 messages per second globally and 1 per second in a chat)
+//!
+//! Usage:
+//! ```ignore
+//! grammersthon.send_limiter(SendLimiter::new(30, Duration::from_secs(1)));
+//!
+//! #[handler("^/ping")]
+//! async fn ping(data: HandlerData) -> HandlerResult {
+//!     data.reply("pong").await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use grammers_client::InputMessage;
+use grammers_client::types::Message;
+use grammers_session::PackedChat;
+use trait_bound_typemap::TypeMap;
+
+use crate::{ApiBudget, Clock, Data, FromHandlerData, Grammersthon, GrammersthonError, HandlerData, Priority, SystemClock};
+
+/// Outgoing messages limiter (`Grammersthon::send_limiter`).
+/// Only the sends through `HandlerData` helpers (`reply`, `reply_to`, `respond`, `send`, conversations, wizards and usage replies)
+/// and the built-in components are limited, call `SendLimiter::acquire` before sending with the `Client` directly.
+#[derive(Clone)]
+pub struct SendLimiter {
+    state: Arc<Mutex<LimiterState>>,
+    clock: Arc<dyn Clock>,
+}
+
+/// Reserved send times
+struct LimiterState {
+    /// Minimum time between any two sends
+    spacing: Duration,
+    /// Minimum time between sends in the same chat
+    per_chat: Duration,
+    slots: BTreeSet<Instant>,
+    /// Chat -> last reserved send
+    chats: HashMap<i64, Instant>,
+}
+
+impl SendLimiter {
+    /// Create new instance allowing `per_second` messages globally and one message per `per_chat` in each chat
+    pub fn new(per_second: u32, per_chat: Duration) -> SendLimiter {
+        SendLimiter { state: Arc::new(Mutex::new(LimiterState::new(per_second, per_chat))), clock: Arc::new(SystemClock) }
+    }
+
+    /// Wait until message can be sent to chat
+    pub async fn acquire(&self, chat: i64) {
+        let now = self.clock.now();
+        let slot = self.state.lock().unwrap().reserve(chat, now);
+        if slot > now {
+            debug!("Delaying message to {chat} by {:?}", slot - now);
+            self.clock.sleep(slot - now).await;
+        }
+    }
+}
+
+impl Default for SendLimiter {
+    fn default() -> Self {
+        SendLimiter::new(30, Duration::from_secs(1))
+    }
+}
+
+impl LimiterState {
+    /// Create new instance
+    fn new(per_second: u32, per_chat: Duration) -> LimiterState {
+        LimiterState {
+            spacing: Duration::from_secs(1) / per_second.max(1),
+            per_chat,
+            slots: BTreeSet::new(),
+            chats: HashMap::new(),
+        }
+    }
+
+    /// Reserve the first free send time for chat
+    fn reserve(&mut self, chat: i64, now: Instant) -> Instant {
+        let spacing = self.spacing;
+        self.slots.retain(|t| *t + spacing > now);
+        if self.chats.len() > 1024 {
+            let per_chat = self.per_chat;
+            self.chats.retain(|_, t| *t + per_chat > now);
+        }
+
+        let mut slot = self.chats.get(&chat).map(|t| *t + self.per_chat).unwrap_or(now).max(now);
+        // Move after the conflicting slots, they are sorted
+        for reserved in &self.slots {
+            if *reserved + spacing <= slot {
+                continue;
+            }
+            if *reserved >= slot + spacing {
+                break;
+            }
+            slot = *reserved + spacing;
+        }
+        self.slots.insert(slot);
+        self.chats.insert(chat, slot);
+        slot
+    }
+}
+
+impl FromHandlerData for SendLimiter {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.data::<SendLimiter>()
+    }
+}
+
+impl Grammersthon {
+    /// Limit the outgoing messages sent through the `HandlerData` helpers and the built-in components,
+    /// set after setting the clock and before installing the components
+    pub fn send_limiter(&mut self, mut limiter: SendLimiter) -> &mut Self {
+        limiter.clock = self.get_clock();
+        self.data.insert::<Data<SendLimiter>>(limiter);
+        self
+    }
+
+    /// Get the outgoing limiter (`Grammersthon::send_limiter`)
+    pub fn get_send_limiter(&self) -> Option<SendLimiter> {
+        self.data.get::<Data<SendLimiter>>().cloned()
+    }
+}
+
+impl HandlerData {
+    /// Wait for the outgoing limiter and API budget (if set) before sending to chat
+    pub async fn send_slot(&self, chat: i64) {
+        if let Some(budget) = self.data::<ApiBudget>() {
+            budget.acquire(Priority::Interactive).await;
+        }
+        if let Some(limiter) = self.data::<SendLimiter>() {
+            limiter.acquire(chat).await;
+        }
+    }
+
+    /// Reply to the message, respecting the outgoing limiter
+    pub async fn reply(&self, message: impl Into<InputMessage>) -> Result<Message, GrammersthonError> {
+        self.reply_to(&self.message, message).await
+    }
+
+    /// Reply to other message (such as answer in conversation), respecting the outgoing limiter
+    pub async fn reply_to(&self, to: &Message, message: impl Into<InputMessage>) -> Result<Message, GrammersthonError> {
+        self.send_slot(to.chat().id()).await;
+        let sent = to.reply(message).await?;
+        self.track_sent(to.chat().pack(), sent.id());
+        Ok(sent)
+    }
+
+    /// Send message to the chat of the message, in the same thread (see `send`)
+    pub async fn respond(&self, message: impl Into<InputMessage>) -> Result<Message, GrammersthonError> {
+        self.send(self.message.chat().pack(), message).await
+    }
+
+    /// Send message to chat, respecting the outgoing limiter. Messages to the chat of the message stay in its thread
+    /// (comments under channel post, forum topic), use `send_unthreaded` to send to the main chat instead
+    pub async fn send(&self, chat: impl Into<PackedChat>, message: impl Into<InputMessage>) -> Result<Message, GrammersthonError> {
+        let chat = chat.into();
+        let mut message = message.into();
+        if chat.id == self.message.chat().id() {
+            if let Some(thread) = self.thread_id().await {
+                message = message.reply_to(Some(thread));
+            }
+        }
+        self.send_unthreaded(chat, message).await
+    }
+
+    /// Send message to chat as is, respecting the outgoing limiter
+    pub async fn send_unthreaded(&self, chat: impl Into<PackedChat>, message: impl Into<InputMessage>) -> Result<Message, GrammersthonError> {
+        let chat = chat.into();
+        self.send_slot(chat.id).await;
+        let sent = self.client.send_message(chat, message).await?;
+        self.track_sent(chat, sent.id());
+        Ok(sent)
+    }
+}
+
+
+/// Test reserving send times
+#[test]
+fn test_send_limiter() {
+    let mut state = LimiterState::new(10, Duration::from_secs(1));
+    let now = Instant::now();
+    let ms = Duration::from_millis;
+    assert_eq!(state.reserve(1, now), now);
+    // Same chat waits for the per chat limit, other chats only for the global spacing
+    assert_eq!(state.reserve(1, now), now + ms(1000));
+    assert_eq!(state.reserve(2, now), now + ms(100));
+    assert_eq!(state.reserve(3, now), now + ms(200));
+    // Fits between the reserved slots
+    assert_eq!(state.reserve(4, now + ms(350)), now + ms(350));
+    assert_eq!(state.reserve(1, now + ms(1000)), now + ms(2000));
+}
@@ -0,0 +1,101 @@
+use grammers_client::types::{Media, Message};
+use grammers_client::types::media::{Document, Contact, Poll, Geo, GeoLive, Dice, Venue};
+use grammers_tl_types as tl;
+
+use crate::{FromHandlerData, HandlerData};
+
+/// All messages of album (media group), requires `Grammersthon::album_window`
+#[derive(Debug, Clone)]
+pub struct Album(pub Vec<Message>);
+
+/// Voice note
+#[derive(Debug, Clone)]
+pub struct Voice(pub Document);
+
+/// Audio file (not voice note)
+#[derive(Debug, Clone)]
+pub struct Audio(pub Document);
+
+/// Video file (including round videos)
+#[derive(Debug, Clone)]
+pub struct Video(pub Document);
+
+/// Round video message
+#[derive(Debug, Clone)]
+pub struct RoundVideo(pub Document);
+
+/// Voice notes have the voice flag in the audio attribute (ogg audio files can be music too)
+pub(crate) fn is_voice(document: &Document) -> bool {
+    match &document.raw.document {
+        Some(tl::enums::Document::Document(d)) => d.attributes.iter()
+            .any(|a| matches!(a, tl::enums::DocumentAttribute::Audio(audio) if audio.voice)),
+        _ => false
+    }
+}
+
+/// Audio files other than voice notes
+pub(crate) fn is_audio(document: &Document) -> bool {
+    document.mime_type().map(|m| m.starts_with("audio/")).unwrap_or(false) && !is_voice(document)
+}
+
+/// Video files (including round videos and GIFs)
+pub(crate) fn is_video(document: &Document) -> bool {
+    document.mime_type().map(|m| m.starts_with("video/")).unwrap_or(false)
+}
+
+/// Round video messages
+pub(crate) fn is_round_video(document: &Document) -> bool {
+    is_video(document) && document.is_round_message()
+}
+
+/// Get document from message if it matches
+fn document(data: &HandlerData, f: fn(&Document) -> bool) -> Option<Document> {
+    match data.message.media() {
+        Some(Media::Document(d)) if f(&d) => Some(d),
+        _ => None
+    }
+}
+
+impl FromHandlerData for Voice {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        document(data, is_voice).map(Voice)
+    }
+}
+
+impl FromHandlerData for Audio {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        document(data, is_audio).map(Audio)
+    }
+}
+
+impl FromHandlerData for Video {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        document(data, is_video).map(Video)
+    }
+}
+
+impl FromHandlerData for RoundVideo {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        document(data, is_round_video).map(RoundVideo)
+    }
+}
+
+impl FromHandlerData for Album {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.album.clone().map(Album)
+    }
+}
+
+/// Generate FromHandlerData for `Media` variants
+macro_rules! from_media_impl({ $($t:ident)* } => {
+    $(impl FromHandlerData for $t {
+        fn from_data(data: &HandlerData) -> Option<Self> {
+            match data.message.media() {
+                Some(Media::$t(m)) => Some(m),
+                _ => None
+            }
+        }
+    })*
+});
+
+from_media_impl!(Contact Poll Geo GeoLive Dice Venue);
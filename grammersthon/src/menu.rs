@@ -0,0 +1,255 @@
+//! Declarative inline keyboard menus with state kept in `Storage`
+//!
+//! Usage:
+//! ```ignore
+//! let settings = Menu::new("Settings")
+//!     .submenu("Notifications", Menu::new("Notifications")
+//!         .toggle("Silent", "silent")
+//!         .toggle("Mentions only", "mentions"))
+//!     .choice("Language", "lang", &["en", "de", "cs"])
+//!     .install(&mut grammersthon);
+//! grammersthon.add_data(settings);
+//!
+//! #[handler("^/settings$")]
+//! async fn settings(data: HandlerData, menu: Data<MenuHandle>, storage: Storage) -> HandlerResult {
+//!     menu.show(&data, &storage).await?;
+//!     Ok(())
+//! }
+//!
+//! // Somewhere else
+//! let silent = menu.enabled(&storage, chat_id, "silent")?;
+//! ```
+//!
+//! Install the menu after setting the storage with `Grammersthon::storage` and the catalog with `Grammersthon::catalog`.
+
+use std::sync::Arc;
+use grammers_client::{Client, InputMessage};
+use grammers_client::types::{CallbackQuery, Chat};
+
+use crate::{Catalog, Grammersthon, GrammersthonError, HandlerData, Storage, chat_lang};
+use crate::keyboard::InlineKeyboard;
+
+/// Menu with items
+#[derive(Debug, Clone)]
+pub struct Menu {
+    title: String,
+    id: String,
+    items: Vec<MenuItem>,
+    per_user: bool,
+}
+
+#[derive(Debug, Clone)]
+enum MenuItem {
+    Submenu(String, Menu),
+    /// Label, state key
+    Toggle(String, String),
+    /// Label, state key, options
+    Choice(String, String, Vec<String>),
+}
+
+impl Menu {
+    /// Create new menu with title (the root menu title is used as its id)
+    pub fn new(title: &str) -> Menu {
+        Menu {
+            title: title.to_string(),
+            id: title.to_lowercase().replace(' ', "_"),
+            items: vec![],
+            per_user: false,
+        }
+    }
+
+    /// Set id of the menu (used in callback data and storage keys)
+    pub fn id(mut self, id: &str) -> Self {
+        self.id = id.to_string();
+        self
+    }
+
+    /// Keep state per user instead of per chat
+    pub fn per_user(mut self, per_user: bool) -> Self {
+        self.per_user = per_user;
+        self
+    }
+
+    /// Add button opening submenu
+    pub fn submenu(mut self, label: &str, menu: Menu) -> Self {
+        self.items.push(MenuItem::Submenu(label.to_string(), menu));
+        self
+    }
+
+    /// Add on/off toggle stored under key
+    pub fn toggle(mut self, label: &str, key: &str) -> Self {
+        self.items.push(MenuItem::Toggle(label.to_string(), key.to_string()));
+        self
+    }
+
+    /// Add button cycling through options, stored under key (defaults to first option)
+    pub fn choice(mut self, label: &str, key: &str, options: &[&str]) -> Self {
+        self.items.push(MenuItem::Choice(label.to_string(), key.to_string(), options.iter().map(|o| o.to_string()).collect()));
+        self
+    }
+
+    /// Register the callback handler
+    pub fn install(self, grammersthon: &mut Grammersthon) -> MenuHandle {
+        let handle = MenuHandle { menu: Arc::new(self), catalog: grammersthon.get_catalog() };
+        let storage = grammersthon.get_storage();
+        let h = handle.clone();
+        grammersthon.callback_handler(handle.prefix(), move |query, client| {
+            let handle = h.clone();
+            let storage = storage.clone();
+            async move { handle.callback(query, &client, &storage).await }
+        });
+        handle
+    }
+
+    /// Get submenu by path of item indexes
+    fn get(&self, path: &[usize]) -> Option<&Menu> {
+        match path.split_first() {
+            None => Some(self),
+            Some((i, rest)) => match self.items.get(*i)? {
+                MenuItem::Submenu(_, menu) => menu.get(rest),
+                _ => None
+            }
+        }
+    }
+}
+
+/// Handle of installed menu, add it with `add_data` to use in handlers
+#[derive(Debug, Clone)]
+pub struct MenuHandle {
+    menu: Arc<Menu>,
+    catalog: Catalog,
+}
+
+impl MenuHandle {
+    /// Prefix of callback data
+    fn prefix(&self) -> String {
+        format!("menu:{}:", self.menu.id)
+    }
+
+    /// Storage key of state value
+    fn key(&self, owner: i64, key: &str) -> String {
+        format!("menu:{}:{owner}:{key}", self.menu.id)
+    }
+
+    /// Get the state of toggle
+    pub fn enabled(&self, storage: &Storage, owner: i64, key: &str) -> Result<bool, GrammersthonError> {
+        Ok(storage.get::<bool>(&self.key(owner, key))?.unwrap_or(false))
+    }
+
+    /// Get the selected option of choice (None if never changed)
+    pub fn selected(&self, storage: &Storage, owner: i64, key: &str) -> Result<Option<String>, GrammersthonError> {
+        storage.get::<String>(&self.key(owner, key))
+    }
+
+    /// Reply to the message of handler with the root menu
+    pub async fn show(&self, data: &HandlerData, storage: &Storage) -> Result<(), GrammersthonError> {
+        let message = &data.message;
+        let owner = match self.menu.per_user {
+            true => message.sender().map(|s| s.id()).unwrap_or(message.chat().id()),
+            false => message.chat().id(),
+        };
+        let lang = message.sender().as_ref().and_then(chat_lang).map(|l| l.to_string());
+        data.reply(self.render(storage, owner, &[], lang.as_deref())?).await?;
+        Ok(())
+    }
+
+    /// Render menu at path
+    fn render(&self, storage: &Storage, owner: i64, path: &[usize], lang: Option<&str>) -> Result<InputMessage, GrammersthonError> {
+        let menu = self.menu.get(path).ok_or(GrammersthonError::MissingParameters("menu path"))?;
+        let path_str = path.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(".");
+        let item_path = |i: usize| match path.is_empty() {
+            true => i.to_string(),
+            false => format!("{path_str}.{i}"),
+        };
+
+        let mut keyboard = InlineKeyboard::new();
+        for (i, item) in menu.items.iter().enumerate() {
+            let data = format!("{}{owner}:i{}", self.prefix(), item_path(i));
+            let label = match item {
+                MenuItem::Submenu(label, _) => format!("{label} ›"),
+                MenuItem::Toggle(label, key) => match self.enabled(storage, owner, key)? {
+                    true => format!("✅ {label}"),
+                    false => format!("⬜ {label}"),
+                },
+                MenuItem::Choice(label, key, options) => {
+                    let selected = self.selected(storage, owner, key)?.or(options.first().cloned()).unwrap_or_default();
+                    format!("{label}: {selected}")
+                }
+            };
+            keyboard = keyboard.row().button(&label, data);
+        }
+        if let Some((_, parent)) = path.split_last() {
+            let parent = parent.iter().map(|i| i.to_string()).collect::<Vec<_>>().join(".");
+            keyboard = keyboard.row().button(self.catalog.get(lang, "menu.back"), format!("{}{owner}:s{parent}", self.prefix()));
+        }
+        Ok(InputMessage::text(&menu.title).reply_markup(&keyboard.build()))
+    }
+
+    /// Handle button press, only the user who opened per user menu, or admins for per chat menu in groups can use it
+    async fn callback(&self, query: CallbackQuery, client: &Client, storage: &Storage) -> Result<(), GrammersthonError> {
+        let lang = chat_lang(query.sender());
+        let data = String::from_utf8_lossy(query.data()).to_string();
+        let (owner, data) = data[self.prefix().len()..].split_once(':')
+            .and_then(|(owner, data)| Some((owner.parse::<i64>().ok()?, data)))
+            .ok_or_else(|| GrammersthonError::Parse(data.to_string(), None))?;
+        let allowed = match self.menu.per_user {
+            true => query.sender().id() == owner,
+            false => match query.chat() {
+                Chat::User(_) => true,
+                chat => {
+                    let permissions = client.get_permissions(chat, query.sender()).await?;
+                    permissions.is_admin() || permissions.is_creator()
+                }
+            }
+        };
+        if !allowed {
+            query.answer().text(self.catalog.get(lang, "menu.not_allowed")).send().await?;
+            return Ok(());
+        }
+        let (action, path) = data.split_at(data.len().min(1));
+        let path = path.split('.').filter(|p| !p.is_empty()).map(|p| p.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| GrammersthonError::Parse(data.to_string(), Some(Box::new(e))))?;
+
+        // Show menu at path, or activate item at path
+        let show = match (action, path.split_last()) {
+            ("s", _) => path,
+            ("i", Some((index, parent))) => {
+                let menu = self.menu.get(parent).ok_or(GrammersthonError::MissingParameters("menu path"))?;
+                match menu.items.get(*index) {
+                    Some(MenuItem::Submenu(..)) => path.clone(),
+                    Some(MenuItem::Toggle(_, key)) => {
+                        let value = self.enabled(storage, owner, key)?;
+                        storage.set(&self.key(owner, key), &!value)?;
+                        parent.to_vec()
+                    },
+                    Some(MenuItem::Choice(_, key, options)) => {
+                        let selected = self.selected(storage, owner, key)?;
+                        let i = options.iter().position(|o| Some(o) == selected.as_ref()).map(|i| i + 1).unwrap_or(1);
+                        if let Some(option) = options.get(i % options.len().max(1)) {
+                            storage.set(&self.key(owner, key), option)?;
+                        }
+                        parent.to_vec()
+                    },
+                    None => return Err(GrammersthonError::MissingParameters("menu item")),
+                }
+            },
+            _ => return Err(GrammersthonError::Parse(data.to_string(), None)),
+        };
+        query.answer().edit(self.render(storage, owner, &show, lang)?).await?;
+        Ok(())
+    }
+}
+
+
+/// Test submenu lookup
+#[test]
+fn test_menu_path() {
+    let menu = Menu::new("Settings")
+        .toggle("A", "a")
+        .submenu("Sub", Menu::new("Sub").submenu("Deep", Menu::new("Deep")));
+    assert_eq!(menu.get(&[]).unwrap().title, "Settings");
+    assert_eq!(menu.get(&[1, 0]).unwrap().title, "Deep");
+    assert!(menu.get(&[0]).is_none());
+    assert!(menu.get(&[5]).is_none());
+}
@@ -0,0 +1,154 @@
+use std::time::Duration;
+use grammers_client::Update;
+use grammers_client::client::chats::InvocationError;
+use prometheus::{Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder};
+use trait_bound_typemap::TypeMap;
+
+use crate::{Data, ErrorKind, FromHandlerData, Grammersthon, GrammersthonError, HandlerData, UpdateKind};
+
+/// Prometheus metrics of the bot (requires the `metrics` feature), collected once the handle is created:
+/// ```ignore
+/// let metrics = grammersthon.metrics_handle();
+/// // Expose in own HTTP server
+/// let body = metrics.render();
+/// // Or register in own registry
+/// metrics.register(&registry)?;
+/// ```
+/// Metrics:
+/// - `grammersthon_updates_total{kind}` - received updates by `UpdateKind`
+/// - `grammersthon_handler_matches_total{handler}` - matched handlers
+/// - `grammersthon_handler_duration_seconds{handler}` - duration of the matched handlers
+/// - `grammersthon_errors_total{kind}` - errors passed to the error handler, by `ErrorKind`
+/// - `grammersthon_flood_waits_total` - `FLOOD_WAIT` errors passed to the error handler
+/// - `grammersthon_reconnects_total` - failures getting updates, after which the client reconnects
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    updates: IntCounterVec,
+    matches: IntCounterVec,
+    duration: HistogramVec,
+    errors: IntCounterVec,
+    flood_waits: IntCounter,
+    reconnects: IntCounter,
+}
+
+impl Metrics {
+    /// Create new instance with own registry
+    pub fn new() -> Metrics {
+        let metrics = Metrics {
+            registry: Registry::new(),
+            updates: IntCounterVec::new(Opts::new("grammersthon_updates_total", "Received updates"), &["kind"]).unwrap(),
+            matches: IntCounterVec::new(Opts::new("grammersthon_handler_matches_total", "Matched handlers"), &["handler"]).unwrap(),
+            duration: HistogramVec::new(HistogramOpts::new("grammersthon_handler_duration_seconds", "Duration of handlers"), &["handler"]).unwrap(),
+            errors: IntCounterVec::new(Opts::new("grammersthon_errors_total", "Errors passed to the error handler"), &["kind"]).unwrap(),
+            flood_waits: IntCounter::new("grammersthon_flood_waits_total", "Flood wait errors").unwrap(),
+            reconnects: IntCounter::new("grammersthon_reconnects_total", "Failures getting updates").unwrap(),
+        };
+        metrics.register(&metrics.registry).unwrap();
+        metrics
+    }
+
+    /// Register the metrics in other registry
+    pub fn register(&self, registry: &Registry) -> Result<(), prometheus::Error> {
+        registry.register(Box::new(self.updates.clone()))?;
+        registry.register(Box::new(self.matches.clone()))?;
+        registry.register(Box::new(self.duration.clone()))?;
+        registry.register(Box::new(self.errors.clone()))?;
+        registry.register(Box::new(self.flood_waits.clone()))?;
+        registry.register(Box::new(self.reconnects.clone()))?;
+        Ok(())
+    }
+
+    /// Own registry with the metrics
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Metrics in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut buffer = vec![];
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer).ok();
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+
+    /// Count received update
+    pub(crate) fn update(&self, update: &Update) {
+        self.updates.with_label_values(&[&format!("{:?}", UpdateKind::of(update))]).inc();
+    }
+
+    /// Record handled update, `handler` is the matched handler
+    pub(crate) fn handled(&self, handler: Option<&str>, duration: Duration, result: &Result<(), GrammersthonError>) {
+        if let Some(handler) = handler {
+            self.matches.with_label_values(&[handler]).inc();
+            self.duration.with_label_values(&[handler]).observe(duration.as_secs_f64());
+        }
+        if let Err(e) = result {
+            if e.kind() == ErrorKind::Cancelled {
+                return;
+            }
+            self.errors.with_label_values(&[&format!("{:?}", e.kind())]).inc();
+            if is_flood_wait(e) {
+                self.flood_waits.inc();
+            }
+        }
+    }
+
+    /// Count failure getting updates
+    pub(crate) fn reconnect(&self) {
+        self.reconnects.inc();
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+/// Is the error `FLOOD_WAIT` (also wrapped)
+fn is_flood_wait(e: &GrammersthonError) -> bool {
+    match e {
+        GrammersthonError::InvocationError(InvocationError::Rpc(rpc)) => rpc.name.starts_with("FLOOD_WAIT"),
+        GrammersthonError::Error(e) => e.downcast_ref::<GrammersthonError>().map(is_flood_wait).unwrap_or(false),
+        _ => false,
+    }
+}
+
+impl FromHandlerData for Metrics {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.data::<Metrics>()
+    }
+}
+
+impl Grammersthon {
+    /// Get the metrics handle, metrics are collected from the first call
+    pub fn metrics_handle(&mut self) -> Metrics {
+        if let Some(metrics) = self.data.get::<Data<Metrics>>() {
+            return metrics.clone();
+        }
+        let metrics = Metrics::new();
+        self.data.insert::<Data<Metrics>>(metrics.clone());
+        metrics
+    }
+
+    /// Metrics handle, if created
+    pub(crate) fn get_metrics(&self) -> Option<Metrics> {
+        self.data.get::<Data<Metrics>>().cloned()
+    }
+}
+
+
+/// Test rendering the collected metrics
+#[test]
+fn test_metrics() {
+    let metrics = Metrics::new();
+    metrics.handled(Some("ping"), Duration::from_millis(5), &Ok(()));
+    metrics.handled(None, Duration::ZERO, &Err(GrammersthonError::Timeout));
+    metrics.handled(None, Duration::ZERO, &Err(GrammersthonError::Cancelled));
+    metrics.reconnect();
+    let output = metrics.render();
+    assert!(output.contains("grammersthon_handler_matches_total{handler=\"ping\"} 1"));
+    assert!(output.contains("grammersthon_errors_total{kind=\"Timeout\"} 1"));
+    assert!(!output.contains("Cancelled"));
+    assert!(output.contains("grammersthon_reconnects_total 1"));
+}
@@ -0,0 +1,323 @@
+//! Built-in interceptors
+//! 
+//! Usage:
+//! ```ignore
+//! grammersthon.interceptor(middleware::loop_guard(4, Duration::from_secs(60)));
+//! Throttle::new().per_user(5, Duration::from_secs(10)).install(&mut grammersthon);
+//! ```
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::{Grammersthon, HandlerData, HandlerResult, GrammersthonError};
+
+/// Future returned by built-in interceptors
+pub type InterceptorFuture = Pin<Box<dyn Future<Output = Result<HandlerData, GrammersthonError>> + Send + Sync>>;
+type FloodHookFn = dyn Fn(HandlerData, ThrottleScope) -> Pin<Box<dyn Future<Output = HandlerResult> + Send + Sync>> + Send + Sync;
+
+/// Suppress reply loops between bots: cancels the message if the same (normalized) content 
+/// was sent `threshold` times by at least 2 different senders in one chat within `window`
+pub fn loop_guard(threshold: usize, window: Duration) -> impl Fn(HandlerData) -> InterceptorFuture + Send + Sync + 'static {
+    let detector = Arc::new(Mutex::new(LoopDetector::new(threshold, window)));
+    move |data: HandlerData| {
+        let detector = detector.clone();
+        Box::pin(async move {
+            let chat = data.message.chat().id();
+            let sender = data.message.sender().map(|s| s.id()).unwrap_or(chat);
+            if detector.lock().unwrap().check(chat, sender, data.message.text(), data.clock().now()) {
+                warn!("Reply loop detected in chat {chat}, ignoring message");
+                return Err(GrammersthonError::Cancelled);
+            }
+            Ok(data)
+        })
+    }
+}
+
+/// Tracks recently seen content per chat
+struct LoopDetector {
+    threshold: usize,
+    window: Duration,
+    /// (chat, content hash) -> (time, sender)
+    seen: HashMap<(i64, u64), VecDeque<(Instant, i64)>>
+}
+
+impl LoopDetector {
+    /// Create new instance
+    fn new(threshold: usize, window: Duration) -> LoopDetector {
+        LoopDetector { threshold, window, seen: HashMap::new() }
+    }
+
+    /// Lowercase, ignore digits and collapse whitespace, so counters don't break the detection
+    fn normalize(text: &str) -> String {
+        text.to_lowercase()
+            .split_whitespace()
+            .map(|w| w.chars().filter(|c| !c.is_ascii_digit()).collect::<String>())
+            .filter(|w| !w.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Register message, returns true if it is part of a loop
+    fn check(&mut self, chat: i64, sender: i64, text: &str, now: Instant) -> bool {
+        let text = Self::normalize(text);
+        if text.is_empty() {
+            return false;
+        }
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        let key = (chat, hasher.finish());
+
+        // Cleanup expired
+        let window = self.window;
+        if self.seen.len() > 1024 {
+            self.seen.retain(|_, v| v.back().map(|(t, _)| now.duration_since(*t) < window).unwrap_or(false));
+        }
+
+        let entries = self.seen.entry(key).or_default();
+        while entries.front().map(|(t, _)| now.duration_since(*t) >= window).unwrap_or(false) {
+            entries.pop_front();
+        }
+        entries.push_back((now, sender));
+
+        let senders = entries.iter().map(|(_, s)| *s).collect::<HashSet<_>>();
+        entries.len() >= self.threshold && senders.len() >= 2
+    }
+}
+
+/// Which limit of `Throttle` was exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ThrottleScope {
+    /// Sender id
+    User(i64),
+    /// Chat id
+    Chat(i64),
+}
+
+/// Anti-flood rate limiter, drops (or delays) excess messages before any handler runs.
+/// Own messages and the exempted users aren't limited
+pub struct Throttle {
+    user: Option<(usize, Duration)>,
+    chat: Option<(usize, Duration)>,
+    max_delay: Duration,
+    exempt: HashSet<i64>,
+    on_flood: Option<Arc<Box<FloodHookFn>>>,
+}
+
+impl Throttle {
+    /// Create new instance without any limits
+    pub fn new() -> Throttle {
+        Throttle { user: None, chat: None, max_delay: Duration::ZERO, exempt: HashSet::new(), on_flood: None }
+    }
+
+    /// At most `max` messages from one user within `window` (across all chats)
+    pub fn per_user(mut self, max: usize, window: Duration) -> Self {
+        self.user = Some((max, window));
+        self
+    }
+
+    /// At most `max` messages in one chat within `window`
+    pub fn per_chat(mut self, max: usize, window: Duration) -> Self {
+        self.chat = Some((max, window));
+        self
+    }
+
+    /// Delay excess messages until the limit allows them, if it is within `max`. Otherwise they are dropped
+    pub fn delay(mut self, max: Duration) -> Self {
+        self.max_delay = max;
+        self
+    }
+
+    /// Users which aren't limited
+    pub fn exempt(mut self, ids: impl IntoIterator<Item = i64>) -> Self {
+        self.exempt.extend(ids);
+        self
+    }
+
+    /// Called with the first dropped message of the flooding user or chat, once per window:
+    /// ```ignore
+    /// .on_flood(|data: HandlerData, _| async move {
+    ///     data.message.reply("Slow down!").await?;
+    ///     Ok(())
+    /// })
+    /// ```
+    pub fn on_flood<H, F>(mut self, hook: H) -> Self
+    where
+        H: (Fn(HandlerData, ThrottleScope) -> F) + Send + Sync + 'static,
+        F: Future<Output = HandlerResult> + Send + Sync + 'static
+    {
+        self.on_flood = Some(Arc::new(Box::new(move |d, s| Box::pin(hook(d, s)))));
+        self
+    }
+
+    /// Register the interceptor, install after setting the clock
+    pub fn install(self, grammersthon: &mut Grammersthon) {
+        let state = Arc::new(Mutex::new(ThrottleState {
+            user: self.user.map(|(max, window)| RateWindow::new(max, window)),
+            chat: self.chat.map(|(max, window)| RateWindow::new(max, window)),
+            notified: HashMap::new(),
+        }));
+        let clock = grammersthon.get_clock();
+        let this = Arc::new(self);
+        grammersthon.interceptor(move |data: HandlerData| {
+            let (this, state, clock) = (this.clone(), state.clone(), clock.clone());
+            async move {
+                let chat = data.message.chat().id();
+                let sender = data.message.sender().map(|s| s.id()).unwrap_or(chat);
+                if sender == data.me.id() || this.exempt.contains(&sender) {
+                    return Ok(data);
+                }
+                let mut delayed = Duration::ZERO;
+                loop {
+                    let now = clock.now();
+                    let (scope, wait) = match state.lock().unwrap().check(sender, chat, now) {
+                        Some(exceeded) => exceeded,
+                        None => return Ok(data)
+                    };
+                    if delayed + wait > this.max_delay {
+                        debug!("Throttled message {} of {sender} in {chat}", data.message.id());
+                        let notify = state.lock().unwrap().notify(scope, now);
+                        if let (true, Some(hook)) = (notify, &this.on_flood) {
+                            if let Err(e) = (*hook)(data, scope).await {
+                                warn!("Flood hook failed: {e}");
+                            }
+                        }
+                        return Err(GrammersthonError::Cancelled);
+                    }
+                    clock.sleep(wait).await;
+                    delayed += wait;
+                }
+            }
+        });
+    }
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Throttle::new()
+    }
+}
+
+/// Bookkeeping of `Throttle`
+struct ThrottleState {
+    user: Option<RateWindow>,
+    chat: Option<RateWindow>,
+    /// Last flood notification
+    notified: HashMap<ThrottleScope, Instant>,
+}
+
+impl ThrottleState {
+    /// Register message if allowed, otherwise returns the exceeded limit and how long until it allows the message
+    fn check(&mut self, user: i64, chat: i64, now: Instant) -> Option<(ThrottleScope, Duration)> {
+        let user_wait = self.user.as_mut().and_then(|w| w.wait(user, now)).map(|w| (ThrottleScope::User(user), w));
+        let chat_wait = self.chat.as_mut().and_then(|w| w.wait(chat, now)).map(|w| (ThrottleScope::Chat(chat), w));
+        match (user_wait, chat_wait) {
+            (None, None) => {
+                self.user.iter_mut().for_each(|w| w.hit(user, now));
+                self.chat.iter_mut().for_each(|w| w.hit(chat, now));
+                None
+            },
+            (a, b) => a.into_iter().chain(b).max_by_key(|(_, wait)| *wait)
+        }
+    }
+
+    /// Should the flood be notified (once per window)
+    fn notify(&mut self, scope: ThrottleScope, now: Instant) -> bool {
+        let window = match scope {
+            ThrottleScope::User(_) => self.user.as_ref(),
+            ThrottleScope::Chat(_) => self.chat.as_ref(),
+        }.map(|w| w.window).unwrap_or_default();
+        if self.notified.len() > 1024 {
+            self.notified.retain(|_, t| now.duration_since(*t) < window);
+        }
+        match self.notified.get(&scope) {
+            Some(t) if now.duration_since(*t) < window => false,
+            _ => {
+                self.notified.insert(scope, now);
+                true
+            }
+        }
+    }
+}
+
+/// Sliding window rate limit per id
+struct RateWindow {
+    max: usize,
+    window: Duration,
+    hits: HashMap<i64, VecDeque<Instant>>,
+}
+
+impl RateWindow {
+    /// Create new instance
+    fn new(max: usize, window: Duration) -> RateWindow {
+        RateWindow { max, window, hits: HashMap::new() }
+    }
+
+    /// How long until the id can hit again, None if it can now
+    fn wait(&mut self, id: i64, now: Instant) -> Option<Duration> {
+        let window = self.window;
+        if self.hits.len() > 1024 {
+            self.hits.retain(|_, times| times.back().map(|t| now.duration_since(*t) < window).unwrap_or(false));
+        }
+        let times = self.hits.entry(id).or_default();
+        while times.front().map(|t| now.duration_since(*t) >= window).unwrap_or(false) {
+            times.pop_front();
+        }
+        if times.len() < self.max {
+            return None;
+        }
+        Some(times.front().map(|t| window - now.duration_since(*t)).unwrap_or(window))
+    }
+
+    /// Register hit of the id
+    fn hit(&mut self, id: i64, now: Instant) {
+        self.hits.entry(id).or_default().push_back(now);
+    }
+}
+
+
+/// Test loop detection
+#[test]
+fn test_loop_detector() {
+    let mut detector = LoopDetector::new(3, Duration::from_secs(10));
+    let now = Instant::now();
+    assert!(!detector.check(1, 10, "Hello 1", now));
+    assert!(!detector.check(1, 20, "hello   2", now));
+    // Different chat
+    assert!(!detector.check(2, 10, "hello 3", now));
+    assert!(detector.check(1, 10, "HELLO 3", now));
+    // Single sender isn't a loop
+    assert!(!detector.check(3, 10, "spam", now));
+    assert!(!detector.check(3, 10, "spam", now));
+    assert!(!detector.check(3, 10, "spam", now));
+    // Outside of window
+    assert!(!detector.check(1, 20, "hello", now + Duration::from_secs(11)));
+}
+
+/// Test throttle limits
+#[test]
+fn test_throttle() {
+    let mut state = ThrottleState {
+        user: Some(RateWindow::new(2, Duration::from_secs(10))),
+        chat: Some(RateWindow::new(3, Duration::from_secs(10))),
+        notified: HashMap::new(),
+    };
+    let now = Instant::now();
+    assert_eq!(state.check(1, 100, now), None);
+    assert_eq!(state.check(1, 100, now + Duration::from_secs(4)), None);
+    assert_eq!(state.check(1, 100, now + Duration::from_secs(5)), Some((ThrottleScope::User(1), Duration::from_secs(5))));
+    // Other user in the same chat, until the chat limit
+    assert_eq!(state.check(2, 100, now + Duration::from_secs(5)), None);
+    assert_eq!(state.check(3, 100, now + Duration::from_secs(6)), Some((ThrottleScope::Chat(100), Duration::from_secs(4))));
+    assert_eq!(state.check(3, 200, now + Duration::from_secs(6)), None);
+    // Window passed
+    assert_eq!(state.check(1, 200, now + Duration::from_secs(10)), None);
+
+    assert!(state.notify(ThrottleScope::User(1), now));
+    assert!(!state.notify(ThrottleScope::User(1), now + Duration::from_secs(9)));
+    assert!(state.notify(ThrottleScope::User(1), now + Duration::from_secs(10)));
+}
@@ -0,0 +1,105 @@
+use std::sync::Arc;
+use serde_json::Value;
+
+use crate::{Grammersthon, GrammersthonError, Storage};
+
+/// Storage key of the schema version
+pub const SCHEMA_VERSION_KEY: &str = "grammersthon:schema_version";
+
+type MigrationFn = dyn Fn(&Storage) -> Result<(), GrammersthonError> + Send + Sync;
+
+/// Versioned migrations of the persisted state, run at startup so changed formats of stored values
+/// (FSM states, component data) are converted instead of failing to deserialize:
+/// ```ignore
+/// grammersthon.storage(Storage::file("storage.json")?);
+/// Migrations::new()
+///     .add(1, |storage| storage.transform("fsm:", |state| Ok(Some(json!({ "step": state })))))
+///     .add(2, |storage| { storage.remove("old_key")?; Ok(()) })
+///     .install(&mut grammersthon)?;
+/// ```
+/// The schema version is kept in the storage, only migrations with higher version run (in version order)
+/// and the version is saved after each one. Storage from newer release (higher version) is rejected
+#[derive(Clone, Default)]
+pub struct Migrations {
+    migrations: Vec<(u32, Arc<MigrationFn>)>,
+}
+
+impl Migrations {
+    /// Create new instance
+    pub fn new() -> Migrations {
+        Migrations::default()
+    }
+
+    /// Add migration to version (starting at 1)
+    pub fn add(mut self, version: u32, migration: impl Fn(&Storage) -> Result<(), GrammersthonError> + Send + Sync + 'static) -> Self {
+        self.migrations.push((version, Arc::new(migration)));
+        self.migrations.sort_by_key(|(v, _)| *v);
+        self
+    }
+
+    /// Latest schema version
+    pub fn version(&self) -> u32 {
+        self.migrations.last().map(|(v, _)| *v).unwrap_or(0)
+    }
+
+    /// Run the pending migrations, returns the new schema version
+    pub fn run(&self, storage: &Storage) -> Result<u32, GrammersthonError> {
+        let current = storage.get::<u32>(SCHEMA_VERSION_KEY)?.unwrap_or(0);
+        if current > self.version() {
+            return Err(GrammersthonError::Parse(format!("storage schema version {current} (newer than {})", self.version()), None));
+        }
+        for (version, migration) in self.migrations.iter().filter(|(v, _)| *v > current) {
+            info!("Migrating storage to schema version {version}");
+            migration(storage)?;
+            storage.set(SCHEMA_VERSION_KEY, version)?;
+        }
+        Ok(current.max(self.version()))
+    }
+
+    /// Run the migrations on the storage of grammersthon, install after setting the storage
+    pub fn install(self, grammersthon: &mut Grammersthon) -> Result<(), GrammersthonError> {
+        self.run(&grammersthon.get_storage())?;
+        Ok(())
+    }
+}
+
+impl Storage {
+    /// Rewrite all the values with key prefix, returning None removes the value
+    pub fn transform(&self, prefix: &str, f: impl Fn(Value) -> Result<Option<Value>, GrammersthonError>) -> Result<(), GrammersthonError> {
+        for key in self.keys(prefix)? {
+            let value = match self.get::<Value>(&key)? {
+                Some(value) => value,
+                None => continue,
+            };
+            match f(value)? {
+                Some(value) => self.set(&key, &value)?,
+                None => { self.remove(&key)?; },
+            }
+        }
+        Ok(())
+    }
+}
+
+
+/// Test running migrations
+#[test]
+fn test_migrations() {
+    let storage = Storage::memory();
+    storage.set("fsm:1", "start").unwrap();
+    storage.set("fsm:2", "end").unwrap();
+    let migrations = Migrations::new()
+        .add(2, |storage| storage.transform("fsm:", |v| Ok((v["step"] != "end").then_some(v))))
+        .add(1, |storage| storage.transform("fsm:", |v| Ok(Some(serde_json::json!({ "step": v })))));
+    assert_eq!(migrations.run(&storage).unwrap(), 2);
+    assert_eq!(storage.get::<Value>("fsm:1").unwrap(), Some(serde_json::json!({ "step": "start" })));
+    assert_eq!(storage.get::<Value>("fsm:2").unwrap(), None);
+
+    // Already migrated
+    let migrations = migrations.add(3, |storage| storage.transform("fsm:", |_| Ok(None)));
+    assert_eq!(migrations.run(&storage).unwrap(), 3);
+    assert!(storage.keys("fsm:").unwrap().is_empty());
+    assert_eq!(migrations.run(&storage).unwrap(), 3);
+
+    // Newer storage
+    assert!(Migrations::new().add(1, |_| Ok(())).run(&storage).is_err());
+}
@@ -0,0 +1,313 @@
+//! Mirror messages from source chats to destination chats, including edits and deletions
+//!
+//! Usage:
+//! ```ignore
+//! Mirror::new([source_chat_id], [destination.pack()])
+//!     .copy(true)
+//!     .filter(filters::photo())
+//!     .install(&mut grammersthon);
+//! ```
+//!
+//! Albums are mirrored as albums only when `Grammersthon::album_window` is enabled.
+//! Edits are only mirrored in copy mode (forwarded messages can't be edited).
+//! Source -> destination message ids are kept in `Storage` for `retention` (default 7 days),
+//! older messages are no longer edited or deleted.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+use grammers_client::{Client, InputMessage, Update};
+use grammers_client::types::{Message, InputMedia};
+use grammers_session::PackedChat;
+use serde::{Serialize, Deserialize};
+
+use crate::{ApiBudget, Clock, Grammersthon, GrammersthonError, HandlerData, HandlerFilter, LeaderLock, Priority, Storage};
+use crate::{budget, leader};
+
+/// How often are expired mappings removed
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Copy of the message in destination chat
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Mirrored {
+    chat: Vec<u8>,
+    id: i32,
+}
+
+/// Copies of source message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Mapping {
+    copies: Vec<Mirrored>,
+    /// Unix timestamp of mirroring, in seconds
+    time: u64,
+}
+
+/// Mirror component
+#[derive(Clone)]
+pub struct Mirror {
+    sources: HashSet<i64>,
+    destinations: Vec<PackedChat>,
+    filters: Vec<HandlerFilter>,
+    copy: bool,
+    edits: bool,
+    deletions: bool,
+    key: String,
+    retention: Duration,
+    budget: Option<ApiBudget>,
+    leader: Option<LeaderLock>,
+    clock: Option<Arc<dyn Clock>>,
+}
+
+impl Mirror {
+    /// Create new mirror from source chat ids to destination chats
+    pub fn new(sources: impl IntoIterator<Item = i64>, destinations: impl IntoIterator<Item = PackedChat>) -> Mirror {
+        Mirror {
+            sources: sources.into_iter().collect(),
+            destinations: destinations.into_iter().collect(),
+            filters: vec![],
+            copy: false,
+            edits: true,
+            deletions: true,
+            key: "mirror".to_string(),
+            retention: Duration::from_secs(7 * 24 * 60 * 60),
+            budget: None,
+            leader: None,
+            clock: None,
+        }
+    }
+
+    /// Only mirror messages matching filter (all filters have to match)
+    pub fn filter(mut self, filter: HandlerFilter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Send as copy without the author, instead of forwarding (default: false)
+    pub fn copy(mut self, copy: bool) -> Self {
+        self.copy = copy;
+        self
+    }
+
+    /// Mirror edits of messages (copy mode only, default: true)
+    pub fn edits(mut self, edits: bool) -> Self {
+        self.edits = edits;
+        self
+    }
+
+    /// Delete mirrored messages when the source is deleted (default: true)
+    pub fn deletions(mut self, deletions: bool) -> Self {
+        self.deletions = deletions;
+        self
+    }
+
+    /// Storage key prefix for the message mapping
+    pub fn key(mut self, key: &str) -> Self {
+        self.key = key.to_string();
+        self
+    }
+
+    /// How long are the message mappings kept, for mirroring edits and deletions (default: 7 days)
+    pub fn retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Register the interceptor and update hook, install after setting the storage and clock (and `LeaderLock`)
+    pub fn install(mut self, grammersthon: &mut Grammersthon) {
+        self.budget = grammersthon.get_api_budget();
+        self.leader = grammersthon.get_leader_lock();
+        self.clock = Some(grammersthon.get_clock());
+        let this = Arc::new(self);
+
+        // Expired mappings
+        let (mirror, storage) = (this.clone(), grammersthon.get_storage());
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = mirror.prune(&storage) {
+                    warn!("Failed removing expired mirror mappings: {e}");
+                }
+                mirror.clock().sleep(PRUNE_INTERVAL).await;
+            }
+        });
+
+        // New messages
+        let mirror = this.clone();
+        grammersthon.interceptor(move |data: HandlerData| {
+            let mirror = mirror.clone();
+            async move {
+                if leader::is_leader(&mirror.leader) && mirror.sources.contains(&data.message.chat().id()) && mirror.is_match(&data).await {
+                    let data = data.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = mirror.mirror(&data).await {
+                            warn!("Failed mirroring message {} from {}: {e}", data.message.id(), data.message.chat().id());
+                        }
+                    });
+                }
+                Ok(data)
+            }
+        });
+
+        // Edits and deletions
+        let storage = grammersthon.get_storage();
+        grammersthon.update_hook(move |client, update| {
+            let mirror = this.clone();
+            let storage = storage.clone();
+            async move {
+                if !leader::is_leader(&mirror.leader) {
+                    return Ok(());
+                }
+                match update {
+                    Update::MessageEdited(message) if mirror.edits && mirror.copy && mirror.sources.contains(&message.chat().id()) => {
+                        mirror.edit(&client, &storage, &message).await
+                    },
+                    Update::MessageDeleted(deletion) if mirror.deletions => {
+                        mirror.delete(&client, &storage, deletion.channel_id(), deletion.messages()).await
+                    },
+                    _ => Ok(())
+                }
+            }
+        });
+    }
+
+    /// Do all the filters match
+    async fn is_match(&self, data: &HandlerData) -> bool {
+        HandlerFilter::all_match(&self.filters, &data.message, &[], data).await
+    }
+
+    /// Storage key of source message.
+    /// Message ids are unique per chat only in channels and supergroups, elsewhere per account
+    fn mapping_key(&self, channel_id: Option<i64>, id: i32) -> String {
+        format!("{}:{}:{id}", self.key, channel_id.unwrap_or(0))
+    }
+
+    /// Clock set on install
+    fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone().unwrap_or_else(|| Arc::new(crate::SystemClock))
+    }
+
+    /// Current unix time in seconds
+    fn now(&self) -> u64 {
+        self.clock().system_time().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+    }
+
+    /// Load mapping, None if missing, expired or invalid
+    fn load(&self, storage: &Storage, key: &str) -> Result<Option<Mapping>, GrammersthonError> {
+        let mapping = storage.get::<serde_json::Value>(key)?.and_then(|v| serde_json::from_value::<Mapping>(v).ok());
+        Ok(mapping.filter(|m| m.time + self.retention.as_secs() > self.now()))
+    }
+
+    /// Remove the expired and invalid mappings
+    fn prune(&self, storage: &Storage) -> Result<(), GrammersthonError> {
+        for key in storage.keys(&format!("{}:", self.key))? {
+            if self.load(storage, &key)?.is_none() {
+                storage.remove(&key)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Channel id of message chat (if it's channel or supergroup)
+    fn channel_id(message: &Message) -> Option<i64> {
+        let chat = message.chat();
+        chat.pack().is_channel().then(|| chat.id())
+    }
+
+    /// Send the message (or album) to all destinations
+    async fn mirror(&self, data: &HandlerData) -> Result<(), GrammersthonError> {
+        let client = &data.client;
+        let storage = data.data::<Storage>().unwrap_or_else(Storage::memory);
+        let messages = data.album.clone().unwrap_or_else(|| vec![data.message.clone()]);
+        let ids = messages.iter().map(|m| m.id()).collect::<Vec<_>>();
+        let mut mirrored: Vec<Vec<Mirrored>> = vec![vec![]; messages.len()];
+
+        for destination in &self.destinations {
+            budget::acquire(&self.budget, Priority::Background).await;
+            let sent = match (self.copy, messages.as_slice()) {
+                (false, _) => client.forward_messages(*destination, &ids, data.message.chat()).await?,
+                (true, [message]) => vec![Some(client.send_message(*destination, Self::input_message(message)).await?)],
+                (true, messages) => {
+                    let media = messages.iter().filter_map(|m| m.media().map(|media| {
+                        InputMedia::caption(m.text()).fmt_entities(m.fmt_entities().cloned().unwrap_or_default()).copy_media(&media)
+                    })).collect();
+                    client.send_album(*destination, media).await?
+                }
+            };
+            for (i, message) in sent.into_iter().enumerate().take(messages.len()) {
+                if let Some(message) = message {
+                    mirrored[i].push(Mirrored { chat: destination.to_bytes().to_vec(), id: message.id() });
+                }
+            }
+        }
+
+        let channel_id = Self::channel_id(&data.message);
+        let time = self.now();
+        for (id, copies) in ids.into_iter().zip(mirrored) {
+            storage.set(&self.mapping_key(channel_id, id), &Mapping { copies, time })?;
+        }
+        Ok(())
+    }
+
+    /// Copy message content into new message
+    fn input_message(message: &Message) -> InputMessage {
+        let input = InputMessage::text(message.text()).fmt_entities(message.fmt_entities().cloned().unwrap_or_default());
+        match message.media() {
+            Some(media) => input.copy_media(&media),
+            None => input,
+        }
+    }
+
+    /// Edit the copies of message
+    async fn edit(&self, client: &Client, storage: &Storage, message: &Message) -> Result<(), GrammersthonError> {
+        let mapping = self.load(storage, &self.mapping_key(Self::channel_id(message), message.id()))?;
+        for copy in mapping.map(|m| m.copies).unwrap_or_default() {
+            let chat = unpack(&copy.chat)?;
+            budget::acquire(&self.budget, Priority::Background).await;
+            client.edit_message(chat, copy.id, Self::input_message(message)).await?;
+        }
+        Ok(())
+    }
+
+    /// Delete the copies of messages
+    async fn delete(&self, client: &Client, storage: &Storage, channel_id: Option<i64>, ids: &[i32]) -> Result<(), GrammersthonError> {
+        // Deletions outside of channels don't contain the chat
+        if let Some(channel_id) = channel_id {
+            if !self.sources.contains(&channel_id) {
+                return Ok(());
+            }
+        }
+        for id in ids {
+            let key = self.mapping_key(channel_id, *id);
+            let mapping = match self.load(storage, &key)? {
+                Some(mapping) => mapping,
+                None => continue,
+            };
+            for copy in mapping.copies {
+                budget::acquire(&self.budget, Priority::Background).await;
+                client.delete_messages(unpack(&copy.chat)?, &[copy.id]).await?;
+            }
+            storage.remove(&key)?;
+        }
+        Ok(())
+    }
+}
+
+/// Load packed chat from bytes
+fn unpack(bytes: &[u8]) -> Result<PackedChat, GrammersthonError> {
+    PackedChat::from_bytes(bytes).map_err(|_| GrammersthonError::Parse("packed chat".to_string(), None))
+}
+
+
+/// Test expiring the message mappings
+#[test]
+fn test_mapping_retention() {
+    let clock = crate::ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000));
+    let mirror = Mirror { clock: Some(Arc::new(clock.clone())), ..Mirror::new([], []).retention(Duration::from_secs(60)) };
+    let storage = Storage::memory();
+    storage.set(&mirror.mapping_key(None, 1), &Mapping { copies: vec![], time: 1000 }).unwrap();
+    storage.set(&mirror.mapping_key(None, 2), &Mapping { copies: vec![], time: 1050 }).unwrap();
+    storage.set(&mirror.mapping_key(None, 3), &vec![Mirrored { chat: vec![], id: 3 }]).unwrap();
+    clock.advance(Duration::from_secs(60));
+    assert!(mirror.load(&storage, &mirror.mapping_key(None, 1)).unwrap().is_none());
+    mirror.prune(&storage).unwrap();
+    assert_eq!(storage.keys("mirror:").unwrap(), vec![mirror.mapping_key(None, 2)]);
+}
@@ -0,0 +1,160 @@
+//! Moderation helpers
+//!
+//! Usage:
+//! ```ignore
+//! #[handler("^/report", filters::groups())]
+//! async fn report(client: Client, message: Message, catalog: Catalog) -> HandlerResult {
+//!     if let Some(reported) = message.get_reply().await? {
+//!         moderation::report_to_admins(&client, &message.chat(), &reported, "Spam", false, &catalog).await?;
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+use grammers_client::{Client, InputMessage};
+use grammers_client::types::{Chat, Message, User, Role};
+use grammers_session::{PackedChat, PackedType};
+use grammers_tl_types as tl;
+
+use crate::{Catalog, GrammersthonError};
+use crate::filters::ADMIN_CACHE_TTL;
+
+/// Chat id -> admins
+type AdminCache = Mutex<HashMap<i64, (Vec<User>, Instant)>>;
+static ADMINS: OnceLock<AdminCache> = OnceLock::new();
+
+/// Get the (human) admins and creator of the group or channel, cached per chat
+pub async fn group_admins(client: &Client, chat: &Chat) -> Result<Vec<User>, GrammersthonError> {
+    let cache = ADMINS.get_or_init(|| Mutex::new(HashMap::new()));
+    if let Some((admins, time)) = cache.lock().unwrap().get(&chat.id()) {
+        if time.elapsed() < ADMIN_CACHE_TTL {
+            return Ok(admins.clone());
+        }
+    }
+
+    let admins = match chat.pack().try_to_input_channel() {
+        Some(channel) => channel_admins(client, channel).await?,
+        // Basic groups are small, filter all members
+        None => {
+            let mut admins = vec![];
+            let mut participants = client.iter_participants(chat);
+            while let Some(participant) = participants.next().await? {
+                if matches!(participant.role, Role::Creator { .. } | Role::Admin { .. }) && !participant.user.is_bot() {
+                    admins.push(participant.user);
+                }
+            }
+            admins
+        }
+    };
+    cache.lock().unwrap().insert(chat.id(), (admins.clone(), Instant::now()));
+    Ok(admins)
+}
+
+/// Get admins of supergroup or channel without iterating all members
+async fn channel_admins(client: &Client, channel: tl::enums::InputChannel) -> Result<Vec<User>, GrammersthonError> {
+    let response = client.invoke(&tl::functions::channels::GetParticipants {
+        channel,
+        filter: tl::enums::ChannelParticipantsFilter::ChannelParticipantsAdmins,
+        offset: 0,
+        limit: 200,
+        hash: 0,
+    }).await?;
+    let participants = match response {
+        tl::enums::channels::ChannelParticipants::Participants(p) => p,
+        tl::enums::channels::ChannelParticipants::NotModified => return Ok(vec![]),
+    };
+    let ids = participants.participants.iter().filter_map(|p| match p {
+        tl::enums::ChannelParticipant::Creator(p) => Some(p.user_id),
+        tl::enums::ChannelParticipant::Admin(p) => Some(p.user_id),
+        _ => None
+    }).collect::<HashSet<_>>();
+
+    let mut admins = vec![];
+    for user in participants.users {
+        let user = match user {
+            tl::enums::User::User(user) if ids.contains(&user.id) && !user.bot => user,
+            _ => continue
+        };
+        let packed = PackedChat { ty: PackedType::User, id: user.id, access_hash: user.access_hash };
+        if let Chat::User(user) = client.unpack_chat(packed).await? {
+            admins.push(user);
+        }
+    }
+    Ok(admins)
+}
+
+/// Link to message, None if the chat doesn't support links (private chats, basic groups)
+pub fn message_link(chat: &Chat, message_id: i32) -> Option<String> {
+    match chat {
+        Chat::User(_) => None,
+        Chat::Group(group) if !group.is_megagroup() => None,
+        chat => Some(match chat.username() {
+            Some(username) => format!("https://t.me/{username}/{message_id}"),
+            None => format!("https://t.me/c/{}/{message_id}", chat.id()),
+        }),
+    }
+}
+
+/// Reply to the reported message mentioning all the admins, optionally pin the report.
+/// The report is in the default language of the catalog
+pub async fn report_to_admins(client: &Client, chat: &Chat, message: &Message, reason: &str, pin: bool, catalog: &Catalog) -> Result<Message, GrammersthonError> {
+    let admins = group_admins(client, chat).await?;
+    let (text, entities) = escalation_text(chat, message, reason, &admins, catalog);
+    let report = client.send_message(chat, InputMessage::text(text).fmt_entities(entities).reply_to(Some(message.id()))).await?;
+    if pin {
+        if let Err(e) = report.pin().await {
+            warn!("Failed pinning report in {}: {e}", chat.id());
+        }
+    }
+    Ok(report)
+}
+
+/// Generate the report message, admins without username are mentioned by mention name entities
+fn escalation_text(chat: &Chat, message: &Message, reason: &str, admins: &[User], catalog: &Catalog) -> (String, Vec<tl::enums::MessageEntity>) {
+    let mut text = format!("{}\n", catalog.format(None, "report.title", &[("reason", &reason)]));
+    if let Some(sender) = message.sender() {
+        text.push_str(&format!("{}\n", catalog.format(None, "report.from", &[("name", &sender.name()), ("id", &sender.id())])));
+    }
+    if let Some(link) = message_link(chat, message.id()) {
+        text.push_str(&format!("{}\n", catalog.format(None, "report.message", &[("link", &link)])));
+    }
+    let mut entities = vec![];
+    if admins.is_empty() {
+        return (text.trim_end().to_string(), entities);
+    }
+
+    // Mentions with their offsets (UTF-16) in the joined list
+    let mut mentions = String::new();
+    let mut names = vec![];
+    for admin in admins {
+        if !mentions.is_empty() {
+            mentions.push(' ');
+        }
+        let offset = mentions.encode_utf16().count();
+        match admin.username() {
+            Some(username) => mentions.push_str(&format!("@{username}")),
+            None => {
+                mentions.push_str(&admin.full_name());
+                if let Some(user) = admin.pack().try_to_input_user() {
+                    names.push((offset, admin.full_name().encode_utf16().count(), user));
+                }
+            }
+        }
+    }
+    let line = catalog.format(None, "report.admins", &[("admins", &mentions)]);
+    if let Some(position) = line.rfind(&mentions) {
+        let start = text.encode_utf16().count() + line[..position].encode_utf16().count();
+        entities.extend(names.into_iter().map(|(offset, length, user_id)| {
+            tl::enums::MessageEntity::InputMessageEntityMentionName(tl::types::InputMessageEntityMentionName {
+                offset: (start + offset) as i32,
+                length: length as i32,
+                user_id,
+            })
+        }));
+    }
+    text.push_str(&line);
+    (text.trim_end().to_string(), entities)
+}
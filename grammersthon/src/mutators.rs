@@ -0,0 +1,47 @@
+//! Built-in pattern mutators
+//! 
+//! Usage:
+//! ```ignore
+//! grammersthon
+//!     .pattern_mutator(mutators::prefix("/"))
+//!     .pattern_mutator(mutators::anchored())
+//!     .pattern_mutator(mutators::case_insensitive());
+//! ```
+
+/// Prefix every pattern (escaped), keeps the `^` anchor at the start
+pub fn prefix(prefix: &str) -> impl Fn(&str) -> String + Send + Sync + 'static {
+    let prefix = regex::escape(prefix);
+    move |pattern| match pattern.strip_prefix('^') {
+        Some(pattern) => format!("^{prefix}{pattern}"),
+        None => format!("{prefix}{pattern}"),
+    }
+}
+
+/// Pattern has to match the whole message
+pub fn anchored() -> impl Fn(&str) -> String + Send + Sync + 'static {
+    |pattern| {
+        let pattern = pattern.strip_prefix('^').unwrap_or(pattern);
+        let pattern = match pattern.ends_with('$') && !pattern.ends_with("\\$") {
+            true => &pattern[..pattern.len() - 1],
+            false => pattern
+        };
+        format!("^(?:{pattern})$")
+    }
+}
+
+/// Pattern will ignore case
+pub fn case_insensitive() -> impl Fn(&str) -> String + Send + Sync + 'static {
+    |pattern| format!("(?i){pattern}")
+}
+
+
+/// Test mutator composition
+#[test]
+fn test_mutators() {
+    assert_eq!(prefix("/")("ping"), "/ping");
+    assert_eq!(prefix(".")("^ping"), "^\\.ping");
+    assert_eq!(anchored()("^ping$"), "^(?:ping)$");
+    assert_eq!(anchored()("cost \\$"), "^(?:cost \\$)$");
+    assert_eq!(anchored()(&prefix("/")("ping")), "^(?:/ping)$");
+    assert_eq!(case_insensitive()(&anchored()("ping")), "(?i)^(?:ping)$");
+}
@@ -0,0 +1,67 @@
+use std::sync::{Arc, Mutex};
+use grammers_session::PackedChat;
+
+use crate::{GrammersthonError, HandlerData, UpdateGaps};
+
+/// Message sent while handling the update (through the `HandlerData` helpers)
+#[derive(Debug, Clone, Copy)]
+pub struct SentMessage {
+    pub chat: PackedChat,
+    pub id: i32,
+}
+
+/// Messages sent while handling the update, shared by all clones of the `HandlerData`
+pub(crate) type Outbox = Arc<Mutex<Vec<SentMessage>>>;
+
+impl HandlerData {
+    /// Messages sent so far while handling this update, oldest first.
+    /// Only the sends through `HandlerData` helpers (`reply`, `respond`, `send`, conversations, wizards...) are tracked,
+    /// also available to after handler hooks (`Grammersthon::after_handler`):
+    /// ```ignore
+    /// grammersthon.after_handler(|data: HandlerData, result: Result<(), ErrorKind>| async move {
+    ///     // Rollback the partial output of failed handler
+    ///     if result.is_err() {
+    ///         data.cleanup().await?;
+    ///     }
+    ///     Ok(())
+    /// });
+    /// ```
+    pub fn outbox(&self) -> Vec<SentMessage> {
+        self.outbox.lock().unwrap().clone()
+    }
+
+    /// Track sent message
+    pub(crate) fn track_sent(&self, chat: PackedChat, id: i32) {
+        if let Some(gaps) = self.data::<UpdateGaps>() {
+            gaps.sent(chat, id);
+        }
+        self.outbox.lock().unwrap().push(SentMessage { chat, id });
+    }
+
+    /// Delete the last sent message, returns it if there was any
+    pub async fn undo(&self) -> Result<Option<SentMessage>, GrammersthonError> {
+        let sent = match self.outbox.lock().unwrap().pop() {
+            Some(sent) => sent,
+            None => return Ok(None),
+        };
+        self.client.delete_messages(sent.chat, &[sent.id]).await?;
+        Ok(Some(sent))
+    }
+
+    /// Delete all the sent messages, returns how many were deleted
+    pub async fn cleanup(&self) -> Result<usize, GrammersthonError> {
+        let sent = std::mem::take(&mut *self.outbox.lock().unwrap());
+        let mut chats: Vec<(PackedChat, Vec<i32>)> = vec![];
+        for message in sent {
+            match chats.iter_mut().find(|(chat, _)| chat.id == message.chat.id) {
+                Some((_, ids)) => ids.push(message.id),
+                None => chats.push((message.chat, vec![message.id])),
+            }
+        }
+        let mut deleted = 0;
+        for (chat, ids) in chats {
+            deleted += self.client.delete_messages(chat, &ids).await?;
+        }
+        Ok(deleted)
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::Arc;
+
+use crate::{Grammersthon, GrammersthonError};
+
+type SetupFn = dyn Fn(&mut Grammersthon) + Send + Sync;
+
+/// Multiple accounts (bot tokens, userbot sessions) running in one process, sharing the handlers and data:
+/// ```ignore
+/// let mut pool = GrammersthonPool::new();
+/// pool.add(Grammersthon::from_env().unwrap().bot_token(token1).connect().await?);
+/// pool.add(Grammersthon::from_env().unwrap().bot_token(token2).connect().await?);
+/// pool.add_data(Storage::file("storage.json")?)
+///     .setup(|grammersthon| { grammersthon.add_handler(h!(ping)); });
+/// pool.start_event_loop().await?;
+///
+/// #[handler("^/ping")]
+/// async fn ping(account: AccountId, message: Message) -> HandlerResult { ... }
+/// ```
+/// Shared data is cloned into every account, so it's shared only if the clones share the state (`Storage`, `Arc`...).
+/// Data and setup functions are applied to each account in the order they were added when the pool starts
+#[derive(Default)]
+pub struct GrammersthonPool {
+    accounts: Vec<Grammersthon>,
+    setup: Vec<Arc<SetupFn>>,
+}
+
+impl GrammersthonPool {
+    /// Create new empty pool
+    pub fn new() -> GrammersthonPool {
+        GrammersthonPool::default()
+    }
+
+    /// Add connected account
+    pub fn add(&mut self, grammersthon: Grammersthon) -> &mut Self {
+        self.accounts.push(grammersthon);
+        self
+    }
+
+    /// Add data shared by all the accounts
+    pub fn add_data<T: Send + Sync + Clone + 'static>(&mut self, data: T) -> &mut Self {
+        self.setup(move |grammersthon| { grammersthon.add_data(data.clone()); })
+    }
+
+    /// Register the shared handlers and components, called for every account
+    pub fn setup(&mut self, setup: impl Fn(&mut Grammersthon) + Send + Sync + 'static) -> &mut Self {
+        self.setup.push(Arc::new(setup));
+        self
+    }
+
+    /// The accounts of the pool
+    pub fn accounts(&self) -> &[Grammersthon] {
+        &self.accounts
+    }
+
+    /// Get account by its user id
+    pub fn get(&self, id: i64) -> Option<&Grammersthon> {
+        self.accounts.iter().find(|g| g.me().id() == id)
+    }
+
+    /// Run the event loops of all the accounts, until all of them finish.
+    /// Failing account doesn't stop the others, the first error is returned
+    pub async fn start_event_loop(self) -> Result<(), GrammersthonError> {
+        let mut tasks = tokio::task::JoinSet::new();
+        for mut grammersthon in self.accounts {
+            for setup in &self.setup {
+                (*setup)(&mut grammersthon);
+            }
+            tasks.spawn(async move {
+                let id = grammersthon.me().id();
+                let result = grammersthon.start_event_loop().await;
+                if let Err(e) = &result {
+                    error!("Event loop of account {id} failed: {e}");
+                }
+                result
+            });
+        }
+        let mut result = Ok(());
+        while let Some(finished) = tasks.join_next().await {
+            let finished = finished.unwrap_or_else(|e| match e.try_into_panic() {
+                Ok(payload) => Err(GrammersthonError::panic(&*payload)),
+                Err(_) => Err(GrammersthonError::Cancelled),
+            });
+            if result.is_ok() {
+                result = finished;
+            }
+        }
+        result
+    }
+}
@@ -0,0 +1,392 @@
+//! Channel post queue with owner approval and time slots (bots only, because of inline buttons)
+//!
+//! Owners send posts (text, media or albums) to the bot in private chat and queue them by replying
+//! with the queue command (`/post`), approve them using the inline buttons and the approved posts are published in the next free slot.
+//! Posts failing to publish repeatedly are parked (`PostStatus::Failed`) and the owner is notified.
+//!
+//! Usage:
+//! ```ignore
+//! Publisher::new(channel.pack())
+//!     .owners([123456789])
+//!     .slot(9, 0)
+//!     .slot(18, 30)
+//!     .install(&mut grammersthon)?;
+//! ```
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
+use grammers_client::{Client, InputMessage};
+use grammers_client::types::{CallbackQuery, Chat, Message, InputMedia};
+use grammers_client::types::{button, reply_markup};
+use grammers_session::PackedChat;
+use serde::{Serialize, Deserialize};
+
+use crate::{ApiBudget, Catalog, Clock, Grammersthon, GrammersthonError, HandlerData, HandlerFilter, HandlerInfo, HandlerResult, LeaderLock, Priority, SendLimiter, Storage, SystemClock, chat_lang};
+use crate::{budget, leader};
+
+/// How often are the due posts checked
+const POST_INTERVAL: Duration = Duration::from_secs(30);
+/// Maximum number of messages in album
+const ALBUM_SIZE: i32 = 10;
+
+/// Queued post
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Post {
+    pub id: u64,
+    /// Packed chat where the post was sent to the bot
+    chat: Vec<u8>,
+    /// Message ids of the post (multiple for albums)
+    pub messages: Vec<i32>,
+    grouped_id: Option<i64>,
+    pub status: PostStatus,
+    /// Unix timestamp when the post should be published
+    pub slot: Option<u64>,
+    /// Failed publishing attempts
+    #[serde(default)]
+    pub failures: u32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PostStatus {
+    /// Waiting for approval
+    Pending,
+    /// Approved, waiting for slot
+    Scheduled,
+    /// Publishing failed `max_failures` times, not retried anymore
+    Failed,
+}
+
+/// Channel publisher component
+pub struct Publisher {
+    channel: PackedChat,
+    owners: HashSet<i64>,
+    /// Minutes of day (UTC)
+    slots: Vec<u32>,
+    key: String,
+    command: String,
+    max_failures: u32,
+    lock: Mutex<()>,
+    catalog: Catalog,
+    clock: Arc<dyn Clock>,
+    budget: Option<ApiBudget>,
+    limiter: Option<SendLimiter>,
+    leader: Option<LeaderLock>,
+}
+
+impl Publisher {
+    /// Create new instance for channel
+    pub fn new(channel: PackedChat) -> Publisher {
+        Publisher {
+            key: format!("publisher:{}", channel.id),
+            channel,
+            owners: HashSet::new(),
+            slots: vec![],
+            command: "/post".to_string(),
+            max_failures: 5,
+            lock: Mutex::new(()),
+            catalog: Catalog::new(),
+            clock: Arc::new(SystemClock),
+            budget: None,
+            limiter: None,
+            leader: None,
+        }
+    }
+
+    /// Users which can queue and approve posts
+    pub fn owners(mut self, ids: impl IntoIterator<Item = i64>) -> Self {
+        self.owners.extend(ids);
+        self
+    }
+
+    /// Add daily slot (UTC time). Without slots, approved posts are published immediately
+    pub fn slot(mut self, hour: u32, minute: u32) -> Self {
+        self.slots.push((hour % 24) * 60 + minute % 60);
+        self.slots.sort();
+        self.slots.dedup();
+        self
+    }
+
+    /// Command replied to the message (or album) to queue it (default: `/post`)
+    pub fn command(mut self, command: &str) -> Self {
+        self.command = command.to_string();
+        self
+    }
+
+    /// Number of failed publishing attempts after which the post is parked (default: 5)
+    pub fn max_failures(mut self, max_failures: u32) -> Self {
+        self.max_failures = max_failures.max(1);
+        self
+    }
+
+    /// Storage key under which the queue is saved
+    pub fn key(mut self, key: &str) -> Self {
+        self.key = key.to_string();
+        self
+    }
+
+    /// Register the handlers and start posting task, install after setting the clock (and `LeaderLock`, `SendLimiter`)
+    pub fn install(mut self, grammersthon: &mut Grammersthon) -> Result<(), GrammersthonError> {
+        let storage = grammersthon.get_storage();
+        self.catalog = grammersthon.get_catalog();
+        self.clock = grammersthon.get_clock();
+        self.budget = grammersthon.get_api_budget();
+        self.limiter = grammersthon.get_send_limiter();
+        self.leader = grammersthon.get_leader_lock();
+        let this = Arc::new(self);
+
+        // Queue posts from owners
+        let owners = this.owners.clone();
+        let command = this.command.clone();
+        let info = HandlerInfo::new("publisher", vec![
+            HandlerFilter::func(move |message, _| {
+                matches!(message.chat(), Chat::User(_)) && owners.contains(&message.chat().id())
+                    && message.text().split_whitespace().next() == Some(command.as_str())
+            })
+        ]).description("Queue the replied message as a channel post");
+        let publisher = this.clone();
+        grammersthon.add_handler((info, move |message: Message, data: HandlerData, storage: Storage| {
+            let publisher = publisher.clone();
+            async move { publisher.queue(message, data, storage).await }
+        }));
+
+        // Approve / reject buttons
+        let publisher = this.clone();
+        let callback_storage = storage.clone();
+        grammersthon.callback_handler(format!("{}:", this.key), move |query, _| {
+            let publisher = publisher.clone();
+            let storage = callback_storage.clone();
+            async move { publisher.callback(query, storage).await }
+        });
+
+        // Publish due posts
+        let client = grammersthon.client();
+        tokio::spawn(async move {
+            loop {
+                if !leader::is_leader(&this.leader) {
+                    this.clock.sleep(POST_INTERVAL).await;
+                    continue;
+                }
+                if let Err(e) = this.publish_due(&client, &storage).await {
+                    error!("Publisher failed publishing posts: {e}");
+                }
+                this.clock.sleep(POST_INTERVAL).await;
+            }
+        });
+        Ok(())
+    }
+
+    /// Get all the queued posts
+    pub fn posts(&self, storage: &Storage) -> Result<Vec<Post>, GrammersthonError> {
+        Ok(storage.get(&self.key)?.unwrap_or_default())
+    }
+
+    /// Modify the queue
+    fn update<R>(&self, storage: &Storage, f: impl FnOnce(&mut Vec<Post>) -> R) -> Result<R, GrammersthonError> {
+        let _lock = self.lock.lock().unwrap();
+        let mut posts = self.posts(storage)?;
+        let r = f(&mut posts);
+        storage.set(&self.key, &posts)?;
+        Ok(r)
+    }
+
+    /// Add the replied message (or album) to queue and send preview
+    async fn queue(&self, message: Message, data: HandlerData, storage: Storage) -> HandlerResult {
+        let lang = chat_lang(&message.chat()).map(|l| l.to_string());
+        let lang = lang.as_deref();
+        let reply = match message.get_reply().await? {
+            Some(reply) => reply,
+            None => {
+                let usage = self.catalog.format(lang, "publisher.usage", &[("command", &self.command)]);
+                data.reply(usage).await?;
+                return Ok(());
+            }
+        };
+
+        // Rest of the album is around the replied message
+        let grouped_id = reply.grouped_id();
+        let messages = match grouped_id {
+            Some(grouped_id) => {
+                let ids = (reply.id() - ALBUM_SIZE + 1..reply.id() + ALBUM_SIZE).collect::<Vec<_>>();
+                let mut album = data.client.get_messages_by_id(&message.chat(), &ids).await?.into_iter().flatten()
+                    .filter(|m| m.grouped_id() == Some(grouped_id))
+                    .map(|m| m.id())
+                    .collect::<Vec<_>>();
+                album.sort();
+                album
+            },
+            None => vec![reply.id()],
+        };
+        let count = messages.len();
+        let chat = message.chat().pack().to_bytes().to_vec();
+        let id = self.update(&storage, |posts| {
+            let id = posts.iter().map(|p| p.id).max().unwrap_or(0) + 1;
+            posts.push(Post { id, chat, messages, grouped_id, status: PostStatus::Pending, slot: None, failures: 0 });
+            id
+        })?;
+        let buttons = reply_markup::inline(vec![vec![
+            button::inline(self.catalog.get(lang, "publisher.approve"), format!("{}:approve:{id}", self.key)),
+            button::inline(self.catalog.get(lang, "publisher.reject"), format!("{}:reject:{id}", self.key)),
+        ]]);
+        let text = self.catalog.format(lang, "publisher.queued", &[("id", &id), ("count", &count)]);
+        data.reply_to(&reply, InputMessage::text(text).reply_markup(&buttons)).await?;
+        Ok(())
+    }
+
+    /// Handle approve / reject button
+    async fn callback(&self, query: CallbackQuery, storage: Storage) -> HandlerResult {
+        let lang = chat_lang(query.sender());
+        if !self.owners.contains(&query.sender().id()) {
+            query.answer().text(self.catalog.get(lang, "publisher.not_allowed")).send().await?;
+            return Ok(());
+        }
+        let data = String::from_utf8_lossy(query.data()).to_string();
+        let mut parts = data[self.key.len() + 1..].split(':');
+        let (action, id) = match (parts.next(), parts.next().and_then(|id| id.parse::<u64>().ok())) {
+            (Some(action), Some(id)) => (action, id),
+            _ => return Err(GrammersthonError::Parse(data, None)),
+        };
+
+        let now = self.now();
+        let text = self.update(&storage, |posts| {
+            let index = posts.iter().position(|p| p.id == id && p.status == PostStatus::Pending)?;
+            match action {
+                "approve" => {
+                    let taken = posts.iter().filter_map(|p| p.slot).collect::<HashSet<_>>();
+                    let slot = next_slot(&self.slots, &taken, now);
+                    posts[index].status = PostStatus::Scheduled;
+                    posts[index].slot = Some(slot);
+                    let time = format_duration(slot.saturating_sub(now));
+                    Some(self.catalog.format(lang, "publisher.scheduled", &[("id", &id), ("time", &time)]))
+                },
+                _ => {
+                    posts.remove(index);
+                    Some(self.catalog.format(lang, "publisher.rejected", &[("id", &id)]))
+                }
+            }
+        })?;
+        match text {
+            Some(text) => query.answer().edit(text).await?,
+            None => query.answer().text(self.catalog.get(lang, "publisher.not_pending")).send().await?,
+        }
+        Ok(())
+    }
+
+    /// Publish all the posts with passed slot
+    async fn publish_due(&self, client: &Client, storage: &Storage) -> Result<(), GrammersthonError> {
+        let now = self.now();
+        let due = self.posts(storage)?.into_iter()
+            .filter(|p| p.status == PostStatus::Scheduled && p.slot.map(|s| s <= now).unwrap_or(true))
+            .collect::<Vec<_>>();
+        for post in due {
+            match self.publish(client, &post).await {
+                Ok(_) => {
+                    info!("Published post #{} to {}", post.id, self.channel.id);
+                    self.update(storage, |posts| posts.retain(|p| p.id != post.id))?;
+                },
+                Err(e) => {
+                    warn!("Failed publishing post #{}: {e}", post.id);
+                    let failures = post.failures + 1;
+                    let parked = failures >= self.max_failures;
+                    self.update(storage, |posts| {
+                        if let Some(post) = posts.iter_mut().find(|p| p.id == post.id) {
+                            post.failures = failures;
+                            if parked {
+                                post.status = PostStatus::Failed;
+                            }
+                        }
+                    })?;
+                    if parked {
+                        self.notify_failed(client, &post, &e).await;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Tell the owner the post was parked
+    async fn notify_failed(&self, client: &Client, post: &Post, error: &GrammersthonError) {
+        let chat = match PackedChat::from_bytes(&post.chat) {
+            Ok(chat) => chat,
+            Err(_) => return,
+        };
+        let text = self.catalog.format(None, "publisher.failed", &[("id", &post.id), ("count", &self.max_failures), ("error", error)]);
+        self.send_slot(chat.id).await;
+        if let Err(e) = client.send_message(chat, InputMessage::text(text).reply_to(post.messages.first().copied())).await {
+            warn!("Failed notifying about post #{}: {e}", post.id);
+        }
+    }
+
+    /// Wait for the API budget and outgoing limiter (if set) before sending to chat
+    async fn send_slot(&self, chat: i64) {
+        budget::acquire(&self.budget, Priority::Background).await;
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire(chat).await;
+        }
+    }
+
+    /// Current unix timestamp
+    fn now(&self) -> u64 {
+        self.clock.system_time().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+    }
+
+    /// Copy the post messages to channel
+    async fn publish(&self, client: &Client, post: &Post) -> Result<(), GrammersthonError> {
+        let chat = PackedChat::from_bytes(&post.chat).map_err(|_| GrammersthonError::Parse("packed chat".to_string(), None))?;
+        budget::acquire(&self.budget, Priority::Background).await;
+        let messages = client.get_messages_by_id(chat, &post.messages).await?.into_iter().flatten().collect::<Vec<_>>();
+        match messages.as_slice() {
+            [] => warn!("Messages of post #{} were deleted", post.id),
+            [message] => {
+                let mut input = InputMessage::text(message.text()).fmt_entities(message.fmt_entities().cloned().unwrap_or_default());
+                if let Some(media) = message.media() {
+                    input = input.copy_media(&media);
+                }
+                self.send_slot(self.channel.id).await;
+                client.send_message(self.channel, input).await?;
+            },
+            messages => {
+                let media = messages.iter().filter_map(|m| m.media().map(|media| {
+                    InputMedia::caption(m.text()).fmt_entities(m.fmt_entities().cloned().unwrap_or_default()).copy_media(&media)
+                })).collect();
+                self.send_slot(self.channel.id).await;
+                client.send_album(self.channel, media).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Get the first free slot after `now`, `now` if there are no slots
+fn next_slot(slots: &[u32], taken: &HashSet<u64>, now: u64) -> u64 {
+    if slots.is_empty() {
+        return now;
+    }
+    let today = now - now % 86400;
+    (0..).flat_map(|day| slots.iter().map(move |slot| today + day * 86400 + *slot as u64 * 60))
+        .find(|time| *time > now && !taken.contains(time))
+        .unwrap()
+}
+
+/// Format duration as `1h 20m`
+fn format_duration(secs: u64) -> String {
+    match (secs / 3600, secs % 3600 / 60) {
+        (0, 0) => "less than a minute".to_string(),
+        (0, m) => format!("{m}m"),
+        (h, m) => format!("{h}h {m}m"),
+    }
+}
+
+
+/// Test slot allocation
+#[test]
+fn test_next_slot() {
+    let day = 86400 * 100;
+    let slots = [9 * 60, 18 * 60];
+    let mut taken = HashSet::new();
+    assert_eq!(next_slot(&slots, &taken, day + 10 * 3600), day + 18 * 3600);
+    taken.insert(day + 18 * 3600);
+    assert_eq!(next_slot(&slots, &taken, day + 10 * 3600), day + 86400 + 9 * 3600);
+    assert_eq!(next_slot(&[], &taken, day), day);
+}
@@ -0,0 +1,213 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use grammers_client::{Client, Config, InitParams, Update};
+use grammers_client::types::PasswordToken;
+use grammers_client::client::chats::InvocationError;
+use grammers_session::Session;
+use grammers_tl_types as tl;
+
+use crate::GrammersthonError;
+
+/// Called with the login URL whenever it changes
+pub(crate) type QrCallback = dyn Fn(&str) + Send + Sync;
+
+/// Result of the QR login
+pub(crate) enum QrLogin {
+    Success,
+    /// Account has 2FA password, continue with `check_password`
+    PasswordRequired(PasswordToken),
+}
+
+/// Telegram calls of the QR login (stubbed in tests)
+pub(crate) trait QrClient {
+    /// `auth.exportLoginToken` in the home DC
+    async fn export_token(&self, api_id: i32, api_hash: &str) -> Result<tl::enums::auth::LoginToken, InvocationError>;
+    /// `auth.importLoginToken` in the home DC
+    async fn import_token(&self, token: Vec<u8>) -> Result<tl::enums::auth::LoginToken, InvocationError>;
+    /// Make `dc_id` the home DC
+    async fn migrate(&mut self, dc_id: i32) -> Result<(), GrammersthonError>;
+    /// `account.getPassword` in the home DC
+    async fn get_password(&self) -> Result<PasswordToken, InvocationError>;
+    /// Wait for the `updateLoginToken` update, at most `timeout`
+    async fn wait_accepted(&self, timeout: Duration) -> Result<(), InvocationError>;
+}
+
+/// Client connection, reconnected on migration
+pub(crate) struct QrConnection {
+    pub client: Client,
+    pub api_id: i32,
+    pub api_hash: String,
+    pub params: InitParams,
+}
+
+impl QrClient for QrConnection {
+    async fn export_token(&self, api_id: i32, api_hash: &str) -> Result<tl::enums::auth::LoginToken, InvocationError> {
+        self.client.invoke(&tl::functions::auth::ExportLoginToken { api_id, api_hash: api_hash.to_string(), except_ids: vec![] }).await
+    }
+
+    async fn import_token(&self, token: Vec<u8>) -> Result<tl::enums::auth::LoginToken, InvocationError> {
+        self.client.invoke(&tl::functions::auth::ImportLoginToken { token }).await
+    }
+
+    async fn migrate(&mut self, dc_id: i32) -> Result<(), GrammersthonError> {
+        // The client connects to the DC of the session user, which is replaced after the login
+        let session = Session::load(&self.client.session().save())
+            .map_err(|e| GrammersthonError::Parse("session".to_string(), Some(Box::new(e))))?;
+        session.set_user(0, dc_id, false);
+        self.client = Client::connect(Config {
+            session,
+            api_id: self.api_id,
+            api_hash: self.api_hash.clone(),
+            params: self.params.clone(),
+        }).await?;
+        Ok(())
+    }
+
+    async fn get_password(&self) -> Result<PasswordToken, InvocationError> {
+        let password: tl::types::account::Password = self.client.invoke(&tl::functions::account::GetPassword {}).await?.into();
+        Ok(PasswordToken::new(password))
+    }
+
+    async fn wait_accepted(&self, timeout: Duration) -> Result<(), InvocationError> {
+        let accepted = async {
+            loop {
+                if let Update::Raw(tl::enums::Update::LoginToken) = self.client.next_update().await? {
+                    return Ok::<_, InvocationError>(());
+                }
+            }
+        };
+        tokio::time::timeout(timeout, accepted).await.unwrap_or(Ok(()))
+    }
+}
+
+/// Login by QR code (`auth.exportLoginToken`), the URL is passed to callback to be displayed.
+/// Waits for the `updateLoginToken` update (or the token expiry) and exports the token again, which finishes the login.
+/// Accounts in other DC switch the home DC to it and import the token there (`auth.importLoginToken`)
+pub(crate) async fn qr_sign_in(client: &mut impl QrClient, api_id: i32, api_hash: &str, callback: &QrCallback) -> Result<QrLogin, GrammersthonError> {
+    let mut url = String::new();
+    loop {
+        let result = match client.export_token(api_id, api_hash).await {
+            Ok(tl::enums::auth::LoginToken::MigrateTo(migrate)) => {
+                debug!("Account is in DC {}, switching to it and importing the login token there", migrate.dc_id);
+                client.migrate(migrate.dc_id).await?;
+                client.import_token(migrate.token).await
+            },
+            result => result,
+        };
+        let token = match result {
+            Ok(tl::enums::auth::LoginToken::Token(token)) => token,
+            Ok(tl::enums::auth::LoginToken::Success(_)) => return Ok(QrLogin::Success),
+            Ok(tl::enums::auth::LoginToken::MigrateTo(migrate)) => {
+                return Err(GrammersthonError::Error(format!("Login token migrated again, to DC {}", migrate.dc_id).into()));
+            },
+            Err(InvocationError::Rpc(e)) if e.name == "SESSION_PASSWORD_NEEDED" => {
+                return Ok(QrLogin::PasswordRequired(client.get_password().await?));
+            },
+            Err(e) => return Err(e.into()),
+        };
+        let new_url = login_url(&token.token);
+        if new_url != url {
+            url = new_url;
+            callback(&url);
+        }
+
+        // Wait for the token to be accepted or expire
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default();
+        let expires = Duration::from_secs((token.expires as i64 - now).clamp(1, 60) as u64);
+        client.wait_accepted(expires).await?;
+    }
+}
+
+/// Print the QR code of the login URL in terminal (requires the `qr` feature, otherwise prints just the URL)
+pub(crate) fn print_qr(url: &str) {
+    #[cfg(feature = "qr")]
+    if let Ok(code) = qrcode::QrCode::new(url) {
+        use qrcode::render::unicode::Dense1x2;
+        let qr = code.render::<Dense1x2>().dark_color(Dense1x2::Light).light_color(Dense1x2::Dark).build();
+        println!("Scan the QR code in Telegram app (Settings > Devices > Link Desktop Device):\n{qr}");
+        return;
+    }
+    println!("Open the login link on logged in device: {url}");
+}
+
+/// URL encoded in the QR code
+fn login_url(token: &[u8]) -> String {
+    format!("tg://login?token={}", base64_url(token))
+}
+
+/// URL safe base64 without padding
+fn base64_url(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut output = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - i * 8));
+        for i in 0..=chunk.len() {
+            output.push(ALPHABET[(n >> (18 - i * 6)) as usize & 63] as char);
+        }
+    }
+    output
+}
+
+
+/// Test encoding of the login URL
+#[test]
+fn test_login_url() {
+    assert_eq!(base64_url(b""), "");
+    assert_eq!(base64_url(b"f"), "Zg");
+    assert_eq!(base64_url(b"fo"), "Zm8");
+    assert_eq!(base64_url(b"foo"), "Zm9v");
+    assert_eq!(base64_url(&[0xfb, 0xff]), "-_8");
+    assert_eq!(login_url(b"foobar"), "tg://login?token=Zm9vYmFy");
+}
+
+/// Test that the login continues in the DC of the account after `auth.loginTokenMigrateTo`
+#[tokio::test]
+async fn test_qr_migrate() {
+    use std::sync::Mutex;
+
+    struct StubClient {
+        dc: i32,
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl StubClient {
+        fn call(&self, name: &str) -> usize {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push(format!("{name}@{}", self.dc));
+            calls.len()
+        }
+    }
+
+    impl QrClient for StubClient {
+        async fn export_token(&self, _api_id: i32, _api_hash: &str) -> Result<tl::enums::auth::LoginToken, InvocationError> {
+            match (self.call("export"), self.dc) {
+                (1, 2) => Ok(tl::enums::auth::LoginToken::MigrateTo(tl::types::auth::LoginTokenMigrateTo { dc_id: 4, token: b"old".to_vec() })),
+                _ => Err(InvocationError::Dropped),
+            }
+        }
+        async fn import_token(&self, token: Vec<u8>) -> Result<tl::enums::auth::LoginToken, InvocationError> {
+            assert_eq!(token, b"old");
+            self.call("import");
+            Ok(tl::enums::auth::LoginToken::Token(tl::types::auth::LoginToken { expires: 0, token: b"new".to_vec() }))
+        }
+        async fn migrate(&mut self, dc_id: i32) -> Result<(), GrammersthonError> {
+            self.dc = dc_id;
+            Ok(())
+        }
+        async fn get_password(&self) -> Result<PasswordToken, InvocationError> {
+            self.call("password");
+            Err(InvocationError::Dropped)
+        }
+        async fn wait_accepted(&self, _timeout: Duration) -> Result<(), InvocationError> {
+            self.call("wait");
+            Ok(())
+        }
+    }
+
+    let mut client = StubClient { dc: 2, calls: Mutex::new(vec![]) };
+    let urls = std::sync::Arc::new(Mutex::new(vec![]));
+    let callback = { let urls = urls.clone(); move |url: &str| urls.lock().unwrap().push(url.to_string()) };
+    let result = qr_sign_in(&mut client, 1, "hash", &callback).await;
+    assert!(result.is_err());
+    assert_eq!(client.calls.into_inner().unwrap(), ["export@2", "import@4", "wait@4", "export@4"]);
+    assert_eq!(*urls.lock().unwrap(), [login_url(b"new")]);
+}
@@ -0,0 +1,200 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use grammers_client::types::{Message, MessageDeletion};
+use trait_bound_typemap::TypeMap;
+
+use crate::{Data, FromHandlerData, Grammersthon, GrammersthonError, HandlerData};
+
+/// Chat -> cached messages
+type ChatMessages = Arc<Mutex<HashMap<i64, CachedChat>>>;
+
+/// Default maximum of cached chats
+const DEFAULT_MAX_CHATS: usize = 1000;
+
+/// Cached messages of single chat
+#[derive(Default)]
+struct CachedChat {
+    /// Channels and supergroups have own message ids, other chats share them
+    channel: bool,
+    /// Messages with the time they were received, oldest first
+    messages: VecDeque<(Message, Instant)>,
+}
+
+/// Opt-in bounded cache of the recent messages of each chat (`Grammersthon::recent_messages`), with the latest
+/// versions of edited messages. Used for the previous versions of edited messages (`Edit`), auditing deleted messages
+/// and short-term context of handlers:
+/// ```ignore
+/// grammersthon.recent_messages(RecentMessages::new(100).ttl(Duration::from_secs(3600)));
+///
+/// #[handler("^/summary")]
+/// async fn summary(data: HandlerData) -> HandlerResult {
+///     let messages = data.recent_messages(data.message.chat().id());
+///     ...
+/// }
+///
+/// grammersthon.update_hook(|client: Client, update: Update| async move {
+///     if let Update::MessageDeleted(deletion) = &update {
+///         for message in recent.deleted(deletion) { ... }
+///     }
+///     Ok(())
+/// });
+/// ```
+#[derive(Clone)]
+pub struct RecentMessages {
+    per_chat: usize,
+    max_chats: usize,
+    ttl: Option<Duration>,
+    chats: ChatMessages,
+}
+
+impl RecentMessages {
+    /// Create new instance keeping the last `per_chat` messages of each chat
+    pub fn new(per_chat: usize) -> RecentMessages {
+        RecentMessages { per_chat: per_chat.max(1), max_chats: DEFAULT_MAX_CHATS, ttl: None, chats: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Keep at most `max_chats` chats (default 1000), the least recently active one is dropped when exceeded
+    pub fn max_chats(mut self, max_chats: usize) -> Self {
+        self.max_chats = max_chats.max(1);
+        self
+    }
+
+    /// Forget messages received longer than `ttl` ago
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Add new message or replace the cached version of edited one, returns the replaced version
+    pub fn insert(&self, message: &Message) -> Option<Message> {
+        let mut chats = self.chats.lock().unwrap();
+        let chat = message.chat();
+        if !chats.contains_key(&chat.id()) && chats.len() >= self.max_chats {
+            let oldest = chats.iter()
+                .min_by_key(|(_, c)| c.messages.back().map(|(_, time)| *time))
+                .map(|(id, _)| *id);
+            if let Some(oldest) = oldest {
+                chats.remove(&oldest);
+            }
+        }
+        let cached = chats.entry(chat.id()).or_insert_with(|| CachedChat { channel: chat.pack().is_channel(), messages: VecDeque::new() });
+        let messages = &mut cached.messages;
+        if let Some((cached, _)) = messages.iter_mut().find(|(m, _)| m.id() == message.id()) {
+            return Some(std::mem::replace(cached, message.clone()));
+        }
+        messages.push_back((message.clone(), Instant::now()));
+        if messages.len() > self.per_chat {
+            messages.pop_front();
+        }
+        self.expire(messages);
+        None
+    }
+
+    /// Cached messages of chat, oldest first
+    pub fn messages(&self, chat: i64) -> Vec<Message> {
+        let mut chats = self.chats.lock().unwrap();
+        match chats.get_mut(&chat) {
+            Some(cached) => {
+                self.expire(&mut cached.messages);
+                cached.messages.iter().map(|(m, _)| m.clone()).collect()
+            },
+            None => vec![]
+        }
+    }
+
+    /// Last `count` cached messages of chat, oldest first
+    pub fn last(&self, chat: i64, count: usize) -> Vec<Message> {
+        let mut messages = self.messages(chat);
+        messages.drain(..messages.len().saturating_sub(count));
+        messages
+    }
+
+    /// Get cached message
+    pub fn get(&self, chat: i64, id: i32) -> Option<Message> {
+        self.messages(chat).into_iter().find(|m| m.id() == id)
+    }
+
+    /// Cached versions of deleted messages, for auditing. Deletions outside channels don't have chat,
+    /// so they are looked up in all the private chats and basic groups (which share message ids)
+    pub fn deleted(&self, deletion: &MessageDeletion) -> Vec<Message> {
+        let chats = self.chats.lock().unwrap();
+        let ids = deletion.messages();
+        chats.iter()
+            .filter(|(id, chat)| match deletion.channel_id() {
+                Some(channel) => chat.channel && channel == **id,
+                None => !chat.channel,
+            })
+            .flat_map(|(_, chat)| chat.messages.iter().map(|(m, _)| m))
+            .filter(|m| ids.contains(&m.id()))
+            .cloned()
+            .collect()
+    }
+
+    /// Remove the expired messages
+    fn expire(&self, messages: &mut VecDeque<(Message, Instant)>) {
+        if let Some(ttl) = self.ttl {
+            while messages.front().map(|(_, time)| time.elapsed() > ttl).unwrap_or(false) {
+                messages.pop_front();
+            }
+        }
+    }
+}
+
+impl FromHandlerData for RecentMessages {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.data::<RecentMessages>()
+    }
+}
+
+impl Grammersthon {
+    /// Enable the cache of recent messages
+    pub fn recent_messages(&mut self, recent: RecentMessages) -> &mut Self {
+        self.handlers.recent = Some(recent.clone());
+        self.data.insert::<Data<RecentMessages>>(recent);
+        self
+    }
+}
+
+impl HandlerData {
+    /// Cached messages of chat, oldest first, empty if the cache isn't enabled (`Grammersthon::recent_messages`)
+    pub fn recent_messages(&self, chat: i64) -> Vec<Message> {
+        self.data::<RecentMessages>().map(|r| r.messages(chat)).unwrap_or_default()
+    }
+}
+
+/// Last messages of the chat (oldest first, including the handled message), for conversational context.
+/// Requires the `context` handler option, which also sets the count (`context = 20`, default `Context::DEFAULT_SIZE`),
+/// messages are taken from the recent messages cache if enabled, otherwise from the chat history (not available to bots):
+/// ```ignore
+/// #[handler("^/ask", context = 20)]
+/// async fn ask(context: Context, message: Message) -> HandlerResult { ... }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Context(pub Vec<Message>);
+
+impl Context {
+    /// Count of messages if not set by the `context` option
+    pub const DEFAULT_SIZE: usize = 10;
+
+    /// Get the last `count` messages of chat of the message
+    pub(crate) async fn fetch(data: &HandlerData, count: usize) -> Result<Context, GrammersthonError> {
+        let chat = data.message.chat();
+        if let Some(recent) = data.data::<RecentMessages>() {
+            return Ok(Context(recent.last(chat.id(), count)));
+        }
+        let mut messages = vec![];
+        let mut history = data.client.iter_messages(&chat).limit(count);
+        while let Some(message) = history.next().await? {
+            messages.push(message);
+        }
+        messages.reverse();
+        Ok(Context(messages))
+    }
+}
+
+impl FromHandlerData for Context {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.context.clone()
+    }
+}
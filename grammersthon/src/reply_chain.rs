@@ -0,0 +1,102 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use grammers_client::types::Message;
+use trait_bound_typemap::TypeMap;
+
+use crate::{Data, GrammersthonError, HandlerData};
+
+/// Default maximum length of reply chain
+pub const REPLY_CHAIN_LIMIT: usize = 32;
+
+/// For how long are the fetched messages cached
+const REPLY_CACHE_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Maximum number of cached messages
+const REPLY_CACHE_SIZE: usize = 4096;
+
+/// (account, chat, message id), as message ids of private chats are per account
+type ReplyKey = (i64, i64, i32);
+
+/// Fetched messages of reply chains, per client.
+/// Deleted messages are not cached, so they don't shadow anything for the whole TTL
+#[derive(Clone, Default)]
+pub(crate) struct ReplyCache {
+    messages: Arc<Mutex<HashMap<ReplyKey, (Message, Instant)>>>,
+}
+
+impl ReplyCache {
+    /// Get cached message
+    fn get(&self, key: ReplyKey) -> Option<Message> {
+        match self.messages.lock().unwrap().get(&key) {
+            Some((message, time)) if time.elapsed() < REPLY_CACHE_TTL => Some(message.clone()),
+            _ => None
+        }
+    }
+
+    /// Cache message, evicting expired and then the oldest messages if full
+    fn insert(&self, key: ReplyKey, message: Message) {
+        let mut messages = self.messages.lock().unwrap();
+        if messages.len() >= REPLY_CACHE_SIZE {
+            messages.retain(|_, (_, time)| time.elapsed() < REPLY_CACHE_TTL);
+        }
+        if messages.len() >= REPLY_CACHE_SIZE {
+            let mut times: Vec<Instant> = messages.values().map(|(_, time)| *time).collect();
+            times.sort_unstable();
+            let cutoff = times[times.len() - REPLY_CACHE_SIZE / 2];
+            messages.retain(|_, (_, time)| *time >= cutoff);
+        }
+        messages.insert(key, (message, Instant::now()));
+    }
+}
+
+/// Walking up the reply-to references of message:
+/// ```ignore
+/// #[handler("^/thread")]
+/// async fn thread(message: Message, data: HandlerData) -> HandlerResult {
+///     let chain = message.reply_chain(&data).await?;
+///     message.reply(format!("{} messages above", chain.len())).await?;
+///     Ok(())
+/// }
+/// ```
+pub trait ReplyChain {
+    /// Messages this message replies to, transitively, closest first. Limited to `REPLY_CHAIN_LIMIT` messages,
+    /// ends early at deleted message. Fetched messages are cached for a while, as chains of the same thread overlap
+    fn reply_chain(&self, data: &HandlerData) -> impl Future<Output = Result<Vec<Message>, GrammersthonError>> + Send {
+        self.reply_chain_limit(data, REPLY_CHAIN_LIMIT)
+    }
+
+    /// Same as `reply_chain`, with maximum length of the chain
+    fn reply_chain_limit(&self, data: &HandlerData, limit: usize) -> impl Future<Output = Result<Vec<Message>, GrammersthonError>> + Send;
+}
+
+impl ReplyChain for Message {
+    async fn reply_chain_limit(&self, data: &HandlerData, limit: usize) -> Result<Vec<Message>, GrammersthonError> {
+        let cache = data.data.get::<Data<ReplyCache>>().cloned().unwrap_or_default();
+        let chat = self.chat();
+        let account = data.me.id();
+        let mut chain: Vec<Message> = vec![];
+        let mut seen = HashSet::from([self.id()]);
+        let mut next = self.reply_to_message_id();
+        while let Some(id) = next {
+            if chain.len() >= limit || !seen.insert(id) {
+                break;
+            }
+            let key = (account, chat.id(), id);
+            let message = match cache.get(key) {
+                Some(message) => message,
+                None => match data.client.get_messages_by_id(&chat, &[id]).await?.pop().flatten() {
+                    Some(message) => {
+                        cache.insert(key, message.clone());
+                        message
+                    },
+                    None => break,
+                }
+            };
+            next = message.reply_to_message_id();
+            chain.push(message);
+        }
+        Ok(chain)
+    }
+}
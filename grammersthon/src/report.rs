@@ -0,0 +1,72 @@
+use std::error::Error;
+use std::sync::Arc;
+use grammers_client::{InputMessage, Update};
+use grammers_session::PackedChat;
+
+use crate::{ErrorContext, ErrorLog, Grammersthon, GrammersthonError};
+
+/// Maximum length of the message text in report
+const REPORT_TEXT_LIMIT: usize = 500;
+
+impl Grammersthon {
+    /// Install error handler which logs the errors and sends them to log chat, replaces the error handler:
+    /// ```ignore
+    /// grammersthon.report_errors_to(log_chat);
+    /// ```
+    /// Report contains the error with its causes, the handler, chat and message text.
+    /// Repeated identical errors are suppressed the same way as in log (`ErrorLog`)
+    pub fn report_errors_to(&mut self, chat: impl Into<PackedChat>) -> &mut Self {
+        let chat = chat.into();
+        let log = Arc::new(ErrorLog::default());
+        self.error_context_handler(move |error: GrammersthonError, ctx: ErrorContext| {
+            let log = log.clone();
+            async move {
+                if let Some(report) = log.record(&error_report(&error, &ctx)) {
+                    error!("{report}");
+                    ctx.client.send_message(chat, InputMessage::text(report)).await?;
+                }
+                Ok(())
+            }
+        })
+    }
+}
+
+/// Format the report of error
+fn error_report(error: &GrammersthonError, ctx: &ErrorContext) -> String {
+    let message = match &ctx.update {
+        Update::NewMessage(m) | Update::MessageEdited(m) => Some(m),
+        _ => None
+    };
+    let lines = [
+        ctx.handler.as_ref().map(|h| format!("Handler: {h}")),
+        message.map(|m| format!("Chat: {} ({})", m.chat().name(), m.chat().id())),
+        message.filter(|m| !m.text().is_empty()).map(|m| format!("Message: {}", truncate(m.text(), REPORT_TEXT_LIMIT))),
+    ];
+    let mut report = format!("Error ({}): {error}", error.kind());
+    let mut source = error.source();
+    while let Some(e) = source {
+        report.push_str(&format!("\nCaused by: {e}"));
+        source = e.source();
+    }
+    for line in lines.into_iter().flatten() {
+        report.push_str(&format!("\n{line}"));
+    }
+    report
+}
+
+/// Shorten text to `limit` characters
+fn truncate(text: &str, limit: usize) -> String {
+    match text.char_indices().nth(limit) {
+        Some((i, _)) => format!("{}...", &text[..i]),
+        None => text.to_string(),
+    }
+}
+
+
+/// Test truncating message text
+#[test]
+fn test_truncate() {
+    assert_eq!(truncate("hello", 10), "hello");
+    assert_eq!(truncate("hello", 5), "hello");
+    assert_eq!(truncate("příliš dlouhé", 6), "příliš...");
+}
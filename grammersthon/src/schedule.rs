@@ -0,0 +1,414 @@
+//! Periodic and cron jobs running in the runtime of the bot, with access to the client and data
+//!
+//! Usage:
+//! ```ignore
+//! Scheduler::new()
+//!     .every(Duration::from_secs(3600), |ctx: JobContext| async move {
+//!         let storage = ctx.data::<Storage>().unwrap();
+//!         ...
+//!         Ok(())
+//!     })
+//!     .cron("0 0 9 * * *", |ctx: JobContext| async move {
+//!         ctx.client.send_message(channel, "Good morning").await?;
+//!         Ok(())
+//!     })?
+//!     .install(&mut grammersthon);
+//! ```
+//!
+//! Persisted jobs survive restarts, the payload is saved in `Storage` with the fire time and reloaded on install:
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Reminder { chat: PackedChat, text: String }
+//!
+//! impl Job for Reminder {
+//!     const NAME: &'static str = "reminder";
+//!     fn run(self, ctx: JobContext) -> JobFuture {
+//!         Box::pin(async move { ctx.client.send_message(self.chat, self.text).await?; Ok(()) })
+//!     }
+//! }
+//!
+//! Scheduler::new().job::<Reminder>().install(&mut grammersthon);
+//!
+//! // In handler
+//! async fn remind(queue: JobQueue, ...) -> HandlerResult {
+//!     queue.schedule_in(&Reminder { ... }, Duration::from_secs(3600))?;
+//! }
+//! ```
+//!
+//! Cron expressions have seconds (`sec min hour day month weekday`) and are evaluated in UTC.
+//! Jobs use the clock of grammersthon (`Grammersthon::clock`), so install after setting it.
+//! With `LeaderLock`, jobs only run on the leader instance.
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, Utc};
+use grammers_client::Client;
+use grammers_client::types::User;
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use trait_bound_typemap::{CloneSendSyncTypeMap, TypeMap};
+
+use crate::{Clock, Data, FromHandlerData, Grammersthon, GrammersthonError, HandlerData, HandlerResult, LeaderLock, Storage};
+use crate::leader;
+
+/// Storage key prefix of the persisted jobs
+pub const JOBS_PREFIX: &str = "grammersthon:jobs:";
+/// How often standby instances check whether the due job was run by the leader
+const STANDBY_RETRY: Duration = Duration::from_secs(30);
+
+/// Future returned by `Job::run`
+pub type JobFuture = Pin<Box<dyn Future<Output = HandlerResult> + Send>>;
+
+type JobFn = dyn Fn(JobContext) -> JobFuture + Send + Sync;
+type JobRunnerFn = dyn Fn(Value, JobContext) -> Result<JobFuture, GrammersthonError> + Send + Sync;
+/// Start persisted job by name with payload
+type JobExecutorFn = dyn Fn(&str, Value, JobQueue) -> Result<JobFuture, GrammersthonError> + Send + Sync;
+
+/// Payload of persisted job, register the type with `Scheduler::job`
+pub trait Job: Serialize + DeserializeOwned + Send + 'static {
+    /// Name of the job type saved with the payload, has to stay the same across versions
+    const NAME: &'static str;
+
+    /// Run the job
+    fn run(self, ctx: JobContext) -> JobFuture;
+}
+
+/// Persisted job saved in storage
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredJob {
+    job: String,
+    payload: Value,
+    /// Unix timestamp in milliseconds
+    at: u64,
+}
+
+/// Passed to scheduled jobs
+#[derive(Clone)]
+pub struct JobContext {
+    pub client: Client,
+    pub me: User,
+    /// Name of the job
+    pub job: String,
+    pub(crate) data: CloneSendSyncTypeMap,
+}
+
+impl JobContext {
+    /// Create new instance for grammersthon
+    pub(crate) fn new(grammersthon: &Grammersthon, job: &str) -> JobContext {
+        JobContext { client: grammersthon.client(), me: grammersthon.me().clone(), job: job.to_string(), data: grammersthon.data.clone() }
+    }
+
+    /// Get any data added with .add_data
+    pub fn data<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.data.get::<Data<T>>().cloned()
+    }
+
+    /// Should jobs run on this instance (see `LeaderLock`)
+    pub(crate) fn is_leader(&self) -> bool {
+        self.data::<LeaderLock>().map(|l| l.is_leader()).unwrap_or(true)
+    }
+}
+
+/// When does job run
+#[derive(Clone)]
+enum Trigger {
+    Every(Duration),
+    Cron(Box<cron::Schedule>),
+}
+
+/// Registered periodic job
+#[derive(Clone)]
+struct PeriodicJob {
+    name: String,
+    trigger: Trigger,
+    job: Arc<JobFn>,
+}
+
+/// Scheduler component
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    jobs: Vec<PeriodicJob>,
+    runners: HashMap<&'static str, Arc<JobRunnerFn>>,
+}
+
+impl Scheduler {
+    /// Create new instance without jobs
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    /// Run job every `interval`, first run is after the interval
+    pub fn every<H, F>(self, interval: Duration, job: H) -> Self
+    where
+        H: (Fn(JobContext) -> F) + Send + Sync + 'static,
+        F: Future<Output = HandlerResult> + Send + 'static
+    {
+        self.add(&format!("every {interval:?}"), Trigger::Every(interval), job)
+    }
+
+    /// Run job on cron schedule (with seconds, in UTC)
+    pub fn cron<H, F>(self, cron: &str, job: H) -> Result<Self, GrammersthonError>
+    where
+        H: (Fn(JobContext) -> F) + Send + Sync + 'static,
+        F: Future<Output = HandlerResult> + Send + 'static
+    {
+        let schedule = cron::Schedule::from_str(cron).map_err(|e| GrammersthonError::Parse(cron.to_string(), Some(Box::new(e))))?;
+        Ok(self.add(cron, Trigger::Cron(Box::new(schedule)), job))
+    }
+
+    fn add<H, F>(mut self, name: &str, trigger: Trigger, job: H) -> Self
+    where
+        H: (Fn(JobContext) -> F) + Send + Sync + 'static,
+        F: Future<Output = HandlerResult> + Send + 'static
+    {
+        self.jobs.push(PeriodicJob { name: name.to_string(), trigger, job: Arc::new(move |ctx| Box::pin(job(ctx))) });
+        self
+    }
+
+    /// Register type of persisted jobs, scheduled with `JobQueue`
+    pub fn job<J: Job>(mut self) -> Self {
+        self.runners.insert(J::NAME, Arc::new(|payload, ctx| Ok(serde_json::from_value::<J>(payload)?.run(ctx))));
+        self
+    }
+
+    /// Start the jobs and reload the persisted ones, install after setting the storage, clock and data used by the jobs
+    pub fn install(self, grammersthon: &mut Grammersthon) {
+        let clock = grammersthon.get_clock();
+        if !self.runners.is_empty() {
+            let ctx = JobContext::new(grammersthon, "");
+            let names = self.runners.keys().copied().collect();
+            let runners = self.runners;
+            let queue = JobQueue {
+                storage: grammersthon.get_storage(),
+                clock: clock.clone(),
+                leader: grammersthon.get_leader_lock(),
+                names: Arc::new(names),
+                executor: Arc::new(move |job, payload, queue| {
+                    let runner = runners.get(job).ok_or_else(|| not_registered(job))?;
+                    let mut ctx = ctx.clone();
+                    ctx.job = job.to_string();
+                    ctx.data.insert::<Data<JobQueue>>(queue);
+                    runner(payload, ctx)
+                }),
+                counter: Default::default(),
+            };
+            grammersthon.data.insert::<Data<JobQueue>>(queue.clone());
+            if let Err(e) = queue.reload() {
+                error!("Failed loading persisted jobs: {e}");
+            }
+        }
+        for job in self.jobs {
+            let ctx = JobContext::new(grammersthon, &job.name);
+            let clock = clock.clone();
+            tokio::spawn(async move {
+                while let Some(wait) = job.trigger.next(&*clock) {
+                    clock.sleep(wait).await;
+                    if !ctx.is_leader() {
+                        continue;
+                    }
+                    debug!("Running job {}", job.name);
+                    if let Err(e) = (*job.job)(ctx.clone()).await {
+                        error!("Job {} failed: {e}", job.name);
+                    }
+                }
+            });
+        }
+    }
+}
+
+impl Trigger {
+    /// Time until the next run, None if there are no more runs
+    fn next(&self, clock: &dyn Clock) -> Option<Duration> {
+        match self {
+            Trigger::Every(interval) => Some(*interval),
+            Trigger::Cron(schedule) => {
+                let now = DateTime::<Utc>::from(clock.system_time());
+                let next = schedule.after(&now).next()?;
+                Some((next - now).to_std().unwrap_or_default())
+            }
+        }
+    }
+}
+
+
+/// Handle for scheduling persisted jobs, available as extractor and in `JobContext::data` after `Scheduler::install`
+#[derive(Clone)]
+pub struct JobQueue {
+    storage: Storage,
+    clock: Arc<dyn Clock>,
+    leader: Option<LeaderLock>,
+    /// Registered job types
+    names: Arc<HashSet<&'static str>>,
+    executor: Arc<JobExecutorFn>,
+    counter: Arc<AtomicU64>,
+}
+
+/// Error for job type missing in the scheduler
+fn not_registered(job: &str) -> GrammersthonError {
+    GrammersthonError::Error(format!("Job type {job} not registered with Scheduler::job").into())
+}
+
+impl JobQueue {
+    /// Schedule job to run at time, returns id of the job
+    pub fn schedule<J: Job>(&self, job: &J, at: impl Into<SystemTime>) -> Result<String, GrammersthonError> {
+        if !self.names.contains(J::NAME) {
+            return Err(not_registered(J::NAME));
+        }
+        let at = at.into();
+        let ms = at.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or_default();
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or_default();
+        // Sorted by fire time
+        let id = format!("{ms:013}-{nanos:08x}{}", self.counter.fetch_add(1, Ordering::Relaxed));
+        let stored = StoredJob { job: J::NAME.to_string(), payload: serde_json::to_value(job)?, at: ms };
+        self.storage.set(&format!("{JOBS_PREFIX}{id}"), &stored)?;
+        self.start(id.clone(), at);
+        Ok(id)
+    }
+
+    /// Schedule job to run after delay, returns id of the job
+    pub fn schedule_in<J: Job>(&self, job: &J, delay: Duration) -> Result<String, GrammersthonError> {
+        self.schedule(job, self.clock.system_time() + delay)
+    }
+
+    /// Cancel pending job, returns if it was pending
+    pub fn cancel(&self, id: &str) -> Result<bool, GrammersthonError> {
+        self.storage.remove(&format!("{JOBS_PREFIX}{id}"))
+    }
+
+    /// Ids of the pending jobs, ordered by fire time
+    pub fn pending(&self) -> Result<Vec<String>, GrammersthonError> {
+        let mut ids = self.storage.keys(JOBS_PREFIX)?.into_iter().map(|k| k[JOBS_PREFIX.len()..].to_string()).collect::<Vec<_>>();
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Start timers of the persisted jobs, overdue ones run right away
+    fn reload(&self) -> Result<(), GrammersthonError> {
+        for id in self.pending()? {
+            match self.storage.get::<StoredJob>(&format!("{JOBS_PREFIX}{id}"))? {
+                Some(job) => self.start(id, UNIX_EPOCH + Duration::from_millis(job.at)),
+                None => continue,
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for the fire time and run the job, until it's run by the leader or cancelled
+    fn start(&self, id: String, at: SystemTime) {
+        let this = self.clone();
+        tokio::spawn(async move {
+            this.clock.sleep(at.duration_since(this.clock.system_time()).unwrap_or_default()).await;
+            let key = format!("{JOBS_PREFIX}{id}");
+            while !leader::is_leader(&this.leader) {
+                this.clock.sleep(STANDBY_RETRY).await;
+                if !matches!(this.storage.get::<Value>(&key), Ok(Some(_))) {
+                    return;
+                }
+            }
+            if let Err(e) = this.run(&key).await {
+                error!("Job {id} failed: {e}");
+            }
+        });
+    }
+
+    /// Run the stored job and remove it (also on failure)
+    async fn run(&self, key: &str) -> HandlerResult {
+        let job = match self.storage.get::<StoredJob>(key)? {
+            Some(job) => job,
+            // Cancelled or run by other instance
+            None => return Ok(()),
+        };
+        debug!("Running job {}", job.job);
+        let result = (*self.executor)(&job.job, job.payload, self.clone());
+        self.storage.remove(key)?;
+        result?.await
+    }
+}
+
+impl FromHandlerData for JobQueue {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.data::<JobQueue>()
+    }
+}
+
+
+/// Test time until the next run
+#[test]
+fn test_trigger() {
+    let clock = crate::ManualClock::new(std::time::UNIX_EPOCH + Duration::from_secs(86400 * 100 + 8 * 3600));
+    let cron = Trigger::Cron(Box::new(cron::Schedule::from_str("0 30 9 * * *").unwrap()));
+    assert_eq!(cron.next(&clock), Some(Duration::from_secs(5400)));
+    assert_eq!(Trigger::Every(Duration::from_secs(60)).next(&clock), Some(Duration::from_secs(60)));
+}
+
+/// Test scheduling, cancelling, running and reloading persisted jobs
+#[tokio::test]
+async fn test_job_queue() {
+    #[derive(Serialize, Deserialize)]
+    struct Ping(u32);
+    impl Job for Ping {
+        const NAME: &'static str = "ping";
+        fn run(self, _: JobContext) -> JobFuture {
+            Box::pin(async { Ok(()) })
+        }
+    }
+    /// Let the job timers run
+    async fn settle() {
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    let clock = crate::ManualClock::new(UNIX_EPOCH + Duration::from_secs(1000));
+    let storage = Storage::memory();
+    let runs = Arc::new(std::sync::Mutex::new(vec![]));
+    let queue = || {
+        let runs = runs.clone();
+        JobQueue {
+            storage: storage.clone(),
+            clock: Arc::new(clock.clone()),
+            leader: None,
+            names: Arc::new(HashSet::from([Ping::NAME])),
+            executor: Arc::new(move |job, payload, _| {
+                runs.lock().unwrap().push((job.to_string(), payload));
+                Ok(Box::pin(async { Ok(()) }))
+            }),
+            counter: Default::default(),
+        }
+    };
+
+    // Schedule and cancel
+    let first = queue();
+    let due = first.schedule_in(&Ping(1), Duration::from_secs(60)).unwrap();
+    let cancelled = first.schedule_in(&Ping(2), Duration::from_secs(30)).unwrap();
+    assert_eq!(first.pending().unwrap(), vec![cancelled.clone(), due.clone()]);
+    assert!(first.cancel(&cancelled).unwrap());
+    assert!(!first.cancel(&cancelled).unwrap());
+    settle().await;
+
+    // Run and remove the due job
+    clock.advance(Duration::from_secs(60));
+    settle().await;
+    assert_eq!(*runs.lock().unwrap(), vec![("ping".to_string(), serde_json::json!(1))]);
+    assert!(first.pending().unwrap().is_empty());
+
+    // Jobs persisted before restart, overdue one runs right away
+    let stored = |payload: u32, at: u64| StoredJob { job: Ping::NAME.to_string(), payload: serde_json::json!(payload), at };
+    storage.set(&format!("{JOBS_PREFIX}0000000001000-0"), &stored(3, 1_000_000)).unwrap();
+    storage.set(&format!("{JOBS_PREFIX}0000000001100-0"), &stored(4, 1_100_000)).unwrap();
+    let restarted = queue();
+    restarted.reload().unwrap();
+    settle().await;
+    assert_eq!(runs.lock().unwrap().len(), 2);
+    assert_eq!(restarted.pending().unwrap(), vec!["0000000001100-0"]);
+    clock.advance(Duration::from_secs(40));
+    settle().await;
+    assert_eq!(runs.lock().unwrap()[2].1, serde_json::json!(4));
+    assert!(restarted.pending().unwrap().is_empty());
+}
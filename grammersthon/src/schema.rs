@@ -0,0 +1,77 @@
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{Grammersthon, ArgInfo, CommandScope, HandlerInfo};
+
+/// Machine readable description of all the registered handlers
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandSchema {
+    pub commands: Vec<CommandInfo>
+}
+
+impl CommandSchema {
+    /// Generate from handlers with their patterns
+    pub(crate) fn new(handlers: Vec<(HandlerInfo, Vec<String>)>) -> CommandSchema {
+        let commands = handlers.into_iter().map(|(info, patterns)| CommandInfo {
+            handler: info.name.clone(),
+            command: patterns.iter().find_map(|p| command_from_pattern(p)),
+            patterns,
+            description: info.description.clone(),
+            args: info.args.clone(),
+            scope: info.command_scope(),
+            category: info.category.clone(),
+            hidden: info.hidden,
+        }).collect();
+        CommandSchema { commands }
+    }
+
+    /// Serialize into JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+/// Description of a single handler
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandInfo {
+    /// Handler function name
+    pub handler: String,
+    /// Command name (without prefix) if the pattern starts with one
+    pub command: Option<String>,
+    /// Regex patterns with mutators applied
+    pub patterns: Vec<String>,
+    pub description: Option<String>,
+    pub args: Vec<ArgInfo>,
+    /// Where should the command be shown in command menu
+    pub scope: Option<CommandScope>,
+    /// Section of the help message
+    pub category: Option<String>,
+    /// Excluded from help and command menu
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+}
+
+/// Get command name from pattern such as `^/ping$` 
+pub(crate) fn command_from_pattern(pattern: &str) -> Option<String> {
+    let regex = Regex::new(r"^(?:\(\?i\))?\^?(?:\(\?:)?(?:/|\\?[!.])([A-Za-z0-9_]+)").unwrap();
+    regex.captures(pattern).map(|c| c[1].to_string())
+}
+
+impl Grammersthon {
+    /// Generate description of all the registered handlers
+    pub fn command_schema(&self) -> CommandSchema {
+        CommandSchema::new(self.handlers.handlers())
+    }
+}
+
+
+/// Test command name detection
+#[test]
+fn test_command_from_pattern() {
+    assert_eq!(command_from_pattern("^/ping$").as_deref(), Some("ping"));
+    assert_eq!(command_from_pattern("/hi").as_deref(), Some("hi"));
+    assert_eq!(command_from_pattern("(?i)^(?:/start)$").as_deref(), Some("start"));
+    assert_eq!(command_from_pattern("^\\.ban").as_deref(), Some("ban"));
+    assert_eq!(command_from_pattern(r"^!start(?:@\w+)?(?:\s|$)").as_deref(), Some("start"));
+    assert_eq!(command_from_pattern("^Ping!$"), None);
+}
@@ -0,0 +1,255 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use grammers_session::Session;
+use trait_bound_typemap::TypeMap;
+
+use crate::{Data, Grammersthon, GrammersthonError};
+
+/// Encode session into a portable hex string
+pub(crate) fn encode_session(session: &Session) -> String {
+    session.save().iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Decode session from string generated by `encode_session`
+pub(crate) fn decode_session(input: &str) -> Result<Session, GrammersthonError> {
+    let input = input.trim();
+    if !input.is_ascii() || !input.len().is_multiple_of(2) {
+        return Err(GrammersthonError::Parse("session string".to_string(), None));
+    }
+    let bytes = (0..input.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&input[i..i + 2], 16))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| GrammersthonError::Parse("session string".to_string(), Some(e.into())))?;
+    Session::load(&bytes).map_err(|e| GrammersthonError::Parse("session string".to_string(), Some(Box::new(e))))
+}
+
+/// Future returned by `SessionStore::load`
+pub type SessionLoadFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<Vec<u8>>, GrammersthonError>> + Send + 'a>>;
+/// Future returned by `SessionStore::save`
+pub type SessionSaveFuture<'a> = Pin<Box<dyn Future<Output = Result<(), GrammersthonError>> + Send + 'a>>;
+
+/// Persistence of the session (`GrammersthonBuilder::session_store`), so it can be kept in database or secret manager.
+/// The session is saved after login, periodically while running the event loop (in background) and on shutdown (Ctrl+C):
+/// ```ignore
+/// impl SessionStore for DbStore {
+///     fn load(&self) -> SessionLoadFuture<'_> { Box::pin(async { Ok(self.db.get("session").await?) }) }
+///     fn save<'a>(&'a self, session: &'a [u8]) -> SessionSaveFuture<'a> { Box::pin(async { Ok(self.db.set("session", session).await?) }) }
+/// }
+/// ```
+pub trait SessionStore: Send + Sync {
+    /// Load the serialized session, None if there is none yet
+    fn load(&self) -> SessionLoadFuture<'_>;
+    /// Save the serialized session
+    fn save<'a>(&'a self, session: &'a [u8]) -> SessionSaveFuture<'a>;
+}
+
+/// Session in file
+#[derive(Debug, Clone)]
+pub struct FileSessionStore {
+    path: PathBuf,
+}
+
+impl FileSessionStore {
+    /// Create new instance
+    pub fn new(path: impl AsRef<Path>) -> FileSessionStore {
+        FileSessionStore { path: path.as_ref().to_owned() }
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn load(&self) -> SessionLoadFuture<'_> {
+        Box::pin(async move {
+            match tokio::fs::read(&self.path).await {
+                Ok(session) => Ok(Some(session)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    fn save<'a>(&'a self, session: &'a [u8]) -> SessionSaveFuture<'a> {
+        Box::pin(async move {
+            // Write whole file first, so crash can't leave broken session
+            let mut tmp = self.path.clone().into_os_string();
+            tmp.push(".tmp");
+            tokio::fs::write(&tmp, session).await?;
+            tokio::fs::rename(&tmp, &self.path).await?;
+            Ok(())
+        })
+    }
+}
+
+/// Session in memory, lost on restart (shared by clones)
+#[derive(Debug, Clone, Default)]
+pub struct MemorySessionStore {
+    session: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl SessionStore for MemorySessionStore {
+    fn load(&self) -> SessionLoadFuture<'_> {
+        let session = self.session.lock().unwrap().clone();
+        Box::pin(async move { Ok(session) })
+    }
+
+    fn save<'a>(&'a self, session: &'a [u8]) -> SessionSaveFuture<'a> {
+        *self.session.lock().unwrap() = Some(session.to_vec());
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// Session encrypted at rest by passphrase (requires the `encryption` feature), wraps other store:
+/// ```ignore
+/// let store = EncryptedSessionStore::new(FileSessionStore::new("bot.session"), &std::env::var("SESSION_PASSPHRASE")?);
+/// ```
+/// The key is derived from the passphrase with Argon2id and the session is encrypted with ChaCha20-Poly1305.
+/// Loading fails with wrong passphrase or unencrypted session
+#[cfg(feature = "encryption")]
+pub struct EncryptedSessionStore {
+    inner: Box<dyn SessionStore>,
+    passphrase: String,
+}
+
+#[cfg(feature = "encryption")]
+impl EncryptedSessionStore {
+    /// Header of the encrypted session
+    const MAGIC: &'static [u8] = b"GTSE1";
+    const SALT_SIZE: usize = 16;
+    const NONCE_SIZE: usize = 12;
+
+    /// Create new instance
+    pub fn new(inner: impl SessionStore + 'static, passphrase: &str) -> EncryptedSessionStore {
+        EncryptedSessionStore { inner: Box::new(inner), passphrase: passphrase.to_string() }
+    }
+
+    /// Derive the key from passphrase
+    fn cipher(passphrase: &str, salt: &[u8]) -> Result<chacha20poly1305::ChaCha20Poly1305, GrammersthonError> {
+        use chacha20poly1305::KeyInit;
+        let mut key = [0u8; 32];
+        argon2::Argon2::default().hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| GrammersthonError::Error(e.to_string().into()))?;
+        Ok(chacha20poly1305::ChaCha20Poly1305::new(&key.into()))
+    }
+
+    /// Encrypt the session with random salt and nonce
+    fn encrypt(passphrase: &str, session: &[u8]) -> Result<Vec<u8>, GrammersthonError> {
+        use chacha20poly1305::aead::{Aead, AeadCore, OsRng, rand_core::RngCore};
+        let mut salt = [0u8; Self::SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = chacha20poly1305::ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let encrypted = Self::cipher(passphrase, &salt)?.encrypt(&nonce, session)
+            .map_err(|_| GrammersthonError::Error("failed encrypting session".into()))?;
+        Ok([Self::MAGIC, &salt, &nonce, &encrypted].concat())
+    }
+
+    /// Decrypt session encrypted by `encrypt`
+    fn decrypt(passphrase: &str, data: &[u8]) -> Result<Vec<u8>, GrammersthonError> {
+        use chacha20poly1305::aead::Aead;
+        let header = Self::MAGIC.len() + Self::SALT_SIZE + Self::NONCE_SIZE;
+        if data.len() < header || !data.starts_with(Self::MAGIC) {
+            return Err(GrammersthonError::Parse("encrypted session (not encrypted)".to_string(), None));
+        }
+        let (salt, rest) = data[Self::MAGIC.len()..].split_at(Self::SALT_SIZE);
+        let (nonce, encrypted) = rest.split_at(Self::NONCE_SIZE);
+        Self::cipher(passphrase, salt)?.decrypt(nonce.into(), encrypted)
+            .map_err(|_| GrammersthonError::Parse("encrypted session (wrong passphrase)".to_string(), None))
+    }
+
+    /// Run the key derivation (slow by design) outside of the async runtime
+    async fn blocking<F>(&self, data: Vec<u8>, f: F) -> Result<Vec<u8>, GrammersthonError>
+    where
+        F: FnOnce(&str, &[u8]) -> Result<Vec<u8>, GrammersthonError> + Send + 'static
+    {
+        let passphrase = self.passphrase.clone();
+        tokio::task::spawn_blocking(move || f(&passphrase, &data)).await
+            .map_err(|e| GrammersthonError::Error(Box::new(e)))?
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl SessionStore for EncryptedSessionStore {
+    fn load(&self) -> SessionLoadFuture<'_> {
+        Box::pin(async move {
+            match self.inner.load().await? {
+                Some(data) => Ok(Some(self.blocking(data, Self::decrypt).await?)),
+                None => Ok(None),
+            }
+        })
+    }
+
+    fn save<'a>(&'a self, session: &'a [u8]) -> SessionSaveFuture<'a> {
+        Box::pin(async move {
+            let encrypted = self.blocking(session.to_vec(), Self::encrypt).await?;
+            self.inner.save(&encrypted).await
+        })
+    }
+}
+
+/// Load session from store, new one if there is none
+pub(crate) async fn load_session(store: &dyn SessionStore) -> Result<Session, GrammersthonError> {
+    match store.load().await? {
+        Some(session) => Session::load(&session).map_err(|e| GrammersthonError::Parse("session".to_string(), Some(Box::new(e)))),
+        None => Ok(Session::new()),
+    }
+}
+
+impl Grammersthon {
+    /// Set where is the session saved (otherwise set by the builder)
+    pub fn session_store(&mut self, store: impl SessionStore + 'static) -> &mut Self {
+        self.data.insert::<Data<Arc<dyn SessionStore>>>(Arc::new(store));
+        self
+    }
+
+    /// Get the session store
+    pub fn get_session_store(&self) -> Option<Arc<dyn SessionStore>> {
+        self.data.get::<Data<Arc<dyn SessionStore>>>().cloned()
+    }
+
+    /// Save the session to the session store, if set
+    pub async fn save_session(&self) -> Result<(), GrammersthonError> {
+        if let Some(store) = self.get_session_store() {
+            store.save(&self.client.session().save()).await?;
+        }
+        Ok(())
+    }
+}
+
+
+/// Test session stores
+#[tokio::test]
+async fn test_session_store() {
+    let memory = MemorySessionStore::default();
+    assert_eq!(memory.load().await.unwrap(), None);
+    memory.clone().save(b"session").await.unwrap();
+    assert_eq!(memory.load().await.unwrap().as_deref(), Some(&b"session"[..]));
+
+    let path = std::env::temp_dir().join(format!("grammersthon-session-{}", std::process::id()));
+    let file = FileSessionStore::new(&path);
+    assert_eq!(file.load().await.unwrap(), None);
+    file.save(b"session").await.unwrap();
+    assert_eq!(file.load().await.unwrap().as_deref(), Some(&b"session"[..]));
+    std::fs::remove_file(path).unwrap();
+}
+
+/// Test rejecting invalid session strings
+#[test]
+fn test_decode_session() {
+    assert!(decode_session("abc").is_err());
+    assert!(decode_session("aé0").is_err());
+    assert!(decode_session("zz").is_err());
+}
+
+/// Test encrypted session store
+#[cfg(feature = "encryption")]
+#[tokio::test]
+async fn test_encrypted_session_store() {
+    let memory = MemorySessionStore::default();
+    let store = EncryptedSessionStore::new(memory.clone(), "passphrase");
+    store.save(b"session").await.unwrap();
+    assert!(!memory.load().await.unwrap().unwrap().windows(7).any(|w| w == b"session"));
+    assert_eq!(store.load().await.unwrap().as_deref(), Some(&b"session"[..]));
+    assert!(EncryptedSessionStore::new(memory.clone(), "wrong").load().await.is_err());
+    memory.save(b"session").await.unwrap();
+    assert!(store.load().await.is_err());
+}
@@ -0,0 +1,131 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::Utc;
+use grammers_client::Client;
+use grammers_client::types::User;
+use trait_bound_typemap::{CloneSendSyncTypeMap, TypeMap};
+
+use crate::{Data, Grammersthon, GrammersthonError, HandlerResult, StartupReport};
+use crate::status::{LastShutdown, SHUTDOWN_KEY};
+
+type ShutdownHookFn = dyn Fn(ShutdownContext) -> Pin<Box<dyn Future<Output = HandlerResult> + Send>> + Send + Sync;
+
+/// Default time for all the shutdown hooks to finish
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Registered shutdown hooks
+#[derive(Clone)]
+struct ShutdownHooks {
+    hooks: Vec<Arc<ShutdownHookFn>>,
+    timeout: Duration,
+}
+
+impl Default for ShutdownHooks {
+    fn default() -> Self {
+        ShutdownHooks { hooks: vec![], timeout: SHUTDOWN_TIMEOUT }
+    }
+}
+
+/// Passed to shutdown hooks
+#[derive(Clone)]
+pub struct ShutdownContext {
+    pub client: Client,
+    pub me: User,
+    data: CloneSendSyncTypeMap,
+}
+
+impl ShutdownContext {
+    /// Get any data added with .add_data
+    pub fn data<T: Clone + Send + Sync + 'static>(&self) -> Option<T> {
+        self.data.get::<Data<T>>().cloned()
+    }
+}
+
+impl Grammersthon {
+    /// Register hook run on graceful shutdown (Ctrl+C, SIGTERM or `Grammersthon::shutdown`), for teardown logic
+    /// such as flushing caches, closing database pools or announcing downtime:
+    /// ```ignore
+    /// grammersthon.on_shutdown(|ctx: ShutdownContext| async move {
+    ///     ctx.client.send_message(log_chat, "Going down for maintenance").await?;
+    ///     Ok(())
+    /// });
+    /// ```
+    /// Hooks run in registration order, hooks not finished before the deadline (`shutdown_timeout`) are skipped
+    pub fn on_shutdown<H, F>(&mut self, hook: H) -> &mut Self
+    where
+        H: (Fn(ShutdownContext) -> F) + Send + Sync + 'static,
+        F: Future<Output = HandlerResult> + Send + 'static
+    {
+        let mut hooks = self.shutdown_hooks();
+        hooks.hooks.push(Arc::new(move |ctx| Box::pin(hook(ctx))));
+        self.data.insert::<Data<ShutdownHooks>>(hooks);
+        self
+    }
+
+    /// Set the time for all the shutdown hooks to finish (default 10s)
+    pub fn shutdown_timeout(&mut self, timeout: Duration) -> &mut Self {
+        let mut hooks = self.shutdown_hooks();
+        hooks.timeout = timeout;
+        self.data.insert::<Data<ShutdownHooks>>(hooks);
+        self
+    }
+
+    /// Run the shutdown hooks and save the session (if session store is set)
+    pub async fn shutdown(&self) -> Result<(), GrammersthonError> {
+        self.shutdown_with("requested").await
+    }
+
+    /// Shutdown, saving the reason for the next startup report
+    pub(crate) async fn shutdown_with(&self, reason: &str) -> Result<(), GrammersthonError> {
+        if let Some(health) = self.get_health() {
+            health.set_running(false);
+        }
+        let last = LastShutdown { reason: reason.to_string(), time: Utc::now().timestamp() };
+        if let Err(e) = self.get_storage().set(SHUTDOWN_KEY, &last) {
+            warn!("Failed saving shutdown reason: {e}");
+        }
+        let hooks = self.shutdown_hooks();
+        let deadline = tokio::time::Instant::now() + hooks.timeout;
+        let ctx = ShutdownContext { client: self.client(), me: self.me.clone(), data: self.data.clone() };
+        for (i, hook) in hooks.hooks.iter().enumerate() {
+            match tokio::time::timeout_at(deadline, hook(ctx.clone())).await {
+                Ok(Ok(())) => (),
+                Ok(Err(e)) => error!("Shutdown hook #{i} failed: {e}"),
+                Err(_) => {
+                    warn!("Shutdown deadline exceeded, skipping {} hooks", hooks.hooks.len() - i);
+                    break;
+                }
+            }
+        }
+        self.save_session().await
+    }
+
+    /// Is there anything to do on shutdown (including saving the reason for startup report)
+    pub(crate) fn has_shutdown_work(&self) -> bool {
+        self.get_session_store().is_some()
+            || !self.shutdown_hooks().hooks.is_empty()
+            || self.data.get::<Data<StartupReport>>().is_some()
+    }
+
+    fn shutdown_hooks(&self) -> ShutdownHooks {
+        self.data.get::<Data<ShutdownHooks>>().cloned().unwrap_or_default()
+    }
+}
+
+/// Wait for Ctrl+C or SIGTERM
+pub(crate) async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        if let Ok(mut sigterm) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => (),
+                _ = sigterm.recv() => (),
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
@@ -0,0 +1,104 @@
+//! Snapshot of the bot state, for migrating between hosts
+//!
+//! Usage:
+//! ```ignore
+//! // Old host
+//! grammersthon.export_state("state.json")?;
+//! // New host, after setting the storage
+//! grammersthon.import_state("state.json")?;
+//! ```
+//!
+//! Everything persistent lives in the `Storage` (FSM states, wizard drafts, menus, scheduled posts,
+//! digests, mirror mappings, template overrides...), so the snapshot contains all its values.
+//! In memory caches (group admins, dedup) are rebuilt on demand and the session is exported separately (`export_session`).
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use crate::{Grammersthon, GrammersthonError, Storage};
+
+/// Current version of the snapshot format
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Serialized state of the bot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    /// Format version, snapshots from newer versions are rejected
+    pub version: u32,
+    /// Unix timestamp of creation
+    pub created: u64,
+    /// All the storage values
+    pub storage: BTreeMap<String, Value>,
+}
+
+impl StateSnapshot {
+    /// Capture the state
+    pub fn capture(storage: &Storage) -> Result<StateSnapshot, GrammersthonError> {
+        let mut values = BTreeMap::new();
+        for key in storage.keys("")? {
+            // Could be removed meanwhile
+            if let Some(value) = storage.get::<Value>(&key)? {
+                values.insert(key, value);
+            }
+        }
+        let created = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        Ok(StateSnapshot { version: SNAPSHOT_VERSION, created, storage: values })
+    }
+
+    /// Restore the state, existing values with the same keys are overwritten. Returns number of restored values
+    pub fn restore(self, storage: &Storage) -> Result<usize, GrammersthonError> {
+        if self.version > SNAPSHOT_VERSION {
+            return Err(GrammersthonError::Parse(format!("snapshot version {}", self.version), None));
+        }
+        let count = self.storage.len();
+        storage.set_many(self.storage.into_iter().collect())?;
+        Ok(count)
+    }
+
+    /// Load from JSON file
+    pub fn load(path: impl AsRef<Path>) -> Result<StateSnapshot, GrammersthonError> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    /// Write to JSON file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), GrammersthonError> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+impl Grammersthon {
+    /// Write snapshot of the bot state to file
+    pub fn export_state(&self, path: impl AsRef<Path>) -> Result<(), GrammersthonError> {
+        StateSnapshot::capture(&self.get_storage())?.save(path)
+    }
+
+    /// Restore the bot state from snapshot file, returns number of restored values
+    pub fn import_state(&self, path: impl AsRef<Path>) -> Result<usize, GrammersthonError> {
+        StateSnapshot::load(path)?.restore(&self.get_storage())
+    }
+}
+
+
+/// Test capturing and restoring state
+#[test]
+fn test_snapshot() {
+    let storage = Storage::memory();
+    storage.set("fsm:1:2", &serde_json::json!({"state": "ask_name"})).unwrap();
+    storage.set("count", &5).unwrap();
+    let snapshot = StateSnapshot::capture(&storage).unwrap();
+    assert_eq!(snapshot.storage.len(), 2);
+
+    let restored = Storage::memory();
+    restored.set("count", &1).unwrap();
+    let json = serde_json::to_string(&snapshot).unwrap();
+    assert_eq!(serde_json::from_str::<StateSnapshot>(&json).unwrap().restore(&restored).unwrap(), 2);
+    assert_eq!(restored.get::<i32>("count").unwrap(), Some(5));
+    assert_eq!(restored.keys("fsm:").unwrap(), vec!["fsm:1:2"]);
+
+    let future = StateSnapshot { version: SNAPSHOT_VERSION + 1, ..snapshot };
+    assert!(future.restore(&restored).is_err());
+}
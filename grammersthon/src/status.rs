@@ -0,0 +1,199 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::{DateTime, TimeZone, Utc};
+use grammers_client::{InputMessage, Update};
+use grammers_session::PackedChat;
+use serde::{Serialize, Deserialize};
+use trait_bound_typemap::TypeMap;
+
+use crate::{Data, Grammersthon, GrammersthonError};
+
+/// Storage key of the last shutdown
+pub(crate) const SHUTDOWN_KEY: &str = "grammersthon:shutdown";
+
+/// Graceful shutdown, saved in `Storage`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct LastShutdown {
+    pub reason: String,
+    /// Unix timestamp
+    pub time: i64,
+}
+
+/// Report sent to owner chat when the event loop starts:
+/// ```ignore
+/// StartupReport::new(owner)
+///     .version(env!("CARGO_PKG_VERSION"))
+///     .plugins(["mirror", "digest"])
+///     .install(&mut grammersthon);
+/// ```
+/// Contains the account, version, plugins, compiled features, handler count, failed self checks
+/// and the last shutdown reason (requires persistent `Storage`, unknown after crash).
+/// Messages missed while offline are delivered after the start, so the catch-up stats are added
+/// to the report by editing it once the catch-up window passes (`catch_up_window`)
+#[derive(Debug, Clone)]
+pub struct StartupReport {
+    chat: PackedChat,
+    version: Option<String>,
+    plugins: Vec<String>,
+    self_check: bool,
+    catch_up_window: Duration,
+}
+
+impl StartupReport {
+    /// Create new instance sending the report to chat
+    pub fn new(chat: impl Into<PackedChat>) -> StartupReport {
+        StartupReport { chat: chat.into(), version: None, plugins: vec![], self_check: true, catch_up_window: Duration::from_secs(30) }
+    }
+
+    /// Version of the application
+    pub fn version(mut self, version: &str) -> Self {
+        self.version = Some(version.to_string());
+        self
+    }
+
+    /// Names of the enabled plugins / components
+    pub fn plugins(mut self, plugins: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.plugins.extend(plugins.into_iter().map(Into::into));
+        self
+    }
+
+    /// Run `Grammersthon::self_check` and include the failed checks (default true)
+    pub fn self_check(mut self, self_check: bool) -> Self {
+        self.self_check = self_check;
+        self
+    }
+
+    /// How long to count the caught up messages (sent before the start) for the catch-up stats,
+    /// zero to leave the stats out (default 30s)
+    pub fn catch_up_window(mut self, window: Duration) -> Self {
+        self.catch_up_window = window;
+        self
+    }
+
+    /// Send the report when the event loop starts
+    pub fn install(self, grammersthon: &mut Grammersthon) {
+        grammersthon.data.insert::<Data<StartupReport>>(self);
+    }
+
+    /// Generate and send the report, returns the catch-up counter if the stats are enabled
+    pub(crate) async fn send(&self, grammersthon: &Grammersthon) -> Result<Option<CatchUp>, GrammersthonError> {
+        let me = grammersthon.me();
+        let mut lines = vec![format!(
+            "Started {} ({} {}){}",
+            me.username().map(|u| format!("@{u}")).unwrap_or_else(|| me.full_name()),
+            if me.is_bot() { "bot" } else { "user" },
+            me.id(),
+            self.version.as_ref().map(|v| format!(" v{v}")).unwrap_or_default(),
+        )];
+        lines.push(format!("Grammersthon: v{}", env!("CARGO_PKG_VERSION")));
+        if !self.plugins.is_empty() {
+            lines.push(format!("Plugins: {}", self.plugins.join(", ")));
+        }
+        let features = FEATURES.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| *name).collect::<Vec<_>>();
+        if !features.is_empty() {
+            lines.push(format!("Features: {}", features.join(", ")));
+        }
+        lines.push(format!("Handlers: {}", grammersthon.handlers.handlers().len()));
+
+        if self.self_check {
+            let report = grammersthon.self_check().await;
+            match report.failed().as_slice() {
+                [] => lines.push("Health: OK".to_string()),
+                failed => lines.extend(failed.iter().map(|c| format!("Health: {} failed: {}", c.name, c.message))),
+            }
+        }
+
+        let storage = grammersthon.get_storage();
+        let last = storage.get::<LastShutdown>(SHUTDOWN_KEY)?;
+        storage.remove(SHUTDOWN_KEY)?;
+        lines.push(match last {
+            Some(last) => format!(
+                "Last shutdown: {} at {}",
+                last.reason,
+                Utc.timestamp_opt(last.time, 0).single().map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string()).unwrap_or_default()
+            ),
+            None => "Last shutdown: unknown (first start or crash)".to_string(),
+        });
+
+        let report = grammersthon.client.send_message(self.chat, InputMessage::text(lines.join("\n"))).await?;
+        if self.catch_up_window.is_zero() {
+            return Ok(None);
+        }
+
+        // Add the catch-up stats once the window passes
+        let clock = grammersthon.get_clock();
+        let catch_up = CatchUp::new(DateTime::<Utc>::from(clock.system_time()));
+        let (window, counter) = (self.catch_up_window, catch_up.clone());
+        tokio::spawn(async move {
+            clock.sleep(window).await;
+            lines.push(counter.summary());
+            if let Err(e) = report.edit(InputMessage::text(lines.join("\n"))).await {
+                warn!("Failed adding catch-up stats to startup report: {e}");
+            }
+        });
+        Ok(Some(catch_up))
+    }
+}
+
+/// Messages sent before the event loop started, received during the catch-up window
+#[derive(Debug, Clone)]
+pub(crate) struct CatchUp {
+    started: DateTime<Utc>,
+    /// Count and the oldest date of the caught up messages
+    messages: Arc<Mutex<(u64, Option<DateTime<Utc>>)>>,
+}
+
+impl CatchUp {
+    fn new(started: DateTime<Utc>) -> CatchUp {
+        CatchUp { started, messages: Default::default() }
+    }
+
+    /// Count the update if it's a message sent before the start
+    pub fn record(&self, update: &Update) {
+        if let Update::NewMessage(message) = update {
+            let date = message.date();
+            if date < self.started {
+                let mut messages = self.messages.lock().unwrap();
+                messages.0 += 1;
+                messages.1 = Some(messages.1.map_or(date, |oldest| oldest.min(date)));
+            }
+        }
+    }
+
+    /// Line of the report
+    fn summary(&self) -> String {
+        match *self.messages.lock().unwrap() {
+            (count, Some(oldest)) => format!(
+                "Catch-up: {count} missed messages, oldest from {}",
+                oldest.format("%Y-%m-%d %H:%M:%S UTC")
+            ),
+            _ => "Catch-up: no missed messages".to_string(),
+        }
+    }
+}
+
+/// Optional features and whether they are compiled in
+const FEATURES: &[(&str, bool)] = &[
+    ("markdown", cfg!(feature = "markdown")),
+    ("html", cfg!(feature = "html")),
+    ("rss", cfg!(feature = "rss")),
+    ("chaos", cfg!(feature = "chaos")),
+    ("anyhow", cfg!(feature = "anyhow")),
+    ("qr", cfg!(feature = "qr")),
+    ("encryption", cfg!(feature = "encryption")),
+    ("metrics", cfg!(feature = "metrics")),
+];
+
+impl Grammersthon {
+    /// Send the startup report if installed (`StartupReport`), returns the catch-up counter for the event loop
+    pub(crate) async fn send_startup_report(&self) -> Option<CatchUp> {
+        let report = self.data.get::<Data<StartupReport>>().cloned()?;
+        match report.send(self).await {
+            Ok(catch_up) => catch_up,
+            Err(e) => {
+                warn!("Failed sending startup report: {e}");
+                None
+            }
+        }
+    }
+}
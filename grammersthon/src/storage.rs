@@ -0,0 +1,314 @@
+//! Key-value storage shared by handlers and built-in components
+//!
+//! Usage:
+//! ```ignore
+//! grammersthon.storage(Storage::file("storage.json")?);
+//!
+//! #[handler("^/count$")]
+//! async fn count(storage: Storage, message: Message) -> HandlerResult {
+//!     let count = storage.get::<u64>("count")?.unwrap_or(0) + 1;
+//!     storage.set("count", &count)?;
+//!     message.reply(format!("Count: {count}")).await?;
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use trait_bound_typemap::TypeMap;
+
+use crate::{Grammersthon, GrammersthonError, FromHandlerData, HandlerData, Data};
+
+/// Storage backend implementation
+pub trait StorageBackend: Send + Sync {
+    /// Get value by key
+    fn get(&self, key: &str) -> Result<Option<Value>, GrammersthonError>;
+    /// Insert or replace value
+    fn set(&self, key: &str, value: Value) -> Result<(), GrammersthonError>;
+    /// Remove value, returns if it existed
+    fn remove(&self, key: &str) -> Result<bool, GrammersthonError>;
+    /// All the keys starting with prefix
+    fn keys(&self, prefix: &str) -> Result<Vec<String>, GrammersthonError>;
+
+    /// Insert or replace multiple values at once
+    fn set_many(&self, values: Vec<(String, Value)>) -> Result<(), GrammersthonError> {
+        for (key, value) in values {
+            self.set(&key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Check if the backend is reachable
+    fn ping(&self) -> Result<(), GrammersthonError> {
+        self.get("").map(|_| ())
+    }
+
+    /// Replace value only if the current value equals `expected` (None if missing), returns if it was replaced.
+    /// The default implementation isn't atomic, override it in backends shared by multiple processes
+    fn compare_and_set(&self, key: &str, expected: Option<&Value>, value: Value) -> Result<bool, GrammersthonError> {
+        if self.get(key)?.as_ref() != expected {
+            return Ok(false);
+        }
+        self.set(key, value)?;
+        Ok(true)
+    }
+}
+
+/// Handle to the storage backend, can be used as handler argument
+#[derive(Clone)]
+pub struct Storage(Arc<dyn StorageBackend>);
+
+impl Storage {
+    /// Create new instance from backend
+    pub fn new(backend: impl StorageBackend + 'static) -> Storage {
+        Storage(Arc::new(backend))
+    }
+
+    /// In memory storage (lost on restart)
+    pub fn memory() -> Storage {
+        Storage::new(MemoryStorage::default())
+    }
+
+    /// JSON file storage
+    pub fn file(path: impl AsRef<Path>) -> Result<Storage, GrammersthonError> {
+        Ok(Storage::new(FileStorage::open(path)?))
+    }
+
+    /// Get value by key
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, GrammersthonError> {
+        match self.0.get(key)? {
+            Some(value) => Ok(Some(serde_json::from_value(value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Insert or replace value
+    pub fn set<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<(), GrammersthonError> {
+        self.0.set(key, serde_json::to_value(value)?)
+    }
+
+    /// Remove value, returns if it existed
+    pub fn remove(&self, key: &str) -> Result<bool, GrammersthonError> {
+        self.0.remove(key)
+    }
+
+    /// All the keys starting with prefix
+    pub fn keys(&self, prefix: &str) -> Result<Vec<String>, GrammersthonError> {
+        self.0.keys(prefix)
+    }
+
+    /// Insert or replace multiple raw values at once
+    pub fn set_many(&self, values: Vec<(String, Value)>) -> Result<(), GrammersthonError> {
+        self.0.set_many(values)
+    }
+
+    /// Check if the backend is reachable
+    pub fn ping(&self) -> Result<(), GrammersthonError> {
+        self.0.ping()
+    }
+
+    /// Replace value only if the current value equals `expected` (None if missing), returns if it was replaced
+    pub fn compare_and_set<T: Serialize + ?Sized>(&self, key: &str, expected: Option<&Value>, value: &T) -> Result<bool, GrammersthonError> {
+        self.0.compare_and_set(key, expected, serde_json::to_value(value)?)
+    }
+
+    /// View of the storage with all keys prefixed by `prefix`
+    pub fn namespace(&self, prefix: &str) -> Storage {
+        Storage::new(PrefixedStorage { inner: self.clone(), prefix: prefix.to_string() })
+    }
+}
+
+impl FromHandlerData for Storage {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.data.get::<Data<Storage>>().cloned()
+    }
+}
+
+/// Keys prefixed view of other storage
+struct PrefixedStorage {
+    inner: Storage,
+    prefix: String,
+}
+
+impl StorageBackend for PrefixedStorage {
+    fn get(&self, key: &str) -> Result<Option<Value>, GrammersthonError> {
+        self.inner.0.get(&format!("{}{key}", self.prefix))
+    }
+
+    fn set(&self, key: &str, value: Value) -> Result<(), GrammersthonError> {
+        self.inner.0.set(&format!("{}{key}", self.prefix), value)
+    }
+
+    fn remove(&self, key: &str) -> Result<bool, GrammersthonError> {
+        self.inner.0.remove(&format!("{}{key}", self.prefix))
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>, GrammersthonError> {
+        Ok(self.inner.0.keys(&format!("{}{prefix}", self.prefix))?.into_iter()
+            .map(|k| k[self.prefix.len()..].to_string())
+            .collect())
+    }
+
+    fn set_many(&self, values: Vec<(String, Value)>) -> Result<(), GrammersthonError> {
+        self.inner.0.set_many(values.into_iter().map(|(k, v)| (format!("{}{k}", self.prefix), v)).collect())
+    }
+
+    fn ping(&self) -> Result<(), GrammersthonError> {
+        self.inner.0.ping()
+    }
+
+    fn compare_and_set(&self, key: &str, expected: Option<&Value>, value: Value) -> Result<bool, GrammersthonError> {
+        self.inner.0.compare_and_set(&format!("{}{key}", self.prefix), expected, value)
+    }
+}
+
+/// Storage kept only in memory
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    values: RwLock<BTreeMap<String, Value>>
+}
+
+impl StorageBackend for MemoryStorage {
+    fn get(&self, key: &str) -> Result<Option<Value>, GrammersthonError> {
+        Ok(self.values.read().unwrap().get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: Value) -> Result<(), GrammersthonError> {
+        self.values.write().unwrap().insert(key.to_string(), value);
+        Ok(())
+    }
+
+    fn remove(&self, key: &str) -> Result<bool, GrammersthonError> {
+        Ok(self.values.write().unwrap().remove(key).is_some())
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>, GrammersthonError> {
+        Ok(self.values.read().unwrap().keys().filter(|k| k.starts_with(prefix)).cloned().collect())
+    }
+
+    fn compare_and_set(&self, key: &str, expected: Option<&Value>, value: Value) -> Result<bool, GrammersthonError> {
+        let mut values = self.values.write().unwrap();
+        if values.get(key) != expected {
+            return Ok(false);
+        }
+        values.insert(key.to_string(), value);
+        Ok(true)
+    }
+}
+
+/// Storage kept in memory and written to JSON file on every change
+#[derive(Debug)]
+pub struct FileStorage {
+    path: PathBuf,
+    memory: MemoryStorage,
+    /// Held while writing the file, so concurrent saves don't interleave
+    writing: Mutex<()>,
+}
+
+impl FileStorage {
+    /// Load from file or create new
+    pub fn open(path: impl AsRef<Path>) -> Result<FileStorage, GrammersthonError> {
+        let path = path.as_ref().to_owned();
+        let values = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(FileStorage { path, memory: MemoryStorage { values: RwLock::new(values) }, writing: Mutex::new(()) })
+    }
+
+    /// Write all values to file
+    fn save(&self) -> Result<(), GrammersthonError> {
+        let _writing = self.writing.lock().unwrap();
+        let data = serde_json::to_vec_pretty(&*self.memory.values.read().unwrap())?;
+        // Write to temporary file first to not corrupt on crash
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl StorageBackend for FileStorage {
+    fn get(&self, key: &str) -> Result<Option<Value>, GrammersthonError> {
+        self.memory.get(key)
+    }
+
+    fn set(&self, key: &str, value: Value) -> Result<(), GrammersthonError> {
+        self.memory.set(key, value)?;
+        self.save()
+    }
+
+    fn set_many(&self, values: Vec<(String, Value)>) -> Result<(), GrammersthonError> {
+        self.memory.set_many(values)?;
+        self.save()
+    }
+
+    fn remove(&self, key: &str) -> Result<bool, GrammersthonError> {
+        let removed = self.memory.remove(key)?;
+        if removed {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    fn keys(&self, prefix: &str) -> Result<Vec<String>, GrammersthonError> {
+        self.memory.keys(prefix)
+    }
+
+    fn ping(&self) -> Result<(), GrammersthonError> {
+        // Check if the directory is still writable
+        self.save()
+    }
+
+    fn compare_and_set(&self, key: &str, expected: Option<&Value>, value: Value) -> Result<bool, GrammersthonError> {
+        let replaced = self.memory.compare_and_set(key, expected, value)?;
+        if replaced {
+            self.save()?;
+        }
+        Ok(replaced)
+    }
+}
+
+impl Grammersthon {
+    /// Set the storage backend (in memory by default)
+    pub fn storage(&mut self, storage: Storage) -> &mut Self {
+        self.data.insert::<Data<Storage>>(storage);
+        self
+    }
+
+    /// Get the storage handle
+    pub fn get_storage(&self) -> Storage {
+        self.data.get::<Data<Storage>>().cloned().unwrap_or_else(Storage::memory)
+    }
+}
+
+
+/// Test memory storage
+#[test]
+fn test_memory_storage() {
+    let storage = Storage::memory();
+    storage.set("a:1", &1).unwrap();
+    storage.set("a:2", &vec!["x", "y"]).unwrap();
+    storage.set("b", "value").unwrap();
+    assert_eq!(storage.get::<i32>("a:1").unwrap(), Some(1));
+    assert_eq!(storage.get::<Vec<String>>("a:2").unwrap(), Some(vec!["x".to_string(), "y".to_string()]));
+    assert_eq!(storage.keys("a:").unwrap(), vec!["a:1", "a:2"]);
+    assert!(storage.remove("b").unwrap());
+    assert_eq!(storage.get::<String>("b").unwrap(), None);
+}
+
+/// Test namespaced storage
+#[test]
+fn test_namespace() {
+    let storage = Storage::memory();
+    let tenant = storage.namespace("tenant:1:");
+    tenant.set("config", &5).unwrap();
+    assert_eq!(storage.get::<i32>("tenant:1:config").unwrap(), Some(5));
+    assert_eq!(tenant.keys("").unwrap(), vec!["config"]);
+    assert_eq!(storage.namespace("tenant:2:").get::<i32>("config").unwrap(), None);
+}
@@ -0,0 +1,192 @@
+//! Per chat overrides of the `Catalog` templates, so each community can customize the wording
+//!
+//! Usage:
+//! ```ignore
+//! TemplateEditor::new()
+//!     .owners([123456789])
+//!     .admins(true)
+//!     .install(&mut grammersthon);
+//! ```
+//!
+//! Commands:
+//! - `/template set <key> <template>`
+//! - `/template reset <key>`
+//! - `/template show <key>`
+//! - `/template list`
+//!
+//! The overrides are used by `HandlerData::tr` and `Catalog::format_for_chat`, in all languages.
+//! The built-in strings of the framework components can only be changed for all chats (`Catalog::add`),
+//! so setting them is rejected.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use grammers_client::types::{Chat, Message};
+
+use crate::{Catalog, Grammersthon, GrammersthonError, HandlerData, HandlerFilter, HandlerInfo, HandlerResult, Storage, chat_lang};
+use crate::moderation::group_admins;
+
+/// Storage key of the overrides of chat
+fn key(chat: i64) -> String {
+    format!("templates:{chat}")
+}
+
+/// Get the template overrides of chat (key -> template)
+pub fn overrides(storage: &Storage, chat: i64) -> Result<HashMap<String, String>, GrammersthonError> {
+    Ok(storage.get(&key(chat))?.unwrap_or_default())
+}
+
+/// Override template in chat
+pub fn set_override(storage: &Storage, chat: i64, template_key: &str, template: &str) -> Result<(), GrammersthonError> {
+    let mut overrides = overrides(storage, chat)?;
+    overrides.insert(template_key.to_string(), template.to_string());
+    storage.set(&key(chat), &overrides)
+}
+
+/// Remove override of template in chat, returns if there was any
+pub fn remove_override(storage: &Storage, chat: i64, template_key: &str) -> Result<bool, GrammersthonError> {
+    let mut overrides = overrides(storage, chat)?;
+    let removed = overrides.remove(template_key).is_some();
+    match overrides.is_empty() {
+        true => storage.remove(&key(chat))?,
+        false => {
+            storage.set(&key(chat), &overrides)?;
+            true
+        }
+    };
+    Ok(removed)
+}
+
+/// Template editing commands component
+#[derive(Debug, Clone)]
+pub struct TemplateEditor {
+    command: String,
+    owners: HashSet<i64>,
+    admins: bool,
+}
+
+impl TemplateEditor {
+    /// Create new instance with default settings
+    pub fn new() -> TemplateEditor {
+        TemplateEditor {
+            command: "/template".to_string(),
+            owners: HashSet::new(),
+            admins: false,
+        }
+    }
+
+    /// Command (default: `/template`)
+    pub fn command(mut self, command: &str) -> Self {
+        self.command = command.to_string();
+        self
+    }
+
+    /// Users which can edit the templates in any chat (own account always can)
+    pub fn owners(mut self, ids: impl IntoIterator<Item = i64>) -> Self {
+        self.owners.extend(ids);
+        self
+    }
+
+    /// Allow admins of group to edit the templates of the group
+    pub fn admins(mut self, admins: bool) -> Self {
+        self.admins = admins;
+        self
+    }
+
+    /// Register the command handler, install after setting the catalog
+    pub fn install(self, grammersthon: &mut Grammersthon) {
+        let catalog = grammersthon.get_catalog();
+        let this = Arc::new(self);
+        let filter = this.clone();
+        let info = HandlerInfo::new("templates", vec![
+            HandlerFilter::async_fn(move |message, data| {
+                let this = filter.clone();
+                async move {
+                    if message.text().split_whitespace().next() != Some(this.command.as_str()) {
+                        return false;
+                    }
+                    let sender = match message.sender() {
+                        Some(sender) => sender.id(),
+                        None => return false
+                    };
+                    if sender == data.me.id() || this.owners.contains(&sender) {
+                        return true;
+                    }
+                    // Private chats don't have admins
+                    this.admins && !matches!(message.chat(), Chat::User(_)) && match group_admins(&data.client, &message.chat()).await {
+                        Ok(admins) => admins.iter().any(|a| a.id() == sender),
+                        Err(e) => {
+                            warn!("Failed getting admins of {}: {e}", message.chat().id());
+                            false
+                        }
+                    }
+                }
+            })
+        ]).description("Customize the bot messages in this chat");
+        grammersthon.add_handler((info, move |message: Message, data: HandlerData, storage: Storage| {
+            let this = this.clone();
+            let catalog = catalog.clone();
+            async move { this.handle_command(message, data, storage, catalog).await }
+        }));
+    }
+
+    /// Handle the command
+    async fn handle_command(&self, message: Message, data: HandlerData, storage: Storage, catalog: Catalog) -> HandlerResult {
+        let lang = message.sender().as_ref().and_then(chat_lang).map(|l| l.to_string());
+        let lang = lang.as_deref();
+        let chat = message.chat().id();
+        let text = message.text()[self.command.len()..].trim().to_string();
+        let mut parts = text.splitn(3, char::is_whitespace);
+        let (action, key, template) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""), parts.next().unwrap_or("").trim());
+
+        let unknown = || catalog.format(lang, "templates.unknown", &[("key", &key)]);
+        let reply = match (action, key.is_empty()) {
+            ("set", false) if !template.is_empty() => match catalog.contains(key) {
+                true if Catalog::is_builtin(key) => catalog.format(lang, "templates.builtin", &[("key", &key)]),
+                true => {
+                    set_override(&storage, chat, key, template)?;
+                    catalog.format(lang, "templates.set", &[("key", &key)])
+                },
+                false => unknown(),
+            },
+            ("reset", false) => match remove_override(&storage, chat, key)? {
+                true => catalog.format(lang, "templates.reset", &[("key", &key)]),
+                false => unknown(),
+            },
+            ("show", false) => match catalog.contains(key) {
+                true => catalog.get_for_chat(&storage, chat, lang, key),
+                false => unknown(),
+            },
+            ("list", _) => {
+                let mut keys = overrides(&storage, chat)?.into_keys().collect::<Vec<_>>();
+                keys.sort();
+                match keys.is_empty() {
+                    true => catalog.get(lang, "templates.empty").to_string(),
+                    false => keys.join("\n"),
+                }
+            },
+            _ => catalog.format(lang, "templates.usage", &[("command", &self.command)]),
+        };
+        data.reply(reply).await?;
+        Ok(())
+    }
+}
+
+impl Default for TemplateEditor {
+    fn default() -> Self {
+        TemplateEditor::new()
+    }
+}
+
+
+/// Test overrides in storage
+#[test]
+fn test_overrides() {
+    let storage = Storage::memory();
+    let catalog = Catalog::new().add("en", "greeting", "Hello {name}");
+    set_override(&storage, 1, "greeting", "Welcome to our group {name}!").unwrap();
+    assert_eq!(catalog.format_for_chat(&storage, 1, None, "greeting", &[("name", &"Anna")]), "Welcome to our group Anna!");
+    assert_eq!(catalog.format_for_chat(&storage, 2, None, "greeting", &[("name", &"Anna")]), "Hello Anna");
+    assert!(remove_override(&storage, 1, "greeting").unwrap());
+    assert!(!remove_override(&storage, 1, "greeting").unwrap());
+    assert_eq!(storage.get::<HashMap<String, String>>(&key(1)).unwrap(), None);
+}
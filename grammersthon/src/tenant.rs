@@ -0,0 +1,176 @@
+use std::collections::{BTreeMap, BTreeSet};
+use grammers_client::types::Chat;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{FromHandlerData, GrammersthonError, HandlerData, Storage};
+
+/// Storage prefix of all tenants
+const PREFIX: &str = "tenant:";
+
+/// Installation of the bot (group which added it or private chat), with isolated state,
+/// configuration, permissions and analytics, so one deployment can serve many communities:
+/// ```ignore
+/// #[handler("^/setup$")]
+/// async fn setup(tenant: Tenant, data: HandlerData) -> HandlerResult {
+///     if tenant.claim(&data).await? {
+///         tenant.set_config(&Config { welcome: true })?;
+///     }
+///     Ok(())
+/// }
+///
+/// #[handler("^/welcome", filters::tenant_admin())]
+/// async fn welcome(tenant: Tenant, args: Args<bool>) -> HandlerResult { ... }
+/// ```
+#[derive(Clone)]
+pub struct Tenant {
+    /// Id of the chat
+    pub id: i64,
+    storage: Storage,
+}
+
+impl Tenant {
+    /// Get tenant of chat
+    pub fn new(storage: &Storage, id: i64) -> Tenant {
+        Tenant { id, storage: storage.namespace(&format!("{PREFIX}{id}:")) }
+    }
+
+    /// Ids of all the tenants with any state
+    pub fn all(storage: &Storage) -> Result<Vec<i64>, GrammersthonError> {
+        let ids = storage.keys(PREFIX)?.into_iter()
+            .filter_map(|k| k[PREFIX.len()..].split(':').next()?.parse().ok())
+            .collect::<BTreeSet<i64>>();
+        Ok(ids.into_iter().collect())
+    }
+
+    /// Storage of this tenant only
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    /// Configuration of the tenant, default if not set yet
+    pub fn config<T: DeserializeOwned + Default>(&self) -> Result<T, GrammersthonError> {
+        Ok(self.storage.get("config")?.unwrap_or_default())
+    }
+
+    /// Save configuration of the tenant
+    pub fn set_config<T: Serialize>(&self, config: &T) -> Result<(), GrammersthonError> {
+        self.storage.set("config", config)
+    }
+
+    /// User who installed the bot
+    pub fn owner(&self) -> Result<Option<i64>, GrammersthonError> {
+        self.storage.get("owner")
+    }
+
+    /// Sender of the message becomes the owner if there is none yet, returns whether the sender is the owner.
+    /// In groups and channels only their admins can claim the tenant
+    pub async fn claim(&self, data: &HandlerData) -> Result<bool, GrammersthonError> {
+        let (chat, sender) = match data.message.sender() {
+            Some(sender) => (data.message.chat(), sender),
+            None => return Ok(false)
+        };
+        if !matches!(chat, Chat::User(_)) {
+            let permissions = data.client.get_permissions(&chat, &sender).await?;
+            if !permissions.is_admin() && !permissions.is_creator() {
+                return Ok(self.owner()? == Some(sender.id()));
+            }
+        }
+        self.take_ownership(sender.id())
+    }
+
+    /// Set the owner if there is none yet (atomically), returns whether the user is the owner
+    fn take_ownership(&self, user: i64) -> Result<bool, GrammersthonError> {
+        if self.storage.compare_and_set("owner", None, &user)? {
+            return Ok(true);
+        }
+        Ok(self.owner()? == Some(user))
+    }
+
+    /// Users which can manage the tenant (besides the owner)
+    pub fn admins(&self) -> Result<BTreeSet<i64>, GrammersthonError> {
+        Ok(self.storage.get("admins")?.unwrap_or_default())
+    }
+
+    /// Allow user to manage the tenant
+    pub fn add_admin(&self, user: i64) -> Result<(), GrammersthonError> {
+        let mut admins = self.admins()?;
+        admins.insert(user);
+        self.storage.set("admins", &admins)
+    }
+
+    /// Remove user from the admins, returns if it was there
+    pub fn remove_admin(&self, user: i64) -> Result<bool, GrammersthonError> {
+        let mut admins = self.admins()?;
+        let removed = admins.remove(&user);
+        self.storage.set("admins", &admins)?;
+        Ok(removed)
+    }
+
+    /// User is the owner or admin of the tenant
+    pub fn is_admin(&self, user: i64) -> Result<bool, GrammersthonError> {
+        Ok(self.owner()? == Some(user) || self.admins()?.contains(&user))
+    }
+
+    /// Count analytics event, returns the new count
+    pub fn track(&self, event: &str) -> Result<u64, GrammersthonError> {
+        let key = format!("stats:{event}");
+        // Retry until no other increment happened in between
+        loop {
+            let current = self.storage.get::<Value>(&key)?;
+            let count = current.as_ref().and_then(Value::as_u64).unwrap_or(0) + 1;
+            if self.storage.compare_and_set(&key, current.as_ref(), &count)? {
+                return Ok(count);
+            }
+        }
+    }
+
+    /// Counts of all the analytics events
+    pub fn stats(&self) -> Result<BTreeMap<String, u64>, GrammersthonError> {
+        let mut stats = BTreeMap::new();
+        for key in self.storage.keys("stats:")? {
+            if let Some(count) = self.storage.get(&key)? {
+                stats.insert(key["stats:".len()..].to_string(), count);
+            }
+        }
+        Ok(stats)
+    }
+}
+
+/// Tenant of the message chat
+impl FromHandlerData for Tenant {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        let storage = Storage::from_data(data)?;
+        Some(Tenant::new(&storage, data.message.chat().id()))
+    }
+}
+
+
+/// Test tenant isolation
+#[test]
+fn test_tenant() {
+    let storage = Storage::memory();
+    let (a, b) = (Tenant::new(&storage, -100), Tenant::new(&storage, 5));
+    a.set_config(&vec!["welcome"]).unwrap();
+    assert_eq!(b.config::<Vec<String>>().unwrap(), Vec::<String>::new());
+    assert!(a.take_ownership(1).unwrap());
+    assert!(!a.take_ownership(2).unwrap());
+    a.add_admin(3).unwrap();
+    assert!(a.is_admin(1).unwrap() && a.is_admin(3).unwrap());
+    assert!(!b.is_admin(1).unwrap());
+    a.track("ban").unwrap();
+    assert_eq!(a.track("ban").unwrap(), 2);
+    assert_eq!(a.stats().unwrap().get("ban"), Some(&2));
+    assert!(b.stats().unwrap().is_empty());
+    b.track("start").unwrap();
+    assert_eq!(Tenant::all(&storage).unwrap(), vec![-100, 5]);
+
+    // Concurrent increments aren't lost
+    let threads = (0..4).map(|_| {
+        let b = b.clone();
+        std::thread::spawn(move || for _ in 0..100 { b.track("join").unwrap(); })
+    }).collect::<Vec<_>>();
+    threads.into_iter().for_each(|t| t.join().unwrap());
+    assert_eq!(b.stats().unwrap().get("join"), Some(&400));
+}
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use grammers_client::Update;
+use grammers_client::types::Chat;
+use grammers_tl_types as tl;
+use trait_bound_typemap::TypeMap;
+
+use crate::{Data, FromHandlerData, Grammersthon, HandlerData};
+
+/// Maximum length of logged update sample
+const SAMPLE_LIMIT: usize = 1000;
+
+/// Class of update, for ignoring whole classes before dispatch (`Grammersthon::ignore_updates`):
+/// ```ignore
+/// grammersthon.ignore_updates([UpdateKind::ChannelPost, UpdateKind::Reaction]);
+/// ```
+/// Telegram has no per-type subscription for MTProto clients, so the updates are still received,
+/// but dropped before any hook, interceptor or handler runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UpdateKind {
+    /// New message in private chat or group
+    NewMessage,
+    /// New or edited post in broadcast channel
+    ChannelPost,
+    MessageEdited,
+    MessageDeleted,
+    CallbackQuery,
+    InlineQuery,
+    /// Changed reactions of message
+    Reaction,
+    /// Other raw updates
+    Raw,
+}
+
+impl UpdateKind {
+    /// Get the kind of update
+    pub fn of(update: &Update) -> UpdateKind {
+        match update {
+            Update::NewMessage(m) | Update::MessageEdited(m) if matches!(m.chat(), Chat::Channel(_)) => UpdateKind::ChannelPost,
+            Update::NewMessage(_) => UpdateKind::NewMessage,
+            Update::MessageEdited(_) => UpdateKind::MessageEdited,
+            Update::MessageDeleted(_) => UpdateKind::MessageDeleted,
+            Update::CallbackQuery(_) => UpdateKind::CallbackQuery,
+            Update::InlineQuery(_) => UpdateKind::InlineQuery,
+            Update::Raw(
+                tl::enums::Update::MessageReactions(_)
+                | tl::enums::Update::BotMessageReaction(_)
+                | tl::enums::Update::BotMessageReactions(_)
+            ) => UpdateKind::Reaction,
+            _ => UpdateKind::Raw,
+        }
+    }
+}
+
+/// Diagnostic tally of the updates which can't be routed to handlers (raw updates, inline queries, deletions),
+/// by constructor name. The first occurrence of each is logged with a sample, to see which update types are worth
+/// typed support (`Grammersthon::update_stats`):
+/// ```ignore
+/// let stats = UpdateStats::new();
+/// grammersthon.update_stats(stats.clone());
+/// ...
+/// for (name, count) in stats.counts() {
+///     info!("{name}: {count}");
+/// }
+/// ```
+/// The tally is also shown by the `AdminConsole` `updates` command
+#[derive(Debug, Clone, Default)]
+pub struct UpdateStats {
+    counts: Arc<Mutex<BTreeMap<String, u64>>>,
+}
+
+impl UpdateStats {
+    /// Create new instance
+    pub fn new() -> UpdateStats {
+        UpdateStats::default()
+    }
+
+    /// Count the update if it can't be routed to handlers
+    pub fn record(&self, update: &Update) {
+        let name = match update {
+            Update::NewMessage(_) | Update::MessageEdited(_) | Update::CallbackQuery(_) => return,
+            Update::Raw(raw) => constructor_name(&format!("{raw:?}")),
+            update => constructor_name(&format!("{update:?}")),
+        };
+        let mut counts = self.counts.lock().unwrap();
+        let count = counts.entry(name.clone()).or_default();
+        if *count == 0 {
+            let mut sample = format!("{update:?}");
+            if let Some((i, _)) = sample.char_indices().nth(SAMPLE_LIMIT) {
+                sample.truncate(i);
+                sample.push('…');
+            }
+            info!("First unrouted update {name}: {sample}");
+        }
+        *count += 1;
+    }
+
+    /// Count of each unrouted update constructor
+    pub fn counts(&self) -> BTreeMap<String, u64> {
+        self.counts.lock().unwrap().clone()
+    }
+}
+
+/// Name of enum variant from its debug output
+fn constructor_name(debug: &str) -> String {
+    debug.split(|c: char| !c.is_alphanumeric() && c != '_').next().unwrap_or_default().to_string()
+}
+
+impl FromHandlerData for UpdateStats {
+    fn from_data(data: &HandlerData) -> Option<Self> {
+        data.data::<UpdateStats>()
+    }
+}
+
+impl Grammersthon {
+    /// Enable counting of the unrouted updates
+    pub fn update_stats(&mut self, stats: UpdateStats) -> &mut Self {
+        self.data.insert::<Data<UpdateStats>>(stats);
+        self
+    }
+
+    /// Get the unrouted updates tally, if enabled
+    pub fn get_update_stats(&self) -> Option<UpdateStats> {
+        self.data.get::<Data<UpdateStats>>().cloned()
+    }
+}
+
+
+/// Test getting the constructor names
+#[test]
+fn test_constructor_name() {
+    assert_eq!(constructor_name("MessageReactions(UpdateMessageReactions { peer: .. })"), "MessageReactions");
+    assert_eq!(constructor_name("Other"), "Other");
+    assert_eq!(constructor_name("BotStopped { user_id: 1 }"), "BotStopped");
+}
@@ -0,0 +1,113 @@
+use std::fmt;
+use grammers_client::Client;
+use grammers_client::types::{Chat, Message, User};
+use grammers_session::{PackedChat, PackedType};
+
+use crate::{Entities, EntityKind, FromArgs, FromReply, GrammersthonError, HandlerData};
+
+/// User passed as argument: `@username`, numeric id or text mention (of user without username).
+/// Resolve it to `User` with `UserRef::resolve`:
+/// ```ignore
+/// #[handler("^/ban", filters::admin_only())]
+/// async fn ban(data: HandlerData, args: Args<UserRef>) -> HandlerResult {
+///     let user = data.resolve_user(&args.0).await?;
+///     ...
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserRef {
+    Username(String),
+    Id(i64),
+    /// Text of the mention, looked up in the message entities
+    Name(String),
+}
+
+impl FromArgs for UserRef {
+    fn parse_arg(input: &str) -> Result<Self, GrammersthonError> {
+        let input = input.trim();
+        if let Some(username) = input.strip_prefix('@') {
+            if username.is_empty() || !username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(GrammersthonError::Parse(input.to_string(), None));
+            }
+            return Ok(UserRef::Username(username.to_string()));
+        }
+        if let Ok(id) = input.parse::<i64>() {
+            return Ok(UserRef::Id(id));
+        }
+        match input.is_empty() {
+            true => Err(GrammersthonError::Parse(input.to_string(), None)),
+            false => Ok(UserRef::Name(input.to_string()))
+        }
+    }
+}
+
+/// Sender of the replied message
+impl FromReply for UserRef {
+    fn from_reply(reply: &Message) -> Option<Self> {
+        reply.sender().map(|s| UserRef::Id(s.id()))
+    }
+}
+
+impl fmt::Display for UserRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UserRef::Username(username) => write!(f, "@{username}"),
+            UserRef::Id(id) => write!(f, "{id}"),
+            UserRef::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl UserRef {
+    /// Id of the user if it can be determined without request (id or text mention in the message)
+    pub fn id(&self, message: &Message) -> Option<i64> {
+        match self {
+            UserRef::Username(_) => None,
+            UserRef::Id(id) => Some(*id),
+            UserRef::Name(name) => {
+                let entities = Entities::parse(message.text(), message.fmt_entities().map(|e| e.as_slice()).unwrap_or(&[]));
+                let mentions = entities.0.into_iter().filter_map(|e| match e.kind {
+                    EntityKind::MentionName(id) => Some((e.text, id)),
+                    _ => None
+                }).collect::<Vec<_>>();
+                // Only first word of multi word mention might be passed
+                mentions.iter().find(|(text, _)| text == name)
+                    .or_else(|| mentions.iter().find(|(text, _)| text.starts_with(&format!("{name} "))))
+                    .map(|(_, id)| *id)
+            }
+        }
+    }
+
+    /// Resolve to user, ids work only for users the account has seen
+    pub async fn resolve(&self, client: &Client, message: &Message) -> Result<User, GrammersthonError> {
+        let not_found = || GrammersthonError::Error(format!("User not found: {self}").into());
+        let chat = match (self, self.id(message)) {
+            (UserRef::Username(username), _) => client.resolve_username(username).await?,
+            (_, Some(id)) => Some(client.unpack_chat(PackedChat { ty: PackedType::User, id, access_hash: None }).await?),
+            (_, None) => None,
+        };
+        match chat {
+            Some(Chat::User(user)) => Ok(user),
+            _ => Err(not_found())
+        }
+    }
+}
+
+impl HandlerData {
+    /// Resolve user passed as argument
+    pub async fn resolve_user(&self, user: &UserRef) -> Result<User, GrammersthonError> {
+        user.resolve(&self.client, &self.message).await
+    }
+}
+
+
+/// Test parsing user references
+#[test]
+fn test_parse_user_ref() {
+    assert_eq!(UserRef::parse_arg(" @some_user").unwrap(), UserRef::Username("some_user".to_string()));
+    assert_eq!(UserRef::parse_arg("123456").unwrap(), UserRef::Id(123456));
+    assert_eq!(UserRef::parse_arg("John").unwrap(), UserRef::Name("John".to_string()));
+    assert!(UserRef::parse_arg("@").is_err());
+    assert!(UserRef::parse_arg("@not-valid").is_err());
+    assert!(UserRef::parse_arg("").is_err());
+}
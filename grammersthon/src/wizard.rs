@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use chrono::Utc;
+use grammers_client::types::Message;
+use regex::Regex;
+use serde::{Serialize, Deserialize};
+
+use crate::{Catalog, Fsm, Grammersthon, GrammersthonError, HandlerData, HandlerFilter, HandlerInfo, HandlerResult, Storage};
+
+/// Multi step form filled in conversation, generated by `#[derive(Wizard)]`:
+/// ```ignore
+/// #[derive(Wizard)]
+/// struct Signup {
+///     #[wizard(prompt = "What's your name?", min_len = 2, max_len = 32)]
+///     name: String,
+///     #[wizard(prompt = "How old are you?", min = 13, max = 120)]
+///     age: u8,
+///     #[wizard(prompt = "Your website?", regex = "^https?://")]
+///     website: Option<String>,
+/// }
+///
+/// grammersthon.wizard(HandlerInfo::new("signup", vec![HandlerFilter::Regex("^/signup$".into())]), |form: Signup, data: HandlerData| async move {
+///     data.message.reply(format!("Welcome {}", form.name)).await?;
+///     Ok(())
+/// });
+/// ```
+/// `Option` fields can be skipped. Users can go back a step or cancel the wizard, see `WizardOptions`.
+/// Unfinished answers are saved as draft, so the wizard continues where the user left off
+/// (after timeout or bot restart) until the draft expires.
+pub trait Wizard: Sized + Send + Sync + 'static {
+    /// Name of the wizard, used in the FSM state
+    fn name() -> &'static str;
+
+    /// Fields in the order they are asked for
+    fn fields() -> Vec<WizardField>;
+
+    /// Build from the collected answers (field name -> answer)
+    fn from_values(values: &HashMap<String, String>) -> Result<Self, GrammersthonError>;
+}
+
+/// Single step of wizard
+#[derive(Debug, Clone)]
+pub struct WizardField {
+    pub name: &'static str,
+    pub prompt: String,
+    pub optional: bool,
+    pub validators: Vec<Validator>,
+    /// Check if the answer can be parsed as the field type
+    pub parse: fn(&str) -> Result<(), GrammersthonError>,
+}
+
+impl WizardField {
+    /// Create new field
+    pub fn new(name: &'static str, prompt: &str, parse: fn(&str) -> Result<(), GrammersthonError>) -> WizardField {
+        WizardField { name, prompt: prompt.to_string(), optional: false, validators: vec![], parse }
+    }
+
+    /// Field can be skipped
+    pub fn optional(mut self, optional: bool) -> Self {
+        self.optional = optional;
+        self
+    }
+
+    /// Add validation of the answer
+    pub fn validator(mut self, validator: Validator) -> Self {
+        self.validators.push(validator);
+        self
+    }
+
+    /// Validate the answer, returns message for the user (in language) on error
+    pub fn validate(&self, input: &str, catalog: &Catalog, lang: Option<&str>) -> Result<(), String> {
+        (self.parse)(input).map_err(|_| catalog.get(lang, "validate.invalid").to_string())?;
+        self.validators.iter().try_for_each(|v| v.validate(input, catalog, lang))
+    }
+}
+
+/// Validation of wizard answer or command argument
+#[derive(Debug, Clone)]
+pub enum Validator {
+    /// Minimum numeric value
+    Min(f64),
+    /// Maximum numeric value
+    Max(f64),
+    /// Minimum length in characters
+    MinLen(usize),
+    /// Maximum length in characters
+    MaxLen(usize),
+    /// Has to match the regex
+    Regex(String),
+}
+
+impl Validator {
+    /// Validate the answer, returns message for the user (in language) on error
+    pub fn validate(&self, input: &str, catalog: &Catalog, lang: Option<&str>) -> Result<(), String> {
+        let number = || input.trim().parse::<f64>().map_err(|_| catalog.get(lang, "validate.number").to_string());
+        match self {
+            Validator::Min(min) if number()? < *min => Err(catalog.format(lang, "validate.min", &[("min", min)])),
+            Validator::Max(max) if number()? > *max => Err(catalog.format(lang, "validate.max", &[("max", max)])),
+            Validator::MinLen(len) if input.chars().count() < *len => Err(catalog.format(lang, "validate.min_len", &[("len", len)])),
+            Validator::MaxLen(len) if input.chars().count() > *len => Err(catalog.format(lang, "validate.max_len", &[("len", len)])),
+            Validator::Regex(regex) => match Regex::new(regex) {
+                Ok(r) if r.is_match(input) => Ok(()),
+                Ok(_) => Err(catalog.get(lang, "validate.format").to_string()),
+                Err(e) => {
+                    warn!("Invalid wizard validation regex {regex}: {e}");
+                    Ok(())
+                }
+            },
+            _ => Ok(())
+        }
+    }
+
+    /// Validate command argument (`#[validate(..)]` of `FromArgs` derive), messages are in the default language
+    pub fn validate_arg(input: &str, validators: &[Validator]) -> Result<(), GrammersthonError> {
+        let catalog = Catalog::new();
+        validators.iter()
+            .try_for_each(|v| v.validate(input, &catalog, None))
+            .map_err(|e| GrammersthonError::Parse(input.to_string(), Some(e.into())))
+    }
+}
+
+/// Controls of wizards, add with `add_data` to override the defaults.
+/// Messages are translated using `Catalog` (`wizard.*` and `validate.*` keys)
+#[derive(Debug, Clone)]
+pub struct WizardOptions {
+    /// For how long to wait for each answer
+    pub timeout: Duration,
+    /// For how long are unfinished answers kept
+    pub draft_expiry: Duration,
+    pub skip: String,
+    pub back: String,
+    pub cancel: String,
+}
+
+impl Default for WizardOptions {
+    fn default() -> Self {
+        WizardOptions {
+            timeout: Duration::from_secs(5 * 60),
+            draft_expiry: Duration::from_secs(24 * 60 * 60),
+            skip: "/skip".to_string(),
+            back: "/back".to_string(),
+            cancel: "/cancel".to_string(),
+        }
+    }
+}
+
+/// Progress of wizard, saved per user and wizard
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct WizardDraft {
+    step: usize,
+    values: HashMap<String, String>,
+    /// Unix timestamp of the last change
+    updated: i64,
+}
+
+impl WizardDraft {
+    /// Storage key of the draft
+    fn key(name: &str, message: &Message) -> String {
+        let chat = message.chat().id();
+        format!("wizard:{name}:{chat}:{}", message.sender().map(|s| s.id()).unwrap_or(chat))
+    }
+
+    /// Load draft if it didn't expire yet
+    fn load(storage: &Storage, key: &str, expiry: Duration) -> Result<Option<WizardDraft>, GrammersthonError> {
+        match storage.get::<WizardDraft>(key)? {
+            Some(draft) if Utc::now().timestamp() - draft.updated < expiry.as_secs() as i64 => Ok(Some(draft)),
+            Some(_) => {
+                storage.remove(key)?;
+                Ok(None)
+            }
+            None => Ok(None)
+        }
+    }
+
+    fn save(&mut self, storage: &Storage, key: &str) -> Result<(), GrammersthonError> {
+        self.updated = Utc::now().timestamp();
+        storage.set(key, self)
+    }
+}
+
+impl HandlerData {
+    /// Run the wizard in conversation with the sender, returns None if it was cancelled
+    pub async fn wizard<W: Wizard>(&self) -> Result<Option<W>, GrammersthonError> {
+        let options = self.data::<WizardOptions>().unwrap_or_default();
+        let storage = self.data::<Storage>().unwrap_or_else(Storage::memory);
+        let fsm = Fsm::for_message(storage.clone(), &self.message);
+        let fields = W::fields();
+        let key = WizardDraft::key(W::name(), &self.message);
+        let catalog = self.data::<Catalog>().unwrap_or_default();
+        let lang = self.lang();
+        let lang = lang.as_deref();
+        if self.message.text().trim() == options.cancel {
+            fsm.clear()?;
+            storage.remove(&key)?;
+            self.reply(catalog.get(lang, "wizard.cancelled")).await?;
+            return Ok(None);
+        }
+        let mut draft = match WizardDraft::load(&storage, &key, options.draft_expiry)? {
+            Some(draft) => {
+                self.reply(catalog.get(lang, "wizard.resumed")).await?;
+                draft
+            },
+            None => WizardDraft::default()
+        };
+        fsm.set_state(&format!("wizard:{}", W::name()))?;
+
+        let mut last: Message = self.message.clone();
+        let mut prompt = true;
+        while let Some(field) = fields.get(draft.step) {
+            draft.save(&storage, &key)?;
+            if prompt {
+                let mut text = field.prompt.clone();
+                if field.optional {
+                    text = format!("{text}\n{}", catalog.format(lang, "wizard.skip_hint", &[("skip", &options.skip)]));
+                }
+                self.reply_to(&last, text).await?;
+            }
+            prompt = true;
+
+            // On timeout the draft and state are kept, to continue later
+            last = self.wait_message(options.timeout).await?;
+            let answer = last.text().trim();
+            if answer == options.cancel {
+                fsm.clear()?;
+                storage.remove(&key)?;
+                self.reply_to(&last, catalog.get(lang, "wizard.cancelled")).await?;
+                return Ok(None);
+            }
+            if answer == options.back {
+                draft.step = draft.step.saturating_sub(1);
+                if let Some(field) = fields.get(draft.step) {
+                    draft.values.remove(field.name);
+                }
+                continue;
+            }
+            if answer == options.skip {
+                match field.optional {
+                    true => draft.step += 1,
+                    false => {
+                        self.reply_to(&last, catalog.get(lang, "wizard.not_optional")).await?;
+                        prompt = false;
+                    }
+                }
+                continue;
+            }
+            match field.validate(answer, &catalog, lang) {
+                Ok(_) => {
+                    draft.values.insert(field.name.to_string(), answer.to_string());
+                    draft.step += 1;
+                },
+                Err(e) => {
+                    self.reply_to(&last, e).await?;
+                    prompt = false;
+                }
+            }
+        }
+
+        fsm.clear()?;
+        storage.remove(&key)?;
+        W::from_values(&draft.values).map(Some)
+    }
+}
+
+impl Grammersthon {
+    /// Start the wizard when handler matches, and pass the filled form to `on_complete`.
+    /// Next message of user with unfinished draft (after timeout or restart) continues the wizard as well
+    pub fn wizard<W, I, H, F>(&mut self, info: I, on_complete: H) -> &mut Self
+    where
+        W: Wizard,
+        I: Into<HandlerInfo>,
+        H: Fn(W, HandlerData) -> F + Send + Sync + Clone + 'static,
+        F: Future<Output = HandlerResult> + Send + Sync
+    {
+        let handler = move |data: HandlerData| {
+            let on_complete = on_complete.clone();
+            async move {
+                match data.wizard::<W>().await? {
+                    Some(form) => on_complete(form, data).await,
+                    None => Ok(())
+                }
+            }
+        };
+
+        // Continue from draft
+        let state = format!("wizard:{}", W::name());
+        let resume = HandlerInfo::new(&format!("{state}:resume"), vec![HandlerFilter::func(move |message, data| {
+            let storage = data.data::<Storage>().unwrap_or_else(Storage::memory);
+            let expiry = data.data::<WizardOptions>().unwrap_or_default().draft_expiry;
+            Fsm::for_message(storage.clone(), message).state().ok().flatten().as_ref() == Some(&state)
+                && matches!(WizardDraft::load(&storage, &WizardDraft::key(W::name(), message), expiry), Ok(Some(_)))
+        })]);
+        self.add_handler((resume, handler.clone()));
+        self.add_handler((info, handler))
+    }
+}
+
+
+/// Test answer validation
+#[test]
+fn test_validate_field() {
+    let field = WizardField::new("age", "Age?", |i| i.parse::<u8>().map(|_| ()).map_err(|e| GrammersthonError::Parse(i.to_string(), Some(e.into()))))
+        .validator(Validator::Min(13.0))
+        .validator(Validator::Max(120.0));
+    let catalog = Catalog::new();
+    assert!(field.validate("20", &catalog, None).is_ok());
+    assert_eq!(field.validate("5", &catalog, None), Err("Has to be at least 13".to_string()));
+    assert!(field.validate("abc", &catalog, None).is_err());
+    assert!(Validator::Regex("^https?://".to_string()).validate("ftp://x", &catalog, None).is_err());
+    assert!(Validator::MaxLen(3).validate("abcd", &catalog, None).is_err());
+}
+
+/// Test draft expiry
+#[test]
+fn test_draft_expiry() {
+    let storage = Storage::memory();
+    let mut draft = WizardDraft { step: 1, ..Default::default() };
+    draft.save(&storage, "draft").unwrap();
+    assert_eq!(WizardDraft::load(&storage, "draft", Duration::from_secs(60)).unwrap().map(|d| d.step), Some(1));
+    draft.updated -= 120;
+    storage.set("draft", &draft).unwrap();
+    assert!(WizardDraft::load(&storage, "draft", Duration::from_secs(60)).unwrap().is_none());
+    assert_eq!(storage.get::<WizardDraft>("draft").unwrap().map(|d| d.step), None);
+}
@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use grammersthon::{FromArgs, GrammersthonError};
+
+#[derive(Debug, PartialEq, FromArgs)]
+struct Greet {
+    name: String,
+    count: Option<u32>,
+    title: Option<String>,
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+struct Search {
+    #[rest]
+    query: String,
+    #[named]
+    limit: Option<u32>,
+    #[flag]
+    safe: bool,
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+struct Deploy {
+    target: String,
+    #[named]
+    tag: String,
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+#[ignore_case]
+enum Action {
+    Play,
+    Volume(u8),
+    Seek { seconds: u32 },
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+struct ActionArgs(#[rest] Action);
+
+#[derive(Debug, PartialEq, FromArgs)]
+#[subcommand]
+enum Playlist {
+    Add { url: String },
+    #[subcommand(alias = "rm")]
+    Remove { index: usize },
+    Clear,
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+struct PlaylistArgs(#[subcommand] Playlist);
+
+#[derive(Debug, PartialEq, FromArgs)]
+struct Repeat {
+    #[validate(range(min = 1, max = 10))]
+    amount: u32,
+    #[rest]
+    #[validate(length(max = 5))]
+    text: String,
+}
+
+#[derive(Debug, PartialEq)]
+struct Hex(u32);
+
+impl FromStr for Hex {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        u32::from_str_radix(s.trim_start_matches("0x"), 16).map(Hex)
+    }
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+#[from_str]
+struct Level(Hex);
+
+impl FromStr for Level {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Hex::from_str(s).map(Level)
+    }
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+struct Paint {
+    #[from_str]
+    color: Hex,
+    level: Level,
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+struct Quote {
+    #[or_reply]
+    text: String,
+    author: Option<String>,
+}
+
+#[derive(Debug, PartialEq, FromArgs)]
+struct Color {
+    r: u8,
+    g: u8,
+    b: Option<u8>,
+}
+
+fn captures(groups: &[(&str, &str)]) -> HashMap<String, String> {
+    groups.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+
+/// Test trailing `Option` fields can be omitted
+#[test]
+fn test_optional_fields() {
+    assert_eq!(Greet::parse_arg("bob").unwrap(), Greet { name: "bob".to_string(), count: None, title: None });
+    assert_eq!(Greet::parse_arg("bob 3 sir").unwrap(), Greet { name: "bob".to_string(), count: Some(3), title: Some("sir".to_string()) });
+    assert_eq!(Greet::usage(), vec!["<name: String> [count: u32] [title: String]"]);
+    let error = Greet::parse_arg("").unwrap_err();
+    assert!(error.is_missing_argument());
+    assert_eq!(error.to_string(), "Missing argument name");
+    assert_eq!(Greet::parse_arg("bob many").unwrap_err().to_string(), "Invalid argument count: Error parsing many: invalid digit found in string");
+}
+
+/// Test named arguments and flags next to the rest field
+#[test]
+fn test_named_fields() {
+    assert_eq!(Search::parse_arg("big cats --limit 5 --safe").unwrap(), Search { query: "big cats".to_string(), limit: Some(5), safe: true });
+    assert_eq!(Search::parse_arg("dogs").unwrap(), Search { query: "dogs".to_string(), limit: None, safe: false });
+    assert_eq!(Search::usage(), vec!["<query...: String> [--limit <u32>] [--safe]"]);
+    let schema = Search::schema();
+    assert!(schema[0].rest);
+    assert!(schema[1].named && schema[1].optional && !schema[1].rest);
+    assert!(schema[2].named && !schema[2].rest);
+
+    assert_eq!(Deploy::parse_arg("prod --tag v1").unwrap(), Deploy { target: "prod".to_string(), tag: "v1".to_string() });
+    let error = Deploy::parse_arg("prod").unwrap_err();
+    assert!(matches!(&error, GrammersthonError::InvalidArgs { error, .. } if matches!(**error, GrammersthonError::MissingArgument { named: true })));
+    // Named arguments aren't prompted for
+    assert!(!error.is_missing_argument());
+    assert_eq!(error.to_string(), "Missing argument --tag");
+}
+
+/// Test enum variants with payloads
+#[test]
+fn test_enum() {
+    assert_eq!(ActionArgs::parse_arg("play").unwrap().0, Action::Play);
+    assert_eq!(ActionArgs::parse_arg("VOLUME 20").unwrap().0, Action::Volume(20));
+    assert_eq!(ActionArgs::parse_arg("seek 90").unwrap().0, Action::Seek { seconds: 90 });
+    assert!(ActionArgs::parse_arg("play now").is_err());
+    assert!(ActionArgs::parse_arg("stop").is_err());
+    assert_eq!(ActionArgs::usage(), vec!["<0...: play|volume|seek>"]);
+    assert_eq!(Action::parse_arg("seek").unwrap_err().to_string(), "Missing argument seconds");
+}
+
+/// Test subcommands, their aliases and usage lines
+#[test]
+fn test_subcommand() {
+    assert_eq!(PlaylistArgs::parse_arg("add https://example.com").unwrap().0, Playlist::Add { url: "https://example.com".to_string() });
+    assert_eq!(PlaylistArgs::parse_arg("rm 2").unwrap().0, Playlist::Remove { index: 2 });
+    assert_eq!(PlaylistArgs::parse_arg("Clear").unwrap().0, Playlist::Clear);
+    assert_eq!(PlaylistArgs::usage(), vec!["add <url: String>", "remove <index: usize>", "clear"]);
+    assert_eq!(PlaylistArgs::parse_arg("remove first").unwrap_err().to_string(), "Invalid argument index: Error parsing first: invalid digit found in string");
+}
+
+/// Test `#[validate]` checks
+#[test]
+fn test_validate() {
+    assert_eq!(Repeat::parse_arg("3 hello").unwrap(), Repeat { amount: 3, text: "hello".to_string() });
+    let error = Repeat::parse_arg("11 hello").unwrap_err();
+    assert!(matches!(&error, GrammersthonError::InvalidArgs { field: Some(field), .. } if field == "amount"));
+    let error = Repeat::parse_arg("3 hello world").unwrap_err();
+    assert!(matches!(&error, GrammersthonError::InvalidArgs { field: Some(field), .. } if field == "text"));
+}
+
+/// Test `#[from_str]` fields and types
+#[test]
+fn test_from_str() {
+    assert_eq!(Paint::parse_arg("0xff 1a").unwrap(), Paint { color: Hex(255), level: Level(Hex(26)) });
+    assert_eq!(Paint::usage(), vec!["<color: Hex> <level: Level>"]);
+    assert_eq!(Paint::parse_arg("zz 1").unwrap_err().to_string(), "Invalid argument color: Error parsing zz: invalid digit found in string");
+}
+
+/// Test `#[or_reply]` fields parsed from the input without reply
+#[test]
+fn test_or_reply() {
+    assert!(Quote::uses_reply());
+    assert!(!Greet::uses_reply());
+    assert_eq!(Quote::parse_arg("hello me").unwrap(), Quote { text: "hello".to_string(), author: Some("me".to_string()) });
+    assert_eq!(Quote::usage(), vec!["[text: String] [author: String]"]);
+}
+
+/// Test parsing from named capture groups
+#[test]
+fn test_parse_captures() {
+    assert_eq!(Color::parse_captures(&captures(&[("r", "1"), ("g", "2")])).unwrap(), Color { r: 1, g: 2, b: None });
+    assert_eq!(Color::parse_captures(&captures(&[("r", "1"), ("g", "2"), ("b", "3")])).unwrap(), Color { r: 1, g: 2, b: Some(3) });
+    let error = Color::parse_captures(&captures(&[("r", "1")])).unwrap_err();
+    assert!(matches!(&error, GrammersthonError::InvalidArgs { field: Some(field), .. } if field == "g"));
+    assert_eq!(Color::parse_captures(&captures(&[("r", "x"), ("g", "2")])).unwrap_err().to_string(), "Invalid argument r: Error parsing x: invalid digit found in string");
+}